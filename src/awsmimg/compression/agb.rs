@@ -2,6 +2,7 @@ use std::io;
 use std::io::{Read, Write};
 use std::cmp::{Ord, Ordering, min};
 use std::collections::binary_heap::BinaryHeap;
+use std::collections::VecDeque;
 
 #[derive(Copy, Clone)]
 enum AGBHuffmanNode {
@@ -11,6 +12,43 @@ enum AGBHuffmanNode {
 
 type AGBHuffmanTree = (AGBHuffmanNode, AGBHuffmanNode);
 
+/// The compression schemes supported by the AGB BIOS.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AGBCompression {
+    LZ77,
+    Huffman,
+    RLE
+}
+
+impl AGBCompression {
+    /// Identify the compression scheme named by the first byte of a BIOS
+    /// compression header, if any.
+    pub fn from_header_byte(byte: u8) -> Option<AGBCompression> {
+        match byte {
+            0x10 => Some(AGBCompression::LZ77),
+            0x24 | 0x28 => Some(AGBCompression::Huffman),
+            0x30 => Some(AGBCompression::RLE),
+            _ => None
+        }
+    }
+}
+
+/// Read a BIOS compression header of the given type, returning the size of
+/// the decompressed data.
+fn read_agb_header<R: Read>(r: &mut R, header_type: u8, name: &str) -> io::Result<(u8, u32)> {
+    let mut hdr = [0u8; 4];
+    
+    if r.read_exact(&mut hdr).is_err() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("The AGB {} header extends past the end of the file.", name)));
+    }
+    
+    if hdr[0] >> 4 != header_type {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("This is not AGB {} data.", name)));
+    }
+    
+    Ok((hdr[0] & 0x0F, (hdr[3] as u32) << 16 | (hdr[2] as u32) << 8 | hdr[1] as u32))
+}
+
 /// Implements a decompression filter for reading compressed graphics data.
 /// 
 /// Reading from this struct will cause Huffman decompression to occur as
@@ -24,32 +62,30 @@ type AGBHuffmanTree = (AGBHuffmanNode, AGBHuffmanNode);
 /// 
 /// AGB compressed data formats contain internal size information that
 /// constitutes a limit on how many bytes can be decompressed from the reader.
-/// This read filter will refrain from providing 
-struct AGBHuffmanDecompressor<'a, R: Read + 'a> {
+/// This read filter will refrain from providing more bytes than that.
+pub struct AGBHuffmanDecompressor<'a, R: Read + 'a> {
     //DATA SOURCE
     r: &'a mut R,
     
     //DECODED AGBHuffman HEADER
-    bits_per_symbol: u8, //AKA "Data Size".
-    header_type: u8, //Must always be 2. Read from header.
+    bits_per_symbol: u8, //AKA "Data Size". Either 4 or 8.
     internal_size: u32, //Number of bytes in decompressed datastream.
     
     //DECODED AGBHuffman TREE
-    tree: Vec<AGBHuffmanTree>, //Huffman tree to decompress with.
+    tree: Vec<u8>, //Raw tree table, including the size byte, so that node addresses keep their alignment.
     
     //INTERNAL DECOMPRESSION STATE
     initialized: bool,
     decompressed_cnt: usize, //Number of bytes decompressed so far.
-    bitbuffer: u32, //Remaining already-read bits
+    bitbuffer: u32, //Current word of the bitstream, consumed from the top bit down.
     bitbuffer_len: u8, //Number of valid bits remaining in the buffer
 }
 
 impl <'a, R: Read + 'a> AGBHuffmanDecompressor<'a, R> {
-    fn new(r: &'a mut R) -> AGBHuffmanDecompressor<'a, R> {
+    pub fn new(r: &'a mut R) -> AGBHuffmanDecompressor<'a, R> {
         AGBHuffmanDecompressor {
-            r: r,
+            r,
             bits_per_symbol: 0,
-            header_type: 2,
             internal_size: 0,
             tree: Vec::new(),
             initialized: false,
@@ -60,178 +96,312 @@ impl <'a, R: Read + 'a> AGBHuffmanDecompressor<'a, R> {
     }
     
     fn read_huffman_header(&mut self) -> io::Result<()> {
-        let mut hdr = [0u8; 4];
-        let readbytes = self.r.read(&mut hdr)?;
+        let (bits_per_symbol, internal_size) = read_agb_header(self.r, 2, "Huffman")?;
         
-        if readbytes < hdr.len() {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The AGB Huffman general header extends past the end of the file."));
+        if bits_per_symbol != 4 && bits_per_symbol != 8 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "AGB Huffman data must use 4 or 8 bit symbols."));
         }
         
-        //AGB is little endian so I THINK this works!?
-        self.bits_per_symbol = hdr[0] & 0x0Fu8;
-        self.header_type = hdr[0] >> 4;
-        self.internal_size = ((hdr[3] as u32) << 16) | ((hdr[2] as u32) << 8) | (hdr[1] as u32);
-        
-        if self.header_type != 2 {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "This is not AGB Huffman data."))
-        }
+        self.bits_per_symbol = bits_per_symbol;
+        self.internal_size = internal_size;
         
         Ok(())
     }
     
     fn read_huffman_tree(&mut self) -> io::Result<()> {
         let mut hdr = [0u8; 1];
-        let readbytes = self.r.read(&mut hdr)?;
         
-        if readbytes < hdr.len() {
+        if self.r.read_exact(&mut hdr).is_err() {
             return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The AGB Huffman tree header extends past the end of the file."));
         }
         
-        let treesize = ((hdr[0] + 1) * 2).into();
-        let mut rawtree = Vec::with_capacity(treesize);
-        rawtree.resize(treesize, 0);
+        let treesize = (hdr[0] as usize + 1) * 2;
         
-        let readbytes2 = self.r.read(&mut rawtree.get_mut(0..treesize).unwrap())?;
+        self.tree = vec![0; treesize];
+        self.tree[0] = hdr[0];
         
-        if readbytes < rawtree.len() {
+        if self.r.read_exact(&mut self.tree[1..]).is_err() {
             return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The AGB Huffman tree data extends past the end of the file."));
         }
         
-        self.tree.resize(treesize / 2, (AGBHuffmanNode::Leaf(0), AGBHuffmanNode::Leaf(0)));
+        Ok(())
+    }
+    
+    /// Get the next bit of the bitstream, which is made of little-endian words
+    /// read from their most significant bit down.
+    fn get_next_bit(&mut self) -> io::Result<u8> {
+        if self.bitbuffer_len < 1 {
+            let mut word = [0u8; 4];
+            
+            //Raise error if we really can't get more bits
+            if self.r.read_exact(&mut word).is_err() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The AGB Huffman datastream ended before we could finish decompressing."));
+            }
+            
+            self.bitbuffer = u32::from_le_bytes(word);
+            self.bitbuffer_len = 32;
+        }
         
-        self.read_huffman_tree_internal(&rawtree, 0, false, false)?;
+        self.bitbuffer_len -= 1;
         
-        Ok(())
+        Ok(((self.bitbuffer >> self.bitbuffer_len) & 0x01) as u8)
     }
     
-    /// Internal recursive function to decode AGBHuffmanTree data.
-    fn read_huffman_tree_internal(&mut self, rawtree: &Vec<u8>, offset: usize, lnode_leaf: bool, rnode_leaf: bool) -> io::Result<()> {
-        let rawnode = rawtree.get(offset..offset+1).unwrap();
-        let mut lnode : AGBHuffmanNode;
-        let mut rnode : AGBHuffmanNode;
+    /// Walk the tree from the root node to a data node, returning its symbol.
+    fn next_symbol(&mut self) -> io::Result<u8> {
+        let mut addr = 1;
         
-        match lnode_leaf {
-            true => {
-                lnode = AGBHuffmanNode::Leaf(rawnode[0]);
-            },
-            false => {
-                lnode = AGBHuffmanNode::Branch((offset / 2) + ((rawnode[0] & 0x3F) + 1) as usize);
-                self.read_huffman_tree_internal(rawtree, (offset & 0xFE) + ((rawnode[0] & 0x3F) * 2) as usize + 2, rawnode[0] & 0x80 == 0x80, rawnode[0] & 0x40 == 0x40)?;
+        loop {
+            let node = self.tree[addr];
+            let nextbit = self.get_next_bit()?;
+            let child = (addr & !1) + (node & 0x3F) as usize * 2 + 2 + nextbit as usize;
+            let is_data = node & (0x80 >> nextbit) != 0;
+            
+            if child >= self.tree.len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "The AGB Huffman tree points outside of itself."));
             }
+            
+            if is_data {
+                return Ok(self.tree[child]);
+            }
+            
+            addr = child;
         }
+    }
+}
+
+impl <'a, R: Read + 'a> Read for AGBHuffmanDecompressor<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.initialized {
+            self.read_huffman_header()?;
+            self.read_huffman_tree()?;
+            self.initialized = true;
+        }
+        
+        let decomp_bytes_this_round = min(buf.len(), self.internal_size as usize - self.decompressed_cnt);
+        let symbols_per_byte = 8 / self.bits_per_symbol;
         
-        match rnode_leaf {
-            true => {
-                rnode = AGBHuffmanNode::Leaf(rawnode[1]);
-            },
-            false => {
-                rnode = AGBHuffmanNode::Branch((offset / 2) + ((rawnode[1] & 0x3F) + 1) as usize);
-                self.read_huffman_tree_internal(rawtree, (offset & 0xFE) + ((rawnode[1] & 0x3F) * 2) as usize + 2, rawnode[1] & 0x80 == 0x80, rawnode[1] & 0x40 == 0x40)?;
+        for byte in buf.iter_mut().take(decomp_bytes_this_round) {
+            *byte = 0;
+            
+            //Symbols are packed into bytes starting from the low bits.
+            for j in 0..symbols_per_byte {
+                *byte |= self.next_symbol()? << (j * self.bits_per_symbol);
             }
         }
         
-        self.tree[offset / 2] = (lnode, rnode);
+        self.decompressed_cnt += decomp_bytes_this_round;
         
-        Ok(())
+        Ok(decomp_bytes_this_round)
+    }
+}
+
+/// Implements a decompression filter for reading LZ77 compressed graphics
+/// data, as decompressed by the AGB BIOS's LZ77UnComp functions.
+/// 
+/// Header, error, and size limit handling are the same as for
+/// AGBHuffmanDecompressor.
+pub struct AGBLZ77Decompressor<'a, R: Read + 'a> {
+    r: &'a mut R,
+    internal_size: u32,
+    initialized: bool,
+    
+    //All data decompressed so far, which back-references may copy from.
+    window: Vec<u8>,
+    served: usize, //Number of decompressed bytes already returned by read.
+}
+
+impl <'a, R: Read + 'a> AGBLZ77Decompressor<'a, R> {
+    pub fn new(r: &'a mut R) -> AGBLZ77Decompressor<'a, R> {
+        AGBLZ77Decompressor {
+            r,
+            internal_size: 0,
+            initialized: false,
+            window: Vec::new(),
+            served: 0
+        }
     }
     
-    /// Attempts to fill the internal Huffman data stream (bitbuffer) with data.
-    /// 
-    /// Under normal circumstances, after calling this function the bitbuffer
-    /// will contain at least 24 bits of data. Up to 7 bits of data will be left
-    /// empty as we cannot read less than 8 bits at a time from the file.
-    /// 
-    /// If the underlying Read reaches an end-of-file condition or returns an
-    /// error, no change will be made to the current size of the bitbuffer.
-    fn fill_bit_buffer(&mut self) -> io::Result<()> {
-        let bits_needed = 32 - self.bitbuffer_len;
-        let bytes_needed : usize = (bits_needed / 8).into();
-        let mut buf = [0u8; 4];
-        let mut bytes_read = 0;
+    /// Decompress one flag byte's worth of blocks.
+    fn decompress_blocks(&mut self) -> io::Result<()> {
+        let mut flags = [0u8; 1];
+        
+        if self.r.read_exact(&mut flags).is_err() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The AGB LZ77 datastream ended before we could finish decompressing."));
+        }
         
-        if (bytes_needed == 0) {
-            return Ok(())
+        for i in 0..8 {
+            if self.window.len() >= self.internal_size as usize {
+                break;
+            }
+            
+            let mut block = [0u8; 2];
+            let blocklen = match flags[0] & (0x80 >> i) {
+                0 => 1,
+                _ => 2
+            };
+            
+            if self.r.read_exact(&mut block[..blocklen]).is_err() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The AGB LZ77 datastream ended before we could finish decompressing."));
+            }
+            
+            if blocklen == 1 {
+                self.window.push(block[0]);
+                continue;
+            }
+            
+            let count = (block[0] >> 4) as usize + 3;
+            let disp = ((block[0] as usize & 0x0F) << 8 | block[1] as usize) + 1;
+            
+            if disp > self.window.len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "The AGB LZ77 datastream refers to data before its start."));
+            }
+            
+            for _ in 0..count {
+                let byte = self.window[self.window.len() - disp];
+                self.window.push(byte);
+            }
         }
         
-        bytes_read = self.r.read(&mut buf[0..bytes_needed])?;
+        self.window.truncate(self.internal_size as usize);
         
-        if (bytes_read == 0) {
-            return Ok(())
+        Ok(())
+    }
+}
+
+impl <'a, R: Read + 'a> Read for AGBLZ77Decompressor<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.initialized {
+            self.internal_size = read_agb_header(self.r, 1, "LZ77")?.1;
+            self.initialized = true;
         }
         
-        for i in 0..bytes_read {
-            self.bitbuffer = self.bitbuffer | (buf[i] << (i * 8 + self.bitbuffer_len as usize)) as u32;
+        let wanted = min(self.served + buf.len(), self.internal_size as usize);
+        
+        while self.window.len() < wanted {
+            self.decompress_blocks()?;
         }
         
-        self.bitbuffer_len += (bytes_read * 8) as u8;
+        let count = wanted - self.served;
         
-        Ok(())
+        buf[..count].copy_from_slice(&self.window[self.served..wanted]);
+        self.served = wanted;
+        
+        Ok(count)
     }
+}
+
+/// Implements a decompression filter for reading run-length encoded graphics
+/// data, as decompressed by the AGB BIOS's RLUnComp functions.
+/// 
+/// Header, error, and size limit handling are the same as for
+/// AGBHuffmanDecompressor.
+pub struct AGBRLEDecompressor<'a, R: Read + 'a> {
+    r: &'a mut R,
+    internal_size: u32,
+    initialized: bool,
+    decompressed_cnt: usize,
     
-    /// Get the next bit from the internal bitbuffer, yielding None if that is
-    /// not possible.
-    fn get_next_bit(&mut self) -> io::Result<u8> {
-        //Get a bit from the bitbuffer
-        if (self.bitbuffer_len < 1) {
-            self.fill_bit_buffer()?;
-        }
+    //CURRENT RUN
+    run_len: usize, //Number of bytes remaining in the current run.
+    run_byte: Option<u8>, //Byte being repeated, or None for a run of uncompressed bytes.
+}
 
-        //Raise error if we really can't get more bits
-        if (self.bitbuffer_len < 1) {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The AGB Huffman datastream ended before we could finish decompressing."));
+impl <'a, R: Read + 'a> AGBRLEDecompressor<'a, R> {
+    pub fn new(r: &'a mut R) -> AGBRLEDecompressor<'a, R> {
+        AGBRLEDecompressor {
+            r,
+            internal_size: 0,
+            initialized: false,
+            decompressed_cnt: 0,
+            run_len: 0,
+            run_byte: None
         }
+    }
+    
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0u8; 1];
         
-        let nextbit = self.bitbuffer & 0x01;
-        self.bitbuffer_len -= 1;
-        self.bitbuffer = self.bitbuffer >> 1;
+        if self.r.read_exact(&mut byte).is_err() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The AGB RLE datastream ended before we could finish decompressing."));
+        }
         
-        Ok(nextbit as u8)
+        Ok(byte[0])
     }
 }
 
-impl <'a, R: Read + 'a> Read for AGBHuffmanDecompressor<'a, R> {
+impl <'a, R: Read + 'a> Read for AGBRLEDecompressor<'a, R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        if (self.initialized == false) {
-            self.read_huffman_header()?;
-            self.read_huffman_tree()?;
+        if !self.initialized {
+            self.internal_size = read_agb_header(self.r, 3, "RLE")?.1;
+            self.initialized = true;
         }
         
         let decomp_bytes_this_round = min(buf.len(), self.internal_size as usize - self.decompressed_cnt);
         
-        for i in 0..decomp_bytes_this_round {
-            buf[i] = 0;
-
-            let mut current_huffman_node = self.tree.get(0).unwrap().clone();
-            let symbols_per_byte = 8 / self.bits_per_symbol;
-            
-            for j in 0..symbols_per_byte {
-                let shift = j * self.bits_per_symbol;
-                let mask : u8 = 0xFF << 8 - shift;
+        for byte in buf.iter_mut().take(decomp_bytes_this_round) {
+            if self.run_len == 0 {
+                let flag = self.read_byte()?;
                 
-                loop {
-                    let nextbit = self.get_next_bit()?;
-                    let node = match nextbit {
-                        0 => current_huffman_node.0,
-                        _ => current_huffman_node.1
-                    };
-
-                    match node {
-                        AGBHuffmanNode::Branch(k) => {
-                            current_huffman_node = self.tree.get(k as usize).unwrap().clone();
-                        },
-                        AGBHuffmanNode::Leaf(d) => {
-                            buf[i] = buf[i] | (d & mask) << shift;
-                            break;
-                        }
-                    }
+                if flag & 0x80 != 0 {
+                    self.run_len = (flag & 0x7F) as usize + 3;
+                    self.run_byte = Some(self.read_byte()?);
+                } else {
+                    self.run_len = (flag & 0x7F) as usize + 1;
+                    self.run_byte = None;
                 }
             }
+            
+            *byte = match self.run_byte {
+                Some(b) => b,
+                None => self.read_byte()?
+            };
+            
+            self.run_len -= 1;
         }
         
+        self.decompressed_cnt += decomp_bytes_this_round;
+        
         Ok(decomp_bytes_this_round)
     }
 }
 
+/// Read an entire stream, decompressing it if it holds data compressed for
+/// the AGB BIOS.
+/// 
+/// Compressed data is recognized by its header. Data that merely starts with
+/// something resembling a header, but fails to decompress to the size the
+/// header specifies, is assumed not to be compressed and is returned as-is.
+pub fn decompress_if_compressed<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    
+    r.read_to_end(&mut data)?;
+    
+    let compression = match data.first().and_then(|b| AGBCompression::from_header_byte(*b)) {
+        Some(c) => c,
+        None => return Ok(data)
+    };
+    
+    let size = data[1..].iter().take(3).rev().fold(0, |acc, b| acc << 8 | *b as usize);
+    
+    if size == 0 {
+        return Ok(data);
+    }
+    
+    let mut out = Vec::with_capacity(size);
+    let mut src = &data[..];
+    let result = match compression {
+        AGBCompression::LZ77 => AGBLZ77Decompressor::new(&mut src).read_to_end(&mut out),
+        AGBCompression::Huffman => AGBHuffmanDecompressor::new(&mut src).read_to_end(&mut out),
+        AGBCompression::RLE => AGBRLEDecompressor::new(&mut src).read_to_end(&mut out)
+    };
+    
+    match result {
+        Ok(_) if out.len() == size => Ok(out),
+        _ => Ok(data)
+    }
+}
+
 /// Type alias for a weight referencing a node ID in a memory arena.
 ///
 /// To be used with a binary heap
@@ -262,8 +432,13 @@ impl Ord for AGBHuffmanNodeWeight {
 /// until it is flushed. Flushing the compressor constitutes compressing and
 /// writing all compressed data plus the header to the underlying file. This is
 /// an additional semantic burden on Write.flush but was considered preferrable
-/// to compressing on Drop.
-struct AGBHuffmanCompressor<'a, W: Write + 'a> {
+/// to compressing on Drop. Each flush writes a complete compressed stream of
+/// the data written since the previous one.
+///
+/// Trees are laid out breadth-first. The BIOS format can only reach child nodes
+/// up to 63 pairs ahead, which some 8-bit trees with many distinct symbols
+/// exceed; flushing such data fails with an InvalidInput error.
+pub struct AGBHuffmanCompressor<'a, W: Write + 'a> {
     // DATA SINK
     w: &'a mut W,
 
@@ -273,94 +448,139 @@ struct AGBHuffmanCompressor<'a, W: Write + 'a> {
     // INTERNAL COMPRESSION STATE
     data: Vec<u8>,
     tree: Vec<AGBHuffmanTree>,
-    frequency: Vec<u32>,
 }
 
 impl<'a, W: Write + 'a> AGBHuffmanCompressor<'a, W> {
     pub fn new(w: &'a mut W, bits_per_symbol: u8) -> AGBHuffmanCompressor<'a, W> {
-        let max_symbols = 2_usize.pow(bits_per_symbol.into());
-
         AGBHuffmanCompressor {
-            w: w,
-            bits_per_symbol: bits_per_symbol,
+            w,
+            bits_per_symbol,
             data: Vec::new(),
-            tree: Vec::with_capacity(max_symbols),
-            frequency: Vec::with_capacity(max_symbols),
+            tree: Vec::new(),
         }
     }
 
-    fn serialize_huffman_tree(&self, tree: &AGBHuffmanTree) -> Vec<u8> {
-        let mut treenode = vec![0u8; 2];
+    /// Split the buffered data into symbols, low bits of each byte first.
+    fn symbols(&self) -> Vec<u8> {
+        let symbols_per_byte = 8 / self.bits_per_symbol;
+        let mask = (0xFFu16 >> (8 - self.bits_per_symbol)) as u8;
+        let mut out = Vec::with_capacity(self.data.len() * symbols_per_byte as usize);
 
-        //TODO: How do we allocate space to write child nodes in?
+        for byte in self.data.iter() {
+            for i in 0..symbols_per_byte {
+                out.push((byte >> (i * self.bits_per_symbol)) & mask);
+            }
+        }
 
-        match tree.0 {
-            AGBHuffmanNode::Branch(i) => {
-                let child0bit = match self.tree[i].0 {
-                    AGBHuffmanNode::Branch(j) => 0x80,
-                    AGBHuffmanNode::Leaf(j) => 0x00,
-                };
-                let child1bit = match self.tree[i].1 {
-                    AGBHuffmanNode::Branch(j) => 0x40,
-                    AGBHuffmanNode::Leaf(j) => 0x00,
-                };
+        out
+    }
 
-                treenode[0] = 0u8 | child0bit | child1bit;
-                treenode.extend(self.serialize_huffman_tree(&self.tree[i]));
-            },
-            AGBHuffmanNode::Leaf(i) => {
-                treenode[0] = i;
+    /// Build self.tree from symbol frequencies, returning the root node.
+    fn build_huffman_tree(&mut self, symbols: &[u8]) -> AGBHuffmanTree {
+        let mut frequency = vec![0u32; 1 << self.bits_per_symbol];
+        let mut heap = BinaryHeap::new();
+
+        for symbol in symbols {
+            frequency[*symbol as usize] += 1;
+        }
+
+        for (i, frequency) in frequency.iter().enumerate() {
+            if *frequency == 0 {
+                continue;
             }
-        };
-
-        match tree.1 {
-            AGBHuffmanNode::Branch(i) => {
-                let child0bit = match self.tree[i].0 {
-                    AGBHuffmanNode::Branch(j) => 0x80,
-                    AGBHuffmanNode::Leaf(j) => 0x00,
-                };
-                let child1bit = match self.tree[i].1 {
-                    AGBHuffmanNode::Branch(j) => 0x40,
-                    AGBHuffmanNode::Leaf(j) => 0x00,
-                };
-                let childpos = treenode.len() / 2 - 1;
-
-                treenode[1] = (childpos & 0x3F) as u8 | child0bit | child1bit;
-                treenode.extend(self.serialize_huffman_tree(&self.tree[i]));
-            },
-            AGBHuffmanNode::Leaf(i) => {
-                treenode[1] = i;
+
+            heap.push(AGBHuffmanNodeWeight{freq: *frequency, pos: None, symbol: Some(i as u8)});
+        }
+
+        self.tree.clear();
+
+        while let Some(AGBHuffmanNodeWeight{freq: freq1, pos: pos1, symbol: sym1}) = heap.pop() {
+            let leftbranch = match pos1 {
+                Some(p) => AGBHuffmanNode::Branch(p),
+                None => AGBHuffmanNode::Leaf(sym1.unwrap())
+            };
+
+            match heap.pop() {
+                Some(AGBHuffmanNodeWeight{freq: freq2, pos: pos2, symbol: sym2}) => {
+                    //There are two weights remaining in the queue - join the
+                    //lesser node to the greater one.
+                    let rightbranch = match pos2 {
+                        Some(p) => AGBHuffmanNode::Branch(p),
+                        None => AGBHuffmanNode::Leaf(sym2.unwrap())
+                    };
+
+                    let newpos = self.tree.len();
+                    self.tree.push((leftbranch, rightbranch));
+                    heap.push(AGBHuffmanNodeWeight{freq: freq1+freq2, pos: Some(newpos), symbol: None});
+                },
+                None => {
+                    //By the way this heap works, the last node processed is
+                    //the root node.
+                    return match leftbranch {
+                        AGBHuffmanNode::Branch(p) => self.tree[p],
+                        //Data with only one distinct symbol still needs a
+                        //root node with two children.
+                        leaf => (leaf, leaf)
+                    };
+                }
             }
-        };
+        }
 
-        treenode
+        //Empty data gets a dummy tree.
+        (AGBHuffmanNode::Leaf(0), AGBHuffmanNode::Leaf(0))
     }
 
-    fn write_huffman_header(&mut self, rootnode: usize) -> io::Result<()> {
-        let mut hdr = [0u8; 5];
+    /// Collect the bit sequence leading to each symbol in the tree.
+    fn collect_codes(&self, node: AGBHuffmanTree, prefix: &mut Vec<bool>, codes: &mut Vec<Vec<bool>>) {
+        for (bit, child) in [(false, node.0), (true, node.1)].iter() {
+            prefix.push(*bit);
 
-        hdr[0] = ((self.bits_per_symbol & 0x0F) | 0x20) as u8;
-        hdr[1] = (self.data.len() & 0xFF) as u8;
-        hdr[2] = ((self.data.len() >> 8) & 0xFF) as u8;
-        hdr[3] = ((self.data.len() >> 16) & 0xFF) as u8;
+            match *child {
+                AGBHuffmanNode::Branch(i) => self.collect_codes(self.tree[i], prefix, codes),
+                AGBHuffmanNode::Leaf(symbol) => {
+                    if codes[symbol as usize].is_empty() {
+                        codes[symbol as usize] = prefix.clone();
+                    }
+                }
+            }
 
-        //Huffman data
-        let hdata = self.serialize_huffman_tree(&self.tree[rootnode]);
+            prefix.pop();
+        }
+    }
 
-        //Huffman header
-        hdr[4] = (hdata.len() / 2 - 1) as u8;
+    /// Lay out the tree in the order the AGB BIOS expects, including the size
+    /// byte at the start.
+    fn serialize_huffman_tree(&self, root: AGBHuffmanTree) -> io::Result<Vec<u8>> {
+        let mut out = vec![0u8; 2];
+        let mut queue = VecDeque::new();
 
-        let written = self.w.write(&hdr)?;
-        if written < 5 {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Couldn't write complete AGB compressed graphics header"))
-        }
+        queue.push_back((root, 1));
+
+        while let Some((node, addr)) = queue.pop_front() {
+            let pair = out.len();
+            let offset = (pair - (addr & !1) - 2) / 2;
 
-        let written2 = self.w.write(&hdata)?;
-        if written2 < hdata.len() {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Couldn't write complete AGB huffman tree"))
+            if offset > 0x3F {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "This data's Huffman tree is too wide for the AGB BIOS tree format."));
+            }
+
+            out[addr] = offset as u8;
+            out.extend_from_slice(&[0, 0]);
+
+            for (i, (child, flag)) in [(node.0, 0x80), (node.1, 0x40)].iter().enumerate() {
+                match *child {
+                    AGBHuffmanNode::Branch(p) => queue.push_back((self.tree[p], pair + i)),
+                    AGBHuffmanNode::Leaf(symbol) => {
+                        out[addr] |= flag;
+                        out[pair + i] = symbol;
+                    }
+                }
+            }
         }
 
-        Ok(())
+        out[0] = (out.len() / 2 - 1) as u8;
+
+        Ok(out)
     }
 }
 
@@ -368,84 +588,144 @@ impl<'a, W: Write + 'a> Write for AGBHuffmanCompressor<'a, W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.data.extend_from_slice(buf);
 
-        for byte in buf {
-            let symbols_per_byte = 8 / self.bits_per_symbol;
-            for i in 0..symbols_per_byte {
-                let shift = i * self.bits_per_symbol;
-                let mask : u8 = 0xFF << 8 - shift;
-                let symbol = ((byte & mask) >> shift) as usize;
-
-                if (self.frequency.len() < symbol) {
-                    self.frequency.resize(symbol, 0);
-                }
-
-                self.frequency[symbol] += 1;
-            }
-        }
-
         Ok(buf.len())
     }
 
     fn flush(&mut self) -> io::Result<()> {
+        if self.bits_per_symbol != 4 && self.bits_per_symbol != 8 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "AGB Huffman data must use 4 or 8 bit symbols."));
+        }
+
+        if self.data.len() > 0xFFFFFF {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "AGB compressed data must decompress to less than 16MB."));
+        }
+
         // Construct a huffman tree and compressed datastream.
-        let mut heap = BinaryHeap::new();
+        let symbols = self.symbols();
+        let root = self.build_huffman_tree(&symbols);
+        let tree = self.serialize_huffman_tree(root)?;
+        let mut codes = vec![Vec::new(); 1 << self.bits_per_symbol];
 
-        for (i, frequency) in self.frequency.iter().enumerate() {
-            if *frequency == 0 {
-                continue;
+        self.collect_codes(root, &mut Vec::new(), &mut codes);
+
+        let hdr = [0x20 | self.bits_per_symbol, (self.data.len() & 0xFF) as u8, ((self.data.len() >> 8) & 0xFF) as u8, ((self.data.len() >> 16) & 0xFF) as u8];
+
+        self.w.write_all(&hdr)?;
+        self.w.write_all(&tree)?;
+
+        // The bitstream is made of little-endian words filled from their most
+        // significant bit down.
+        let mut word = 0u32;
+        let mut word_len = 0;
+
+        for symbol in symbols {
+            for bit in codes[symbol as usize].iter() {
+                word |= (*bit as u32) << (31 - word_len);
+                word_len += 1;
+
+                if word_len == 32 {
+                    self.w.write_all(&word.to_le_bytes())?;
+                    word = 0;
+                    word_len = 0;
+                }
             }
+        }
 
-            heap.push(AGBHuffmanNodeWeight{freq: *frequency, pos: None, symbol: Some(i as u8)});
+        if word_len > 0 {
+            self.w.write_all(&word.to_le_bytes())?;
         }
 
-        //By the way this heap works, the last node processed is the root node.
-        let mut lastnode = 0;
+        self.data.clear();
 
-        while let Some(AGBHuffmanNodeWeight{freq: freq1, pos: pos1, symbol: sym1}) = heap.pop() {
-            match heap.pop() {
-                Some(AGBHuffmanNodeWeight{freq: freq2, pos: pos2, symbol: sym2}) => {
-                    //There are two weights remaining in the queue - join the
-                    //lesser node to the greater one.
+        // Flush the underlying file.
+        self.w.flush()
+    }
+}
 
-                    let leftbranch = match pos1 {
-                        Some(p) => {
-                            AGBHuffmanNode::Branch(p)
-                        },
-                        None => {
-                            AGBHuffmanNode::Leaf(sym1.unwrap())
-                        }
-                    };
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::io::Write;
+    use awsmimg::compression::agb::{AGBLZ77Decompressor, AGBRLEDecompressor, AGBHuffmanDecompressor, AGBHuffmanCompressor, decompress_if_compressed};
 
-                    let rightbranch = match pos2 {
-                        Some(p) => {
-                            AGBHuffmanNode::Branch(p)
-                        },
-                        None => {
-                            AGBHuffmanNode::Leaf(sym2.unwrap())
-                        }
-                    };
+    #[test]
+    fn lz77_decompress() {
+        let src : Vec<u8> = vec![0x10, 0x08, 0x00, 0x00, 0x10, b'A', b'B', b'C', 0x20, 0x02];
+        let mut out = Vec::new();
 
-                    let newpos = self.tree.len();
-                    self.tree.push((leftbranch, rightbranch));
-                    heap.push(AGBHuffmanNodeWeight{freq: freq1+freq2, pos: Some(newpos), symbol: None});
-                    lastnode = newpos;
-                },
-                None => {
-                    break;
-                }
-            }
+        AGBLZ77Decompressor::new(&mut &src[..]).read_to_end(&mut out).unwrap();
+
+        assert_eq!(out, b"ABCABCAB".to_vec());
+        assert!(AGBLZ77Decompressor::new(&mut &src[..9]).read_to_end(&mut Vec::new()).is_err());
+        assert!(AGBLZ77Decompressor::new(&mut &[0x10u8, 0x04, 0x00, 0x00, 0x80, 0x00, 0x00][..]).read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn rle_decompress() {
+        let src : Vec<u8> = vec![0x30, 0x06, 0x00, 0x00, 0x01, b'x', b'y', 0x81, b'z'];
+        let mut out = Vec::new();
+
+        AGBRLEDecompressor::new(&mut &src[..]).read_to_end(&mut out).unwrap();
+
+        assert_eq!(out, b"xyzzzz".to_vec());
+    }
+
+    #[test]
+    fn huffman_decompress() {
+        let src : Vec<u8> = vec![0x28, 0x04, 0x00, 0x00, 0x01, 0xC0, b'a', b'b', 0x00, 0x00, 0x00, 0x60];
+        let mut out = Vec::new();
+
+        AGBHuffmanDecompressor::new(&mut &src[..]).read_to_end(&mut out).unwrap();
+
+        assert_eq!(out, b"abba".to_vec());
+
+        let nibbles : Vec<u8> = vec![0x24, 0x02, 0x00, 0x00, 0x01, 0xC0, 0x01, 0x02, 0x00, 0x00, 0x00, 0x60];
+        let mut out = Vec::new();
+
+        AGBHuffmanDecompressor::new(&mut &nibbles[..]).read_to_end(&mut out).unwrap();
+
+        assert_eq!(out, vec![0x21, 0x12]);
+    }
+
+    fn huffman_round_trip(data: &[u8], bits_per_symbol: u8) -> Vec<u8> {
+        let mut compressed = Vec::new();
+
+        {
+            let mut comp = AGBHuffmanCompressor::new(&mut compressed, bits_per_symbol);
+
+            comp.write_all(data).unwrap();
+            comp.flush().unwrap();
         }
 
-        // At this point self.tree is populated with an ostensibly completed
-        // Huffman tree, and we now need to write it out in the order the AGB
-        // BIOS expects.
+        let mut out = Vec::new();
 
+        AGBHuffmanDecompressor::new(&mut &compressed[..]).read_to_end(&mut out).unwrap();
 
+        out
+    }
 
-        // Now we need to actually use our tree to encode the data!
+    #[test]
+    fn huffman_compress() {
+        let text = b"a man, a plan, a canal: panama".to_vec();
+        let tiles : Vec<u8> = (0..64).map(|i| (i % 7) as u8 | ((i % 3) as u8) << 4).collect();
 
-        // Flush the underlying file.
-        self.w.flush()?;
-        Ok(())
+        assert_eq!(huffman_round_trip(&text, 8), text);
+        assert_eq!(huffman_round_trip(&tiles, 4), tiles);
+        assert_eq!(huffman_round_trip(&tiles, 8), tiles);
+        assert_eq!(huffman_round_trip(&[0x11, 0x11, 0x11], 4), vec![0x11, 0x11, 0x11]);
+        assert_eq!(huffman_round_trip(&[], 8), Vec::<u8>::new());
+
+        assert!(AGBHuffmanCompressor::new(&mut Vec::new(), 2).flush().is_err());
+    }
+
+    #[test]
+    fn detect_compression() {
+        let rle : Vec<u8> = vec![0x30, 0x03, 0x00, 0x00, 0x80, 0x07];
+        let fake : Vec<u8> = vec![0x10, 0xFF, 0x00, 0x00, 0x00];
+        let raw : Vec<u8> = vec![0x01, 0x02, 0x03];
+
+        assert_eq!(decompress_if_compressed(&mut &rle[..]).unwrap(), vec![7, 7, 7]);
+        assert_eq!(decompress_if_compressed(&mut &fake[..]).unwrap(), fake);
+        assert_eq!(decompress_if_compressed(&mut &raw[..]).unwrap(), raw);
     }
 }
@@ -1,6 +1,7 @@
-use image::{GenericImage, Pixel, Primitive, ImageBuffer, LumaA};
+use image::{GenericImage, Pixel, Primitive, ImageBuffer, Luma, LumaA, Rgba};
 use num::NumCast;
-use std::ops::Div;
+
+use awsmimg::formats::DEFAULT_ALPHA_THRESHOLD;
 
 /// Given an image, produce a stream of index data to encode by interpreting
 /// the grayscale values of the image as indexes.
@@ -11,9 +12,9 @@ use std::ops::Div;
 ///
 /// RGB data will be converted to grayscale. Once converted to luminance data,
 /// each individual value will be mapped to an integer within the range
-/// [0, maxcol) to produce a final integer value. Alpha values within the image
-/// with a value of zero will be ignored for the purposes of determining the
-/// size of the data to be converted. When preoparing an image whose dimensions
+/// [0, maxcol) to produce a final integer value. Pixels whose alpha is below
+/// formats::DEFAULT_ALPHA_THRESHOLD will be ignored for the purposes of
+/// determining the size of the data to be converted. When preoparing an image whose dimensions
 /// do not divide cleanly into the tile count, you may add "blank" tiles
 /// consisting of transparent pixels to indicate that they should not be
 /// encoded.
@@ -24,7 +25,20 @@ use std::ops::Div;
 /// implementation-defined.
 pub fn indexes_from_luma<I, P, S>(image: &I, maxcol: S, tsize: (u32, u32)) -> Vec<S>
     where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
-    
+
+    indexes_from_luma_with_alpha_threshold(image, maxcol, tsize, DEFAULT_ALPHA_THRESHOLD)
+}
+
+/// Given an image, produce a stream of index data as indexes_from_luma does,
+/// treating pixels as transparent unless their 8-bit alpha is at least the
+/// given threshold.
+///
+/// indexes_from_luma uses formats::DEFAULT_ALPHA_THRESHOLD, as palette and
+/// direct color encoders do by default. When choosing another threshold, pass
+/// it to those as well, so that they agree on which colors are transparent.
+pub fn indexes_from_luma_with_alpha_threshold<I, P, S>(image: &I, maxcol: S, tsize: (u32, u32), alpha_threshold: u8) -> Vec<S>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
     let (width, height) = image.dimensions();
     let (tw, th) = tsize;
     let mut out : Vec<S> = Vec::with_capacity(width as usize * height as usize);
@@ -37,21 +51,199 @@ pub fn indexes_from_luma<I, P, S>(image: &I, maxcol: S, tsize: (u32, u32)) -> Ve
     for (ix, iy, pixel) in image.pixels() {
         let la = pixel.to_luma_alpha();
         let gray = la[0].to_f32().unwrap();
-        let alpha = la[1].to_u8().unwrap();
+        let alpha = (la[1].to_f32().unwrap() / imgmax * 255f32) as u8;
         
         let tx = ix / tw;
         let px = ix % tw;
-        let ty = iy / tw;
-        let py = iy % tw;
+        let ty = iy / th;
+        let py = iy % th;
         
         let itile = ty * (width / tw) + tx;
         let outidx = (itile * tlen + py * tw + px) as usize;
         
-        if outidx >= out.len() && alpha != 0u8 {
+        if outidx >= out.len() && alpha >= alpha_threshold {
             out.resize(outidx + 1, S::from(0u8).unwrap());
         }
         
-        out[outidx] = S::from((gray / imgmax * maxcol_adj).floor()).unwrap();
+        if outidx < out.len() {
+            out[outidx] = S::from((gray / imgmax * maxcol_adj).floor()).unwrap();
+        }
+    }
+
+    out
+}
+
+/// How grayscale values stand for color indexes.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GrayRamp {
+    /// Indexes are spread evenly over the whole range of grays, as
+    /// indexes_from_luma and luma_from_indexes do.
+    Linear,
+
+    /// Each index is drawn with one particular gray, given in index order.
+    /// Grays are read as the index whose gray is nearest.
+    Levels(Vec<u8>),
+
+    /// Grays up to each breakpoint, in ascending order, are read as one
+    /// index; grays at or past the last breakpoint are read as the index
+    /// after it. Each index is drawn with the lowest gray read as it.
+    Breakpoints(Vec<u8>)
+}
+
+impl GrayRamp {
+    /// Read an 8-bit gray as an index no greater than maxcol.
+    pub fn index_of(&self, gray: u8, maxcol: u16) -> u16 {
+        let index = match self {
+            GrayRamp::Linear => (gray as f32 / 255f32 * maxcol as f32).floor() as u16,
+            GrayRamp::Levels(levels) => levels.iter().enumerate()
+                .min_by_key(|(_, l)| (**l as i16 - gray as i16).abs())
+                .map_or(0, |(i, _)| i as u16),
+            GrayRamp::Breakpoints(points) => points.iter().filter(|p| **p <= gray).count() as u16
+        };
+
+        index.min(maxcol)
+    }
+
+    /// Find the 8-bit gray an index no greater than maxcol is drawn with.
+    ///
+    /// Indexes beyond the end of a list of levels are drawn with the last
+    /// level.
+    pub fn gray_of(&self, index: u16, maxcol: u16) -> u8 {
+        match self {
+            GrayRamp::Linear => (index as f32 * (255f32 / maxcol as f32)) as u8,
+            GrayRamp::Levels(levels) => levels.get(index as usize).or(levels.last()).cloned().unwrap_or(0),
+            GrayRamp::Breakpoints(points) => match index {
+                0 => 0,
+                i => points.get(i as usize - 1).or(points.last()).cloned().unwrap_or(0)
+            }
+        }
+    }
+}
+
+/// Parse a grayscale ramp from a description such as `linear`,
+/// `levels:0,96,160,255` or `breakpoints:90,150,250`.
+///
+/// Levels and breakpoints are 8-bit grays separated by commas. Breakpoints
+/// must be given in ascending order.
+pub fn interpret_gray_ramp(ramp_given: &str) -> Option<GrayRamp> {
+    let ramp = ramp_given.to_ascii_lowercase();
+    let (kind, grays) = match ramp.find(':') {
+        Some(i) => (&ramp[..i], Some(&ramp[i + 1..])),
+        None => (&ramp[..], None)
+    };
+    let grays = match grays.map(|g| g.split(',').map(|v| v.trim().parse::<u8>()).collect::<Result<Vec<u8>, _>>()) {
+        Some(Ok(g)) => Some(g),
+        Some(Err(_)) => return None,
+        None => None
+    };
+
+    match (kind, grays) {
+        ("linear", None) => Some(GrayRamp::Linear),
+        ("levels", Some(levels)) => Some(GrayRamp::Levels(levels)),
+        ("breakpoints", Some(ref points)) if points.windows(2).all(|p| p[0] < p[1]) => Some(GrayRamp::Breakpoints(points.clone())),
+        _ => None
+    }
+}
+
+/// Given an image, produce a stream of index data to encode by interpreting
+/// its grayscale values as indexes through the given ramp.
+///
+/// This behaves as indexes_from_luma does, including its handling of tiles
+/// and transparent pixels, except that grays are read through the ramp
+/// rather than by scaling. Grays are taken at 8 bits of precision.
+pub fn indexes_from_luma_with_ramp<I, P, S>(image: &I, ramp: &GrayRamp, maxcol: u16, tsize: (u32, u32)) -> Vec<u16>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    indexes_from_luma_with_ramp_and_alpha_threshold(image, ramp, maxcol, tsize, DEFAULT_ALPHA_THRESHOLD)
+}
+
+/// Given an image, produce a stream of index data as
+/// indexes_from_luma_with_ramp does, treating pixels as transparent unless
+/// their 8-bit alpha is at least the given threshold, as in
+/// indexes_from_luma_with_alpha_threshold.
+pub fn indexes_from_luma_with_ramp_and_alpha_threshold<I, P, S>(image: &I, ramp: &GrayRamp, maxcol: u16, tsize: (u32, u32), alpha_threshold: u8) -> Vec<u16>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    let (width, height) = image.dimensions();
+    let (tw, th) = tsize;
+    let mut out : Vec<u16> = Vec::with_capacity(width as usize * height as usize);
+    let imgmax: f32 = NumCast::from(S::max_value()).unwrap();
+
+    let tlen = tw * th;
+
+    for (ix, iy, pixel) in image.pixels() {
+        let la = pixel.to_luma_alpha();
+        let gray = (la[0].to_f32().unwrap() / imgmax * 255f32).round() as u8;
+        let alpha = (la[1].to_f32().unwrap() / imgmax * 255f32) as u8;
+
+        let itile = (iy / th) * (width / tw) + ix / tw;
+        let outidx = (itile * tlen + (iy % th) * tw + ix % tw) as usize;
+
+        if outidx >= out.len() && alpha >= alpha_threshold {
+            out.resize(outidx + 1, 0);
+        }
+
+        if outidx < out.len() {
+            out[outidx] = ramp.index_of(gray, maxcol);
+        }
+    }
+
+    out
+}
+
+/// Given an image, produce a stream of 8-bit alpha values in the same order
+/// as indexes_from_luma would produce index data for a given tile size.
+///
+/// This allows encoders for formats that store per-pixel alpha alongside
+/// color indexes to take alpha from the source image, since the indexes
+/// themselves are derived from luminance alone.
+pub fn alpha_from_image<I, P, S>(image: &I, tsize: (u32, u32)) -> Vec<u8>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    let (width, height) = image.dimensions();
+    let (tw, th) = tsize;
+    let mut out : Vec<u8> = vec![0; width as usize * height as usize];
+    let imgmax: f32 = NumCast::from(S::max_value()).unwrap();
+
+    let tlen = tw * th;
+
+    for (ix, iy, pixel) in image.pixels() {
+        let alpha = pixel.to_luma_alpha()[1].to_f32().unwrap();
+
+        let itile = (iy / th) * (width / tw) + ix / tw;
+        let outidx = (itile * tlen + (iy % th) * tw + ix % tw) as usize;
+
+        if outidx < out.len() {
+            out[outidx] = (alpha / imgmax * 255f32) as u8;
+        }
+    }
+
+    out
+}
+
+/// Given an image of color indexes, such as one read from an indexed-color
+/// PNG, produce a stream of index data to encode.
+///
+/// Pixels are separated into tiles as with indexes_from_luma, but each pixel
+/// is taken as an index exactly as-is; no grayscale conversion or scaling
+/// takes place, so index assignments made by an artist survive unchanged.
+/// The image dimensions must be a multiple of the tile size.
+pub fn indexes_from_index_image<I>(image: &I, tsize: (u32, u32)) -> Vec<u8>
+    where I: GenericImage<Pixel=Luma<u8>> {
+
+    let (width, height) = image.dimensions();
+    let (tw, th) = tsize;
+    let mut out : Vec<u8> = vec![0; width as usize * height as usize];
+
+    let tlen = tw * th;
+
+    for (ix, iy, pixel) in image.pixels() {
+        let itile = (iy / th) * (width / tw) + ix / tw;
+        let outidx = (itile * tlen + (iy % th) * tw + ix % tw) as usize;
+
+        if outidx < out.len() {
+            out[outidx] = pixel[0];
+        }
     }
 
     out
@@ -75,8 +267,146 @@ pub fn indexes_from_luma<I, P, S>(image: &I, maxcol: S, tsize: (u32, u32)) -> Ve
 /// pixels. As a result, the pixel format of returned images will be locked to
 /// LumaA pixels.
 pub fn luma_from_indexes<'a, S>(data: Vec<S>, maxcol: u16, tsize: (u32, u32), isize: Option<(u32, u32)>) -> Option<Box<ImageBuffer<LumaA<u8>, Vec<u8>>>> where S: Primitive + 'a {
-    let mut iw;
-    let mut ih;
+    let maxcol : f32 = NumCast::from(maxcol).unwrap();
+    let colscale : f32 = 255f32 / maxcol;
+    
+    //TODO: What if we have a format that needs more than 8 bits of precision?
+    image_from_indexes(data, tsize, isize, LumaA([0u8, 0u8]), |index| {
+        let tileval : f32 = NumCast::from(index).unwrap();
+        LumaA([NumCast::from(tileval * colscale).unwrap(), 255u8])
+    })
+}
+
+/// Given a stream of decoded index data, produce a grayscale image of it as
+/// luma_from_indexes does, drawing each index with its gray in the given
+/// ramp.
+pub fn luma_from_indexes_with_ramp<'a, S>(data: Vec<S>, ramp: &GrayRamp, maxcol: u16, tsize: (u32, u32), isize: Option<(u32, u32)>) -> Option<Box<ImageBuffer<LumaA<u8>, Vec<u8>>>> where S: Primitive + 'a {
+    image_from_indexes(data, tsize, isize, LumaA([0u8, 0u8]), |index| {
+        LumaA([ramp.gray_of(index.to_u16().unwrap_or(maxcol), maxcol), 255u8])
+    })
+}
+
+/// Given index data and a palette, produce a full-color image by looking up
+/// each index in the palette.
+/// 
+/// Tile layout and image sizing follow the same rules as luma_from_indexes,
+/// including the use of fully transparent pixels for parts of the image not
+/// holding decoded index data. Indexes beyond the end of the palette also
+/// produce fully transparent pixels.
+pub fn rgba_from_indexes<'a, S>(data: Vec<S>, palette: &[Rgba<u8>], tsize: (u32, u32), isize: Option<(u32, u32)>) -> Option<Box<ImageBuffer<Rgba<u8>, Vec<u8>>>> where S: Primitive + 'a {
+    image_from_indexes(data, tsize, isize, Rgba([0u8, 0u8, 0u8, 0u8]), |index| {
+        let index : usize = NumCast::from(index).unwrap();
+        palette.get(index).cloned().unwrap_or(Rgba([0u8, 0u8, 0u8, 0u8]))
+    })
+}
+
+/// Given index data, lay it out as an image holding each index as-is.
+/// 
+/// Unlike luma_from_indexes, indexes are not scaled to span the range of
+/// grayscale values, so the image can serve as the pixel data of an indexed
+/// color image. Tile layout and image sizing follow the same rules as
+/// luma_from_indexes, except that parts of the image not holding decoded
+/// index data use index zero. Indexes above 255 are clamped to 255.
+pub fn index_image_from_indexes<'a, S>(data: Vec<S>, tsize: (u32, u32), isize: Option<(u32, u32)>) -> Option<Box<ImageBuffer<Luma<u8>, Vec<u8>>>> where S: Primitive + 'a {
+    image_from_indexes(data, tsize, isize, Luma([0u8]), |index| {
+        Luma([index.to_u8().unwrap_or(255)])
+    })
+}
+
+/// Given a palette, lay out its colors as a grid of square swatches, for
+/// checking palette conversions by eye.
+/// 
+/// Swatches are size pixels square and placed left to right, top to bottom,
+/// columns to a row. Parts of the last row past the end of the palette are
+/// fully transparent.
+pub fn swatches_from_palette(palette: &[Rgba<u8>], columns: u32, size: u32) -> Option<Box<ImageBuffer<Rgba<u8>, Vec<u8>>>> {
+    if palette.is_empty() || columns == 0 || size == 0 {
+        return None;
+    }
+
+    let rows = (palette.len() as u32).div_ceil(columns);
+    let data : Vec<u16> = (0..palette.len() as u16).flat_map(|i| vec![i; (size * size) as usize]).collect();
+
+    rgba_from_indexes(data, palette, (size, size), Some((columns * size, rows * size)))
+}
+
+/// Given index data, draw it into an existing image as grayscale values.
+/// 
+/// Tiles are laid out left to right, top to bottom across the whole width of
+/// the target image, with grayscale values mapped as by luma_from_indexes. To
+/// draw into part of a larger image, such as one sheet of a composite, pass a
+/// sub_image of it. Pixels not covered by the index data are left untouched.
+/// 
+/// Returns the number of tiles drawn, or None if the target image's size is
+/// not a multiple of the tile size, the index data is not a whole number of
+/// tiles, or the target image cannot hold every tile.
+pub fn luma_into_image<'a, S, I>(data: &[S], maxcol: u16, tsize: (u32, u32), target: &mut I) -> Option<usize>
+    where S: Primitive + 'a, I: GenericImage<Pixel=LumaA<u8>> {
+    
+    let maxcol : f32 = NumCast::from(maxcol).unwrap();
+    let colscale : f32 = 255f32 / maxcol;
+    
+    draw_indexes(data, tsize, target, |index| {
+        let tileval : f32 = NumCast::from(index).unwrap();
+        LumaA([NumCast::from(tileval * colscale).unwrap(), 255u8])
+    })
+}
+
+/// Given index data and a palette, draw it into an existing image as full
+/// color.
+/// 
+/// Layout follows the same rules as luma_into_image. Indexes beyond the end
+/// of the palette are drawn as fully transparent pixels.
+pub fn rgba_into_image<'a, S, I>(data: &[S], palette: &[Rgba<u8>], tsize: (u32, u32), target: &mut I) -> Option<usize>
+    where S: Primitive + 'a, I: GenericImage<Pixel=Rgba<u8>> {
+    
+    draw_indexes(data, tsize, target, |index| {
+        let index : usize = NumCast::from(index).unwrap();
+        palette.get(index).cloned().unwrap_or(Rgba([0u8, 0u8, 0u8, 0u8]))
+    })
+}
+
+/// Draw tiled index data into an image, converting each index to a pixel with
+/// the given function. See luma_into_image for the layout rules.
+fn draw_indexes<'a, S, Px, I, F>(data: &[S], tsize: (u32, u32), target: &mut I, convert: F) -> Option<usize>
+    where S: Primitive + 'a, I: GenericImage<Pixel=Px>, F: Fn(S) -> Px {
+    
+    let (tw, th) = tsize;
+    let (iw, ih) = target.dimensions();
+    let tstride = (tw * th) as usize;
+    
+    if (iw % tw != 0) || (ih % th != 0) || !data.len().is_multiple_of(tstride) {
+        return None;
+    }
+    
+    let columns = (iw / tw) as usize;
+    let tcount = data.len() / tstride;
+    
+    if tcount > columns * (ih / th) as usize {
+        return None;
+    }
+    
+    for (tileid, tile) in data.chunks(tstride).enumerate() {
+        let tx = (tileid % columns) as u32 * tw;
+        let ty = (tileid / columns) as u32 * th;
+        
+        for (tilepx, index) in tile.iter().enumerate() {
+            let tilepx = tilepx as u32;
+            
+            target.put_pixel(tx + tilepx % tw, ty + tilepx / tw, convert(*index));
+        }
+    }
+    
+    Some(tcount)
+}
+
+/// Lay out tiled index data as an image, converting each index to a pixel
+/// with the given function. See luma_from_indexes for the layout rules.
+fn image_from_indexes<'a, S, Px, F>(data: Vec<S>, tsize: (u32, u32), isize: Option<(u32, u32)>, blank: Px, convert: F) -> Option<Box<ImageBuffer<Px, Vec<u8>>>>
+    where S: Primitive + 'a, Px: Pixel<Subpixel=u8> + 'static, F: Fn(S) -> Px {
+    
+    let iw;
+    let ih;
     let (tw, th) = tsize;
     let tstride = tw * th;
     let tcount = data.len() as u32 / tstride;
@@ -102,10 +432,6 @@ pub fn luma_from_indexes<'a, S>(data: Vec<S>, maxcol: u16, tsize: (u32, u32), is
         return None;
     }
     
-    let maxcol : f32 = NumCast::from(maxcol).unwrap();
-    let colscale : f32 = 255f32 / maxcol;
-    
-    //TODO: What if we have a format that needs more than 8 bits of precision?
     Some(Box::new(ImageBuffer::from_fn(iw, ih, |x, y| {
         let tx = x / tw; // tile units
         let ty = y / th;
@@ -118,10 +444,9 @@ pub fn luma_from_indexes<'a, S>(data: Vec<S>, maxcol: u16, tsize: (u32, u32), is
         let tileidx : usize = NumCast::from(tileid * tstride + tilepx).unwrap();
         
         if tileidx >= data.len() {
-            LumaA([0u8, 0u8])
+            blank
         } else {
-            let tileval : f32 = NumCast::from(data[tileidx]).unwrap();
-            LumaA([NumCast::from(tileval * colscale).unwrap(), 255u8])
+            convert(data[tileidx])
         }
     })))
 }
@@ -131,8 +456,8 @@ mod test {
     extern crate image;
     extern crate num;
     
-    use awsmimg::conversion::{indexes_from_luma, luma_from_indexes};
-    use image::{GenericImage, Pixel, ImageBuffer, LumaA};
+    use awsmimg::conversion::{indexes_from_luma_with_alpha_threshold, GrayRamp, interpret_gray_ramp, indexes_from_luma_with_ramp, indexes_from_luma_with_ramp_and_alpha_threshold, luma_from_indexes_with_ramp, indexes_from_luma, indexes_from_index_image, swatches_from_palette, luma_from_indexes, rgba_from_indexes, alpha_from_image, luma_into_image, rgba_into_image};
+    use image::{GenericImage, Pixel, ImageBuffer, Luma, LumaA, Rgba};
     use num::NumCast;
     
     #[test]
@@ -162,10 +487,160 @@ mod test {
             grays0.push(NumCast::from(pixel.to_rgba()[0]).unwrap());
         }
         
-        for (_, _, pixel) in test_output.pixels() {
+        for pixel in test_output.pixels() {
             grays1.push(NumCast::from(pixel.to_rgba()[0]).unwrap());
         }
         
         assert_eq!(&grays0, &grays1);
     }
+
+    #[test]
+    fn alpha_threshold_test() {
+        let edge : ImageBuffer<LumaA<u8>, Vec<u8>> = ImageBuffer::from_fn(4, 1, |x, _| LumaA([255, [255u8, 200, 40, 0][x as usize]]));
+
+        assert_eq!(indexes_from_luma(&edge, 15, (1, 1)), vec![15, 15]);
+        assert_eq!(indexes_from_luma_with_alpha_threshold(&edge, 15, (1, 1), 1), vec![15, 15, 15]);
+        assert_eq!(indexes_from_luma_with_alpha_threshold(&edge, 15, (1, 1), 0), vec![15, 15, 15, 15]);
+    }
+
+    #[test]
+    fn gray_ramp_test() {
+        let grays : ImageBuffer<LumaA<u8>, Vec<u8>> = ImageBuffer::from_fn(4, 1, |x, _| LumaA([[0u8, 100, 170, 255][x as usize], 255]));
+        let linear = indexes_from_luma_with_ramp(&grays, &GrayRamp::Linear, 3, (1, 1));
+        let levels = GrayRamp::Levels(vec![0, 96, 160, 255]);
+        let breakpoints = GrayRamp::Breakpoints(vec![90, 150, 250]);
+
+        assert_eq!(linear, vec![0, 1, 2, 3]);
+        assert_eq!(linear, indexes_from_luma(&grays, 3, (1, 1)).iter().map(|i| *i as u16).collect::<Vec<_>>());
+        assert_eq!(indexes_from_luma_with_ramp(&grays, &levels, 3, (1, 1)), vec![0, 1, 2, 3]);
+        assert_eq!(indexes_from_luma_with_ramp(&grays, &breakpoints, 3, (1, 1)), vec![0, 1, 2, 3]);
+        assert_eq!(breakpoints.index_of(89, 3), 0);
+        assert_eq!(breakpoints.index_of(90, 3), 1);
+        assert_eq!(levels.index_of(128, 3), 1);
+        assert_eq!(levels.index_of(129, 3), 2);
+        assert_eq!(GrayRamp::Levels(vec![0, 10, 20, 30, 40]).index_of(255, 3), 3);
+
+        let drawn = luma_from_indexes_with_ramp(vec![0u8, 1, 2, 3], &levels, 3, (1, 1), Some((4, 1))).unwrap();
+
+        assert_eq!(drawn.pixels().map(|p| p[0]).collect::<Vec<_>>(), vec![0, 96, 160, 255]);
+        assert_eq!(indexes_from_luma_with_ramp(&*drawn, &levels, 3, (1, 1)), vec![0, 1, 2, 3]);
+        assert_eq!(breakpoints.gray_of(2, 3), 150);
+        assert_eq!(indexes_from_luma_with_ramp(&*luma_from_indexes_with_ramp(vec![0u8, 1, 2, 3], &breakpoints, 3, (1, 1), None).unwrap(), &breakpoints, 3, (1, 1)), vec![0, 1, 2, 3]);
+        assert_eq!(GrayRamp::Linear.gray_of(3, 3), 255);
+    }
+
+    #[test]
+    fn gray_ramp_wide_images() {
+        let grays : ImageBuffer<Rgba<u16>, Vec<u16>> = ImageBuffer::from_fn(4, 1, |x, _| {
+            let gray = [0u16, 25700, 43690, 65535][x as usize];
+            Rgba([gray, gray, gray, 65535])
+        });
+        let levels = GrayRamp::Levels(vec![0, 96, 160, 255]);
+
+        assert_eq!(indexes_from_luma_with_ramp(&grays, &levels, 3, (1, 1)), vec![0, 1, 2, 3]);
+
+        let faint : ImageBuffer<Rgba<u16>, Vec<u16>> = ImageBuffer::from_fn(2, 1, |x, _| Rgba([65535u16, 65535, 65535, [65535u16, 255][x as usize]]));
+
+        assert_eq!(indexes_from_luma_with_ramp(&faint, &levels, 3, (1, 1)), vec![3]);
+    }
+
+    #[test]
+    fn gray_ramp_alpha_threshold() {
+        let edge : ImageBuffer<LumaA<u8>, Vec<u8>> = ImageBuffer::from_fn(4, 1, |x, _| LumaA([160, [255u8, 0x7F, 0x40, 0][x as usize]]));
+        let levels = GrayRamp::Levels(vec![0, 96, 160, 255]);
+
+        assert_eq!(indexes_from_luma_with_ramp(&edge, &levels, 3, (1, 1)), vec![2]);
+        assert_eq!(indexes_from_luma_with_ramp_and_alpha_threshold(&edge, &levels, 3, (1, 1), 0x40), vec![2, 2, 2]);
+        assert_eq!(indexes_from_luma_with_ramp_and_alpha_threshold(&edge, &levels, 3, (1, 1), 0), vec![2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn gray_ramp_names() {
+        assert_eq!(interpret_gray_ramp("Linear"), Some(GrayRamp::Linear));
+        assert_eq!(interpret_gray_ramp("levels:0, 96,160,255"), Some(GrayRamp::Levels(vec![0, 96, 160, 255])));
+        assert_eq!(interpret_gray_ramp("breakpoints:90,150,250"), Some(GrayRamp::Breakpoints(vec![90, 150, 250])));
+        assert_eq!(interpret_gray_ramp("breakpoints:150,90"), None);
+        assert_eq!(interpret_gray_ramp("levels:0,256"), None);
+        assert_eq!(interpret_gray_ramp("levels"), None);
+        assert_eq!(interpret_gray_ramp("linear:0"), None);
+    }
+
+    #[test]
+    fn alpha_tile_order_test() {
+        let test_input : ImageBuffer<LumaA<u8>, Vec<u8>> = ImageBuffer::from_fn(4, 2, |x, y| {
+            LumaA([0u8, (y * 4 + x) as u8])
+        });
+
+        assert_eq!(alpha_from_image(&test_input, (1, 1)), vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(alpha_from_image(&test_input, (2, 2)), vec![0, 1, 4, 5, 2, 3, 6, 7]);
+    }
+
+    #[test]
+    fn conv_nonsquare_tile_test() {
+        let test_input : ImageBuffer<LumaA<u8>, Vec<u8>> = ImageBuffer::from_fn(4, 2, |x, y| {
+            LumaA([(y * 4 + x) as u8, 255u8])
+        });
+
+        assert_eq!(indexes_from_luma(&test_input, 255, (2, 1)), vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(indexes_from_luma(&test_input, 255, (1, 2)), vec![0, 4, 1, 5, 2, 6, 3, 7]);
+    }
+
+    #[test]
+    fn index_image_exact_test() {
+        //Indexes are taken as-is, not scaled as luminance would be.
+        let test_input : ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(4, 2, |x, y| {
+            Luma([(y * 4 + x) as u8 + 3])
+        });
+
+        assert_eq!(indexes_from_index_image(&test_input, (2, 2)), vec![3, 4, 7, 8, 5, 6, 9, 10]);
+    }
+
+    #[test]
+    fn palette_swatch_test() {
+        let palette = vec![Rgba([255u8, 0, 0, 255]), Rgba([0u8, 255, 0, 255]), Rgba([0u8, 0, 255, 255])];
+        let swatches = swatches_from_palette(&palette, 2, 3).unwrap();
+
+        assert_eq!(swatches.dimensions(), (6, 6));
+        assert_eq!(swatches.get_pixel(2, 2), &palette[0]);
+        assert_eq!(swatches.get_pixel(3, 0), &palette[1]);
+        assert_eq!(swatches.get_pixel(0, 5), &palette[2]);
+        assert_eq!(swatches.get_pixel(5, 5), &Rgba([0u8, 0, 0, 0]));
+        assert!(swatches_from_palette(&[], 2, 3).is_none());
+    }
+
+    #[test]
+    fn rgba_palette_lookup_test() {
+        let palette = vec![Rgba([0u8, 0, 0, 255]), Rgba([255u8, 0, 0, 255])];
+        let test_output = rgba_from_indexes(vec![0u8, 1, 1, 2], &palette, (2, 1), Some((2, 3))).unwrap();
+        
+        assert_eq!(test_output.get_pixel(0, 0), &Rgba([0u8, 0, 0, 255]));
+        assert_eq!(test_output.get_pixel(1, 0), &Rgba([255u8, 0, 0, 255]));
+        assert_eq!(test_output.get_pixel(0, 1), &Rgba([255u8, 0, 0, 255]));
+        assert_eq!(test_output.get_pixel(1, 1), &Rgba([0u8, 0, 0, 0]));
+        assert_eq!(test_output.get_pixel(0, 2), &Rgba([0u8, 0, 0, 0]));
+        assert!(rgba_from_indexes(vec![0u8, 1, 1], &palette, (2, 1), None).is_none());
+    }
+
+    #[test]
+    fn draw_into_subimage_test() {
+        let mut sheet : ImageBuffer<LumaA<u8>, Vec<u8>> = ImageBuffer::from_pixel(6, 4, LumaA([7u8, 7u8]));
+        
+        assert_eq!(luma_into_image(&[0u8, 1, 2, 3, 3, 2, 1, 0], 3, (2, 2), &mut sheet.sub_image(2, 0, 4, 4)), Some(2));
+        assert_eq!(sheet.get_pixel(0, 0), &LumaA([7u8, 7]));
+        assert_eq!(sheet.get_pixel(2, 0), &LumaA([0u8, 255]));
+        assert_eq!(sheet.get_pixel(3, 1), &LumaA([255u8, 255]));
+        assert_eq!(sheet.get_pixel(4, 0), &LumaA([255u8, 255]));
+        assert_eq!(sheet.get_pixel(5, 1), &LumaA([0u8, 255]));
+        assert_eq!(sheet.get_pixel(2, 2), &LumaA([7u8, 7]));
+        
+        let palette = vec![Rgba([0u8, 0, 0, 255]), Rgba([255u8, 0, 0, 255])];
+        let mut color : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(2, 2);
+        
+        assert_eq!(rgba_into_image(&[1u8, 0, 2, 1], &palette, (2, 1), &mut color), Some(2));
+        assert_eq!(color.get_pixel(0, 0), &Rgba([255u8, 0, 0, 255]));
+        assert_eq!(color.get_pixel(0, 1), &Rgba([0u8, 0, 0, 0]));
+        assert!(rgba_into_image(&[0u8; 6], &palette, (2, 1), &mut color).is_none());
+        assert!(rgba_into_image(&[0u8; 3], &palette, (2, 1), &mut color).is_none());
+        assert!(rgba_into_image(&[0u8; 3], &palette, (3, 1), &mut color).is_none());
+    }
 }
@@ -1,13 +1,20 @@
-use std::ops::Div;
-
 use std::io;
-use std::io::Read;
-use image::{GenericImage, ImageBuffer, Primitive, Pixel, LumaA};
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::ops::Range;
+use image::{DynamicImage, GenericImage, ImageBuffer, Primitive, LumaA, Rgba};
 
-use awsmimg::formats::{IndexedGraphicsProperties, IndexedFormat};
-use awsmimg::formats::agb::{AGB4Encoder, AGB8Encoder};
-use awsmimg::conversion::luma_from_indexes;
+use awsmimg::formats::{IndexedGraphicsProperties, IndexedFormat, DirectFormat, Format, interpret_format_name};
+use awsmimg::ripper::tile_bytes_for_format;
+use awsmimg::conversion::{luma_from_indexes, rgba_from_indexes, index_image_from_indexes, luma_into_image, rgba_into_image};
+use awsmimg::indexed_png::write_indexed_png;
+use awsmimg::dump::IndexDump;
 
+/// Represents a struct which can decode a particular indexed image format back
+/// into color indexes.
+/// 
+/// This is the inverse of IndexedGraphicsEncoder. Decoders read from whatever
+/// data source they were constructed with; byte slices may be decoded by way
+/// of their Read impl.
 pub trait IndexedGraphicsDecoder : IndexedGraphicsProperties {
     /// Decode previously-encoded data into a vector of index data.
     /// 
@@ -31,6 +38,110 @@ pub trait IndexedGraphicsDecoder : IndexedGraphicsProperties {
     /// where data is being misinterpreted, misdecoded, or is incomplete
     /// results in an error rather than invalid data.
     fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>>;
+    
+    /// Decode previously-encoded palette data into a vector of RGBA colors.
+    /// 
+    /// The size parameter bounds the number of bytes read, as with
+    /// decode_indexes. Every color within that bound must be decoded; palettes
+    /// must not be truncated to the number of colors the format can index.
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>>;
+
+    /// The number of bytes a single tile occupies, or None if tiles cannot be
+    /// decoded one at a time.
+    /// 
+    /// Formats whose tiles depend on data outside of them, such as headers,
+    /// control words or the width of the image, return None, and cannot be
+    /// streamed, ripped or seeked by tile. Untiled formats otherwise count
+    /// each pixel as a tile.
+    fn tile_bytes(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Read up to size bytes from a decoder's data source, which must hold a
+/// whole number of units of the given length.
+/// 
+/// If the data ends partway through a unit, an UnexpectedEof error with the
+/// given message is yielded instead.
+pub fn read_units<R: Read>(r: &mut R, size: usize, unit: usize, msg: &str) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    
+    r.take(size as u64).read_to_end(&mut buf)?;
+    
+    if !buf.len().is_multiple_of(unit) {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg.to_string()));
+    }
+    
+    Ok(buf)
+}
+
+/// Represents a struct which can decode a particular direct color image format
+/// back into color images.
+/// 
+/// This is the inverse of DirectGraphicsEncoder. Decoders read from whatever
+/// data source they were constructed with; byte slices may be decoded by way
+/// of their Read impl.
+pub trait DirectGraphicsDecoder {
+    /// Decode previously-encoded data into an RGBA image of the given width.
+    /// 
+    /// The size parameter bounds the number of bytes read from the decoder's
+    /// data source, with the same meaning as for IndexedGraphicsDecoder. The
+    /// height of the returned image is determined by how much data could be
+    /// decoded within that bound. If the decoded data does not form a whole
+    /// number of rows, or tiles for tiled formats, the decoder must yield an
+    /// error.
+    /// 
+    /// Colors of lower bit depths must be expanded to 8 bits per channel such
+    /// that re-encoding the image yields the original data.
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>>;
+}
+
+/// Iterates over the tiles of a decoder's data source one at a time.
+/// 
+/// Each tile is decoded by asking the decoder for exactly one tile's worth of
+/// bytes, so only a single tile of data is ever held in memory. This allows
+/// decoding from sources that cannot be read all at once or seeked, such as
+/// pipes or readers over compressed archives. Iteration ends cleanly at the
+/// end of the data source; if the data source ends partway through a tile,
+/// the decoder's error is yielded and iteration stops.
+pub struct TileStream<E> {
+    enc: E,
+    tile_bytes: usize,
+    done: bool
+}
+
+impl<E: IndexedGraphicsDecoder> TileStream<E> {
+    /// Stream tiles out of a decoder, given how many bytes each tile occupies
+    /// in its format.
+    pub fn new(enc: E, tile_bytes: usize) -> TileStream<E> {
+        TileStream {
+            enc,
+            tile_bytes,
+            done: tile_bytes == 0
+        }
+    }
+}
+
+impl<E: IndexedGraphicsDecoder> Iterator for TileStream<E> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.enc.decode_indexes(self.tile_bytes) {
+            Ok(ref tile) if tile.is_empty() => {
+                self.done = true;
+                None
+            },
+            Ok(tile) => Some(Ok(tile)),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
 }
 
 /// Given an image and a decoder, decode index data by interpreting the
@@ -48,6 +159,85 @@ pub fn decode_indexes_as_image<'a, E>(enc: &mut E, size: usize, isize: Option<(u
     }
 }
 
+/// Given a decoder for index data and a decoder for palette data, decode a
+/// full-color image by looking up each index in the palette.
+///
+/// Unlike decode_indexes_as_image, which produces a grayscale view of the
+/// index data, this produces the image as it would appear on the target
+/// platform. The whole of the palette decoder's data source is decoded.
+pub fn decode_indexes_as_color_image<'a, E, D>(enc: &mut E, pal: &mut D, size: usize, isize: Option<(u32, u32)>) -> io::Result<Box<ImageBuffer<Rgba<u8>, Vec<u8>>>> where E: IndexedGraphicsDecoder + 'a, D: IndexedGraphicsDecoder + 'a {
+    let palette = pal.decode_palette(usize::MAX)?;
+    decode_indexes_with_palette(enc, &palette, size, isize)
+}
+
+/// Decode index data and look each index up in an already-decoded palette.
+fn decode_indexes_with_palette<'a, E>(enc: &mut E, palette: &[Rgba<u8>], size: usize, isize: Option<(u32, u32)>) -> io::Result<Box<ImageBuffer<Rgba<u8>, Vec<u8>>>> where E: IndexedGraphicsDecoder + 'a {
+    let indexes : Vec<u8> = enc.decode_indexes(size)?;
+    let img = rgba_from_indexes(indexes, palette, enc.tile_size(), isize);
+    match img {
+        Some(i) => Ok(i),
+        None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Image size does not fit the decoded tiles."))
+    }
+}
+
+/// Given a decoder, decode index data into an index dump, keeping every index
+/// exactly as decoded.
+///
+/// Tiles are laid out as by decode_indexes_as_image; see IndexDump::from_tiles.
+pub fn decode_indexes_as_dump<'a, E>(enc: &mut E, size: usize, isize: Option<(u32, u32)>) -> io::Result<IndexDump> where E: IndexedGraphicsDecoder + 'a {
+    let indexes : Vec<u16> = enc.decode_indexes(size)?;
+    IndexDump::from_tiles(&indexes, enc.palette_maxcol(), enc.tile_size(), isize)
+}
+
+/// Given a decoder for index data and a decoder for palette data, decode an
+/// image and write it as an indexed-color PNG.
+///
+/// Unlike decode_indexes_as_image, the exact index of every pixel is kept
+/// alongside the palette; see write_indexed_png. The whole of the palette
+/// decoder's data source is decoded, and must hold at most 256 colors.
+pub fn decode_indexes_as_indexed_png<'a, E, D, W>(enc: &mut E, pal: &mut D, size: usize, isize: Option<(u32, u32)>, w: W) -> io::Result<()> where E: IndexedGraphicsDecoder + 'a, D: IndexedGraphicsDecoder + 'a, W: Write {
+    let palette = pal.decode_palette(usize::MAX)?;
+    decode_indexes_with_palette_as_indexed_png(enc, &palette, size, isize, w)
+}
+
+/// Decode index data and write it as an indexed-color PNG with an
+/// already-decoded palette.
+fn decode_indexes_with_palette_as_indexed_png<'a, E, W>(enc: &mut E, palette: &[Rgba<u8>], size: usize, isize: Option<(u32, u32)>, w: W) -> io::Result<()> where E: IndexedGraphicsDecoder + 'a, W: Write {
+    let indexes : Vec<u8> = enc.decode_indexes(size)?;
+    match index_image_from_indexes(indexes, enc.tile_size(), isize) {
+        Some(i) => write_indexed_png(w, &i, palette),
+        None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Image size does not fit the decoded tiles."))
+    }
+}
+
+/// Given a decoder, decode index data as grayscale values into an existing
+/// image, or a sub_image of one, rather than allocating a new one.
+///
+/// Tiles are laid out across the whole width of the target image and pixels
+/// not covered by the decoded tiles are left untouched; see luma_into_image.
+/// Returns the number of tiles decoded.
+pub fn decode_indexes_into_image<'a, E, I>(enc: &mut E, size: usize, target: &mut I) -> io::Result<usize> where E: IndexedGraphicsDecoder + 'a, I: GenericImage<Pixel=LumaA<u8>> {
+    let indexes : Vec<u8> = enc.decode_indexes(size)?;
+    match luma_into_image(&indexes, enc.palette_maxcol(), enc.tile_size(), target) {
+        Some(count) => Ok(count),
+        None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Target image does not fit the decoded tiles."))
+    }
+}
+
+/// Given a decoder for index data and a decoder for palette data, decode a
+/// full-color image into an existing image, or a sub_image of one.
+///
+/// This is to decode_indexes_as_color_image what decode_indexes_into_image is
+/// to decode_indexes_as_image. Returns the number of tiles decoded.
+pub fn decode_indexes_into_color_image<'a, E, D, I>(enc: &mut E, pal: &mut D, size: usize, target: &mut I) -> io::Result<usize> where E: IndexedGraphicsDecoder + 'a, D: IndexedGraphicsDecoder + 'a, I: GenericImage<Pixel=Rgba<u8>> {
+    let indexes : Vec<u8> = enc.decode_indexes(size)?;
+    let palette = pal.decode_palette(usize::MAX)?;
+    match rgba_into_image(&indexes, &palette, enc.tile_size(), target) {
+        Some(count) => Ok(count),
+        None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Target image does not fit the decoded tiles."))
+    }
+}
+
 /// Given an image, a writer, and a format description, encode index data by
 /// interpreting the grayscale values of an image as indicies.
 ///
@@ -56,9 +246,299 @@ pub fn decode_indexes_as_image<'a, E>(enc: &mut E, size: usize, isize: Option<(u
 /// other means as they are private and IndexedGraphicsEncoder cannot be
 /// dynamically dispatched.
 pub fn decode_indexes_as_image_with_format<'a, R>(format: IndexedFormat, r: &mut R, size: usize, imgsize: Option<(u32, u32)>) -> io::Result<Box<ImageBuffer<LumaA<u8>, Vec<u8>>>> where R: Read + 'a {
-    match format {
-        IndexedFormat::AGB4 => decode_indexes_as_image(&mut AGB4Encoder::new(r), size, imgsize),
-        IndexedFormat::AGB8Tiled => decode_indexes_as_image(&mut AGB8Encoder::new_tiled(r), size, imgsize),
-        IndexedFormat::AGB8Chunky => decode_indexes_as_image(&mut AGB8Encoder::new_chunky(r), size, imgsize)
+    with_indexed_codec!(format, r, |dec| decode_indexes_as_image(&mut dec, size, imgsize))
+}
+
+/// Given tile data, palette data, and a format description, decode a
+/// full-color image.
+///
+/// This function allows access to built-in, private type implementations of
+/// these traits, in the same manner as decode_indexes_as_image_with_format.
+pub fn decode_to_image<'a, R, Q>(format: IndexedFormat, tile_data: &mut R, palette_data: &mut Q, size: usize, imgsize: Option<(u32, u32)>) -> io::Result<Box<ImageBuffer<Rgba<u8>, Vec<u8>>>> where R: Read + 'a, Q: Read + 'a {
+    let palette = with_indexed_codec!(format, palette_data, |pal| pal.decode_palette(usize::MAX))?;
+
+    with_indexed_codec!(format, tile_data, |dec| decode_indexes_with_palette(&mut dec, &palette, size, imgsize))
+}
+
+/// Given a reader and a format description, decode index data into an index
+/// dump.
+///
+/// This function allows access to built-in, private type implementations of
+/// these traits, in the same manner as decode_indexes_as_image_with_format.
+pub fn decode_indexes_as_dump_with_format<'a, R>(format: IndexedFormat, r: &mut R, size: usize, imgsize: Option<(u32, u32)>) -> io::Result<IndexDump> where R: Read + 'a {
+    with_indexed_codec!(format, r, |dec| decode_indexes_as_dump(&mut dec, size, imgsize))
+}
+
+/// Given tile data, palette data, and a format description, decode an image
+/// and write it as an indexed-color PNG.
+///
+/// This function allows access to built-in, private type implementations of
+/// these traits, in the same manner as decode_indexes_as_image_with_format.
+pub fn decode_to_indexed_png<'a, R, Q, W>(format: IndexedFormat, tile_data: &mut R, palette_data: &mut Q, size: usize, imgsize: Option<(u32, u32)>, w: W) -> io::Result<()> where R: Read + 'a, Q: Read + 'a, W: Write {
+    let palette = with_indexed_codec!(format, palette_data, |pal| pal.decode_palette(usize::MAX))?;
+
+    with_indexed_codec!(format, tile_data, |dec| decode_indexes_with_palette_as_indexed_png(&mut dec, &palette, size, imgsize, w))
+}
+
+/// Given a reader, an image width, and a format description, decode direct
+/// color data into an RGBA image.
+///
+/// This function allows access to built-in, private type implementations of
+/// these traits. It is currently not possible to access these types through any
+/// other means as they are private and DirectGraphicsDecoder cannot be
+/// dynamically dispatched.
+pub fn decode_image_as_direct_color_with_format<'a, R>(format: DirectFormat, r: &mut R, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> where R: Read + 'a {
+    with_direct_decoder!(format, r, |dec| dec.decode_colors(width, size))
+}
+
+/// Given a reader and a format description, decode index data as grayscale
+/// values into an existing image, or a sub_image of one.
+///
+/// This function allows access to built-in, private type implementations of
+/// these traits, in the same manner as decode_indexes_as_image_with_format.
+pub fn decode_indexes_into_image_with_format<'a, R, I>(format: IndexedFormat, r: &mut R, size: usize, target: &mut I) -> io::Result<usize> where R: Read + 'a, I: GenericImage<Pixel=LumaA<u8>> {
+    with_indexed_codec!(format, r, |dec| decode_indexes_into_image(&mut dec, size, target))
+}
+
+/// Given a reader and the name of a format, decode up to size bytes of image
+/// data.
+/// 
+/// Indexed formats are decoded as a grayscale view of their index data, as
+/// with decode_indexes_as_image_with_format, and laid out as it does when no
+/// image size is given. Direct color formats are decoded into RGBA images of
+/// the given width, which they require. Format names are those accepted by
+/// interpret_format_name.
+pub fn decode<'a, R>(name: &str, r: &mut R, size: usize, width: Option<u32>) -> io::Result<DynamicImage> where R: Read + 'a {
+    match interpret_format_name(name) {
+        Some(Format::Indexed(format)) => Ok(DynamicImage::ImageLumaA8(*decode_indexes_as_image_with_format(format, r, size, None)?)),
+        Some(Format::Direct(format)) => match width {
+            Some(width) => Ok(DynamicImage::ImageRgba8(decode_image_as_direct_color_with_format(format, r, width, size)?)),
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Direct color formats require an image width to decode."))
+        },
+        None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown format name."))
+    }
+}
+
+/// Given a seekable reader and a format description, decode only the tiles
+/// within the given range as grayscale values.
+///
+/// Tiles are numbered from the reader's current position. The reader is
+/// seeked directly to the first tile in the range, so that the tiles before it
+/// are never read, and at most the tiles in the range are decoded. As with
+/// decode_indexes_as_image, decoding stops early at the end of the data.
+///
+/// This function allows access to built-in, private type implementations of
+/// these traits, in the same manner as decode_indexes_as_image_with_format.
+pub fn decode_tile_range_with_format<'a, R>(format: IndexedFormat, r: &mut R, tiles: Range<usize>, imgsize: Option<(u32, u32)>) -> io::Result<Box<ImageBuffer<LumaA<u8>, Vec<u8>>>> where R: Read + Seek + 'a {
+    let tbytes = match tile_bytes_for_format(format) {
+        Some(t) => t as u64,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "This format cannot be decoded one tile at a time."))
+    };
+
+    if tiles.start > tiles.end {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Tile range must not end before it starts."));
+    }
+
+    let base = r.stream_position()?;
+    let length = r.seek(SeekFrom::End(0))?;
+    let start = base + tiles.start as u64 * tbytes;
+
+    if start > length {
+        //Seeking beyond the end of a stream is implementation defined. Hence, we error out
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Tile range starts beyond the end of the data."));
+    }
+
+    r.seek(SeekFrom::Start(start))?;
+
+    let size = ((tiles.end - tiles.start) as u64 * tbytes).min(length - start) as usize;
+
+    decode_indexes_as_image_with_format(format, r, size, imgsize)
+}
+
+/// Given a reader and a format description, decode up to size bytes of index
+/// data one tile at a time, handing each tile to a callback along with its
+/// number.
+/// 
+/// Unlike the other decoding functions, the index data is never held in
+/// memory all at once; see TileStream. Returns the number of tiles decoded.
+/// 
+/// This function allows access to built-in, private type implementations of
+/// these traits, in the same manner as decode_indexes_as_image_with_format.
+pub fn for_each_tile_with_format<'a, R, F>(format: IndexedFormat, r: &mut R, size: usize, f: F) -> io::Result<usize> where R: Read + 'a, F: FnMut(usize, Vec<u8>) -> io::Result<()> {
+    let mut r = r.take(size as u64);
+
+    with_indexed_codec!(format, &mut r, |dec| match dec.tile_bytes() {
+        Some(tbytes) => for_each_tile(TileStream::new(dec, tbytes), f),
+        None => Err(io::Error::new(io::ErrorKind::InvalidInput, "This format cannot be decoded one tile at a time."))
+    })
+}
+
+fn for_each_tile<E, F>(tiles: TileStream<E>, mut f: F) -> io::Result<usize> where E: IndexedGraphicsDecoder, F: FnMut(usize, Vec<u8>) -> io::Result<()> {
+    let mut count = 0;
+
+    for tile in tiles {
+        f(count, tile?)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io;
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+    use awsmimg::formats::{IndexedFormat, INDEXED_FORMAT_NAMES, DIRECT_FORMAT_NAMES};
+    use awsmimg::formats::agb::AGB4Encoder;
+    use awsmimg::encoder::{encode, encode_dump_with_format};
+    use awsmimg::decoder::{decode, decode_indexes_as_dump_with_format, decode_tile_range_with_format, for_each_tile_with_format, TileStream};
+
+    /// A reader that yields at most three bytes per read, like a slow pipe.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(3).min(self.0.len());
+
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn tile_range_seeks() {
+        let data : Vec<u8> = (0..4u8).flat_map(|t| vec![t * 0x11; 32]).collect();
+        let mut r = Cursor::new(data);
+
+        let img = decode_tile_range_with_format(IndexedFormat::AGB4, &mut r, 1..3, Some((16, 8))).unwrap();
+
+        assert_eq!(img.get_pixel(0, 0), &image::LumaA([17u8, 255]));
+        assert_eq!(img.get_pixel(8, 0), &image::LumaA([34u8, 255]));
+        assert_eq!(r.position(), 96);
+
+        r.seek(SeekFrom::Start(32)).unwrap();
+
+        let tail = decode_tile_range_with_format(IndexedFormat::AGB4, &mut r, 2..10, Some((16, 8))).unwrap();
+
+        assert_eq!(tail.get_pixel(0, 0), &image::LumaA([51u8, 255]));
+        assert_eq!(tail.get_pixel(8, 0), &image::LumaA([0u8, 0]));
+        assert!(decode_tile_range_with_format(IndexedFormat::AGB4, &mut r, 5..6, None).is_err());
+        assert!(decode_tile_range_with_format(IndexedFormat::CGA, &mut r, 0..1, None).is_err());
+    }
+
+    #[test]
+    fn tile_stream_incremental() {
+        let data : Vec<u8> = (0..3u8).flat_map(|t| vec![t * 0x11; 32]).collect();
+        let mut r = Trickle(&data);
+        let tiles : Vec<Vec<u8>> = TileStream::new(AGB4Encoder::new(&mut r), 32).map(|t| t.unwrap()).collect();
+
+        assert_eq!(tiles.len(), 3);
+        assert_eq!(tiles[2], vec![2u8; 64]);
+
+        let mut partial = Trickle(&data[..40]);
+        let results : Vec<bool> = TileStream::new(AGB4Encoder::new(&mut partial), 32).map(|t| t.is_ok()).collect();
+
+        assert_eq!(results, vec![true, false]);
+    }
+
+    #[test]
+    fn for_each_tile_bounded() {
+        let data : Vec<u8> = (0..4u8).flat_map(|t| vec![t; 16]).collect();
+        let mut seen = Vec::new();
+
+        let count = for_each_tile_with_format(IndexedFormat::GB2, &mut Trickle(&data), 48, |n, tile| {
+            seen.push((n, tile.len()));
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(count, 3);
+        assert_eq!(seen, vec![(0, 64), (1, 64), (2, 64)]);
+        assert!(for_each_tile_with_format(IndexedFormat::GB2, &mut Trickle(&data), 40, |_, _| Ok(())).is_err());
+    }
+
+    #[test]
+    fn decode_by_name() {
+        let img = image::ImageBuffer::from_fn(2, 1, |x, _| image::Rgba([(x * 0xFF) as u8, 0u8, 0u8, 255u8]));
+        let mut agb16 = Vec::new();
+
+        encode("agb16", &mut agb16, &img).unwrap();
+
+        let direct = decode("AGB16", &mut &agb16[..], agb16.len(), Some(2)).unwrap().to_rgba();
+
+        assert_eq!(direct.into_raw(), img.into_raw());
+        assert!(decode("agb16", &mut &agb16[..], agb16.len(), None).is_err());
+
+        let indexed = decode("agb4", &mut &[0x21u8; 32][..], 32, None).unwrap().to_luma_alpha();
+
+        assert_eq!(indexed.dimensions(), (8, 8));
+        assert_eq!(indexed.get_pixel(1, 0), &image::LumaA([34u8, 255]));
+        assert!(decode("nonsense", &mut &[0u8; 32][..], 32, None).is_err());
+    }
+
+    #[test]
+    fn dump_roundtrip() {
+        let data : Vec<u8> = (0..64u8).map(|i| i.wrapping_mul(37)).collect();
+        let dump = decode_indexes_as_dump_with_format(IndexedFormat::AGB8Tiled, &mut &data[..], 64, None).unwrap();
+
+        assert_eq!(dump.maxcol, 255);
+        assert_eq!(dump.indexes[3], 111);
+
+        let mut out = Vec::new();
+
+        encode_dump_with_format(IndexedFormat::AGB8Tiled, &mut out, &dump).unwrap();
+
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn every_format_name_roundtrips() {
+        for &(name, format) in INDEXED_FORMAT_NAMES {
+            let (w, h) = match name {
+                "agbm4" => (240, 160),
+                _ => (16, 16)
+            };
+            let img = image::ImageBuffer::from_fn(w, h, |x, y| {
+                let v = ((x * 7 + y * 13) % 4 * 0x55) as u8;
+                image::Rgba([v, v, v, 255u8])
+            });
+            let mut data = Vec::new();
+
+            encode(name, &mut data, &img).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert!(decode(name, &mut &data[..], data.len(), None).is_ok(), "{}", name);
+
+            let dump = decode_indexes_as_dump_with_format(format, &mut &data[..], data.len(), Some((w, h))).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            let mut out = Vec::new();
+
+            encode_dump_with_format(format, &mut out, &dump).unwrap();
+            assert_eq!(out, data, "{}", name);
+        }
+
+        for &(name, _) in DIRECT_FORMAT_NAMES {
+            let (w, h) = match name {
+                "scr" => (256, 192),
+                "agbm3" => (240, 160),
+                "agbm5" => (160, 128),
+                "sfchdma" => (1, 16),
+                _ => (16, 16)
+            };
+            let img = image::ImageBuffer::from_fn(w, h, |x, y| match (w - x, h - y) {
+                (1, 1) => image::Rgba([255u8, 255, 255, 255]),
+                _ => image::Rgba([0u8, 0, 0, 255])
+            });
+            let mut data = Vec::new();
+
+            encode(name, &mut data, &img).unwrap_or_else(|e| panic!("{}: {}", name, e));
+
+            let decoded = decode(name, &mut &data[..], data.len(), Some(w)).unwrap_or_else(|e| panic!("{}: {}", name, e)).to_rgba();
+            let mut out = Vec::new();
+
+            assert_eq!(decoded.dimensions(), (w, h), "{}", name);
+            encode(name, &mut out, &decoded).unwrap();
+            assert_eq!(out, data, "{}", name);
+        }
     }
 }
@@ -0,0 +1,253 @@
+use std::io;
+use std::io::{Read, Write};
+use image::Primitive;
+
+/// Index data laid out as a plain image, for exchange with other tools.
+///
+/// Indexes are held row-major, one per pixel, exactly as decoded; unlike the
+/// grayscale images produced by luma_from_indexes they are not scaled, so
+/// they survive being passed between tools unchanged. maxcol records the
+/// largest index the format the data came from can represent.
+///
+/// Dumps are read and written as binary PGM files, with maxcol as the PGM
+/// maximum value, so they can be inspected and edited with ordinary image
+/// tools. Formats of up to 256 colors use one byte per index and larger ones
+/// two, big-endian, as PGM requires.
+///
+/// Dumps made from tiled data remember how many tiles they were made from,
+/// so that tiles added to pad out the image are not encoded. The count is
+/// kept in a "# tiles" comment in the PGM header.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct IndexDump {
+    pub width: u32,
+    pub height: u32,
+    pub maxcol: u16,
+    pub tile_count: Option<usize>,
+    pub indexes: Vec<u16>
+}
+
+impl IndexDump {
+    /// Lay out tiled index data, as produced by a decoder, as a dump.
+    ///
+    /// Tiles are placed left to right, top to bottom, as by luma_from_indexes,
+    /// including its choice of image size if none is given. Parts of the dump
+    /// not covered by the index data hold index zero. The index data must
+    /// hold a whole number of tiles, and the image size must be a multiple of
+    /// the tile size.
+    pub fn from_tiles<S: Primitive>(data: &[S], maxcol: u16, tsize: (u32, u32), isize: Option<(u32, u32)>) -> io::Result<IndexDump> {
+        let (tw, th) = (tsize.0.max(1), tsize.1.max(1));
+        let tlen = (tw * th) as usize;
+
+        if !data.len().is_multiple_of(tlen) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Index data does not hold a whole number of tiles."));
+        }
+
+        let tcount = (data.len() / tlen) as u32;
+        let (width, height) = match isize {
+            Some(s) => s,
+            None => {
+                let columns = (tcount as f32).sqrt().ceil() as u32;
+                (columns * tw, tcount.div_ceil(columns.max(1)) * th)
+            }
+        };
+
+        if !width.is_multiple_of(tw) || !height.is_multiple_of(th) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image size is not a multiple of the tile size."));
+        }
+
+        let mut indexes = vec![0u16; (width * height) as usize];
+        let columns = (width / tw) as usize;
+
+        for (tileid, tile) in data.chunks(tlen).enumerate().take(columns * (height / th) as usize) {
+            let tx = (tileid % columns) as u32 * tw;
+            let ty = (tileid / columns) as u32 * th;
+
+            for (tilepx, index) in tile.iter().enumerate() {
+                let x = tx + tilepx as u32 % tw;
+                let y = ty + tilepx as u32 / tw;
+
+                indexes[(y * width + x) as usize] = index.to_u16().unwrap_or(u16::MAX);
+            }
+        }
+
+        Ok(IndexDump { width, height, maxcol, tile_count: Some(tcount as usize), indexes })
+    }
+
+    /// Split the dump back into tiled index data, ready to be encoded.
+    ///
+    /// The dump's size must be a multiple of the tile size. If the dump has a
+    /// tile count, only that many tiles are returned.
+    pub fn to_tiles(&self, tsize: (u32, u32)) -> io::Result<Vec<u16>> {
+        let (tw, th) = (tsize.0.max(1), tsize.1.max(1));
+
+        if !self.width.is_multiple_of(tw) || !self.height.is_multiple_of(th) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Index dump size is not a multiple of the tile size."));
+        }
+
+        let mut out = Vec::with_capacity(self.indexes.len());
+
+        for ty in (0..self.height).step_by(th as usize) {
+            for tx in (0..self.width).step_by(tw as usize) {
+                for y in ty..ty + th {
+                    for x in tx..tx + tw {
+                        out.push(self.indexes[(y * self.width + x) as usize]);
+                    }
+                }
+            }
+        }
+
+        if let Some(count) = self.tile_count {
+            out.truncate(count * (tw * th) as usize);
+        }
+
+        Ok(out)
+    }
+}
+
+/// Write an index dump as a binary PGM file.
+pub fn write_index_dump<W: Write>(w: &mut W, dump: &IndexDump) -> io::Result<()> {
+    if dump.indexes.len() != (dump.width * dump.height) as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Index dump does not hold one index per pixel."));
+    }
+
+    let maxval = dump.maxcol.max(1);
+    let comment = match dump.tile_count {
+        Some(count) => format!("# tiles {}\n", count),
+        None => String::new()
+    };
+    let mut out = format!("P5\n{}{} {}\n{}\n", comment, dump.width, dump.height, maxval).into_bytes();
+
+    for index in &dump.indexes {
+        if maxval > 255 {
+            out.push((index >> 8) as u8);
+        }
+
+        out.push((index & 0xFF) as u8);
+    }
+
+    w.write_all(&out)
+}
+
+/// Read an index dump from a binary PGM file.
+///
+/// Any binary PGM file is accepted, with its maximum value taken as the
+/// dump's maxcol. Samples greater than the maximum value are rejected.
+pub fn read_index_dump<R: Read>(r: &mut R) -> io::Result<IndexDump> {
+    let mut data = Vec::new();
+
+    r.read_to_end(&mut data)?;
+
+    if !data.starts_with(b"P5") {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Index dumps must be binary PGM files."));
+    }
+
+    //Header fields are separated by whitespace, and may be interspersed with
+    //comments running from # to the end of the line.
+    let mut pos = 2;
+    let mut fields = [0u32; 3];
+    let mut tile_count = None;
+
+    for field in fields.iter_mut() {
+        loop {
+            match data.get(pos) {
+                Some(b'#') => {
+                    let start = pos;
+
+                    while data.get(pos).is_some_and(|c| *c != b'\n') {
+                        pos += 1;
+                    }
+
+                    let comment = String::from_utf8_lossy(&data[start..pos]);
+
+                    if let Some(count) = comment.strip_prefix("# tiles ") {
+                        tile_count = count.trim().parse().ok();
+                    }
+                },
+                Some(c) if c.is_ascii_whitespace() => pos += 1,
+                _ => break
+            }
+        }
+
+        let start = pos;
+
+        while data.get(pos).is_some_and(|c| c.is_ascii_digit()) {
+            pos += 1;
+        }
+
+        *field = match String::from_utf8_lossy(&data[start..pos]).parse() {
+            Ok(v) => v,
+            Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Index dump header is malformed."))
+        };
+    }
+
+    let [width, height, maxval] = fields;
+
+    if maxval == 0 || maxval > 65535 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Index dump maximum value is out of range."));
+    }
+
+    //Exactly one whitespace character separates the header from the samples.
+    pos += 1;
+
+    let sample_bytes = if maxval > 255 { 2 } else { 1 };
+    let count = width as usize * height as usize;
+
+    if data.len() < pos + count * sample_bytes {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Index dump ends before its last index."));
+    }
+
+    let indexes : Vec<u16> = data[pos..pos + count * sample_bytes].chunks(sample_bytes).map(|s| {
+        s.iter().fold(0u16, |acc, b| acc << 8 | *b as u16)
+    }).collect();
+
+    if indexes.iter().any(|i| *i as u32 > maxval) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Index dump holds an index beyond its maximum value."));
+    }
+
+    Ok(IndexDump { width, height, maxcol: maxval as u16, tile_count, indexes })
+}
+
+#[cfg(test)]
+mod tests {
+    use awsmimg::dump::{IndexDump, write_index_dump, read_index_dump};
+
+    #[test]
+    fn dump_tile_layout() {
+        let data : Vec<u8> = vec![0, 1, 2, 3, 4, 5, 6, 7];
+        let dump = IndexDump::from_tiles(&data, 7, (2, 2), Some((4, 2))).unwrap();
+
+        assert_eq!(dump.indexes, vec![0, 1, 4, 5, 2, 3, 6, 7]);
+        assert_eq!(dump.to_tiles((2, 2)).unwrap(), vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        assert!(dump.to_tiles((3, 2)).is_err());
+        assert!(IndexDump::from_tiles(&data[..7], 7, (2, 2), None).is_err());
+        let padded = IndexDump::from_tiles(&data[..4], 7, (2, 2), Some((4, 2))).unwrap();
+
+        assert_eq!(padded.indexes, vec![0, 1, 0, 0, 2, 3, 0, 0]);
+        assert_eq!(padded.to_tiles((2, 2)).unwrap(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn dump_pgm_roundtrip() {
+        let small = IndexDump { width: 3, height: 1, maxcol: 15, tile_count: None, indexes: vec![0, 15, 7] };
+        let large = IndexDump { width: 2, height: 1, maxcol: 511, tile_count: Some(1), indexes: vec![300, 2] };
+        let mut small_out = Vec::new();
+        let mut large_out = Vec::new();
+
+        write_index_dump(&mut small_out, &small).unwrap();
+        write_index_dump(&mut large_out, &large).unwrap();
+
+        assert_eq!(small_out, b"P5\n3 1\n15\n\x00\x0F\x07".to_vec());
+        assert_eq!(read_index_dump(&mut &small_out[..]).unwrap(), small);
+        assert_eq!(read_index_dump(&mut &large_out[..]).unwrap(), large);
+    }
+
+    #[test]
+    fn dump_pgm_parsing() {
+        let commented = b"P5 # made by hand\n2 # wide\n1\n3\n\x01\x02".to_vec();
+
+        assert_eq!(read_index_dump(&mut &commented[..]).unwrap().indexes, vec![1, 2]);
+        assert!(read_index_dump(&mut &b"P5\n2 1\n3\n\x01\x04"[..]).is_err());
+        assert!(read_index_dump(&mut &b"P5\n2 1\n3\n\x01"[..]).is_err());
+        assert!(read_index_dump(&mut &b"P2\n1 1\n3\n1"[..]).is_err());
+    }
+}
@@ -1,10 +1,12 @@
 use std::io;
 use std::io::Write;
-use image::{GenericImage, Primitive, Rgba, Pixel};
+use image::{GenericImage, Primitive, Rgba, Pixel, Luma};
 
-use awsmimg::formats::{IndexedGraphicsProperties, IndexedFormat, DirectFormat};
-use awsmimg::formats::agb::{AGB4Encoder, AGB8Encoder, AGB16Encoder};
-use awsmimg::conversion::indexes_from_luma;
+use awsmimg::formats::{IndexedGraphicsProperties, IndexedFormat, DirectFormat, Format, interpret_format_name, DEFAULT_ALPHA_THRESHOLD};
+use awsmimg::formats::agb::AGB16Encoder;
+use awsmimg::formats::ntr::{NTRTranslucentEncoder, NTRTex4x4Encoder};
+use awsmimg::conversion::{indexes_from_luma_with_alpha_threshold, indexes_from_luma_with_ramp_and_alpha_threshold, indexes_from_index_image, alpha_from_image, GrayRamp};
+use awsmimg::dump::IndexDump;
 
 /// Represents a struct which can encode color indexes and their palettes into
 /// a particular indexed image format.
@@ -45,9 +47,17 @@ pub trait IndexedGraphicsEncoder : IndexedGraphicsProperties {
 /// unambiguous color to every index, allowing editing of the graphical data
 /// using image manipulation tools that don't provide palette editing.
 pub fn encode_image_as_indexes<'a, E, I, P, S>(enc: &mut E, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static, E: IndexedGraphicsEncoder + 'a {
+    encode_image_as_indexes_with_alpha_threshold(enc, image, DEFAULT_ALPHA_THRESHOLD)
+}
+
+/// Given an image and an encoder, encode index data as encode_image_as_indexes
+/// does, ignoring trailing pixels whose 8-bit alpha is below the given
+/// threshold rather than formats::DEFAULT_ALPHA_THRESHOLD (see
+/// conversion::indexes_from_luma_with_alpha_threshold).
+pub fn encode_image_as_indexes_with_alpha_threshold<'a, E, I, P, S>(enc: &mut E, image: &I, alpha_threshold: u8) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static, E: IndexedGraphicsEncoder + 'a {
     let (width, height) = image.dimensions();
     
-    let gdata = indexes_from_luma(image, S::from(enc.palette_maxcol()).unwrap(), enc.tile_size());
+    let gdata = indexes_from_luma_with_alpha_threshold(image, S::from(enc.palette_maxcol()).unwrap(), enc.tile_size(), alpha_threshold);
     enc.encode_indexes(gdata, width, height)
 }
 
@@ -59,11 +69,131 @@ pub fn encode_image_as_indexes<'a, E, I, P, S>(enc: &mut E, image: &I) -> io::Re
 /// other means as they are private and IndexedGraphicsEncoder cannot be
 /// dynamically dispatched.
 pub fn encode_image_as_indexes_with_format<'a, W, I, P, S>(format: IndexedFormat, w: &mut W, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static, W: Write + 'a {
+    encode_image_as_indexes_with_format_and_alpha_threshold(format, w, image, DEFAULT_ALPHA_THRESHOLD)
+}
+
+/// Given an image, a writer, and a format description, encode index data as
+/// encode_image_as_indexes_with_format does, with the given alpha threshold
+/// as in encode_image_as_indexes_with_alpha_threshold.
+pub fn encode_image_as_indexes_with_format_and_alpha_threshold<'a, W, I, P, S>(format: IndexedFormat, w: &mut W, image: &I, alpha_threshold: u8) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static, W: Write + 'a {
     match format {
-        IndexedFormat::AGB4 => encode_image_as_indexes(&mut AGB4Encoder::new(w), image),
-        IndexedFormat::AGB8Tiled => encode_image_as_indexes(&mut AGB8Encoder::new_tiled(w), image),
-        IndexedFormat::AGB8Chunky => encode_image_as_indexes(&mut AGB8Encoder::new_chunky(w), image)
+        IndexedFormat::NTRA3I5 => encode_image_as_indexes_with_alpha_threshold(&mut NTRTranslucentEncoder::new_a3i5(w).with_alpha(alpha_from_image(image, (1, 1))), image, alpha_threshold),
+        IndexedFormat::NTRA5I3 => encode_image_as_indexes_with_alpha_threshold(&mut NTRTranslucentEncoder::new_a5i3(w).with_alpha(alpha_from_image(image, (1, 1))), image, alpha_threshold),
+        _ => with_indexed_codec!(format, w, |enc| encode_image_as_indexes_with_alpha_threshold(&mut enc, image, alpha_threshold))
+    }
+}
+
+/// Given an image and an encoder, encode index data by reading the grayscale
+/// values of an image as indexes through the given ramp.
+/// 
+/// This is encode_image_as_indexes for images drawn with particular grays
+/// standing for each index, rather than with grays spread evenly over the
+/// whole range; see conversion::indexes_from_luma_with_ramp. Pixels whose
+/// 8-bit alpha is below alpha_threshold are treated as transparent, as in
+/// encode_image_as_indexes_with_alpha_threshold.
+pub fn encode_image_as_indexes_with_ramp<'a, E, I, P, S>(enc: &mut E, image: &I, ramp: &GrayRamp, alpha_threshold: u8) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static, E: IndexedGraphicsEncoder + 'a {
+    let (width, height) = image.dimensions();
+
+    let gdata = indexes_from_luma_with_ramp_and_alpha_threshold(image, ramp, enc.palette_maxcol(), enc.tile_size(), alpha_threshold);
+    enc.encode_indexes(gdata, width, height)
+}
+
+/// Given an image, a writer, a format description and a grayscale ramp,
+/// encode index data as encode_image_as_indexes_with_ramp does.
+/// 
+/// This function allows access to built-in, private type implementations of
+/// these traits, in the same manner as encode_image_as_indexes_with_format.
+pub fn encode_image_as_indexes_with_format_and_ramp<'a, W, I, P, S>(format: IndexedFormat, w: &mut W, image: &I, ramp: &GrayRamp, alpha_threshold: u8) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static, W: Write + 'a {
+    match format {
+        IndexedFormat::NTRA3I5 => encode_image_as_indexes_with_ramp(&mut NTRTranslucentEncoder::new_a3i5(w).with_alpha(alpha_from_image(image, (1, 1))), image, ramp, alpha_threshold),
+        IndexedFormat::NTRA5I3 => encode_image_as_indexes_with_ramp(&mut NTRTranslucentEncoder::new_a5i3(w).with_alpha(alpha_from_image(image, (1, 1))), image, ramp, alpha_threshold),
+        _ => with_indexed_codec!(format, w, |enc| encode_image_as_indexes_with_ramp(&mut enc, image, ramp, alpha_threshold))
+    }
+}
+
+/// Given an index dump and an encoder, encode the dump's index data as-is.
+/// 
+/// Unlike encode_image_as_indexes, no grayscale conversion takes place, so
+/// every index is encoded exactly as it appears in the dump.
+pub fn encode_dump<'a, E>(enc: &mut E, dump: &IndexDump) -> io::Result<()> where E: IndexedGraphicsEncoder + 'a {
+    let data = dump.to_tiles(enc.tile_size())?;
+    enc.encode_indexes(data, dump.width, dump.height)
+}
+
+/// Given an index dump, a writer, and a format description, encode the dump's
+/// index data as-is.
+/// 
+/// This function allows access to built-in, private type implementations of
+/// these traits, in the same manner as encode_image_as_indexes_with_format.
+/// Formats which take per-pixel alpha are encoded fully opaque, as dumps do
+/// not hold alpha.
+pub fn encode_dump_with_format<'a, W>(format: IndexedFormat, w: &mut W, dump: &IndexDump) -> io::Result<()> where W: Write + 'a {
+    with_indexed_codec!(format, w, |enc| encode_dump(&mut enc, dump))
+}
+
+/// Given an image of color indexes and an encoder, encode the image's pixels
+/// as indexes as-is.
+/// 
+/// This is the input path for indexed-color images, such as those read with
+/// read_indexed_png: unlike encode_image_as_indexes, no grayscale conversion
+/// takes place, so every index is encoded exactly as the image holds it.
+/// Images holding indexes the format cannot represent are rejected.
+pub fn encode_index_image<'a, E, I>(enc: &mut E, image: &I) -> io::Result<()> where I: GenericImage<Pixel=Luma<u8>>, E: IndexedGraphicsEncoder + 'a {
+    let (width, height) = image.dimensions();
+    let gdata = indexes_from_index_image(image, enc.tile_size());
+
+    if gdata.iter().any(|i| *i as u16 > enc.palette_maxcol()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Image holds an index beyond the format's palette."));
+    }
+
+    enc.encode_indexes(gdata, width, height)
+}
+
+/// Given an image of color indexes, a writer, and a format description,
+/// encode the image's pixels as indexes as-is.
+/// 
+/// This function allows access to built-in, private type implementations of
+/// these traits, in the same manner as encode_image_as_indexes_with_format.
+/// Formats which take per-pixel alpha are encoded fully opaque.
+pub fn encode_index_image_with_format<'a, W, I>(format: IndexedFormat, w: &mut W, image: &I) -> io::Result<()> where I: GenericImage<Pixel=Luma<u8>>, W: Write + 'a {
+    with_indexed_codec!(format, w, |enc| encode_index_image(&mut enc, image))
+}
+
+/// Given a palette, a writer, and a format description, encode the palette.
+/// 
+/// Colors are encoded in the order given, so a palette read alongside index
+/// data, such as the PLTE chunk of an indexed-color PNG, keeps every index
+/// pointing at the same color.
+pub fn encode_palette_with_format<'a, W, T>(format: IndexedFormat, w: &mut W, palette: Vec<Rgba<T>>) -> io::Result<()> where T: Primitive, W: Write + 'a {
+    with_indexed_codec!(format, w, |enc| enc.encode_palette(palette))
+}
+
+/// How a palette is fitted to the hardware palette slot it will be loaded
+/// into, before it is encoded.
+/// 
+/// Encoders never pad or truncate palettes themselves, so palettes shorter
+/// than their slot produce palette data that leaves the next slot misaligned.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PaletteFit {
+    AsIs,                 //Encode the palette at whatever length it is
+    Limit(usize),         //Reject palettes longer than the slot
+    Pad(usize, Rgba<u8>)  //Reject palettes longer than the slot, and pad shorter ones with the given color
+}
+
+/// Fit a palette to its hardware slot, as described by fit.
+pub fn fit_palette(palette: Vec<Rgba<u8>>, fit: PaletteFit) -> io::Result<Vec<Rgba<u8>>> {
+    let mut palette = palette;
+
+    match fit {
+        PaletteFit::AsIs => {},
+        PaletteFit::Limit(size) | PaletteFit::Pad(size, _) if palette.len() > size => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Palette of {} colors does not fit a slot of {} colors.", palette.len(), size)));
+        },
+        PaletteFit::Limit(_) => {},
+        PaletteFit::Pad(size, fill) => palette.resize(size, fill)
     }
+
+    Ok(palette)
 }
 
 /// Represents a struct which can encode color images into a particular direct
@@ -98,8 +228,83 @@ pub trait DirectGraphicsEncoder {
 /// other means as they are private and DirectGraphicsEncoder cannot be
 /// dynamically dispatched.
 pub fn encode_image_as_direct_color_with_format<'a, W, I, P, S>(format: DirectFormat, w: &mut W, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static, W: Write + 'a {
-    match format {
-        DirectFormat::AGB16 => AGB16Encoder::new_agb(w).encode_colors(image),
-        DirectFormat::NTR16 => AGB16Encoder::new_ntr(w).encode_colors(image)
+    with_direct_encoder!(format, w, |enc| enc.encode_colors(image))
+}
+
+/// Given an image, a writer, and the name of a format, encode the image.
+/// 
+/// Indexed formats are encoded from the image's grayscale values, as with
+/// encode_image_as_indexes_with_format. Format names are those accepted by
+/// interpret_format_name.
+pub fn encode<'a, W, I, P, S>(name: &str, w: &mut W, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static, W: Write + 'a {
+    encode_with_alpha_threshold(name, w, image, DEFAULT_ALPHA_THRESHOLD)
+}
+
+/// Given an image, a writer, and the name of a format, encode the image as
+/// encode does, treating pixels whose 8-bit alpha is below the given threshold
+/// as transparent.
+/// 
+/// The threshold decides which trailing pixels of indexed formats are encoded,
+/// the alpha bit of NTR 16-bit direct color, and which texels of NTR 4x4
+/// compressed textures are transparent. Other direct color formats keep
+/// their own fixed alpha handling and ignore it.
+pub fn encode_with_alpha_threshold<'a, W, I, P, S>(name: &str, w: &mut W, image: &I, alpha_threshold: u8) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static, W: Write + 'a {
+    match interpret_format_name(name) {
+        Some(Format::Indexed(format)) => encode_image_as_indexes_with_format_and_alpha_threshold(format, w, image, alpha_threshold),
+        Some(Format::Direct(DirectFormat::NTR16)) => AGB16Encoder::new_ntr(w).with_alpha_threshold(alpha_threshold).encode_colors(image),
+        Some(Format::Direct(DirectFormat::NTRTex4x4)) => NTRTex4x4Encoder::new(w).with_alpha_threshold(alpha_threshold).encode_colors(image),
+        Some(Format::Direct(format)) => encode_image_as_direct_color_with_format(format, w, image),
+        None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown format name."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use image::{ImageBuffer, Rgba};
+    use awsmimg::formats::{IndexedFormat, DEFAULT_ALPHA_THRESHOLD};
+    use awsmimg::conversion::GrayRamp;
+    use awsmimg::encoder::{PaletteFit, fit_palette, encode, encode_with_alpha_threshold, encode_image_as_indexes_with_format_and_ramp};
+
+    #[test]
+    fn palette_fit() {
+        let red = Rgba([255u8, 0, 0, 255]);
+        let fill = Rgba([1u8, 2, 3, 255]);
+        let palette = vec![red; 3];
+
+        assert_eq!(fit_palette(palette.clone(), PaletteFit::AsIs).unwrap(), palette);
+        assert_eq!(fit_palette(palette.clone(), PaletteFit::Limit(3)).unwrap(), palette);
+        assert!(fit_palette(palette.clone(), PaletteFit::Limit(2)).is_err());
+        assert_eq!(fit_palette(palette.clone(), PaletteFit::Pad(5, fill)).unwrap(), vec![red, red, red, fill, fill]);
+        assert!(fit_palette(vec![red; 17], PaletteFit::Pad(16, fill)).is_err());
+    }
+
+    #[test]
+    fn ramp_by_format() {
+        let img : ImageBuffer<Rgba<u16>, Vec<u16>> = ImageBuffer::from_fn(8, 8, |x, _| {
+            let gray = [0u16, 96 * 257, 160 * 257, 65535][x as usize % 4];
+            Rgba([gray, gray, gray, 65535])
+        });
+        let mut out = Vec::new();
+
+        encode_image_as_indexes_with_format_and_ramp(IndexedFormat::AGB4, &mut out, &img, &GrayRamp::Levels(vec![0, 96, 160, 255]), DEFAULT_ALPHA_THRESHOLD).unwrap();
+
+        assert_eq!(out, [0x10u8, 0x32].iter().cycle().take(32).cloned().collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn alpha_threshold_by_name() {
+        let img : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(4, 4, |x, _| Rgba([255, 0, 0, if x == 0 { 0x40 } else { 255 }]));
+
+        for name in ["ntr16", "tex4x4"].iter() {
+            let mut default_out = Vec::new();
+            let mut custom_out = Vec::new();
+
+            encode(name, &mut default_out, &img).unwrap();
+            encode_with_alpha_threshold(name, &mut custom_out, &img, 0x01).unwrap();
+
+            assert_ne!(default_out, custom_out);
+        }
     }
 }
@@ -1,67 +1,213 @@
-use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::{IndexedGraphicsProperties, ChannelRounding};
+use awsmimg::formats::generic::{DirectColor16Encoder, DirectColor16Decoder};
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, BGR555};
 use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
-use awsmimg::decoder::IndexedGraphicsDecoder;
+use awsmimg::decoder::{IndexedGraphicsDecoder, DirectGraphicsDecoder};
 
 use std::io;
 use std::io::{Write, Read, ErrorKind};
-use image::{GenericImage, Primitive, Rgba, Pixel};
+use image::{GenericImage, ImageBuffer, Primitive, Rgba, Pixel};
 
 /// Encode a series of RGBA colors as palette data.
-fn encode_palette<'a, I: Iterator, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I, use_alpha: bool) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
-    let imgmax = T::max_value();
-    let mut out: [u8; 2] = [0, 0];
-
-    for rgba in palette {
-        let r : u16 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
-        let g : u16 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
-        let b : u16 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
-        let a : u16 = match use_alpha {
-            true => (rgba[3].to_f32().unwrap() / imgmax.to_f32().unwrap()) as u16,
-            false => 0
+///
+/// Colors are written as little-endian 15-bit BGR words. This is also the
+/// palette format of the CGB and SFC, which encoders for those platforms write
+/// through palette::BGR555 unless given another format with
+/// with_palette_format.
+///
+/// If use_alpha is true, the top bit of each word is set for colors that are
+/// at least half opaque, as in the NTR direct color format.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I, use_alpha: bool) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    encode_palette_rounded(w, palette, use_alpha, ChannelRounding::Truncate)
+}
+
+/// Encode a series of RGBA colors as palette data, as encode_palette does,
+/// reducing each channel to 5 bits with the given rounding.
+pub fn encode_palette_rounded<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I, use_alpha: bool, rounding: ChannelRounding) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    BGR555 { use_alpha, rounding, ..BGR555::default() }.encode_colors(w, palette)
+}
+
+/// Decode palette data written by encode_palette back into RGBA colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. Each
+/// 5-bit channel is expanded to 8 bits by repeating its top bits, so that
+/// re-encoding the palette yields the original data.
+///
+/// If use_alpha is true, colors without the top bit set are decoded as fully
+/// transparent. Otherwise, every color is opaque.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize, use_alpha: bool) -> io::Result<Vec<Rgba<u8>>> {
+    let mut buf = Vec::new();
+    
+    r.take(size as u64).read_to_end(&mut buf)?;
+    
+    if !buf.len().is_multiple_of(2) {
+        return Err(io::Error::new(ErrorKind::UnexpectedEof, "Palette data ends partway through a color"));
+    }
+    
+    let expand = |v: u16| ((v & 0x1F) << 3 | (v & 0x1F) >> 2) as u8;
+    
+    Ok(buf.chunks(2).map(|word| {
+        let value = word[0] as u16 | (word[1] as u16) << 8;
+        let alpha = match use_alpha {
+            true if value & 0x8000 == 0 => 0,
+            _ => 255
         };
         
-        let enc_color: u16 = (a & 0x80) << 8 | (b & 0xF8) << 7 | (g & 0xF8) << 2 | r >> 3;
-        
-        out[0] = ((enc_color >> 0) & 0xFF) as u8;
-        out[1] = ((enc_color >> 8) & 0xFF) as u8;
-        w.write(&out)?;
-    }
-
-    Ok(())
+        Rgba([expand(value), expand(value >> 5), expand(value >> 10), alpha])
+    }).collect())
 }
 
-struct ImageRgbaIterator<'a, I, P, S> where I: Iterator<Item=(u32, u32, P)> + 'a, P: Pixel<Subpixel=S> + 'a, S: Primitive + 'a {
-    i: &'a mut I
+/// A model of how an AGB-family screen displays colors, used to pre-correct
+/// colors so that they look on hardware as they do on a PC monitor.
+///
+/// Screens are modelled as raising each 0.0-1.0 channel to the screen's
+/// gamma, mixing channels through a matrix, then converting to an sRGB-like
+/// 2.2 gamma and scaling by brightness. Both profiles are approximations.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LCDProfile {
+    pub gamma: f32,
+    pub matrix: [[f32; 3]; 3],
+    pub brightness: f32
 }
 
-impl<'a, I, P, S> ImageRgbaIterator<'a, I, P, S> where I: Iterator<Item=(u32, u32, P)> + 'a, P: Pixel<Subpixel=S> + 'a, S: Primitive + 'a {
-    pub fn new(i: &'a mut I) -> ImageRgbaIterator<'a, I, P, S> {
-        ImageRgbaIterator {
-            i: i
+impl LCDProfile {
+    /// The original AGB's unlit screen, which is dark and washed out, after
+    /// the widely used model from byuu's emulators.
+    pub const AGB: LCDProfile = LCDProfile {
+        gamma: 4.0,
+        matrix: [[1.0, 50.0 / 255.0, 0.0],
+                 [10.0 / 255.0, 230.0 / 255.0, 30.0 / 255.0],
+                 [50.0 / 255.0, 10.0 / 255.0, 220.0 / 255.0]],
+        brightness: 255.0 / 280.0
+    };
+
+    /// The backlit screens of the AGS-101 and NTR, which are much closer to a
+    /// PC monitor, with only slight desaturation.
+    pub const AGS101: LCDProfile = LCDProfile {
+        gamma: 2.2,
+        matrix: [[0.90, 0.10, 0.00],
+                 [0.05, 0.90, 0.05],
+                 [0.00, 0.10, 0.90]],
+        brightness: 1.0
+    };
+
+    /// Simulate how a color will appear on this screen.
+    pub fn simulate(&self, color: Rgba<u8>) -> Rgba<u8> {
+        let linear : Vec<f32> = (0..3).map(|c| (color[c] as f32 / 255.0).powf(self.gamma)).collect();
+        let mut out = color;
+
+        for (c, row) in self.matrix.iter().enumerate() {
+            let mixed : f32 = row.iter().zip(linear.iter()).map(|(m, l)| m * l).sum();
+
+            out[c] = (mixed.max(0.0).powf(1.0 / 2.2) * self.brightness * 255.0).round().clamp(0.0, 255.0) as u8;
         }
+
+        out
     }
-}
 
-impl<'a, I, P, S> Iterator for ImageRgbaIterator<'a, I, P, S> where I: Iterator<Item=(u32, u32, P)> + 'a, P: Pixel<Subpixel=S> + 'a, S: Primitive + 'a {
-    type Item = Rgba<S>;
-    
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.i.next() {
-            None => None,
-            Some((_, _, p)) => Some(p.to_rgba())
+    /// Find the color which, shown on this screen, looks closest to the given
+    /// color on a PC monitor.
+    ///
+    /// Colors beyond what the screen can show are clamped to its gamut.
+    pub fn precorrect(&self, color: Rgba<u8>) -> Rgba<u8> {
+        let m = &self.matrix;
+        let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+                - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+                + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+        let inverse = [
+            [(m[1][1] * m[2][2] - m[1][2] * m[2][1]) / det, (m[0][2] * m[2][1] - m[0][1] * m[2][2]) / det, (m[0][1] * m[1][2] - m[0][2] * m[1][1]) / det],
+            [(m[1][2] * m[2][0] - m[1][0] * m[2][2]) / det, (m[0][0] * m[2][2] - m[0][2] * m[2][0]) / det, (m[0][2] * m[1][0] - m[0][0] * m[1][2]) / det],
+            [(m[1][0] * m[2][1] - m[1][1] * m[2][0]) / det, (m[0][1] * m[2][0] - m[0][0] * m[2][1]) / det, (m[0][0] * m[1][1] - m[0][1] * m[1][0]) / det]
+        ];
+        let mixed : Vec<f32> = (0..3).map(|c| (color[c] as f32 / 255.0 / self.brightness).powf(2.2)).collect();
+        let mut out = color;
+
+        for (c, row) in inverse.iter().enumerate() {
+            let linear : f32 = row.iter().zip(mixed.iter()).map(|(m, l)| m * l).sum();
+
+            out[c] = (linear.clamp(0.0, 1.0).powf(1.0 / self.gamma) * 255.0).round() as u8;
         }
+
+        out
+    }
+}
+
+/// Encode palette data for an AGB encoder in the given color format,
+/// pre-correcting colors for a screen if one is given.
+///
+/// Rounding only applies to BGR555 palettes; other formats reduce colors in
+/// their own way.
+fn encode_palette_for_screen<'a, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: Vec<Rgba<T>>, format: PaletteColorFormat, rounding: ChannelRounding, lcd: Option<LCDProfile>) -> io::Result<()> {
+    match lcd {
+        Some(profile) => {
+            let imgmax = T::max_value().to_f32().unwrap();
+            let colors = palette.into_iter().map(|c| {
+                let mut color = Rgba([0u8; 4]);
+
+                for i in 0..4 {
+                    color[i] = (c[i].to_f32().unwrap() / imgmax * 255f32) as u8;
+                }
+
+                profile.precorrect(color)
+            });
+
+            encode_palette_as(w, colors, format, rounding)
+        },
+        None => encode_palette_as(w, palette.into_iter(), format, rounding)
+    }
+}
+
+fn encode_palette_as<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I, format: PaletteColorFormat, rounding: ChannelRounding) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    match format {
+        PaletteColorFormat::BGR555 => encode_palette_rounded(w, palette, false, rounding),
+        format => format.encode_colors(w, palette)
     }
 }
 
 /// Encoder/decoder for 4bpp tile patterns for the AGB platform.
+///
+/// Each 8x8 tile is 32 bytes, two pixels per byte with the leftmost pixel in
+/// the low nibble.
 pub struct AGB4Encoder<'a, F: 'a> {
     f: &'a mut F,
+    palette_format: PaletteColorFormat,
+    rounding: ChannelRounding,
+    lcd: Option<LCDProfile>
 }
 
 impl<'a, F: 'a> AGB4Encoder<'a, F> {
     pub fn new(file: &'a mut F) -> AGB4Encoder<'a, F> {
         AGB4Encoder {
-            f: file
+            f: file,
+            palette_format: PaletteColorFormat::BGR555,
+            rounding: ChannelRounding::default(),
+            lcd: None
+        }
+    }
+
+    /// Reduce palette colors to 5 bits per channel with the given rounding,
+    /// rather than by truncation.
+    pub fn with_rounding(self, rounding: ChannelRounding) -> AGB4Encoder<'a, F> {
+        AGB4Encoder {
+            rounding,
+            ..self
+        }
+    }
+
+    /// Pre-correct palette colors for the given screen, so that they look on
+    /// hardware as they do on a PC.
+    pub fn with_lcd_profile(self, profile: LCDProfile) -> AGB4Encoder<'a, F> {
+        AGB4Encoder {
+            lcd: Some(profile),
+            ..self
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as BGR555.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> AGB4Encoder<'a, F> {
+        AGB4Encoder {
+            palette_format,
+            ..self
         }
     }
 }
@@ -81,60 +227,123 @@ impl<'a, F: 'a> IndexedGraphicsProperties for AGB4Encoder<'a, F> {
 }
 
 impl<'a, F: 'a> IndexedGraphicsEncoder for AGB4Encoder<'a, F> where F: Write {
-    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, width: u32, _height: u32) -> io::Result<()> {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
         let mut out: [u8; 1] = [0];
         
         for byte in data.chunks(2) {
             out[0] = byte[0].to_u8().unwrap() & 0x0F | (byte[1].to_u8().unwrap() & 0x0F) << 4;
-            self.f.write(&out)?;
+            self.f.write_all(&out)?;
         }
         
         Ok(())
     }
     
     fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
-        encode_palette(self.f, palette.into_iter(), false)
+        encode_palette_for_screen(self.f, palette, self.palette_format, self.rounding, self.lcd)
+    }
+}
+
+impl<'a, F: 'a> AGB4Encoder<'a, F> where F: Read {
+    /// Decode exactly count tiles of index data.
+    /// 
+    /// Unlike decode_indexes, which stops early at the end of the data source,
+    /// this yields an error if fewer than count tiles are available.
+    pub fn decode_tiles<P: Primitive>(&mut self, count: usize) -> io::Result<Vec<P>> {
+        let out : Vec<P> = self.decode_indexes(count * 32)?;
+        
+        if out.len() < count * 64 {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "File is shorter than image being decoded"));
+        }
+        
+        Ok(out)
     }
 }
 
 impl<'a, F: 'a> IndexedGraphicsDecoder for AGB4Encoder<'a, F> where F: Read {
     fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
-        let mut out = Vec::new();
-        let mut buf: [u8; 1] = [0];
+        let mut buf = Vec::new();
         
-        for i in 0..size {
-            let readcnt = self.f.read(&mut buf)?;
-            
-            if readcnt < 1 {
-                return Err(io::Error::new(ErrorKind::UnexpectedEof, "File is shorter than image being decoded"));
-            }
-            
-            out.push(P::from(buf[0] & 0x0F).unwrap());
-            out.push(P::from(buf[0] >> 4).unwrap());
+        self.f.by_ref().take(size as u64).read_to_end(&mut buf)?;
+        
+        if !buf.len().is_multiple_of(32) {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "AGB 4bpp data ends partway through a tile"));
+        }
+        
+        let mut out = Vec::with_capacity(buf.len() * 2);
+        
+        for byte in buf {
+            out.push(P::from(byte & 0x0F).unwrap());
+            out.push(P::from(byte >> 4).unwrap());
         }
         
         Ok(out)
     }
+    
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(32)
+    }
 }
 
-/// Encoder for 8bpp tile patterns for the AGB platform.
+/// Encoder/decoder for 8bpp tile patterns for the AGB platform.
+///
+/// Each pixel is one byte. Tiled data, from new_tiled, is grouped into 8x8
+/// tiles of 64 bytes; chunky data, from new_chunky, is stored row by row.
 pub struct AGB8Encoder<'a, F: 'a> {
     f: &'a mut F,
-    tsize: u32
+    tsize: u32,
+    palette_format: PaletteColorFormat,
+    rounding: ChannelRounding,
+    lcd: Option<LCDProfile>
 }
 
 impl<'a, F: 'a> AGB8Encoder<'a, F> {
     pub fn new_tiled(file: &'a mut F) -> AGB8Encoder<'a, F> {
         AGB8Encoder {
             f: file,
-            tsize: 8
+            tsize: 8,
+            palette_format: PaletteColorFormat::BGR555,
+            rounding: ChannelRounding::default(),
+            lcd: None
         }
     }
     
     pub fn new_chunky(file: &'a mut F) -> AGB8Encoder<'a, F> { 
         AGB8Encoder {
             f: file,
-            tsize: 1
+            tsize: 1,
+            palette_format: PaletteColorFormat::BGR555,
+            rounding: ChannelRounding::default(),
+            lcd: None
+        }
+    }
+
+    /// Reduce palette colors to 5 bits per channel with the given rounding,
+    /// rather than by truncation.
+    pub fn with_rounding(self, rounding: ChannelRounding) -> AGB8Encoder<'a, F> {
+        AGB8Encoder {
+            rounding,
+            ..self
+        }
+    }
+
+    /// Pre-correct palette colors for the given screen, so that they look on
+    /// hardware as they do on a PC.
+    pub fn with_lcd_profile(self, profile: LCDProfile) -> AGB8Encoder<'a, F> {
+        AGB8Encoder {
+            lcd: Some(profile),
+            ..self
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as BGR555.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> AGB8Encoder<'a, F> {
+        AGB8Encoder {
+            palette_format,
+            ..self
         }
     }
 }
@@ -154,65 +363,283 @@ impl<'a, F: 'a> IndexedGraphicsProperties for AGB8Encoder<'a, F> {
 }
 
 impl<'a, F: 'a> IndexedGraphicsEncoder for AGB8Encoder<'a, F> where F: Write {
-    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, width: u32, _height: u32) -> io::Result<()> {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
         let mut out: [u8; 1] = [0];
         
         for byte in data {
-            out[0] = byte.to_u8().unwrap() & 0xFF;
-            self.f.write(&out)?;
+            out[0] = byte.to_u8().unwrap();
+            self.f.write_all(&out)?;
         }
         
         Ok(())
     }
     
     fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
-        encode_palette(self.f, palette.into_iter(), false)
+        encode_palette_for_screen(self.f, palette, self.palette_format, self.rounding, self.lcd)
+    }
+}
+
+impl<'a, F: 'a> AGB8Encoder<'a, F> where F: Read {
+    /// Decode up to size bytes of index data, returning it along with the
+    /// number of tiles it holds.
+    /// 
+    /// Tiled data holds one tile per 64 indexes. Chunky data is untiled, so
+    /// its tile count is the number of pixels decoded.
+    pub fn decode_with_tile_count<P: Primitive>(&mut self, size: usize) -> io::Result<(Vec<P>, usize)> {
+        let out : Vec<P> = self.decode_indexes(size)?;
+        let count = out.len() / (self.tsize * self.tsize) as usize;
+        
+        Ok((out, count))
     }
 }
 
 impl<'a, F: 'a> IndexedGraphicsDecoder for AGB8Encoder<'a, F> where F: Read {
     fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
-        let mut out = Vec::with_capacity(size);
-        let mut buf: [u8; 1] = [0];
+        let mut buf = Vec::new();
         
-        for i in 0..size {
-            let readcnt = self.f.read(&mut buf)?;
-            
-            if readcnt < 1 {
-                return Err(io::Error::new(ErrorKind::UnexpectedEof, "File is shorter than image being decoded"));
-            }
-            
-            out.push(P::from(buf[0]).unwrap());
+        self.f.by_ref().take(size as u64).read_to_end(&mut buf)?;
+        
+        if !buf.len().is_multiple_of((self.tsize * self.tsize) as usize) {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "AGB 8bpp data ends partway through a tile"));
         }
         
-        Ok(out)
+        Ok(buf.into_iter().map(|byte| P::from(byte).unwrap()).collect())
+    }
+    
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some((self.tsize * self.tsize) as usize)
     }
 }
 
-pub struct AGB16Encoder<'a, W: Write + 'a> {
+/// Encoder for 16-bit direct color graphics for the AGB platform.
+///
+/// This is a DirectColor16Encoder; use its new_agb or new_ntr presets.
+pub type AGB16Encoder<'a, W> = DirectColor16Encoder<'a, W>;
+
+/// Decoder for 16-bit direct color graphics for the AGB and NTR platforms.
+///
+/// This is a DirectColor16Decoder; use its new_agb or new_ntr presets. new_ntr
+/// treats the top bit of each word as the NTR alpha bit, decoding pixels
+/// without it as fully transparent, while new_agb decodes opaque pixels.
+pub type AGB16Decoder<'a, R> = DirectColor16Decoder<'a, R>;
+
+/// Size of one page of bitmap mode video memory.
+const AGB_PAGE_SIZE: usize = 0xA000;
+
+/// Split encoded bitmap frames into pages, padding every frame but the last
+/// to the page size so that the second frame lands on the back buffer.
+fn write_pages<W: Write>(w: &mut W, data: &[u8], frame_size: usize) -> io::Result<()> {
+    let frames: Vec<&[u8]> = data.chunks(frame_size).collect();
+    
+    for (i, frame) in frames.iter().enumerate() {
+        w.write_all(frame)?;
+        
+        if i + 1 < frames.len() && frame.len() < AGB_PAGE_SIZE {
+            w.write_all(&vec![0; AGB_PAGE_SIZE - frame.len()])?;
+        }
+    }
+    
+    Ok(())
+}
+
+/// Encoder for 16-bit bitmap mode frame buffers for the AGB platform.
+///
+/// Pixels are encoded as with AGB16Encoder::new_agb, after validating that the
+/// image matches the frame buffer of the selected mode. Mode 3 frame buffers
+/// are 240x160. Mode 5 frame buffers are 160x128; a 160x256 image is treated
+/// as two frames stacked vertically and written as both page-flipping frame
+/// buffers, with the second frame starting at the back buffer offset.
+pub struct AGBBitmapEncoder<'a, W: Write + 'a> {
     w: &'a mut W,
-    allow_ntr_alpha: bool
+    size: (u32, u32),
+    pages: bool
 }
 
-impl<'a, W: Write + 'a> AGB16Encoder<'a, W> {
-    pub fn new_agb(write: &'a mut W) -> AGB16Encoder<'a, W> {
-        AGB16Encoder {
+impl<'a, W: Write + 'a> AGBBitmapEncoder<'a, W> {
+    pub fn new_mode3(write: &'a mut W) -> AGBBitmapEncoder<'a, W> {
+        AGBBitmapEncoder {
             w: write,
-            allow_ntr_alpha: false
+            size: (240, 160),
+            pages: false
         }
     }
     
-    pub fn new_ntr(write: &'a mut W) -> AGB16Encoder<'a, W> {
-        AGB16Encoder {
+    pub fn new_mode5(write: &'a mut W) -> AGBBitmapEncoder<'a, W> {
+        AGBBitmapEncoder {
             w: write,
-            allow_ntr_alpha: true
+            size: (160, 128),
+            pages: true
         }
     }
 }
 
-impl<'a, W: Write> DirectGraphicsEncoder for AGB16Encoder<'a, W> {
+impl<'a, W: Write> DirectGraphicsEncoder for AGBBitmapEncoder<'a, W> {
     fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
-        encode_palette(self.w, ImageRgbaIterator::new(&mut image.pixels()), self.allow_ntr_alpha)
+        let (width, height) = image.dimensions();
+        let (fw, fh) = self.size;
+        
+        if width != fw || (height != fh && !(self.pages && height == fh * 2)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Image does not match the {}x{} bitmap mode frame buffer.", fw, fh)));
+        }
+        
+        let mut data: Vec<u8> = Vec::with_capacity((width * height * 2) as usize);
+        
+        AGB16Encoder::new_agb(&mut data).encode_colors(image)?;
+        write_pages(self.w, &data, (fw * fh * 2) as usize)
+    }
+}
+
+/// Join frames split into pages by write_pages back together, dropping the
+/// padding after every frame but the last.
+///
+/// The data must hold a whole number of frames.
+fn read_pages<R: Read>(r: &mut R, size: usize, frame_size: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+
+    r.take(size as u64).read_to_end(&mut buf)?;
+
+    let frames: Vec<&[u8]> = buf.chunks(AGB_PAGE_SIZE.max(frame_size)).map(|page| &page[..page.len().min(frame_size)]).collect();
+
+    if frames.iter().any(|frame| frame.len() < frame_size) {
+        return Err(io::Error::new(ErrorKind::UnexpectedEof, "Bitmap mode data ends partway through a frame"));
+    }
+
+    Ok(frames.concat())
+}
+
+/// Decoder for 16-bit bitmap mode frame buffers for the AGB platform.
+///
+/// This reads frame buffers written by an AGBBitmapEncoder for the same mode,
+/// decoding each pixel as AGB16Decoder::new_agb does. Successive mode 5
+/// frames are stacked vertically, as the encoder expects them to be. The
+/// width given when decoding must match the frame buffer.
+pub struct AGBBitmapDecoder<'a, R: Read + 'a> {
+    r: &'a mut R,
+    size: (u32, u32)
+}
+
+impl<'a, R: Read + 'a> AGBBitmapDecoder<'a, R> {
+    pub fn new_mode3(read: &'a mut R) -> AGBBitmapDecoder<'a, R> {
+        AGBBitmapDecoder {
+            r: read,
+            size: (240, 160)
+        }
+    }
+
+    pub fn new_mode5(read: &'a mut R) -> AGBBitmapDecoder<'a, R> {
+        AGBBitmapDecoder {
+            r: read,
+            size: (160, 128)
+        }
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for AGBBitmapDecoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        let (fw, fh) = self.size;
+
+        if width != fw {
+            return Err(io::Error::new(ErrorKind::InvalidInput, format!("Bitmap mode frame buffers are {} pixels wide.", fw)));
+        }
+
+        let frames = read_pages(self.r, size, (fw * fh * 2) as usize)?;
+
+        AGB16Decoder::new_agb(&mut &frames[..]).decode_colors(width, frames.len())
+    }
+}
+
+/// Encoder for 8-bit bitmap mode frame buffers for the AGB platform.
+///
+/// Mode 4 frame buffers are 240x160 with one byte per pixel. A 240x320 image
+/// is treated as two frames stacked vertically and written as both
+/// page-flipping frame buffers, with the first frame padded so that the
+/// second starts at the back buffer offset.
+pub struct AGBMode4Encoder<'a, F: 'a> {
+    f: &'a mut F,
+    palette_format: PaletteColorFormat,
+    rounding: ChannelRounding,
+    lcd: Option<LCDProfile>
+}
+
+impl<'a, F: 'a> AGBMode4Encoder<'a, F> {
+    pub fn new(file: &'a mut F) -> AGBMode4Encoder<'a, F> {
+        AGBMode4Encoder {
+            f: file,
+            palette_format: PaletteColorFormat::BGR555,
+            rounding: ChannelRounding::default(),
+            lcd: None
+        }
+    }
+
+    /// Reduce palette colors to 5 bits per channel with the given rounding,
+    /// rather than by truncation.
+    pub fn with_rounding(self, rounding: ChannelRounding) -> AGBMode4Encoder<'a, F> {
+        AGBMode4Encoder {
+            rounding,
+            ..self
+        }
+    }
+
+    /// Pre-correct palette colors for the given screen, so that they look on
+    /// hardware as they do on a PC.
+    pub fn with_lcd_profile(self, profile: LCDProfile) -> AGBMode4Encoder<'a, F> {
+        AGBMode4Encoder {
+            lcd: Some(profile),
+            ..self
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as BGR555.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> AGBMode4Encoder<'a, F> {
+        AGBMode4Encoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for AGBMode4Encoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (1, 1)
+    }
+    
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+    
+    fn palette_maxcol(&self) -> u16 {
+        255
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for AGBMode4Encoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, width: u32, height: u32) -> io::Result<()> {
+        if width != 240 || (height != 160 && height != 320) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image does not match the 240x160 bitmap mode frame buffer."));
+        }
+        
+        let data: Vec<u8> = data.iter().map(|index| index.to_u8().unwrap()).collect();
+        
+        write_pages(self.f, &data, 240 * 160)
+    }
+    
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        encode_palette_for_screen(self.f, palette, self.palette_format, self.rounding, self.lcd)
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for AGBMode4Encoder<'a, F> where F: Read {
+    /// Decode one or more frame buffers, skipping the padding between them.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        Ok(read_pages(self.f, size, 240 * 160)?.into_iter().map(|byte| P::from(byte).unwrap()).collect())
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
     }
 }
 
@@ -223,9 +650,98 @@ mod tests {
     
     use std::io::Cursor;
     use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
-    use awsmimg::decoder::IndexedGraphicsDecoder;
-    use awsmimg::formats::agb::{AGB4Encoder, AGB8Encoder, AGB16Encoder};
+    use awsmimg::decoder::{IndexedGraphicsDecoder, DirectGraphicsDecoder, decode_to_image};
+    use awsmimg::formats::IndexedFormat;
+    use awsmimg::conversion::luma_from_indexes;
+    use awsmimg::formats::ChannelRounding;
+    use awsmimg::formats::palette::PaletteColorFormat;
+    use awsmimg::formats::agb::{encode_palette, encode_palette_rounded, LCDProfile, decode_palette, AGB4Encoder, AGB8Encoder, AGB16Encoder, AGB16Decoder, AGBBitmapEncoder, AGBMode4Encoder};
+    
+    #[test]
+    fn palette_decode() {
+        let src : Vec<u8> = vec![0x1F, 0x00, 0xE0, 0x83, 0x21, 0x04];
+        
+        let palette = decode_palette(&mut Cursor::new(&src), usize::MAX, false).unwrap();
+        let alpha_palette = decode_palette(&mut Cursor::new(&src), 4, true).unwrap();
+        let mut test_out = Cursor::new(Vec::with_capacity(6));
+        
+        assert_eq!(palette, vec![image::Rgba([255u8, 0, 0, 255]),
+                                 image::Rgba([0u8, 255, 0, 255]),
+                                 image::Rgba([8u8, 8, 8, 255])]);
+        assert_eq!(alpha_palette, vec![image::Rgba([255u8, 0, 0, 0]),
+                                       image::Rgba([0u8, 255, 0, 255])]);
+        assert!(decode_palette(&mut Cursor::new(&src), 5, false).is_err());
+        
+        encode_palette(&mut test_out, alpha_palette.into_iter(), true).unwrap();
+        
+        assert_eq!(test_out.get_ref(), &src[..4].to_vec());
+    }
     
+    #[test]
+    fn palette_rounding() {
+        //Channels of 0x04 and 0xFC fall between two 5-bit steps.
+        let palette = vec![image::Rgba([0x04u8, 0xFC, 0x84, 255])];
+        let mut truncated = Vec::new();
+        let mut rounded = Vec::new();
+        let mut scaled = Vec::new();
+
+        encode_palette_rounded(&mut truncated, palette.clone().into_iter(), false, ChannelRounding::Truncate).unwrap();
+        encode_palette_rounded(&mut rounded, palette.clone().into_iter(), false, ChannelRounding::RoundHalfUp).unwrap();
+        encode_palette_rounded(&mut scaled, palette.clone().into_iter(), false, ChannelRounding::Scale).unwrap();
+
+        assert_eq!(truncated, vec![0xE0, 0x43]);
+        assert_eq!(rounded, vec![0xE1, 0x47]);
+        assert_eq!(scaled, vec![0xE0, 0x43]);
+
+        let mut test_out = Vec::new();
+
+        AGB4Encoder::new(&mut test_out).with_rounding(ChannelRounding::RoundHalfUp).encode_palette(palette).unwrap();
+
+        assert_eq!(test_out, rounded);
+    }
+
+    #[test]
+    fn palette_format_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255])];
+        let mut md_out = Vec::new();
+        let mut rgb565_out = Vec::new();
+
+        AGB4Encoder::new(&mut md_out).with_palette_format(PaletteColorFormat::MD).encode_palette(palette.clone()).unwrap();
+        AGB8Encoder::new_tiled(&mut rgb565_out).with_palette_format(PaletteColorFormat::RGB565).with_rounding(ChannelRounding::RoundHalfUp).encode_palette(palette).unwrap();
+
+        assert_eq!(md_out, vec![0x00, 0x0E]);
+        assert_eq!(rgb565_out, vec![0x00, 0xF8]);
+    }
+
+    #[test]
+    fn lcd_precorrection() {
+        let gray = image::Rgba([128u8, 128, 128, 255]);
+        let muted = image::Rgba([110u8, 150, 140, 255]);
+
+        for profile in [LCDProfile::AGB, LCDProfile::AGS101].iter() {
+            for color in [gray, muted].iter() {
+                let shown = profile.simulate(profile.precorrect(*color));
+
+                for c in 0..3 {
+                    assert!((shown[c] as i32 - color[c] as i32).abs() <= 2);
+                }
+            }
+
+            assert_eq!(profile.precorrect(image::Rgba([0u8, 0, 0, 255])), image::Rgba([0u8, 0, 0, 255]));
+        }
+
+        //The unlit AGB screen is dark, so colors must be brightened for it.
+        assert!(LCDProfile::AGB.precorrect(gray)[1] > 160);
+
+        let mut test_out = Vec::new();
+        let mut expected = Vec::new();
+
+        AGB4Encoder::new(&mut test_out).with_lcd_profile(LCDProfile::AGB).encode_palette(vec![gray]).unwrap();
+        encode_palette(&mut expected, vec![LCDProfile::AGB.precorrect(gray)].into_iter(), false).unwrap();
+
+        assert_eq!(test_out, expected);
+    }
+
     #[test]
     fn data4_encode() {
         let src = num::range(0, 64).collect();
@@ -263,6 +779,49 @@ mod tests {
         assert_eq!(&test_out, &valid_out)
     }
     
+    #[test]
+    fn data4_decode_tiles() {
+        let mut src : Vec<u8> = vec![0; 48];
+        src[0] = 0x21;
+        src[63 / 2] = 0xF0;
+        let mut test_in = Cursor::new(&src);
+        
+        let test_out : Vec<u8> = AGB4Encoder::new(&mut test_in).decode_tiles(1).unwrap();
+        let img = luma_from_indexes(test_out, 15, (8, 8), None).unwrap();
+        
+        assert_eq!(img.dimensions(), (8, 8));
+        assert_eq!(img.get_pixel(0, 0), &image::LumaA([17u8, 255]));
+        assert_eq!(img.get_pixel(1, 0), &image::LumaA([34u8, 255]));
+        assert_eq!(img.get_pixel(7, 7), &image::LumaA([255u8, 255]));
+        assert!(AGB4Encoder::new(&mut Cursor::new(&src)).decode_tiles::<u8>(2).is_err());
+    }
+    
+    #[test]
+    fn data4_decode_bounds() {
+        let src : Vec<u8> = vec![0x11; 64];
+        
+        let whole : Vec<u8> = AGB4Encoder::new(&mut Cursor::new(&src)).decode_indexes(usize::MAX).unwrap();
+        
+        assert_eq!(whole.len(), 128);
+        assert!(AGB4Encoder::new(&mut Cursor::new(&src)).decode_indexes::<u8>(40).is_err());
+        assert!(AGB4Encoder::new(&mut Cursor::new(&src[..40])).decode_indexes::<u8>(usize::MAX).is_err());
+    }
+    
+    #[test]
+    fn data4_decode_to_image() {
+        let mut tiles : Vec<u8> = vec![0; 64];
+        tiles[0] = 0x10;
+        tiles[63] = 0x20;
+        let palette : Vec<u8> = vec![0x00, 0x00, 0x1F, 0x00, 0x00, 0x7C];
+        
+        let img = decode_to_image(IndexedFormat::AGB4, &mut Cursor::new(&tiles), &mut Cursor::new(&palette), usize::MAX, Some((16, 8))).unwrap();
+        
+        assert_eq!(img.get_pixel(0, 0), &image::Rgba([0u8, 0, 0, 255]));
+        assert_eq!(img.get_pixel(1, 0), &image::Rgba([255u8, 0, 0, 255]));
+        assert_eq!(img.get_pixel(15, 7), &image::Rgba([0u8, 0, 255, 255]));
+        assert!(decode_to_image(IndexedFormat::AGB4, &mut Cursor::new(&tiles), &mut Cursor::new(&palette), usize::MAX, Some((12, 8))).is_err());
+    }
+    
     #[test]
     fn data8t_encode() {
         let src = num::range(0, 64).collect();
@@ -307,20 +866,31 @@ mod tests {
         assert_eq!(&test_out, &valid_out)
     }
     
+    #[test]
+    fn data8_decode_tile_count() {
+        let src : Vec<u8> = num::range(0, 192).collect();
+        
+        let (tiled, tcount) : (Vec<u8>, usize) = AGB8Encoder::new_tiled(&mut Cursor::new(&src)).decode_with_tile_count(usize::MAX).unwrap();
+        let (chunky, ccount) : (Vec<u8>, usize) = AGB8Encoder::new_chunky(&mut Cursor::new(&src)).decode_with_tile_count(100).unwrap();
+        
+        assert_eq!((tiled, tcount), (src.clone(), 3));
+        assert_eq!((chunky, ccount), (src[..100].to_vec(), 100));
+        assert!(AGB8Encoder::new_tiled(&mut Cursor::new(&src)).decode_with_tile_count::<u8>(100).is_err());
+    }
+    
     #[test]
     fn data16_encode() {
         let img = image::ImageBuffer::from_fn(8, 8, |x, y| {
-            image::Rgba([(x * 8) as u8, (y as i16 * -8 + 255) as u8, (x * y) as u8, ((x + y) & 0x01 * 255) as u8])
+            image::Rgba([(x * 8) as u8, (y as i16 * -8 + 255) as u8, (x * y) as u8, (((x + y) & 0x01) * 255) as u8])
         });
         let mut test_out = Cursor::new(Vec::with_capacity(64));
         
         {
             let mut agb16 = AGB16Encoder::new_agb(&mut test_out);
             
-            agb16.encode_colors(&img);
+            agb16.encode_colors(&img).unwrap();
         }
         
-        //TODO: Actually round-trip this against an AGB16 decoder (not yet written)
         //This vector was obtained by grabbing some valid-looking output from
         //the code under test and spot-checking a few values against the above
         let valid_out : Vec<u8> = vec![224, 3, 225, 3, 226, 3, 227, 3, 228, 3, 229, 3, 230, 3, 231, 3,
@@ -334,4 +904,96 @@ mod tests {
         
         assert_eq!(test_out.get_ref(), &valid_out)
     }
+
+    #[test]
+    fn data16_decode_roundtrip() {
+        let src : Vec<u8> = vec![0x1F, 0x00, 0xE0, 0x83, 0x00, 0x7C, 0x21, 0x84];
+        let mut agb_out = Cursor::new(Vec::with_capacity(8));
+        let mut ntr_out = Cursor::new(Vec::with_capacity(8));
+        
+        let agb_img = AGB16Decoder::new_agb(&mut Cursor::new(&src)).decode_colors(2, usize::MAX).unwrap();
+        let ntr_img = AGB16Decoder::new_ntr(&mut Cursor::new(&src)).decode_colors(2, usize::MAX).unwrap();
+        
+        assert_eq!(agb_img.dimensions(), (2, 2));
+        assert_eq!(agb_img.get_pixel(0, 0), &image::Rgba([255u8, 0, 0, 255]));
+        assert_eq!(agb_img.get_pixel(1, 1), &image::Rgba([8u8, 8, 8, 255]));
+        assert_eq!(ntr_img.get_pixel(0, 0), &image::Rgba([255u8, 0, 0, 0]));
+        assert_eq!(ntr_img.get_pixel(1, 0), &image::Rgba([0u8, 255, 0, 255]));
+        
+        AGB16Encoder::new_ntr(&mut ntr_out).encode_colors(&ntr_img).unwrap();
+        AGB16Encoder::new_agb(&mut agb_out).encode_colors(&agb_img).unwrap();
+        
+        assert_eq!(ntr_out.get_ref(), &src);
+        assert_eq!(agb_out.get_ref(), &vec![0x1Fu8, 0x00, 0xE0, 0x03, 0x00, 0x7C, 0x21, 0x04]);
+        assert!(AGB16Decoder::new_agb(&mut Cursor::new(&src)).decode_colors(3, usize::MAX).is_err());
+    }
+    
+    #[test]
+    fn mode3_encode() {
+        let img = image::ImageBuffer::from_fn(240, 160, |x, y| image::Rgba([if (x, y) == (0, 0) { 255u8 } else { 0 }, 0, 0, 255]));
+        let bad_img = image::ImageBuffer::from_fn(240, 320, |_, _| image::Rgba([0u8, 0, 0, 255]));
+        let mut test_out = Cursor::new(Vec::new());
+        
+        AGBBitmapEncoder::new_mode3(&mut test_out).encode_colors(&img).unwrap();
+        
+        assert_eq!(test_out.get_ref().len(), 240 * 160 * 2);
+        assert_eq!(&test_out.get_ref()[0..4], &[0x1F, 0x00, 0x00, 0x00]);
+        assert!(AGBBitmapEncoder::new_mode3(&mut Cursor::new(Vec::new())).encode_colors(&bad_img).is_err());
+    }
+    
+    #[test]
+    fn mode5_pages_encode() {
+        let img = image::ImageBuffer::from_fn(160, 256, |_, y| image::Rgba([if y < 128 { 0u8 } else { 255 }, 0, 0, 255]));
+        let mut test_out = Cursor::new(Vec::new());
+        
+        AGBBitmapEncoder::new_mode5(&mut test_out).encode_colors(&img).unwrap();
+        
+        let out = test_out.get_ref();
+        
+        assert_eq!(out.len(), 0xA000 * 2);
+        assert_eq!(&out[0x9FFE..0xA002], &[0x00, 0x00, 0x1F, 0x00]);
+    }
+    
+    #[test]
+    fn mode4_pages_encode() {
+        let src : Vec<u8> = (0..240 * 320).map(|i| if i < 240 * 160 { 1 } else { 2 }).collect();
+        let mut test_out = Cursor::new(Vec::new());
+        
+        {
+            let mut agb = AGBMode4Encoder::new(&mut test_out);
+            
+            agb.encode_indexes(src, 240, 320).unwrap();
+            assert!(agb.encode_indexes(vec![0u8; 160 * 128], 160, 128).is_err());
+        }
+        
+        let out = test_out.get_ref();
+        
+        assert_eq!(out.len(), 0xA000 + 240 * 160);
+        assert_eq!(out[240 * 160 - 1], 1);
+        assert_eq!(out[240 * 160], 0);
+        assert_eq!(out[0xA000], 2);
+    }
+
+    #[test]
+    fn ntr16_alpha_threshold() {
+        let img = image::ImageBuffer::from_fn(4, 1, |x, _| image::Rgba([0u8, 0, 0, [0x00, 0x7F, 0x80, 0xFF][x as usize]]));
+        let mut default_out = Cursor::new(Vec::with_capacity(8));
+        let mut custom_out = Cursor::new(Vec::with_capacity(8));
+        
+        AGB16Encoder::new_ntr(&mut default_out).encode_colors(&img).unwrap();
+        AGB16Encoder::new_ntr(&mut custom_out).with_alpha_threshold(0x01).encode_colors(&img).unwrap();
+        
+        assert_eq!(default_out.get_ref(), &vec![0x00u8, 0x00, 0x00, 0x00, 0x00, 0x80, 0x00, 0x80]);
+        assert_eq!(custom_out.get_ref(), &vec![0x00u8, 0x00, 0x00, 0x80, 0x00, 0x80, 0x00, 0x80]);
+    }
+    
+    #[test]
+    fn palette_alpha_encode() {
+        let palette = vec![image::Rgba([0u8, 0, 0, 0x80]), image::Rgba([0u8, 0, 0, 0x7F])];
+        let mut test_out = Cursor::new(Vec::with_capacity(4));
+        
+        encode_palette(&mut test_out, palette.into_iter(), true).unwrap();
+        
+        assert_eq!(test_out.get_ref(), &vec![0x00u8, 0x80, 0x00, 0x00]);
+    }
 }
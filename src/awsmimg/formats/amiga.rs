@@ -0,0 +1,282 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, read_color_words, expand_channel};
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::{IndexedGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{Primitive, Rgba};
+
+/// Encode a series of RGBA colors as OCS/ECS color register data.
+///
+/// Colors are written as big-endian 12-bit words in the 0000RRRRGGGGBBBB
+/// layout. Alpha is ignored.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 2] = [0, 0];
+
+    for rgba in palette {
+        let r : u16 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let g : u16 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let b : u16 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+
+        let enc_color: u16 = (r & 0xF0) << 4 | (g & 0xF0) | b >> 4;
+
+        out[0] = ((enc_color >> 8) & 0xFF) as u8;
+        out[1] = (enc_color & 0xFF) as u8;
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode OCS/ECS color register data written by encode_palette into RGBA
+/// colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. Every
+/// color is opaque.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    Ok(read_color_words(r, size, true)?.into_iter().map(|v| {
+        Rgba([expand_channel(v >> 8, 4), expand_channel(v >> 4, 4), expand_channel(v, 4), 255])
+    }).collect())
+}
+
+/// Encoder for hardware sprites for the Amiga platform.
+///
+/// Hardware sprites are 16 pixels wide. Images are split into 16 pixel wide
+/// strips, left to right, each of which is written as a complete sprite: two
+/// control words (SPRxPOS and SPRxCTL), one pair of data words per line, and
+/// two zero words to end the sprite. The leftmost pixel of each line is
+/// stored in the most significant bit of each data word. Strips narrower than
+/// 16 pixels are padded with transparent pixels.
+///
+/// Constructed with new, sprites are 2bpp: each strip becomes one sprite. With
+/// new_attached, sprites are 4bpp: each strip becomes an attached pair, the
+/// even sprite holding bitplanes 0 and 1 and the odd sprite holding bitplanes
+/// 2 and 3 with the attach bit set.
+///
+/// Sprite positions are not known at encoding time, so control words are
+/// written as zero apart from the attach bit and must be filled in before the
+/// sprite is displayed.
+///
+/// Palettes are written as OCS/ECS color register data unless another format
+/// is given with with_palette_format.
+pub struct AmigaSpriteEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    attached: bool,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> AmigaSpriteEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> AmigaSpriteEncoder<'a, F> {
+        AmigaSpriteEncoder {
+            f: file,
+            attached: false,
+            palette_format: PaletteColorFormat::Amiga
+        }
+    }
+
+    pub fn new_attached(file: &'a mut F) -> AmigaSpriteEncoder<'a, F> {
+        AmigaSpriteEncoder {
+            f: file,
+            attached: true,
+            palette_format: PaletteColorFormat::Amiga
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as OCS/ECS colors.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> AmigaSpriteEncoder<'a, F> {
+        AmigaSpriteEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for AmigaSpriteEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        match self.attached {
+            true => 15,
+            false => 3
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for AmigaSpriteEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, width: u32, height: u32) -> io::Result<()> {
+        let width = width as usize;
+        let planes: &[u8] = if self.attached { &[0, 2] } else { &[0] };
+        let mut out: Vec<u8> = Vec::new();
+
+        for strip in 0..width.div_ceil(16) {
+            for &plane in planes {
+                let ctl: u8 = if plane == 2 { 0x80 } else { 0x00 };
+
+                out.clear();
+                out.extend_from_slice(&[0x00, 0x00, 0x00, ctl]);
+
+                for y in 0..height as usize {
+                    let mut words: [u16; 2] = [0, 0];
+
+                    for x in 0..16 {
+                        let index = match strip * 16 + x {
+                            sx if sx < width => data.get(y * width + sx).map_or(0, |i| i.to_u8().unwrap()),
+                            _ => 0
+                        };
+
+                        for (p, word) in words.iter_mut().enumerate() {
+                            *word |= (((index >> (plane as usize + p)) & 0x01) as u16) << (15 - x);
+                        }
+                    }
+
+                    for word in &words {
+                        out.push((word >> 8) as u8);
+                        out.push((word & 0xFF) as u8);
+                    }
+                }
+
+                out.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+                self.f.write_all(&out)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for AmigaSpriteEncoder<'a, F> where F: Read {
+    /// Decode a single sprite, or a single attached pair, into color indexes
+    /// for a 16 pixel wide image.
+    ///
+    /// Sprite data carries no height of its own, so the data read must hold
+    /// exactly one sprite or attached pair, and its height is taken from the
+    /// amount of data. Wider images must be decoded one strip at a time.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let sprites = if self.attached { 2 } else { 1 };
+        let buf = read_units(self.f, size, 4 * sprites, "Sprite data ends partway through a line")?;
+        let sprite_len = buf.len() / sprites;
+
+        if sprite_len < 8 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Sprite data ends before its control words"));
+        }
+
+        let sprite_data: Vec<&[u8]> = buf.chunks(sprite_len).collect();
+
+        for (i, sprite) in sprite_data.iter().enumerate() {
+            if sprite[sprite_len - 4..] != [0, 0, 0, 0] || sprite[3] & 0x80 != (i as u8) << 7 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Data does not hold a single sprite or attached pair"));
+            }
+        }
+
+        let height = (sprite_len - 8) / 4;
+        let mut out = Vec::with_capacity(height * 16);
+
+        for y in 0..height {
+            for x in 0..16 {
+                let mut index = 0u8;
+
+                for (i, sprite) in sprite_data.iter().enumerate() {
+                    for p in 0..2 {
+                        let word = (sprite[4 + y * 4 + p * 2] as u16) << 8 | sprite[5 + y * 4 + p * 2] as u16;
+
+                        index |= (((word >> (15 - x)) & 0x01) as u8) << (i * 2 + p);
+                    }
+                }
+
+                out.push(P::from(index).unwrap());
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::formats::amiga::AmigaSpriteEncoder;
+
+    #[test]
+    fn sprite_encode() {
+        let mut src : Vec<u8> = vec![0; 40];
+        src[0] = 1;
+        src[15] = 2;
+        src[16] = 3;
+        src[20 + 19] = 1;
+        let mut test_out = Cursor::new(Vec::with_capacity(32));
+
+        {
+            let mut spr = AmigaSpriteEncoder::new(&mut test_out);
+
+            spr.encode_indexes(src, 20, 2).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x00, 0x00, 0x00, 0x00,
+                                       0x80, 0x00, 0x00, 0x01,
+                                       0x00, 0x00, 0x00, 0x00,
+                                       0x00, 0x00, 0x00, 0x00,
+                                       0x00, 0x00, 0x00, 0x00,
+                                       0x80, 0x00, 0x80, 0x00,
+                                       0x10, 0x00, 0x00, 0x00,
+                                       0x00, 0x00, 0x00, 0x00];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn attached_sprite_encode() {
+        let mut src : Vec<u8> = vec![0; 16];
+        src[0] = 0x5;
+        src[1] = 0xA;
+        let mut test_out = Cursor::new(Vec::with_capacity(24));
+
+        {
+            let mut spr = AmigaSpriteEncoder::new_attached(&mut test_out);
+
+            spr.encode_indexes(src, 16, 1).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x00, 0x00, 0x00, 0x00,
+                                       0x80, 0x00, 0x40, 0x00,
+                                       0x00, 0x00, 0x00, 0x00,
+                                       0x00, 0x00, 0x00, 0x80,
+                                       0x80, 0x00, 0x40, 0x00,
+                                       0x00, 0x00, 0x00, 0x00];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn palette_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]),
+                           image::Rgba([0x12u8, 0x34, 0x56, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(4));
+
+        {
+            let mut spr = AmigaSpriteEncoder::new(&mut test_out);
+
+            spr.encode_palette(palette).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x0Fu8, 0x00, 0x01, 0x35])
+    }
+}
@@ -0,0 +1,296 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, read_color_words, expand_channel};
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::{IndexedGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{Primitive, Rgba};
+
+/// Encode a series of RGBA colors as ST or STE palette register data.
+///
+/// Colors are written as big-endian words in the 00000RRR0GGG0BBB layout. If
+/// ste is true, each channel is extended to four bits, with the least
+/// significant bit of each channel stored in the high bit of its nibble as
+/// the STE shifter expects. Alpha is ignored.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I, ste: bool) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 2] = [0, 0];
+
+    for rgba in palette {
+        let mut enc_color: u16 = 0;
+
+        for c in 0..3 {
+            let v : u16 = (rgba[c].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+            let nibble = match ste {
+                true => (v >> 5) | (v & 0x10) >> 1,
+                false => v >> 5
+            };
+
+            enc_color |= nibble << ((2 - c) * 4);
+        }
+
+        out[0] = ((enc_color >> 8) & 0xFF) as u8;
+        out[1] = (enc_color & 0xFF) as u8;
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode ST or STE palette data written by encode_palette into RGBA colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. If ste
+/// is false, the STE's extra low bit of each channel is ignored. Every color
+/// is opaque.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize, ste: bool) -> io::Result<Vec<Rgba<u8>>> {
+    let channel = |nibble: u16| match ste {
+        true => expand_channel((nibble & 0x07) << 1 | (nibble & 0x08) >> 3, 4),
+        false => expand_channel(nibble, 3)
+    };
+
+    Ok(read_color_words(r, size, true)?.into_iter().map(|v| {
+        Rgba([channel(v >> 8), channel(v >> 4), channel(v), 255])
+    }).collect())
+}
+
+/// Encoder for word-interleaved bitplane graphics for the ST platform.
+///
+/// Each row is stored as groups of 16 pixels. Each group is one big-endian
+/// word per bitplane, lowest plane first, with the leftmost pixel in the most
+/// significant bit. Use new_low for 4 plane (low resolution) data or
+/// new_medium for 2 plane (medium resolution) data.
+///
+/// By default the image is encoded as a screen, one full-width row at a time.
+/// Use with_tile_size to instead encode tiles, left to right then top to
+/// bottom; tile widths must be a multiple of 16. Rows narrower than a group
+/// are padded with index zero.
+///
+/// Palettes are written in the ST format unless with_ste_palette or
+/// with_palette_format selects another.
+pub struct AtariSTEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    planes: u8,
+    tsize: (u32, u32),
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> AtariSTEncoder<'a, F> {
+    pub fn new_low(file: &'a mut F) -> AtariSTEncoder<'a, F> {
+        AtariSTEncoder {
+            f: file,
+            planes: 4,
+            tsize: (1, 1),
+            palette_format: PaletteColorFormat::AtariST
+        }
+    }
+
+    pub fn new_medium(file: &'a mut F) -> AtariSTEncoder<'a, F> {
+        AtariSTEncoder {
+            planes: 2,
+            ..AtariSTEncoder::new_low(file)
+        }
+    }
+
+    pub fn with_tile_size(self, tsize: (u32, u32)) -> AtariSTEncoder<'a, F> {
+        AtariSTEncoder {
+            tsize,
+            ..self
+        }
+    }
+
+    pub fn with_ste_palette(self, ste_palette: bool) -> AtariSTEncoder<'a, F> {
+        AtariSTEncoder {
+            palette_format: match ste_palette {
+                true => PaletteColorFormat::AtariSTE,
+                false => PaletteColorFormat::AtariST
+            },
+            ..self
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as ST palette
+    /// register data.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> AtariSTEncoder<'a, F> {
+        AtariSTEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for AtariSTEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        self.tsize
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        (1 << self.planes) - 1
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for AtariSTEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, width: u32, _height: u32) -> io::Result<()> {
+        let row_width = match self.tsize {
+            (1, 1) => width as usize,
+            (tw, _) if tw.is_multiple_of(16) => tw as usize,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "ST tile width must be a multiple of 16."))
+        };
+        let mut out: Vec<u8> = Vec::new();
+
+        for row in data.chunks(row_width) {
+            for group in row.chunks(16) {
+                let mut words: [u16; 4] = [0; 4];
+
+                for (x, index) in group.iter().enumerate() {
+                    let index = index.to_u16().unwrap();
+
+                    for (plane, word) in words.iter_mut().enumerate() {
+                        *word |= ((index >> plane) & 0x01) << (15 - x);
+                    }
+                }
+
+                for word in words.iter().take(self.planes as usize) {
+                    out.push((word >> 8) as u8);
+                    out.push((word & 0xFF) as u8);
+                }
+            }
+        }
+
+        self.f.write_all(&out)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for AtariSTEncoder<'a, F> where F: Read {
+    /// Decode word-interleaved bitplane data into color indexes.
+    ///
+    /// Every group of 16 pixels is decoded. Screens whose width is not a
+    /// multiple of 16 therefore decode with the padding of each row intact.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let group_bytes = self.planes as usize * 2;
+        let unit = match self.tile_bytes() {
+            Some(tile_bytes) => tile_bytes,
+            None if self.tsize == (1, 1) => group_bytes,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "ST tile width must be a multiple of 16."))
+        };
+        let buf = read_units(self.f, size, unit, "ST data ends partway through a group of pixels")?;
+        let mut out = Vec::with_capacity(buf.len() * 8 / self.planes as usize);
+
+        for group in buf.chunks(group_bytes) {
+            for x in 0..16 {
+                let mut index = 0u8;
+
+                for (plane, word) in group.chunks(2).enumerate() {
+                    let word = (word[0] as u16) << 8 | word[1] as u16;
+
+                    index |= (((word >> (15 - x)) & 0x01) as u8) << plane;
+                }
+
+                out.push(P::from(index).unwrap());
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        match self.tsize {
+            (tw, th) if tw.is_multiple_of(16) && tw > 0 => Some((tw * th) as usize * self.planes as usize / 8),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::formats::atarist::AtariSTEncoder;
+
+    #[test]
+    fn low_encode() {
+        let mut src : Vec<u8> = vec![0; 32];
+        src[0] = 0x1;
+        src[1] = 0x2;
+        src[2] = 0x4;
+        src[3] = 0x8;
+        src[31] = 0xF;
+        let mut test_out = Cursor::new(Vec::with_capacity(16));
+
+        {
+            let mut st = AtariSTEncoder::new_low(&mut test_out);
+
+            st.encode_indexes(src, 32, 1).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x80, 0x00, 0x40, 0x00, 0x20, 0x00, 0x10, 0x00,
+                                       0x00, 0x01, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn medium_encode() {
+        let src : Vec<u8> = vec![3, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3];
+        let mut test_out = Cursor::new(Vec::with_capacity(4));
+
+        {
+            let mut st = AtariSTEncoder::new_medium(&mut test_out);
+
+            st.encode_indexes(src, 16, 1).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0xC0u8, 0x01, 0xA0, 0x01])
+    }
+
+    #[test]
+    fn tiled_encode() {
+        let src : Vec<u8> = (0..32).map(|i| if i % 16 == 0 { 1 } else { 0 }).collect();
+        let mut test_out = Cursor::new(Vec::with_capacity(8));
+
+        {
+            let mut st = AtariSTEncoder::new_medium(&mut test_out).with_tile_size((16, 2));
+
+            st.encode_indexes(src, 16, 2).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x80u8, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00])
+    }
+
+    #[test]
+    fn palette_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]),
+                           image::Rgba([0x10u8, 0x20, 0xFF, 255])];
+        let mut st_out = Cursor::new(Vec::with_capacity(4));
+        let mut ste_out = Cursor::new(Vec::with_capacity(4));
+
+        {
+            let mut st = AtariSTEncoder::new_low(&mut st_out);
+
+            st.encode_palette(palette.clone()).unwrap();
+        }
+
+        {
+            let mut ste = AtariSTEncoder::new_low(&mut ste_out).with_ste_palette(true);
+
+            ste.encode_palette(palette).unwrap();
+        }
+
+        assert_eq!(st_out.get_ref(), &vec![0x07u8, 0x00, 0x00, 0x17]);
+        assert_eq!(ste_out.get_ref(), &vec![0x0Fu8, 0x00, 0x08, 0x1F]);
+    }
+}
@@ -0,0 +1,574 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat};
+use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
+use awsmimg::decoder::{IndexedGraphicsDecoder, DirectGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{GenericImage, ImageBuffer, Primitive, Pixel, Rgba};
+
+/// The RGB values of the 16 VIC-II colors, as measured by Pepto.
+const C64_PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF], [0x68, 0x37, 0x2B], [0x70, 0xA4, 0xB2],
+    [0x6F, 0x3D, 0x86], [0x58, 0x8D, 0x43], [0x35, 0x28, 0x79], [0xB8, 0xC7, 0x6F],
+    [0x6F, 0x4F, 0x25], [0x43, 0x39, 0x00], [0x9A, 0x67, 0x59], [0x44, 0x44, 0x44],
+    [0x6C, 0x6C, 0x6C], [0x9A, 0xD2, 0x84], [0x6C, 0x5E, 0xB5], [0x95, 0x95, 0x95]
+];
+
+fn c64_distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    (0..3).map(|i| (a[i] as i32 - b[i] as i32).pow(2) as u32).sum()
+}
+
+/// The nearest VIC-II color number to an RGB value, out of a given set of
+/// color numbers.
+fn c64_nearest(rgb: [u8; 3], colors: &[u8]) -> u8 {
+    *colors.iter().min_by_key(|&&c| c64_distance(rgb, C64_PALETTE[c as usize])).unwrap()
+}
+
+/// Map every pixel of an image to the nearest VIC-II color number.
+fn c64_colors<I, P, S>(image: &I) -> Vec<u8> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+    let (width, height) = image.dimensions();
+    let imgmax = S::max_value().to_f32().unwrap();
+    let all_colors: Vec<u8> = (0..16).collect();
+    let mut out = Vec::with_capacity((width * height) as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            let rgba = image.get_pixel(x, y).to_rgba();
+            let mut rgb = [0u8; 3];
+
+            for (c, v) in rgb.iter_mut().enumerate() {
+                *v = (rgba[c].to_f32().unwrap() / imgmax * 255f32) as u8;
+            }
+
+            out.push(c64_nearest(rgb, &all_colors));
+        }
+    }
+
+    out
+}
+
+/// Encode a series of RGBA colors as VIC-II color numbers.
+///
+/// Each color is written as one byte holding the number of the nearest color
+/// in the VIC-II palette. Alpha is ignored.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let all_colors: Vec<u8> = (0..16).collect();
+    let mut out: Vec<u8> = Vec::new();
+
+    for rgba in palette {
+        let mut rgb = [0u8; 3];
+
+        for (c, v) in rgb.iter_mut().enumerate() {
+            *v = (rgba[c].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u8;
+        }
+
+        out.push(c64_nearest(rgb, &all_colors));
+    }
+
+    w.write_all(&out)
+}
+
+/// Decode C64 color numbers written by encode_palette into RGBA colors.
+///
+/// Up to size bytes are read, one color per byte. Only the low nibble of each
+/// byte selects a color, as with the VIC-II color registers.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    let mut buf = Vec::new();
+
+    r.take(size as u64).read_to_end(&mut buf)?;
+
+    Ok(buf.iter().map(|c| {
+        let rgb = C64_PALETTE[(c & 0x0F) as usize];
+
+        Rgba([rgb[0], rgb[1], rgb[2], 255])
+    }).collect())
+}
+
+/// Encoder for hires character sets for the C64 platform.
+///
+/// Each 8x8 character is stored as eight bytes, one per row, with the leftmost
+/// pixel in the most significant bit. Index 0 clears a bit and index 1 sets
+/// it.
+///
+/// When constructed with new, every tile of the image is written as its own
+/// character. When constructed with new_deduplicated, identical characters
+/// are only written once to the charset writer, and a screen matrix with one
+/// character number per tile, left to right then top to bottom, is written to
+/// the screen writer. A charset holds at most 256 characters.
+///
+/// Palettes are written as C64 color numbers unless another format is given
+/// with with_palette_format.
+pub struct C64CharsetEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    screen: Option<&'a mut F>,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> C64CharsetEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> C64CharsetEncoder<'a, F> {
+        C64CharsetEncoder {
+            f: file,
+            screen: None,
+            palette_format: PaletteColorFormat::C64
+        }
+    }
+
+    pub fn new_deduplicated(charset: &'a mut F, screen: &'a mut F) -> C64CharsetEncoder<'a, F> {
+        C64CharsetEncoder {
+            f: charset,
+            screen: Some(screen),
+            palette_format: PaletteColorFormat::C64
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as C64 color
+    /// numbers.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> C64CharsetEncoder<'a, F> {
+        C64CharsetEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for C64CharsetEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        1
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for C64CharsetEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        let chars: Vec<Vec<u8>> = data.chunks(64).map(|tile| {
+            tile.chunks(8).map(|row| {
+                row.iter().enumerate().fold(0u8, |byte, (x, index)| byte | (index.to_u8().unwrap() & 0x01) << (7 - x))
+            }).collect()
+        }).collect();
+
+        match self.screen {
+            Some(ref mut screen) => {
+                let mut charset: Vec<&Vec<u8>> = Vec::new();
+                let mut screen_out: Vec<u8> = Vec::with_capacity(chars.len());
+
+                for c in chars.iter() {
+                    let num = match charset.iter().position(|d| *d == c) {
+                        Some(num) => num,
+                        None => {
+                            charset.push(c);
+                            charset.len() - 1
+                        }
+                    };
+
+                    if num > 255 {
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image has more than 256 unique characters."));
+                    }
+
+                    screen_out.push(num as u8);
+                }
+
+                for c in charset {
+                    self.f.write_all(c)?;
+                }
+
+                screen.write_all(&screen_out)
+            },
+            None => {
+                for c in chars.iter() {
+                    self.f.write_all(c)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for C64CharsetEncoder<'a, F> where F: Read {
+    /// Decode a character set into color indexes.
+    ///
+    /// When constructed with new_deduplicated, up to size bytes of the screen
+    /// matrix are also read, and the characters it names are decoded in its
+    /// order.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let charset = read_units(self.f, size, 8, "Character set ends partway through a character")?;
+        let chars: Vec<&[u8]> = charset.chunks(8).collect();
+        let order: Vec<usize> = match self.screen {
+            Some(ref mut screen) => {
+                let mut screen_data = Vec::new();
+
+                screen.take(size as u64).read_to_end(&mut screen_data)?;
+
+                if screen_data.iter().any(|&num| num as usize >= chars.len()) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Screen matrix names a character past the end of the character set."));
+                }
+
+                screen_data.into_iter().map(|num| num as usize).collect()
+            },
+            None => (0..chars.len()).collect()
+        };
+
+        Ok(order.into_iter().flat_map(|num| chars[num].iter().flat_map(|row| (0..8).map(move |x| P::from((row >> (7 - x)) & 0x01).unwrap())).collect::<Vec<P>>()).collect())
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        match self.screen {
+            Some(_) => None,
+            None => Some(8)
+        }
+    }
+}
+
+/// Encoder for multicolor bitmaps for the C64 platform.
+///
+/// Images are given in multicolor pixels, which are twice as wide as hires
+/// pixels; a full screen is 160x200. Dimensions must be multiples of 4x8.
+/// Colors are matched against the 16 colors of the VIC-II palette.
+///
+/// The image is split into 4x8 cells, left to right then top to bottom. Each
+/// cell is written as eight bitmap bytes with two bits per pixel, leftmost
+/// pixel in the most significant bits. Bit pair 00 selects the background
+/// color shared by the whole image, 01 the high nibble of the cell's screen
+/// RAM byte, 10 its low nibble, and 11 its color RAM nibble. The background
+/// color is the most common color in the image unless with_background is
+/// used. The three most common other colors of each cell are assigned to the
+/// cell's color slots; pixels in cells with more colors than that are mapped
+/// to the nearest of the cell's colors.
+///
+/// When constructed with new_split, the bitmap, screen RAM and color RAM are
+/// written to separate writers. When constructed with new, they are written to
+/// one writer in that order, as in a Koala file without its load address. In
+/// either case, the background color number is written as one byte after the
+/// color RAM.
+pub struct C64MulticolorEncoder<'a, W: Write + 'a> {
+    w: &'a mut W,
+    screen: Option<&'a mut W>,
+    color: Option<&'a mut W>,
+    background: Option<u8>
+}
+
+impl<'a, W: Write + 'a> C64MulticolorEncoder<'a, W> {
+    pub fn new(write: &'a mut W) -> C64MulticolorEncoder<'a, W> {
+        C64MulticolorEncoder {
+            w: write,
+            screen: None,
+            color: None,
+            background: None
+        }
+    }
+
+    pub fn new_split(bitmap: &'a mut W, screen: &'a mut W, color: &'a mut W) -> C64MulticolorEncoder<'a, W> {
+        C64MulticolorEncoder {
+            w: bitmap,
+            screen: Some(screen),
+            color: Some(color),
+            background: None
+        }
+    }
+
+    pub fn with_background(self, background: u8) -> C64MulticolorEncoder<'a, W> {
+        C64MulticolorEncoder {
+            background: Some(background & 0x0F),
+            ..self
+        }
+    }
+}
+
+impl<'a, W: Write> DirectGraphicsEncoder for C64MulticolorEncoder<'a, W> {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        let (width, height) = image.dimensions();
+
+        if !width.is_multiple_of(4) || !height.is_multiple_of(8) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "C64 multicolor bitmap dimensions must be multiples of 4x8."));
+        }
+
+        let colors = c64_colors(image);
+        let background = self.background.unwrap_or_else(|| {
+            let mut counts = [0u32; 16];
+
+            for c in &colors {
+                counts[*c as usize] += 1;
+            }
+
+            (0..16).max_by_key(|&c| (counts[c as usize], std::cmp::Reverse(c))).unwrap()
+        });
+
+        let mut bitmap_out: Vec<u8> = Vec::with_capacity((width * height / 4) as usize);
+        let mut screen_out: Vec<u8> = Vec::with_capacity((width * height / 32) as usize);
+        let mut color_out: Vec<u8> = Vec::with_capacity((width * height / 32) as usize);
+
+        for cy in 0..height / 8 {
+            for cx in 0..width / 4 {
+                let cell: Vec<u8> = (0..32).map(|i| colors[((cy * 8 + i / 4) * width + cx * 4 + i % 4) as usize]).collect();
+                let mut counts = [0u32; 16];
+
+                for c in &cell {
+                    counts[*c as usize] += 1;
+                }
+
+                counts[background as usize] = 0;
+
+                let mut ranked: Vec<u8> = (0..16).collect();
+                ranked.sort_by_key(|&c| std::cmp::Reverse(counts[c as usize]));
+
+                //Slots are background, screen high, screen low and color RAM.
+                let mut slots: Vec<u8> = vec![background];
+                slots.extend(ranked.iter().take(3).filter(|&&c| counts[c as usize] > 0));
+
+                for row in cell.chunks(4) {
+                    let mut byte = 0u8;
+
+                    for (x, c) in row.iter().enumerate() {
+                        let slot = slots.iter().position(|s| s == c).unwrap_or_else(|| {
+                            let nearest = c64_nearest(C64_PALETTE[*c as usize], &slots);
+
+                            slots.iter().position(|s| *s == nearest).unwrap()
+                        });
+
+                        byte |= (slot as u8) << (6 - x * 2);
+                    }
+
+                    bitmap_out.push(byte);
+                }
+
+                slots.resize(4, 0);
+                screen_out.push(slots[1] << 4 | slots[2]);
+                color_out.push(slots[3]);
+            }
+        }
+
+        color_out.push(background);
+
+        self.w.write_all(&bitmap_out)?;
+
+        match self.screen {
+            Some(ref mut screen) => screen.write_all(&screen_out)?,
+            None => self.w.write_all(&screen_out)?
+        }
+
+        match self.color {
+            Some(ref mut color) => color.write_all(&color_out),
+            None => self.w.write_all(&color_out)
+        }
+    }
+}
+
+/// Decoder for multicolor bitmaps for the C64 platform.
+///
+/// This reads data written by C64MulticolorEncoder. When constructed with
+/// new_split, the bitmap, screen RAM and color RAM (followed by the background
+/// color) are read from separate readers, up to size bytes each. When
+/// constructed with new, they are read from one reader in that order.
+pub struct C64MulticolorDecoder<'a, R: Read + 'a> {
+    r: &'a mut R,
+    screen: Option<&'a mut R>,
+    color: Option<&'a mut R>
+}
+
+impl<'a, R: Read + 'a> C64MulticolorDecoder<'a, R> {
+    pub fn new(read: &'a mut R) -> C64MulticolorDecoder<'a, R> {
+        C64MulticolorDecoder {
+            r: read,
+            screen: None,
+            color: None
+        }
+    }
+
+    pub fn new_split(bitmap: &'a mut R, screen: &'a mut R, color: &'a mut R) -> C64MulticolorDecoder<'a, R> {
+        C64MulticolorDecoder {
+            r: bitmap,
+            screen: Some(screen),
+            color: Some(color)
+        }
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for C64MulticolorDecoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        if width == 0 || !width.is_multiple_of(4) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "C64 multicolor bitmap dimensions must be multiples of 4x8."));
+        }
+
+        let (bitmap, screen, color) = match (self.screen.as_mut(), self.color.as_mut()) {
+            (Some(screen_r), Some(color_r)) => {
+                let (mut screen, mut color) = (Vec::new(), Vec::new());
+
+                screen_r.take(size as u64).read_to_end(&mut screen)?;
+                color_r.take(size as u64).read_to_end(&mut color)?;
+
+                (read_units(self.r, size, 8, "Bitmap data ends partway through a cell")?, screen, color)
+            },
+            _ => {
+                let mut buf = Vec::new();
+
+                self.r.take(size as u64).read_to_end(&mut buf)?;
+
+                let cells = buf.len().saturating_sub(1) / 10;
+
+                if buf.len() != cells * 10 + 1 {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Multicolor data ends partway through a cell"));
+                }
+
+                (buf[..cells * 8].to_vec(), buf[cells * 8..cells * 9].to_vec(), buf[cells * 9..].to_vec())
+            }
+        };
+
+        let cells = bitmap.len() / 8;
+        let row_cells = (width / 4) as usize;
+
+        if cells == 0 || screen.len() != cells || color.len() != cells + 1 || !cells.is_multiple_of(row_cells) {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Multicolor data ends partway through a row of cells"));
+        }
+
+        let background = color[cells];
+
+        Ok(ImageBuffer::from_fn(width, (cells / row_cells) as u32 * 8, |x, y| {
+            let cell = (y / 8) as usize * row_cells + (x / 4) as usize;
+            let slot = (bitmap[cell * 8 + (y % 8) as usize] >> (6 - (x % 4) * 2)) & 0x03;
+            let c = match slot {
+                0 => background,
+                1 => screen[cell] >> 4,
+                2 => screen[cell],
+                _ => color[cell]
+            };
+            let rgb = C64_PALETTE[(c & 0x0F) as usize];
+
+            Rgba([rgb[0], rgb[1], rgb[2], 255])
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
+    use awsmimg::formats::c64::{C64CharsetEncoder, C64MulticolorEncoder};
+
+    fn charset_src() -> Vec<u8> {
+        let mut src : Vec<u8> = vec![0; 64 * 3];
+        src[0] = 1;
+        src[63] = 1;
+        src[128] = 1;
+        src[191] = 1;
+
+        src
+    }
+
+    #[test]
+    fn charset_encode() {
+        let mut test_out = Cursor::new(Vec::with_capacity(24));
+
+        {
+            let mut c64 = C64CharsetEncoder::new(&mut test_out);
+
+            c64.encode_indexes(charset_src(), 8, 24).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+                                       0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                                       0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn charset_dedup_encode() {
+        let mut charset = Cursor::new(Vec::with_capacity(16));
+        let mut screen = Cursor::new(Vec::with_capacity(3));
+
+        {
+            let mut c64 = C64CharsetEncoder::new_deduplicated(&mut charset, &mut screen);
+
+            c64.encode_indexes(charset_src(), 8, 24).unwrap();
+        }
+
+        let valid_charset : Vec<u8> = vec![0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+                                           0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+        assert_eq!(charset.get_ref(), &valid_charset);
+        assert_eq!(screen.get_ref(), &vec![0u8, 1, 0]);
+    }
+
+    #[test]
+    fn palette_encode() {
+        let palette = vec![image::Rgba([0u8, 0, 0, 255]),
+                           image::Rgba([0xFFu8, 0xFF, 0xF0, 255]),
+                           image::Rgba([0x30u8, 0x28, 0x80, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(3));
+
+        {
+            let mut c64 = C64CharsetEncoder::new(&mut test_out);
+
+            c64.encode_palette(palette).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0u8, 1, 6])
+    }
+
+    fn test_image() -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+        image::ImageBuffer::from_fn(8, 8, |x, y| {
+            match (x, y) {
+                (0, 0) => image::Rgba([0xFFu8, 0xFF, 0xFF, 255]),
+                (1, 0) | (2, 0) => image::Rgba([0x68u8, 0x37, 0x2B, 255]),
+                (3, 0) => image::Rgba([0xB8u8, 0xC7, 0x6F, 255]),
+                _ => image::Rgba([0x35u8, 0x28, 0x79, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn multicolor_encode() {
+        let mut test_out = Cursor::new(Vec::with_capacity(21));
+
+        {
+            let mut c64 = C64MulticolorEncoder::new(&mut test_out);
+
+            c64.encode_colors(&test_image()).unwrap();
+        }
+
+        let mut valid_out : Vec<u8> = vec![0; 21];
+        valid_out[0] = 0x97;
+        valid_out[16] = 0x21;
+        valid_out[18] = 0x07;
+        valid_out[20] = 0x06;
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn multicolor_split_encode() {
+        let mut bitmap = Cursor::new(Vec::with_capacity(16));
+        let mut screen = Cursor::new(Vec::with_capacity(2));
+        let mut color = Cursor::new(Vec::with_capacity(3));
+
+        {
+            let mut c64 = C64MulticolorEncoder::new_split(&mut bitmap, &mut screen, &mut color).with_background(0);
+
+            c64.encode_colors(&test_image()).unwrap();
+        }
+
+        assert_eq!(&bitmap.get_ref()[8..16], &[0x55u8; 8]);
+        assert_eq!(screen.get_ref()[1], 0x60);
+        assert_eq!(color.get_ref()[1], 0x00);
+        assert_eq!(color.get_ref()[2], 0x00);
+    }
+}
@@ -0,0 +1,194 @@
+use awsmimg::encoder::DirectGraphicsEncoder;
+use awsmimg::decoder::{DirectGraphicsDecoder, read_units};
+use awsmimg::formats::palette::expand_channel;
+use awsmimg::tiles::morton_tile_order;
+
+use std::io;
+use std::io::{Write, Read};
+use image::{GenericImage, ImageBuffer, Primitive, Pixel, Rgba};
+
+/// The direct-color texture formats supported by CTRTextureEncoder.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CTRTextureFormat {
+    RGBA4444, //16 bits per pixel, little-endian RGBA4444
+    RGB565,   //16 bits per pixel, little-endian RGB565
+    RGBA8     //32 bits per pixel, stored as ABGR bytes
+}
+
+impl CTRTextureFormat {
+    /// The number of bytes each texel occupies.
+    fn texel_bytes(self) -> usize {
+        match self {
+            CTRTextureFormat::RGBA8 => 4,
+            _ => 2
+        }
+    }
+
+    /// Unpack a texel of this format into an 8-bit-per-channel RGBA color.
+    fn unpack(self, texel: &[u8]) -> Rgba<u8> {
+        let word = texel[0] as u16 | (texel[1] as u16) << 8;
+
+        match self {
+            CTRTextureFormat::RGBA4444 => Rgba([expand_channel(word >> 12, 4), expand_channel(word >> 8, 4), expand_channel(word >> 4, 4), expand_channel(word, 4)]),
+            CTRTextureFormat::RGB565 => Rgba([expand_channel(word >> 11, 5), expand_channel(word >> 5, 6), expand_channel(word, 5), 255]),
+            CTRTextureFormat::RGBA8 => Rgba([texel[3], texel[2], texel[1], texel[0]])
+        }
+    }
+}
+
+/// Encoder for direct-color textures for the CTR platform.
+///
+/// Textures are split into 8x8 tiles, stored left to right then top to
+/// bottom, with the texels of each tile stored in Morton order. Texture
+/// dimensions must be multiples of 8.
+///
+/// Images are not flipped. The GPU places the texture origin at the bottom
+/// left, so images meant to be sampled right side up should be flipped
+/// vertically before encoding.
+pub struct CTRTextureEncoder<'a, W: Write + 'a> {
+    w: &'a mut W,
+    format: CTRTextureFormat
+}
+
+impl<'a, W: Write + 'a> CTRTextureEncoder<'a, W> {
+    pub fn new(write: &'a mut W, format: CTRTextureFormat) -> CTRTextureEncoder<'a, W> {
+        CTRTextureEncoder {
+            w: write,
+            format
+        }
+    }
+}
+
+impl<'a, W: Write> DirectGraphicsEncoder for CTRTextureEncoder<'a, W> {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        let (width, height) = image.dimensions();
+
+        if !width.is_multiple_of(8) || !height.is_multiple_of(8) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "CTR texture dimensions must be multiples of 8."));
+        }
+
+        let imgmax = S::max_value().to_f32().unwrap();
+        let mut out: Vec<u8> = Vec::with_capacity((width * height * 4) as usize);
+
+        for (x, y) in morton_tile_order(width, height, 8) {
+            let rgba = image.get_pixel(x, y).to_rgba();
+            let c: Vec<u16> = (0..4).map(|i| (rgba[i].to_f32().unwrap() / imgmax * 255f32) as u16).collect();
+
+            match self.format {
+                CTRTextureFormat::RGBA4444 => {
+                    let enc_color: u16 = (c[0] & 0xF0) << 8 | (c[1] & 0xF0) << 4 | (c[2] & 0xF0) | c[3] >> 4;
+
+                    out.push((enc_color & 0xFF) as u8);
+                    out.push((enc_color >> 8) as u8);
+                },
+                CTRTextureFormat::RGB565 => {
+                    let enc_color: u16 = (c[0] & 0xF8) << 8 | (c[1] & 0xFC) << 3 | c[2] >> 3;
+
+                    out.push((enc_color & 0xFF) as u8);
+                    out.push((enc_color >> 8) as u8);
+                },
+                CTRTextureFormat::RGBA8 => {
+                    out.extend(c.iter().rev().map(|v| *v as u8));
+                }
+            }
+        }
+
+        self.w.write_all(&out)
+    }
+}
+
+/// Decoder for direct-color textures for the CTR platform.
+///
+/// This reads data written by CTRTextureEncoder, which must hold a whole
+/// number of rows of 8x8 tiles.
+pub struct CTRTextureDecoder<'a, R: Read + 'a> {
+    r: &'a mut R,
+    format: CTRTextureFormat
+}
+
+impl<'a, R: Read + 'a> CTRTextureDecoder<'a, R> {
+    pub fn new(read: &'a mut R, format: CTRTextureFormat) -> CTRTextureDecoder<'a, R> {
+        CTRTextureDecoder {
+            r: read,
+            format
+        }
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for CTRTextureDecoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        if width == 0 || !width.is_multiple_of(8) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "CTR texture dimensions must be multiples of 8."));
+        }
+
+        let row_bytes = width as usize * 8 * self.format.texel_bytes();
+        let buf = read_units(self.r, size, row_bytes, "CTR texture data ends partway through a row of tiles")?;
+        let height = (buf.len() / row_bytes) as u32 * 8;
+        let mut image = ImageBuffer::new(width, height);
+
+        for ((x, y), texel) in morton_tile_order(width, height, 8).into_iter().zip(buf.chunks(self.format.texel_bytes())) {
+            image.put_pixel(x, y, self.format.unpack(texel));
+        }
+
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::DirectGraphicsEncoder;
+    use awsmimg::formats::ctr::{CTRTextureEncoder, CTRTextureFormat};
+
+    fn test_image() -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+        image::ImageBuffer::from_fn(8, 8, |x, y| {
+            match (x, y) {
+                (0, 1) => image::Rgba([255u8, 0, 0, 255]),
+                (1, 0) => image::Rgba([0u8, 255, 0, 255]),
+                (7, 7) => image::Rgba([0u8, 0, 255, 128]),
+                _ => image::Rgba([0u8, 0, 0, 0])
+            }
+        })
+    }
+
+    fn encode(format: CTRTextureFormat) -> Vec<u8> {
+        let mut test_out = Cursor::new(Vec::new());
+
+        {
+            let mut ctr = CTRTextureEncoder::new(&mut test_out, format);
+
+            ctr.encode_colors(&test_image()).unwrap();
+        }
+
+        test_out.into_inner()
+    }
+
+    #[test]
+    fn rgba4444_encode() {
+        let mut valid_out : Vec<u8> = vec![0; 128];
+        valid_out[2..6].copy_from_slice(&[0x0F, 0x0F, 0x0F, 0xF0]);
+        valid_out[126..128].copy_from_slice(&[0xF8, 0x00]);
+
+        assert_eq!(encode(CTRTextureFormat::RGBA4444), valid_out);
+    }
+
+    #[test]
+    fn rgb565_encode() {
+        let mut valid_out : Vec<u8> = vec![0; 128];
+        valid_out[2..6].copy_from_slice(&[0xE0, 0x07, 0x00, 0xF8]);
+        valid_out[126..128].copy_from_slice(&[0x1F, 0x00]);
+
+        assert_eq!(encode(CTRTextureFormat::RGB565), valid_out);
+    }
+
+    #[test]
+    fn rgba8_encode() {
+        let mut valid_out : Vec<u8> = vec![0; 256];
+        valid_out[4..12].copy_from_slice(&[0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0x00, 0xFF]);
+        valid_out[252..256].copy_from_slice(&[0x80, 0xFF, 0x00, 0x00]);
+
+        assert_eq!(encode(CTRTextureFormat::RGBA8), valid_out);
+    }
+}
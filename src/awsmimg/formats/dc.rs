@@ -0,0 +1,349 @@
+use awsmimg::encoder::DirectGraphicsEncoder;
+use awsmimg::decoder::{DirectGraphicsDecoder, read_units};
+use awsmimg::formats::palette::expand_channel;
+use awsmimg::tiles::morton_tile_order;
+
+use std::io;
+use std::io::{Write, Read};
+use image::{GenericImage, ImageBuffer, Primitive, Pixel, Rgba};
+
+/// The direct-color texture formats supported by DCTextureEncoder.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DCTextureFormat {
+    ARGB1555, //16 bits per pixel, little-endian ARGB1555
+    RGB565,   //16 bits per pixel, little-endian RGB565
+    ARGB4444  //16 bits per pixel, little-endian ARGB4444
+}
+
+impl DCTextureFormat {
+    /// Pack an 8-bit-per-channel RGBA color into a texel of this format.
+    fn pack(self, c: [u16; 4]) -> u16 {
+        match self {
+            DCTextureFormat::ARGB1555 => (c[3] & 0x80) << 8 | (c[0] & 0xF8) << 7 | (c[1] & 0xF8) << 2 | c[2] >> 3,
+            DCTextureFormat::RGB565 => (c[0] & 0xF8) << 8 | (c[1] & 0xFC) << 3 | c[2] >> 3,
+            DCTextureFormat::ARGB4444 => (c[3] & 0xF0) << 8 | (c[0] & 0xF0) << 4 | (c[1] & 0xF0) | c[2] >> 4
+        }
+    }
+
+    /// Unpack a texel of this format into an 8-bit-per-channel RGBA color.
+    fn unpack(self, texel: u16) -> Rgba<u8> {
+        match self {
+            DCTextureFormat::ARGB1555 => Rgba([expand_channel(texel >> 10, 5), expand_channel(texel >> 5, 5), expand_channel(texel, 5), if texel & 0x8000 != 0 { 255 } else { 0 }]),
+            DCTextureFormat::RGB565 => Rgba([expand_channel(texel >> 11, 5), expand_channel(texel >> 5, 6), expand_channel(texel, 5), 255]),
+            DCTextureFormat::ARGB4444 => Rgba([expand_channel(texel >> 8, 4), expand_channel(texel >> 4, 4), expand_channel(texel, 4), expand_channel(texel >> 12, 4)])
+        }
+    }
+}
+
+/// List the pixel coordinates of an image in PowerVR twiddled order.
+///
+/// Twiddling is Morton order with the y coordinate in the low bit. Square
+/// textures are twiddled as a whole; rectangular textures are split into
+/// squares the size of their shorter side, stored one after another.
+fn twiddle_order(width: u32, height: u32) -> Vec<(u32, u32)> {
+    morton_tile_order(height, width, width.min(height)).into_iter().map(|(y, x)| (x, y)).collect()
+}
+
+/// Number of entries in a VQ codebook.
+const VQ_CODEBOOK_SIZE: usize = 256;
+
+/// Number of refinement passes used when generating a VQ codebook.
+const VQ_ITERATIONS: usize = 8;
+
+fn vq_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+fn vq_nearest(codebook: &[Vec<f32>], v: &[f32]) -> usize {
+    (0..codebook.len()).min_by(|&i, &j| vq_distance(&codebook[i], v).partial_cmp(&vq_distance(&codebook[j], v)).unwrap()).unwrap()
+}
+
+/// Generate a codebook for a set of vectors using k-means refinement.
+///
+/// If there are no more unique vectors than codebook entries, the codebook
+/// holds every unique vector and the result is lossless. Otherwise, the
+/// codebook is seeded with evenly spaced unique vectors and refined.
+fn vq_codebook(vectors: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    let mut unique: Vec<Vec<f32>> = Vec::new();
+
+    for v in vectors {
+        if !unique.contains(v) {
+            unique.push(v.clone());
+        }
+    }
+
+    if unique.len() <= VQ_CODEBOOK_SIZE {
+        return unique;
+    }
+
+    let step = unique.len() as f32 / VQ_CODEBOOK_SIZE as f32;
+    let mut codebook: Vec<Vec<f32>> = (0..VQ_CODEBOOK_SIZE).map(|i| unique[(i as f32 * step) as usize].clone()).collect();
+
+    for _ in 0..VQ_ITERATIONS {
+        let mut sums: Vec<Vec<f32>> = vec![vec![0.0; vectors[0].len()]; VQ_CODEBOOK_SIZE];
+        let mut counts: Vec<u32> = vec![0; VQ_CODEBOOK_SIZE];
+
+        for v in vectors {
+            let nearest = vq_nearest(&codebook, v);
+
+            for (sum, x) in sums[nearest].iter_mut().zip(v.iter()) {
+                *sum += x;
+            }
+
+            counts[nearest] += 1;
+        }
+
+        for (i, entry) in codebook.iter_mut().enumerate() {
+            if counts[i] > 0 {
+                *entry = sums[i].iter().map(|s| s / counts[i] as f32).collect();
+            }
+        }
+    }
+
+    codebook
+}
+
+/// Encoder for twiddled textures for the DC platform.
+///
+/// Texels are written as little-endian 16-bit words in twiddled order.
+/// Texture dimensions must be powers of two.
+///
+/// With VQ compression enabled by with_vq, the texture is instead written as
+/// a 2048-byte codebook of 256 2x2 texel blocks followed by one byte per
+/// block, with the blocks in twiddled order. The four texels of each codebook
+/// entry are also stored in twiddled order. Codebooks are generated by
+/// k-means clustering when the texture has more than 256 unique blocks.
+pub struct DCTextureEncoder<'a, W: Write + 'a> {
+    w: &'a mut W,
+    format: DCTextureFormat,
+    vq: bool
+}
+
+impl<'a, W: Write + 'a> DCTextureEncoder<'a, W> {
+    pub fn new(write: &'a mut W, format: DCTextureFormat) -> DCTextureEncoder<'a, W> {
+        DCTextureEncoder {
+            w: write,
+            format,
+            vq: false
+        }
+    }
+
+    pub fn with_vq(self, vq: bool) -> DCTextureEncoder<'a, W> {
+        DCTextureEncoder {
+            vq,
+            ..self
+        }
+    }
+}
+
+impl<'a, W: Write> DirectGraphicsEncoder for DCTextureEncoder<'a, W> {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        let (width, height) = image.dimensions();
+
+        if !width.is_power_of_two() || !height.is_power_of_two() || (self.vq && (width < 2 || height < 2)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "DC texture dimensions must be powers of two."));
+        }
+
+        let imgmax = S::max_value().to_f32().unwrap();
+        let color = |x: u32, y: u32| -> [u16; 4] {
+            let rgba = image.get_pixel(x, y).to_rgba();
+            let mut c = [0u16; 4];
+
+            for (i, v) in c.iter_mut().enumerate() {
+                *v = (rgba[i].to_f32().unwrap() / imgmax * 255f32) as u16;
+            }
+
+            c
+        };
+        let mut out: Vec<u8> = Vec::new();
+
+        if self.vq {
+            let blocks = twiddle_order(width / 2, height / 2);
+            let vectors: Vec<Vec<f32>> = blocks.iter().map(|&(bx, by)| {
+                twiddle_order(2, 2).iter().flat_map(|&(x, y)| color(bx * 2 + x, by * 2 + y).to_vec()).map(|v| v as f32).collect()
+            }).collect();
+            let codebook = vq_codebook(&vectors);
+
+            for i in 0..VQ_CODEBOOK_SIZE {
+                for texel in 0..4 {
+                    let mut c = [0u16; 4];
+
+                    if let Some(entry) = codebook.get(i) {
+                        for (ch, v) in c.iter_mut().enumerate() {
+                            *v = entry[texel * 4 + ch].round() as u16;
+                        }
+                    }
+
+                    let texel = self.format.pack(c);
+
+                    out.push((texel & 0xFF) as u8);
+                    out.push((texel >> 8) as u8);
+                }
+            }
+
+            for v in vectors.iter() {
+                out.push(vq_nearest(&codebook, v) as u8);
+            }
+        } else {
+            for (x, y) in twiddle_order(width, height) {
+                let texel = self.format.pack(color(x, y));
+
+                out.push((texel & 0xFF) as u8);
+                out.push((texel >> 8) as u8);
+            }
+        }
+
+        self.w.write_all(&out)
+    }
+}
+
+/// Decoder for twiddled textures for the DC platform.
+///
+/// This reads data written by DCTextureEncoder with the same format and VQ
+/// setting. The height of the texture is taken from the amount of data read,
+/// and must be a power of two.
+pub struct DCTextureDecoder<'a, R: Read + 'a> {
+    r: &'a mut R,
+    format: DCTextureFormat,
+    vq: bool
+}
+
+impl<'a, R: Read + 'a> DCTextureDecoder<'a, R> {
+    pub fn new(read: &'a mut R, format: DCTextureFormat) -> DCTextureDecoder<'a, R> {
+        DCTextureDecoder {
+            r: read,
+            format,
+            vq: false
+        }
+    }
+
+    pub fn with_vq(self, vq: bool) -> DCTextureDecoder<'a, R> {
+        DCTextureDecoder {
+            vq,
+            ..self
+        }
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for DCTextureDecoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        let buf = read_units(self.r, size, 2, "DC texture data ends partway through a texel")?;
+        let texels: Vec<Rgba<u8>> = match self.vq {
+            false => buf.chunks(2).map(|t| self.format.unpack(t[0] as u16 | (t[1] as u16) << 8)).collect(),
+            true => {
+                if buf.len() < VQ_CODEBOOK_SIZE * 8 {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "VQ texture data ends partway through the codebook"));
+                }
+
+                let (codebook, indexes) = buf.split_at(VQ_CODEBOOK_SIZE * 8);
+
+                indexes.iter().flat_map(|&i| codebook[i as usize * 8..i as usize * 8 + 8].chunks(2).map(|t| self.format.unpack(t[0] as u16 | (t[1] as u16) << 8)).collect::<Vec<_>>()).collect()
+            }
+        };
+        let height = match width {
+            0 => 0,
+            _ => (texels.len() / width as usize) as u32
+        };
+
+        if !width.is_power_of_two() || !height.is_power_of_two() || (width * height) as usize != texels.len() || (self.vq && (width < 2 || height < 2)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "DC texture dimensions must be powers of two."));
+        }
+
+        let order: Vec<(u32, u32)> = match self.vq {
+            false => twiddle_order(width, height),
+            true => twiddle_order(width / 2, height / 2).into_iter().flat_map(|(bx, by)| twiddle_order(2, 2).into_iter().map(move |(x, y)| (bx * 2 + x, by * 2 + y))).collect()
+        };
+        let mut image = ImageBuffer::new(width, height);
+
+        for ((x, y), rgba) in order.into_iter().zip(texels) {
+            image.put_pixel(x, y, rgba);
+        }
+
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::DirectGraphicsEncoder;
+    use awsmimg::decoder::DirectGraphicsDecoder;
+    use awsmimg::formats::dc::{DCTextureEncoder, DCTextureDecoder, DCTextureFormat};
+
+    #[test]
+    fn twiddled_rgb565_encode() {
+        let img = image::ImageBuffer::from_fn(4, 2, |x, y| {
+            image::Rgba([0u8, 0, (y * 4 + x) as u8 * 8, 255])
+        });
+        let mut test_out = Cursor::new(Vec::with_capacity(16));
+
+        {
+            let mut dc = DCTextureEncoder::new(&mut test_out, DCTextureFormat::RGB565);
+
+            dc.encode_colors(&img).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0, 0, 4, 0, 1, 0, 5, 0,
+                                       2, 0, 6, 0, 3, 0, 7, 0];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn twiddled_argb_encode() {
+        let img = image::ImageBuffer::from_fn(1, 1, |_, _| image::Rgba([255u8, 0, 0, 255]));
+        let mut argb1555 = Cursor::new(Vec::with_capacity(2));
+        let mut argb4444 = Cursor::new(Vec::with_capacity(2));
+
+        DCTextureEncoder::new(&mut argb1555, DCTextureFormat::ARGB1555).encode_colors(&img).unwrap();
+        DCTextureEncoder::new(&mut argb4444, DCTextureFormat::ARGB4444).encode_colors(&img).unwrap();
+
+        assert_eq!(argb1555.get_ref(), &vec![0x00u8, 0xFC]);
+        assert_eq!(argb4444.get_ref(), &vec![0x00u8, 0xFF]);
+    }
+
+    #[test]
+    fn vq_encode() {
+        let img = image::ImageBuffer::from_fn(4, 4, |x, _| {
+            match x {
+                0 | 1 => image::Rgba([0u8, 0, 255, 255]),
+                _ => image::Rgba([255u8, 0, 0, 255])
+            }
+        });
+        let mut test_out = Cursor::new(Vec::new());
+
+        {
+            let mut dc = DCTextureEncoder::new(&mut test_out, DCTextureFormat::RGB565).with_vq(true);
+
+            dc.encode_colors(&img).unwrap();
+        }
+
+        let out = test_out.get_ref();
+
+        assert_eq!(out.len(), 2048 + 4);
+        assert_eq!(&out[0..8], &[0x1F, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x1F, 0x00]);
+        assert_eq!(&out[8..16], &[0x00, 0xF8, 0x00, 0xF8, 0x00, 0xF8, 0x00, 0xF8]);
+        assert_eq!(&out[2048..], &[0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn twiddled_decode() {
+        let img = image::ImageBuffer::from_fn(8, 8, |x, y| {
+            image::Rgba([(x * 0x21) as u8 | 0x84, (y % 2 * 0xFF) as u8, 0xFFu8, 255u8])
+        });
+
+        for vq in &[false, true] {
+            let mut tex = Vec::new();
+
+            DCTextureEncoder::new(&mut tex, DCTextureFormat::RGB565).with_vq(*vq).encode_colors(&img).unwrap();
+
+            let decoded = DCTextureDecoder::new(&mut &tex[..], DCTextureFormat::RGB565).with_vq(*vq).decode_colors(8, tex.len()).unwrap();
+
+            assert_eq!(decoded.into_raw(), img.clone().into_raw());
+        }
+
+        assert!(DCTextureDecoder::new(&mut &[0u8; 24][..], DCTextureFormat::RGB565).decode_colors(3, 24).is_err());
+    }
+}
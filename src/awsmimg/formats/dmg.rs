@@ -0,0 +1,491 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat};
+use awsmimg::formats::generic::{PlanarEncoder, PlaneInterleave};
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::IndexedGraphicsDecoder;
+
+use std::io;
+use std::io::{Write, Read};
+use image::{Primitive, Rgba};
+
+/// How the two bitplanes of a 2bpp tile are arranged.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GB2PlaneLayout {
+    Interleaved, //Both planes of a row, then the next row, as on the DMG
+    Separated    //Every row of the low plane, then every row of the high plane, as on the NES
+}
+
+/// Encode index data as interleaved-bitplane 2bpp rows.
+fn encode_rows<'a, P: Primitive, W: Write + 'a>(w: &'a mut W, data: &[P]) -> io::Result<()> {
+    let mut out: [u8; 2] = [0, 0];
+
+    for row in data.chunks(8) {
+        out[0] = 0;
+        out[1] = 0;
+
+        for (x, index) in row.iter().enumerate() {
+            let index = index.to_u8().unwrap();
+
+            out[0] |= (index & 0x01) << (7 - x);
+            out[1] |= ((index >> 1) & 0x01) << (7 - x);
+        }
+
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Encode a series of RGBA colors as DMG palette register values
+/// (BGP/OBP0/OBP1).
+///
+/// Each color is reduced to one of the four DMG shades by luminance, with
+/// white being shade 0 and black being shade 3. Four colors are packed into
+/// each byte, with the first color in the least significant bits.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value().to_f32().unwrap();
+    let palette: Vec<Rgba<T>> = palette.collect();
+
+    for colors in palette.chunks(4) {
+        let mut out: [u8; 1] = [0];
+
+        for (i, rgba) in colors.iter().enumerate() {
+            let r = rgba[0].to_f32().unwrap() / imgmax;
+            let g = rgba[1].to_f32().unwrap() / imgmax;
+            let b = rgba[2].to_f32().unwrap() / imgmax;
+            let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            let shade = ((1.0 - luma) * 3.0).round() as u8;
+
+            out[0] |= (shade & 0x03) << (i * 2);
+        }
+
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode DMG palette register values written by encode_palette into RGBA
+/// colors.
+///
+/// Each byte yields four colors, first color from the least significant
+/// bits, with shade 0 decoded as white and shade 3 as black.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    let mut buf = Vec::new();
+
+    r.take(size as u64).read_to_end(&mut buf)?;
+
+    Ok(buf.iter().flat_map(|byte| (0..4).map(move |i| {
+        let gray = 255 - ((byte >> (i * 2)) & 0x03) * 85;
+
+        Rgba([gray, gray, gray, 255])
+    })).collect())
+}
+
+/// Decode 2bpp tiles in either plane layout into index data.
+///
+/// Up to size bytes are read, and must hold a whole number of tiles. Other
+/// platforms which use either layout, such as the NES, share this function.
+pub fn decode_2bpp_tiles<R: Read, P: Primitive>(r: &mut R, size: usize, layout: GB2PlaneLayout) -> io::Result<Vec<P>> {
+    let mut buf = Vec::new();
+
+    r.take(size as u64).read_to_end(&mut buf)?;
+
+    if !buf.len().is_multiple_of(16) {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "2bpp data ends partway through a tile"));
+    }
+
+    let mut out = Vec::with_capacity(buf.len() * 4);
+
+    for tile in buf.chunks(16) {
+        for row in 0..8 {
+            let (lo, hi) = match layout {
+                GB2PlaneLayout::Interleaved => (tile[row * 2], tile[row * 2 + 1]),
+                GB2PlaneLayout::Separated => (tile[row], tile[row + 8])
+            };
+
+            for x in 0..8 {
+                out.push(P::from((lo >> (7 - x)) & 0x01 | ((hi >> (7 - x)) & 0x01) << 1).unwrap());
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Encoder for 2bpp tile patterns for the DMG platform.
+///
+/// Each 8x8 tile is stored as eight rows of two bytes. The first byte of each
+/// row holds the low bit of every pixel's index and the second byte holds the
+/// high bit. The leftmost pixel of a row is stored in the most significant bit
+/// of each byte.
+///
+/// The same tiles can also be read or written with the bitplanes separated,
+/// as in NES CHR data, by selecting GB2PlaneLayout::Separated with
+/// with_plane_layout.
+///
+/// Palettes are written as DMG palette register values unless another format
+/// is given with with_palette_format.
+pub struct GB2Encoder<'a, F: 'a> {
+    f: &'a mut F,
+    layout: GB2PlaneLayout,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> GB2Encoder<'a, F> {
+    pub fn new(file: &'a mut F) -> GB2Encoder<'a, F> {
+        GB2Encoder {
+            f: file,
+            layout: GB2PlaneLayout::Interleaved,
+            palette_format: PaletteColorFormat::DMG
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as DMG palette
+    /// register values.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> GB2Encoder<'a, F> {
+        GB2Encoder {
+            palette_format,
+            ..self
+        }
+    }
+
+    pub fn with_plane_layout(self, layout: GB2PlaneLayout) -> GB2Encoder<'a, F> {
+        GB2Encoder {
+            layout,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for GB2Encoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        3
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for GB2Encoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        match self.layout {
+            GB2PlaneLayout::Interleaved => encode_rows(self.f, &data),
+            GB2PlaneLayout::Separated => PlanarEncoder::new(self.f, 2, PlaneInterleave::Tile)?.encode_indexes(data, 8, 8)
+        }
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for GB2Encoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        decode_2bpp_tiles(self.f, size, self.layout)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(16)
+    }
+}
+
+/// Encoder for 2bpp tile patterns for the CGB platform.
+///
+/// Tile data is identical to the DMG format, but palettes are written as
+/// 15-bit BGR colors in the same layout as the AGB palette writer.
+///
+/// The CGB has two banks of VRAM tile memory. When constructed with
+/// new_banked, the first tiles_per_bank tiles are written to the bank 0
+/// writer and the remainder to the bank 1 writer. A bank assignment table is
+/// then written with one byte per tile, holding the VRAM bank bit (0x08) of
+/// the CGB BG map attribute byte for that tile, ready to be OR'd into an
+/// attribute map.
+///
+/// Palettes are written to the tile writer unless a separate palette writer
+/// is given with with_palette. Banked encoders have no writer of their own to
+/// spare for palettes, so they refuse to encode one without it.
+pub struct CGB2Encoder<'a, F: 'a> {
+    f: &'a mut F,
+    bank1: Option<&'a mut F>,
+    banktable: Option<&'a mut F>,
+    palette: Option<&'a mut F>,
+    palette_format: PaletteColorFormat,
+    tiles_per_bank: usize
+}
+
+impl<'a, F: 'a> CGB2Encoder<'a, F> {
+    pub fn new(file: &'a mut F) -> CGB2Encoder<'a, F> {
+        CGB2Encoder {
+            f: file,
+            bank1: None,
+            banktable: None,
+            palette: None,
+            palette_format: PaletteColorFormat::BGR555,
+            tiles_per_bank: usize::MAX
+        }
+    }
+
+    pub fn new_banked(bank0: &'a mut F, bank1: &'a mut F, banktable: &'a mut F, tiles_per_bank: usize) -> CGB2Encoder<'a, F> {
+        CGB2Encoder {
+            f: bank0,
+            bank1: Some(bank1),
+            banktable: Some(banktable),
+            palette: None,
+            palette_format: PaletteColorFormat::BGR555,
+            tiles_per_bank
+        }
+    }
+
+    pub fn with_palette(self, palette: &'a mut F) -> CGB2Encoder<'a, F> {
+        CGB2Encoder {
+            palette: Some(palette),
+            ..self
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as BGR555.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> CGB2Encoder<'a, F> {
+        CGB2Encoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for CGB2Encoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        3
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for CGB2Encoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        let tiles: Vec<&[P]> = data.chunks(64).collect();
+        let tiles_per_bank = self.tiles_per_bank;
+        let bank1_tiles = tiles.len().saturating_sub(tiles_per_bank);
+
+        if bank1_tiles > 0 && (self.bank1.is_none() || bank1_tiles > tiles_per_bank) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image has more tiles than fit in the available VRAM banks."));
+        }
+
+        for (i, tile) in tiles.iter().enumerate() {
+            match self.bank1 {
+                Some(ref mut bank1) if i >= tiles_per_bank => encode_rows(*bank1, tile)?,
+                _ => encode_rows(self.f, tile)?
+            }
+        }
+
+        if let Some(ref mut banktable) = self.banktable {
+            let table: Vec<u8> = (0..tiles.len()).map(|i| if i >= tiles_per_bank { 0x08 } else { 0x00 }).collect();
+
+            banktable.write_all(&table)?;
+        }
+
+        Ok(())
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        match (&mut self.palette, &self.bank1) {
+            (Some(ref mut w), _) => self.palette_format.encode_colors(*w, palette.into_iter()),
+            (None, Some(_)) => Err(io::Error::new(io::ErrorKind::InvalidInput, "Banked CGB encoders need a separate palette writer.")),
+            (None, None) => self.palette_format.encode_colors(self.f, palette.into_iter())
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for CGB2Encoder<'a, F> where F: Read {
+    /// Decode tile data from the tile reader.
+    ///
+    /// Banked decoders only read bank 0; decode bank 1 with a decoder of its
+    /// own.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        decode_2bpp_tiles(self.f, size, GB2PlaneLayout::Interleaved)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        match self.palette {
+            Some(ref mut r) => self.palette_format.decode_colors(*r, size),
+            None => self.palette_format.decode_colors(self.f, size)
+        }
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::decoder::IndexedGraphicsDecoder;
+    use awsmimg::formats::dmg::{GB2Encoder, GB2PlaneLayout, CGB2Encoder};
+    use awsmimg::formats::nes::NesChrEncoder;
+    use awsmimg::formats::palette::PaletteColorFormat;
+
+    #[test]
+    fn data2_encode() {
+        let src : Vec<u8> = vec![0,1,2,3,0,1,2,3,
+                                 3,3,3,3,0,0,0,0,
+                                 1,1,1,1,1,1,1,1,
+                                 2,2,2,2,2,2,2,2,
+                                 0,0,0,0,0,0,0,0,
+                                 3,2,1,0,3,2,1,0,
+                                 0,0,0,0,0,0,0,3,
+                                 3,0,0,0,0,0,0,0];
+        let mut test_out = Cursor::new(Vec::with_capacity(16));
+
+        {
+            let mut gb2 = GB2Encoder::new(&mut test_out);
+
+            gb2.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x55, 0x33, 0xF0, 0xF0, 0xFF, 0x00, 0x00, 0xFF,
+                                       0x00, 0x00, 0xAA, 0xCC, 0x01, 0x01, 0x80, 0x80];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn palette_encode() {
+        let palette = vec![image::Rgba([255u8, 255, 255, 255]),
+                           image::Rgba([170u8, 170, 170, 255]),
+                           image::Rgba([85u8, 85, 85, 255]),
+                           image::Rgba([0u8, 0, 0, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(1));
+
+        {
+            let mut gb2 = GB2Encoder::new(&mut test_out);
+
+            gb2.encode_palette(palette).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0xE4u8])
+    }
+
+    #[test]
+    fn cgb_banked_encode() {
+        let mut src : Vec<u8> = vec![1; 64];
+        src.extend(vec![2; 64]);
+        src.extend(vec![3; 64]);
+        let mut bank0 = Cursor::new(Vec::new());
+        let mut bank1 = Cursor::new(Vec::new());
+        let mut banktable = Cursor::new(Vec::new());
+
+        {
+            let mut cgb2 = CGB2Encoder::new_banked(&mut bank0, &mut bank1, &mut banktable, 2);
+
+            cgb2.encode_indexes(src, 24, 8).unwrap();
+        }
+
+        let mut valid_bank0 : Vec<u8> = [0xFF, 0x00].repeat(8);
+        valid_bank0.extend([0x00, 0xFF].repeat(8));
+        let valid_bank1 : Vec<u8> = [0xFF, 0xFF].repeat(8);
+
+        assert_eq!(bank0.get_ref(), &valid_bank0);
+        assert_eq!(bank1.get_ref(), &valid_bank1);
+        assert_eq!(banktable.get_ref(), &vec![0x00u8, 0x00, 0x08]);
+    }
+
+    #[test]
+    fn cgb_bank_overflow() {
+        let src : Vec<u8> = vec![0; 64 * 3];
+        let mut bank0 = Cursor::new(Vec::new());
+        let mut bank1 = Cursor::new(Vec::new());
+        let mut banktable = Cursor::new(Vec::new());
+        let mut cgb2 = CGB2Encoder::new_banked(&mut bank0, &mut bank1, &mut banktable, 1);
+
+        assert!(cgb2.encode_indexes(src, 24, 8).is_err());
+    }
+
+    #[test]
+    fn cgb_palette_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]), image::Rgba([0u8, 0, 255, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(4));
+
+        {
+            let mut cgb2 = CGB2Encoder::new(&mut test_out);
+
+            cgb2.encode_palette(palette.clone()).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x1Fu8, 0x00, 0x00, 0x7C]);
+
+        let mut amiga_out = Cursor::new(Vec::new());
+
+        CGB2Encoder::new(&mut amiga_out).with_palette_format(PaletteColorFormat::Amiga).encode_palette(palette).unwrap();
+
+        assert_eq!(amiga_out.get_ref(), &vec![0x0Fu8, 0x00, 0x00, 0x0F])
+    }
+
+    #[test]
+    fn cgb_banked_palette() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255])];
+        let mut bank0 = Cursor::new(Vec::new());
+        let mut bank1 = Cursor::new(Vec::new());
+        let mut banktable = Cursor::new(Vec::new());
+        let mut pal = Cursor::new(Vec::new());
+
+        assert!(CGB2Encoder::new_banked(&mut bank0, &mut bank1, &mut banktable, 1).encode_palette(palette.clone()).is_err());
+
+        CGB2Encoder::new_banked(&mut bank0, &mut bank1, &mut banktable, 1).with_palette(&mut pal).encode_palette(palette).unwrap();
+
+        assert!(bank0.get_ref().is_empty());
+        assert_eq!(pal.get_ref(), &vec![0x1Fu8, 0x00]);
+    }
+
+    #[test]
+    fn data2_decode_layouts() {
+        let src : Vec<u8> = (0..128).map(|i| (i * 5 % 4) as u8).collect();
+        let mut gb_out = Cursor::new(Vec::new());
+        let mut nes_out = Cursor::new(Vec::new());
+
+        GB2Encoder::new(&mut gb_out).encode_indexes(src.clone(), 16, 8).unwrap();
+        NesChrEncoder::new(&mut nes_out).encode_indexes(src.clone(), 16, 8).unwrap();
+        gb_out.set_position(0);
+        nes_out.set_position(0);
+
+        let gb_decoded : Vec<u8> = GB2Encoder::new(&mut gb_out).decode_indexes(usize::MAX).unwrap();
+        let nes_decoded : Vec<u8> = GB2Encoder::new(&mut nes_out).with_plane_layout(GB2PlaneLayout::Separated).decode_indexes(usize::MAX).unwrap();
+        let mut separated_out = Cursor::new(Vec::new());
+
+        GB2Encoder::new(&mut separated_out).with_plane_layout(GB2PlaneLayout::Separated).encode_indexes(src.clone(), 16, 8).unwrap();
+
+        assert_eq!(gb_decoded, src);
+        assert_eq!(nes_decoded, src);
+        assert_eq!(separated_out.get_ref(), nes_out.get_ref());
+        assert!(GB2Encoder::new(&mut Cursor::new(vec![0u8; 17])).decode_indexes::<u8>(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn palette_decode() {
+        let mut test_in = Cursor::new(vec![0xE4u8]);
+        let palette = GB2Encoder::new(&mut test_in).decode_palette(usize::MAX).unwrap();
+
+        assert_eq!(palette, vec![image::Rgba([255u8, 255, 255, 255]),
+                                 image::Rgba([170u8, 170, 170, 255]),
+                                 image::Rgba([85u8, 85, 85, 255]),
+                                 image::Rgba([0u8, 0, 0, 255])]);
+    }
+}
@@ -0,0 +1,894 @@
+use awsmimg::formats::{IndexedGraphicsProperties, DEFAULT_ALPHA_THRESHOLD};
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat};
+use awsmimg::formats::palette::expand_channel;
+use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
+use awsmimg::decoder::{IndexedGraphicsDecoder, DirectGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{GenericImage, ImageBuffer, Primitive, Pixel, Rgba};
+
+/// The order in which pixels are packed into each byte.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PixelOrder {
+    LsbFirst, //Leftmost pixel in the least significant bits
+    MsbFirst  //Leftmost pixel in the most significant bits
+}
+
+/// How the bitplanes of a planar image are interleaved.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PlaneInterleave {
+    Row,  //Every plane of a row, then the next row
+    Tile, //Every row of a plane, then the next plane, for each tile
+    Frame //Every row of the whole image for a plane, then the next plane
+}
+
+/// The unit that bitplane data is grouped into.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PlaneUnit {
+    Byte,   //8 pixels per byte
+    WordBE, //16 pixels per big-endian word
+    WordLE  //16 pixels per little-endian word
+}
+
+/// The order of the color channels within a direct color value, from most
+/// significant to least significant.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ChannelOrder {
+    RGB, //Red in the most significant bits, blue in the least
+    BGR  //Blue in the most significant bits, red in the least
+}
+
+/// The bit depths of the channels of a 16-bit direct color value.
+///
+/// Alpha, where present, is always stored in the most significant bits.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Color16Depth {
+    Depth555,  //5 bits per color channel, top bit clear
+    Depth565,  //5 bits of red and blue, 6 bits of green
+    Depth1555, //1 bit of alpha, 5 bits per color channel
+    Depth4444  //4 bits of alpha, 4 bits per color channel
+}
+
+/// The byte order of multi-byte values.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ByteOrder {
+    BigEndian,
+    LittleEndian
+}
+
+/// Encoder for packed-pixel graphics of any common bit depth.
+///
+/// Indexes are packed into bytes at 1, 2, 4 or 8 bits per pixel, in the
+/// given pixel order, one row after another. By default the image is encoded
+/// untiled; with_tile_size encodes it as tiles, left to right then top to
+/// bottom. This covers one-off formats that have no encoder of their own; for
+/// example, AGB4Encoder is equivalent to a 4bpp, LSB-first encoder with 8x8
+/// tiles.
+///
+/// Palettes are written as 15-bit BGR words, as with the AGB palette writer,
+/// unless another format is given with with_palette_format.
+pub struct PackedEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    bpp: u8,
+    order: PixelOrder,
+    tsize: (u32, u32),
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> PackedEncoder<'a, F> {
+    pub fn new(file: &'a mut F, bpp: u8, order: PixelOrder) -> PackedEncoder<'a, F> {
+        PackedEncoder {
+            f: file,
+            bpp,
+            order,
+            tsize: (1, 1),
+            palette_format: PaletteColorFormat::BGR555
+        }
+    }
+
+    pub fn with_tile_size(self, tsize: (u32, u32)) -> PackedEncoder<'a, F> {
+        PackedEncoder {
+            tsize,
+            ..self
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as BGR555.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> PackedEncoder<'a, F> {
+        PackedEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for PackedEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        self.tsize
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        match self.tsize {
+            (1, 1) => (0, 0),
+            tsize => tsize
+        }
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        //Depths above 8bpp are rejected by encode_indexes; clamp here so that
+        //the shift can't overflow before we get there.
+        (1 << self.bpp.min(8)) - 1
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for PackedEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, width: u32, _height: u32) -> io::Result<()> {
+        let bpp = match self.bpp {
+            1 | 2 | 4 | 8 => self.bpp as usize,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Packed pixels must be 1, 2, 4 or 8 bits per pixel."))
+        };
+        let row_width = match self.tsize {
+            (1, 1) => width as usize,
+            (tw, _) => tw as usize
+        };
+        let mask = ((1u16 << bpp) - 1) as u8;
+        let mut out: Vec<u8> = Vec::with_capacity(data.len() * bpp / 8);
+
+        for row in data.chunks(row_width) {
+            for group in row.chunks(8 / bpp) {
+                let mut byte = 0u8;
+
+                for (x, index) in group.iter().enumerate() {
+                    let shift = match self.order {
+                        PixelOrder::LsbFirst => x * bpp,
+                        PixelOrder::MsbFirst => 8 - (x + 1) * bpp
+                    };
+
+                    byte |= (index.to_u8().unwrap() & mask) << shift;
+                }
+
+                out.push(byte);
+            }
+        }
+
+        self.f.write_all(&out)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> PackedEncoder<'a, F> {
+    /// The number of pixels in each row and bytes in each tile.
+    ///
+    /// Untiled data is treated as rows of a single byte each.
+    fn layout(&self, bpp: usize) -> (usize, usize) {
+        match self.tsize {
+            (1, 1) => (8 / bpp, 1),
+            (tw, th) => (tw as usize, (tw as usize * bpp).div_ceil(8) * th as usize)
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for PackedEncoder<'a, F> where F: Read {
+    /// Decode packed index data.
+    ///
+    /// Untiled data is decoded as one long row, so it must not have been
+    /// padded at the end of each row of the image.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let bpp = match self.bpp {
+            1 | 2 | 4 | 8 => self.bpp as usize,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Packed pixels must be 1, 2, 4 or 8 bits per pixel."))
+        };
+        let (row_width, tbytes) = self.layout(bpp);
+        let buf = read_units(self.f, size, tbytes, "Packed data ends partway through a tile")?;
+        let per_byte = 8 / bpp;
+        let mask = ((1u16 << bpp) - 1) as u8;
+        let mut out = Vec::with_capacity(buf.len() * per_byte);
+
+        for row in buf.chunks(row_width.div_ceil(per_byte)) {
+            for x in 0..row_width {
+                let shift = match self.order {
+                    PixelOrder::LsbFirst => (x % per_byte) * bpp,
+                    PixelOrder::MsbFirst => 8 - (x % per_byte + 1) * bpp
+                };
+
+                out.push(P::from((row[x / per_byte] >> shift) & mask).unwrap());
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        match self.bpp {
+            1 | 2 | 4 | 8 if self.tsize != (1, 1) || self.bpp == 8 => Some(self.layout(self.bpp as usize).1),
+            _ => None
+        }
+    }
+}
+
+/// Encoder for planar graphics with any number of bitplanes.
+///
+/// Each bitplane holds one bit of every pixel's index, with the leftmost
+/// pixel in the most significant bit of each unit; units are bytes unless
+/// with_unit selects 16-bit words of either byte order. Rows are padded to a
+/// whole number of units. Planes are written lowest first and interleaved
+/// by row, by tile or by frame as given.
+///
+/// Images are split into 8x8 tiles, left to right then top to bottom, unless
+/// with_tile_size selects another tile size; a tile size of (1, 1) encodes the
+/// image untiled. This covers the long tail of planar formats through
+/// configuration; for example, NesChrEncoder is equivalent to a two plane,
+/// tile interleaved encoder, and SMS4Encoder to a four plane, row interleaved
+/// encoder.
+///
+/// Palettes are written as 15-bit BGR words, as with the AGB palette writer,
+/// unless another format is given with with_palette_format.
+pub struct PlanarEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    planes: u8,
+    interleave: PlaneInterleave,
+    unit: PlaneUnit,
+    tsize: (u32, u32),
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> PlanarEncoder<'a, F> {
+    /// Create a planar encoder with the given number of planes, which must be
+    /// between 1 and 8.
+    pub fn new(file: &'a mut F, planes: u8, interleave: PlaneInterleave) -> io::Result<PlanarEncoder<'a, F>> {
+        if planes == 0 || planes > 8 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Planar graphics must have between 1 and 8 planes."));
+        }
+
+        Ok(PlanarEncoder {
+            f: file,
+            planes,
+            interleave,
+            unit: PlaneUnit::Byte,
+            tsize: (8, 8),
+            palette_format: PaletteColorFormat::BGR555
+        })
+    }
+
+    pub fn with_unit(self, unit: PlaneUnit) -> PlanarEncoder<'a, F> {
+        PlanarEncoder {
+            unit,
+            ..self
+        }
+    }
+
+    pub fn with_tile_size(self, tsize: (u32, u32)) -> PlanarEncoder<'a, F> {
+        PlanarEncoder {
+            tsize,
+            ..self
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as BGR555.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> PlanarEncoder<'a, F> {
+        PlanarEncoder {
+            palette_format,
+            ..self
+        }
+    }
+
+    /// Encode one plane of a row of indexes.
+    fn encode_plane_row<P: Primitive>(&self, out: &mut Vec<u8>, row: &[P], plane: u8) {
+        let unit_bits = match self.unit {
+            PlaneUnit::Byte => 8,
+            _ => 16
+        };
+
+        for group in row.chunks(unit_bits) {
+            let mut word = 0u16;
+
+            for (x, index) in group.iter().enumerate() {
+                word |= ((index.to_u16().unwrap() >> plane) & 0x01) << (unit_bits - 1 - x);
+            }
+
+            match self.unit {
+                PlaneUnit::Byte => out.push(word as u8),
+                PlaneUnit::WordBE => out.extend_from_slice(&[(word >> 8) as u8, (word & 0xFF) as u8]),
+                PlaneUnit::WordLE => out.extend_from_slice(&[(word & 0xFF) as u8, (word >> 8) as u8])
+            }
+        }
+    }
+
+    /// The number of bytes one plane of a row of the given width occupies.
+    fn plane_row_bytes(&self, width: usize) -> usize {
+        match self.unit {
+            PlaneUnit::Byte => width.div_ceil(8),
+            _ => width.div_ceil(16) * 2
+        }
+    }
+
+    /// Decode one plane of a row of indexes, merging its bits into the row.
+    fn decode_plane_row(&self, row: &mut [u16], data: &[u8], plane: u8) {
+        let unit_bits = match self.unit {
+            PlaneUnit::Byte => 8,
+            _ => 16
+        };
+
+        for (u, group) in row.chunks_mut(unit_bits).enumerate() {
+            let word = match self.unit {
+                PlaneUnit::Byte => data[u] as u16,
+                PlaneUnit::WordBE => (data[u * 2] as u16) << 8 | data[u * 2 + 1] as u16,
+                PlaneUnit::WordLE => data[u * 2] as u16 | (data[u * 2 + 1] as u16) << 8
+            };
+
+            for (x, index) in group.iter_mut().enumerate() {
+                *index |= ((word >> (unit_bits - 1 - x)) & 0x01) << plane;
+            }
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for PlanarEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        self.tsize
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        match self.tsize {
+            (1, 1) => (0, 0),
+            tsize => tsize
+        }
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        1u32.checked_shl(self.planes as u32).map_or(u16::MAX, |colors| (colors - 1).min(u16::MAX as u32) as u16)
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for PlanarEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, width: u32, height: u32) -> io::Result<()> {
+        let (row_width, tile_rows) = match self.tsize {
+            (1, 1) => (width as usize, height as usize),
+            (tw, th) => (tw as usize, th as usize)
+        };
+        let rows: Vec<&[P]> = data.chunks(row_width).collect();
+        let mut out: Vec<u8> = Vec::new();
+
+        match self.interleave {
+            PlaneInterleave::Row => {
+                for row in rows.iter() {
+                    for plane in 0..self.planes {
+                        self.encode_plane_row(&mut out, row, plane);
+                    }
+                }
+            },
+            PlaneInterleave::Tile => {
+                for tile in rows.chunks(tile_rows.max(1)) {
+                    for plane in 0..self.planes {
+                        for row in tile.iter() {
+                            self.encode_plane_row(&mut out, row, plane);
+                        }
+                    }
+                }
+            },
+            PlaneInterleave::Frame => {
+                for plane in 0..self.planes {
+                    for row in rows.iter() {
+                        self.encode_plane_row(&mut out, row, plane);
+                    }
+                }
+            }
+        }
+
+        self.f.write_all(&out)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for PlanarEncoder<'a, F> where F: Read {
+    /// Decode planar index data.
+    ///
+    /// Untiled data can only be decoded when interleaved by frame, and is
+    /// decoded as one long row, so it must not have been padded at the end of
+    /// each row of the image. Frame interleaved data is split evenly between
+    /// the planes.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let planes = self.planes as usize;
+        let mut out: Vec<u16>;
+
+        match (self.interleave, self.tsize) {
+            (PlaneInterleave::Frame, tsize) => {
+                let (row_width, tile_rows) = match tsize {
+                    (1, 1) => (self.plane_row_bytes(1) * 8, 1),
+                    (tw, th) => (tw as usize, th as usize)
+                };
+                let row_bytes = self.plane_row_bytes(row_width);
+                let buf = read_units(self.f, size, row_bytes * tile_rows * planes, "Planar data ends partway through a tile")?;
+                let plane_bytes = buf.len() / planes;
+
+                out = vec![0; plane_bytes / row_bytes * row_width];
+
+                for plane in 0..planes {
+                    for (y, row) in out.chunks_mut(row_width).enumerate() {
+                        self.decode_plane_row(row, &buf[plane * plane_bytes + y * row_bytes..], plane as u8);
+                    }
+                }
+            },
+            (_, (1, 1)) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Untiled planar data must be interleaved by frame to be decoded.")),
+            (interleave, (tw, th)) => {
+                let (tw, th) = (tw as usize, th as usize);
+                let row_bytes = self.plane_row_bytes(tw);
+                let tbytes = row_bytes * planes * th;
+                let buf = read_units(self.f, size, tbytes, "Planar data ends partway through a tile")?;
+
+                out = vec![0; buf.len() / tbytes * tw * th];
+
+                for (tile, data) in out.chunks_mut(tw * th).zip(buf.chunks(tbytes)) {
+                    for (y, row) in tile.chunks_mut(tw).enumerate() {
+                        for plane in 0..planes {
+                            let offset = match interleave {
+                                PlaneInterleave::Row => (y * planes + plane) * row_bytes,
+                                _ => (plane * th + y) * row_bytes
+                            };
+
+                            self.decode_plane_row(row, &data[offset..], plane as u8);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(out.into_iter().map(|index| P::from(index).unwrap()).collect())
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        match (self.interleave, self.tsize) {
+            (PlaneInterleave::Frame, _) | (_, (1, 1)) => None,
+            (_, (tw, th)) => Some(self.plane_row_bytes(tw as usize) * self.planes as usize * th as usize)
+        }
+    }
+}
+
+/// Encoder for 16-bit direct color graphics.
+///
+/// Pixels are written one row after another as 16-bit words with the given
+/// channel order, channel bit depths and byte order. Each channel is reduced
+/// to its bit depth by discarding low bits. In the 1555 layout, the alpha bit
+/// is set for pixels whose 8-bit alpha is at least the alpha threshold, which
+/// is formats::DEFAULT_ALPHA_THRESHOLD unless changed with
+/// with_alpha_threshold.
+///
+/// new_agb and new_ntr are presets for the little-endian BGR formats of the
+/// AGB and NTR platforms: 555 and 1555 respectively.
+pub struct DirectColor16Encoder<'a, W: Write + 'a> {
+    w: &'a mut W,
+    order: ChannelOrder,
+    depth: Color16Depth,
+    endian: ByteOrder,
+    alpha_threshold: u8
+}
+
+impl<'a, W: Write + 'a> DirectColor16Encoder<'a, W> {
+    pub fn new(write: &'a mut W, order: ChannelOrder, depth: Color16Depth, endian: ByteOrder) -> DirectColor16Encoder<'a, W> {
+        DirectColor16Encoder {
+            w: write,
+            order,
+            depth,
+            endian,
+            alpha_threshold: DEFAULT_ALPHA_THRESHOLD
+        }
+    }
+
+    pub fn new_agb(write: &'a mut W) -> DirectColor16Encoder<'a, W> {
+        DirectColor16Encoder::new(write, ChannelOrder::BGR, Color16Depth::Depth555, ByteOrder::LittleEndian)
+    }
+
+    pub fn new_ntr(write: &'a mut W) -> DirectColor16Encoder<'a, W> {
+        DirectColor16Encoder::new(write, ChannelOrder::BGR, Color16Depth::Depth1555, ByteOrder::LittleEndian)
+    }
+
+    pub fn with_alpha_threshold(self, alpha_threshold: u8) -> DirectColor16Encoder<'a, W> {
+        DirectColor16Encoder {
+            alpha_threshold,
+            ..self
+        }
+    }
+
+    /// Pack an 8-bit-per-channel RGBA color into a 16-bit value.
+    fn pack(&self, c: [u16; 4]) -> u16 {
+        let (a_bits, rgb_bits) = match self.depth {
+            Color16Depth::Depth555 => (1, [5, 5, 5]),
+            Color16Depth::Depth565 => (0, [5, 6, 5]),
+            Color16Depth::Depth1555 => (1, [5, 5, 5]),
+            Color16Depth::Depth4444 => (4, [4, 4, 4])
+        };
+        let alpha = match self.depth {
+            Color16Depth::Depth555 => 0,
+            Color16Depth::Depth1555 => (c[3] >= self.alpha_threshold as u16) as u16,
+            _ => c[3] >> (8 - a_bits)
+        };
+        let channels = match self.order {
+            ChannelOrder::RGB => [0, 1, 2],
+            ChannelOrder::BGR => [2, 1, 0]
+        };
+
+        channels.iter().fold(alpha, |value, &ch| value << rgb_bits[ch] | c[ch] >> (8 - rgb_bits[ch]))
+    }
+}
+
+impl<'a, W: Write> DirectGraphicsEncoder for DirectColor16Encoder<'a, W> {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        let imgmax = S::max_value().to_f32().unwrap();
+        let mut out: Vec<u8> = Vec::with_capacity((image.width() * image.height() * 2) as usize);
+
+        for (_, _, pixel) in image.pixels() {
+            let rgba = pixel.to_rgba();
+            let mut c = [0u16; 4];
+
+            for (i, v) in c.iter_mut().enumerate() {
+                *v = (rgba[i].to_f32().unwrap() / imgmax * 255f32) as u16;
+            }
+
+            let value = self.pack(c);
+
+            match self.endian {
+                ByteOrder::BigEndian => out.extend_from_slice(&[(value >> 8) as u8, (value & 0xFF) as u8]),
+                ByteOrder::LittleEndian => out.extend_from_slice(&[(value & 0xFF) as u8, (value >> 8) as u8])
+            }
+        }
+
+        self.w.write_all(&out)
+    }
+}
+
+/// Decoder for 16-bit direct color graphics.
+///
+/// This reads data written by a DirectColor16Encoder with the same channel
+/// order, channel bit depths and byte order. Channels are expanded to 8 bits
+/// by repeating their top bits. In the 555 and 565 layouts every pixel is
+/// opaque; in the 1555 layout, pixels without the alpha bit are fully
+/// transparent.
+pub struct DirectColor16Decoder<'a, R: Read + 'a> {
+    r: &'a mut R,
+    order: ChannelOrder,
+    depth: Color16Depth,
+    endian: ByteOrder
+}
+
+impl<'a, R: Read + 'a> DirectColor16Decoder<'a, R> {
+    pub fn new(read: &'a mut R, order: ChannelOrder, depth: Color16Depth, endian: ByteOrder) -> DirectColor16Decoder<'a, R> {
+        DirectColor16Decoder {
+            r: read,
+            order,
+            depth,
+            endian
+        }
+    }
+
+    pub fn new_agb(read: &'a mut R) -> DirectColor16Decoder<'a, R> {
+        DirectColor16Decoder::new(read, ChannelOrder::BGR, Color16Depth::Depth555, ByteOrder::LittleEndian)
+    }
+
+    pub fn new_ntr(read: &'a mut R) -> DirectColor16Decoder<'a, R> {
+        DirectColor16Decoder::new(read, ChannelOrder::BGR, Color16Depth::Depth1555, ByteOrder::LittleEndian)
+    }
+
+    /// Unpack a 16-bit value into an 8-bit-per-channel RGBA color.
+    fn unpack(&self, value: u16) -> Rgba<u8> {
+        let rgb_bits = match self.depth {
+            Color16Depth::Depth565 => [5, 6, 5],
+            Color16Depth::Depth4444 => [4, 4, 4],
+            _ => [5, 5, 5]
+        };
+        let channels = match self.order {
+            ChannelOrder::RGB => [2, 1, 0],
+            ChannelOrder::BGR => [0, 1, 2]
+        };
+        let mut out = Rgba([0, 0, 0, 255]);
+        let mut shift = 0;
+
+        for &ch in channels.iter() {
+            out[ch] = expand_channel(value >> shift, rgb_bits[ch]);
+            shift += rgb_bits[ch];
+        }
+
+        out[3] = match self.depth {
+            Color16Depth::Depth1555 if value & 0x8000 == 0 => 0,
+            Color16Depth::Depth4444 => expand_channel(value >> 12, 4),
+            _ => 255
+        };
+
+        out
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for DirectColor16Decoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        let buf = read_units(self.r, size, 2, "16bpp data ends partway through a pixel")?;
+        let colors: Vec<Rgba<u8>> = buf.chunks(2).map(|word| self.unpack(match self.endian {
+            ByteOrder::BigEndian => (word[0] as u16) << 8 | word[1] as u16,
+            ByteOrder::LittleEndian => word[0] as u16 | (word[1] as u16) << 8
+        })).collect();
+
+        image_from_rows(colors, width)
+    }
+}
+
+/// Lay decoded pixels out as an image of the given width, one row after
+/// another.
+///
+/// The pixels must form a whole number of rows.
+pub fn image_from_rows(colors: Vec<Rgba<u8>>, width: u32) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+    if width == 0 || !colors.len().is_multiple_of(width as usize) {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Direct color data ends partway through a row"));
+    }
+
+    let height = (colors.len() / width as usize) as u32;
+
+    Ok(ImageBuffer::from_fn(width, height, |x, y| colors[(y * width + x) as usize]))
+}
+
+/// Encoder for 8-bit-per-channel direct color graphics.
+///
+/// Pixels are written one row after another, either as 24-bit RGB888 values
+/// (new_rgb888) or 32-bit RGBA8888 values with alpha in the least significant
+/// byte (new_rgba8888). Big-endian values are stored red byte first;
+/// little-endian values are stored with the bytes reversed.
+pub struct DirectColor32Encoder<'a, W: Write + 'a> {
+    w: &'a mut W,
+    alpha: bool,
+    endian: ByteOrder
+}
+
+impl<'a, W: Write + 'a> DirectColor32Encoder<'a, W> {
+    pub fn new_rgb888(write: &'a mut W, endian: ByteOrder) -> DirectColor32Encoder<'a, W> {
+        DirectColor32Encoder {
+            w: write,
+            alpha: false,
+            endian
+        }
+    }
+
+    pub fn new_rgba8888(write: &'a mut W, endian: ByteOrder) -> DirectColor32Encoder<'a, W> {
+        DirectColor32Encoder {
+            w: write,
+            alpha: true,
+            endian
+        }
+    }
+}
+
+impl<'a, W: Write> DirectGraphicsEncoder for DirectColor32Encoder<'a, W> {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        let imgmax = S::max_value().to_f32().unwrap();
+        let channels = if self.alpha { 4 } else { 3 };
+        let mut out: Vec<u8> = Vec::with_capacity((image.width() * image.height()) as usize * channels);
+
+        for (_, _, pixel) in image.pixels() {
+            let rgba = pixel.to_rgba();
+            let mut c: Vec<u8> = (0..channels).map(|i| (rgba[i].to_f32().unwrap() / imgmax * 255f32) as u8).collect();
+
+            if self.endian == ByteOrder::LittleEndian {
+                c.reverse();
+            }
+
+            out.extend_from_slice(&c);
+        }
+
+        self.w.write_all(&out)
+    }
+}
+
+/// Decoder for 8-bit-per-channel direct color graphics.
+///
+/// This reads data written by a DirectColor32Encoder with the same layout and
+/// byte order. RGB888 pixels are decoded as opaque.
+pub struct DirectColor32Decoder<'a, R: Read + 'a> {
+    r: &'a mut R,
+    alpha: bool,
+    endian: ByteOrder
+}
+
+impl<'a, R: Read + 'a> DirectColor32Decoder<'a, R> {
+    pub fn new_rgb888(read: &'a mut R, endian: ByteOrder) -> DirectColor32Decoder<'a, R> {
+        DirectColor32Decoder {
+            r: read,
+            alpha: false,
+            endian
+        }
+    }
+
+    pub fn new_rgba8888(read: &'a mut R, endian: ByteOrder) -> DirectColor32Decoder<'a, R> {
+        DirectColor32Decoder {
+            r: read,
+            alpha: true,
+            endian
+        }
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for DirectColor32Decoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        let channels = if self.alpha { 4 } else { 3 };
+        let buf = read_units(self.r, size, channels, "Direct color data ends partway through a pixel")?;
+        let colors = buf.chunks(channels).map(|pixel| {
+            let mut c = pixel.to_vec();
+
+            if self.endian == ByteOrder::LittleEndian {
+                c.reverse();
+            }
+
+            Rgba([c[0], c[1], c[2], *c.get(3).unwrap_or(&255)])
+        }).collect();
+
+        image_from_rows(colors, width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::{encode_image_as_indexes, IndexedGraphicsEncoder, DirectGraphicsEncoder};
+    use awsmimg::formats::IndexedGraphicsProperties;
+    use awsmimg::formats::agb::AGB4Encoder;
+    use awsmimg::formats::nes::NesChrEncoder;
+    use awsmimg::formats::sms::SMS4Encoder;
+    use awsmimg::formats::pc::EGAEncoder;
+    use awsmimg::formats::generic::{PackedEncoder, PixelOrder, PlanarEncoder, PlaneInterleave, PlaneUnit};
+    use awsmimg::formats::generic::{DirectColor16Encoder, DirectColor32Encoder, ChannelOrder, Color16Depth, ByteOrder};
+
+    fn test_image() -> image::ImageBuffer<image::Luma<u8>, Vec<u8>> {
+        image::ImageBuffer::from_fn(16, 8, |x, y| image::Luma([((x * 3 + y * 5) % 16 * 17) as u8]))
+    }
+
+    #[test]
+    fn packed_agb4_equivalent() {
+        let img = test_image();
+        let mut agb_out = Cursor::new(Vec::new());
+        let mut packed_out = Cursor::new(Vec::new());
+
+        encode_image_as_indexes(&mut AGB4Encoder::new(&mut agb_out), &img).unwrap();
+        encode_image_as_indexes(&mut PackedEncoder::new(&mut packed_out, 4, PixelOrder::LsbFirst).with_tile_size((8, 8)), &img).unwrap();
+
+        assert_eq!(agb_out.get_ref(), packed_out.get_ref());
+    }
+
+    #[test]
+    fn packed1_msb_encode() {
+        let src : Vec<u8> = vec![1, 0, 0, 0, 0, 0, 0, 1, 1, 1,
+                                 0, 1, 0, 0, 0, 0, 0, 0, 0, 1];
+        let mut test_out = Cursor::new(Vec::with_capacity(4));
+
+        {
+            let mut packed = PackedEncoder::new(&mut test_out, 1, PixelOrder::MsbFirst);
+
+            packed.encode_indexes(src, 10, 2).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x81u8, 0xC0, 0x40, 0x40])
+    }
+
+    #[test]
+    fn packed2_lsb_encode() {
+        let src : Vec<u8> = vec![0, 1, 2, 3, 3];
+        let mut test_out = Cursor::new(Vec::with_capacity(2));
+
+        {
+            let mut packed = PackedEncoder::new(&mut test_out, 2, PixelOrder::LsbFirst);
+
+            packed.encode_indexes(src, 5, 1).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0xE4u8, 0x03]);
+        assert!(PackedEncoder::new(&mut Cursor::new(Vec::new()), 3, PixelOrder::LsbFirst).encode_indexes(vec![0u8; 8], 8, 1).is_err());
+    }
+
+    #[test]
+    fn packed_invalid_bpp() {
+        let mut test_out = Cursor::new(Vec::new());
+        let mut packed = PackedEncoder::new(&mut test_out, 32, PixelOrder::MsbFirst);
+
+        assert_eq!(packed.palette_maxcol(), 255);
+        assert!(packed.encode_indexes(vec![0u8], 1, 1).is_err());
+    }
+
+    #[test]
+    fn planar_equivalents() {
+        let img = test_image();
+        let mut nes_out = Cursor::new(Vec::new());
+        let mut sms_out = Cursor::new(Vec::new());
+        let mut ega_out = Cursor::new(Vec::new());
+        let mut planar_nes = Cursor::new(Vec::new());
+        let mut planar_sms = Cursor::new(Vec::new());
+        let mut planar_ega = Cursor::new(Vec::new());
+
+        encode_image_as_indexes(&mut NesChrEncoder::new(&mut nes_out), &img).unwrap();
+        encode_image_as_indexes(&mut SMS4Encoder::new_sms(&mut sms_out), &img).unwrap();
+        encode_image_as_indexes(&mut EGAEncoder::new(&mut ega_out), &img).unwrap();
+        encode_image_as_indexes(&mut PlanarEncoder::new(&mut planar_nes, 2, PlaneInterleave::Tile).unwrap(), &img).unwrap();
+        encode_image_as_indexes(&mut PlanarEncoder::new(&mut planar_sms, 4, PlaneInterleave::Row).unwrap(), &img).unwrap();
+        encode_image_as_indexes(&mut PlanarEncoder::new(&mut planar_ega, 4, PlaneInterleave::Frame).unwrap().with_tile_size((1, 1)), &img).unwrap();
+
+        assert_eq!(nes_out.get_ref(), planar_nes.get_ref());
+        assert_eq!(sms_out.get_ref(), planar_sms.get_ref());
+        assert_eq!(ega_out.get_ref(), planar_ega.get_ref());
+    }
+
+    #[test]
+    fn planar_word_encode() {
+        let mut src : Vec<u8> = vec![0; 16];
+        src[0] = 1;
+        src[15] = 2;
+        let mut be_out = Cursor::new(Vec::with_capacity(4));
+        let mut le_out = Cursor::new(Vec::with_capacity(4));
+
+        PlanarEncoder::new(&mut be_out, 2, PlaneInterleave::Row).unwrap().with_unit(PlaneUnit::WordBE).with_tile_size((1, 1)).encode_indexes(src.clone(), 16, 1).unwrap();
+        PlanarEncoder::new(&mut le_out, 2, PlaneInterleave::Row).unwrap().with_unit(PlaneUnit::WordLE).with_tile_size((1, 1)).encode_indexes(src, 16, 1).unwrap();
+
+        assert_eq!(be_out.get_ref(), &vec![0x80u8, 0x00, 0x00, 0x01]);
+        assert_eq!(le_out.get_ref(), &vec![0x00u8, 0x80, 0x01, 0x00]);
+    }
+
+    #[test]
+    fn planar_invalid_planes() {
+        let mut test_out : Cursor<Vec<u8>> = Cursor::new(Vec::new());
+
+        assert!(PlanarEncoder::new(&mut test_out, 0, PlaneInterleave::Row).is_err());
+        assert!(PlanarEncoder::new(&mut test_out, 9, PlaneInterleave::Row).is_err());
+        assert!(PlanarEncoder::new(&mut test_out, 32, PlaneInterleave::Tile).is_err());
+        assert_eq!(PlanarEncoder::new(&mut test_out, 8, PlaneInterleave::Frame).unwrap().palette_maxcol(), 255);
+    }
+
+    #[test]
+    fn direct16_encode() {
+        let img = image::ImageBuffer::from_fn(2, 1, |x, _| {
+            match x {
+                0 => image::Rgba([0xFFu8, 0x80, 0x00, 0xFF]),
+                _ => image::Rgba([0x08u8, 0x04, 0xF8, 0x40])
+            }
+        });
+        let encode = |order, depth, endian| {
+            let mut test_out = Cursor::new(Vec::with_capacity(4));
+
+            DirectColor16Encoder::new(&mut test_out, order, depth, endian).encode_colors(&img).unwrap();
+
+            test_out.into_inner()
+        };
+
+        assert_eq!(encode(ChannelOrder::RGB, Color16Depth::Depth565, ByteOrder::BigEndian), vec![0xFCu8, 0x00, 0x08, 0x3F]);
+        assert_eq!(encode(ChannelOrder::BGR, Color16Depth::Depth555, ByteOrder::LittleEndian), vec![0x1Fu8, 0x02, 0x01, 0x7C]);
+        assert_eq!(encode(ChannelOrder::RGB, Color16Depth::Depth1555, ByteOrder::BigEndian), vec![0xFEu8, 0x00, 0x04, 0x1F]);
+        assert_eq!(encode(ChannelOrder::RGB, Color16Depth::Depth4444, ByteOrder::LittleEndian), vec![0x80u8, 0xFF, 0x0F, 0x40]);
+    }
+
+    #[test]
+    fn direct32_encode() {
+        let img = image::ImageBuffer::from_fn(2, 1, |x, _| image::Rgba([0x10u8 + x as u8, 0x20, 0x30, 0x40]));
+        let mut rgb_be = Cursor::new(Vec::with_capacity(6));
+        let mut rgb_le = Cursor::new(Vec::with_capacity(6));
+        let mut rgba_be = Cursor::new(Vec::with_capacity(8));
+        let mut rgba_le = Cursor::new(Vec::with_capacity(8));
+
+        DirectColor32Encoder::new_rgb888(&mut rgb_be, ByteOrder::BigEndian).encode_colors(&img).unwrap();
+        DirectColor32Encoder::new_rgb888(&mut rgb_le, ByteOrder::LittleEndian).encode_colors(&img).unwrap();
+        DirectColor32Encoder::new_rgba8888(&mut rgba_be, ByteOrder::BigEndian).encode_colors(&img).unwrap();
+        DirectColor32Encoder::new_rgba8888(&mut rgba_le, ByteOrder::LittleEndian).encode_colors(&img).unwrap();
+
+        assert_eq!(rgb_be.get_ref(), &vec![0x10u8, 0x20, 0x30, 0x11, 0x20, 0x30]);
+        assert_eq!(rgb_le.get_ref(), &vec![0x30u8, 0x20, 0x10, 0x30, 0x20, 0x11]);
+        assert_eq!(rgba_be.get_ref(), &vec![0x10u8, 0x20, 0x30, 0x40, 0x11, 0x20, 0x30, 0x40]);
+        assert_eq!(rgba_le.get_ref(), &vec![0x40u8, 0x30, 0x20, 0x10, 0x40, 0x30, 0x20, 0x11]);
+    }
+}
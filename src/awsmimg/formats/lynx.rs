@@ -0,0 +1,274 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, expand_channel};
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::IndexedGraphicsDecoder;
+
+use std::io;
+use std::io::{Write, Read};
+use image::{Primitive, Rgba};
+
+/// Encode a series of RGBA colors as Lynx palette register data.
+///
+/// The Lynx stores its palette in two banks of registers: one of 0000GGGG
+/// bytes, followed by one of BBBBRRRR bytes. All green bytes are written
+/// first, then all blue/red bytes, so that the output can be copied directly
+/// over both banks. Alpha is ignored.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut greens: Vec<u8> = Vec::new();
+    let mut bluereds: Vec<u8> = Vec::new();
+
+    for rgba in palette {
+        let r : u8 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u8;
+        let g : u8 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u8;
+        let b : u8 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u8;
+
+        greens.push(g >> 4);
+        bluereds.push((b & 0xF0) | r >> 4);
+    }
+
+    w.write_all(&greens)?;
+    w.write_all(&bluereds)
+}
+
+/// Decode Lynx palette data written by encode_palette into RGBA colors.
+///
+/// Up to size bytes are read. As the green bytes of every color precede the
+/// blue/red bytes, the data must split evenly into the two halves. Every
+/// color is opaque.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    let mut buf = Vec::new();
+
+    r.take(size as u64).read_to_end(&mut buf)?;
+
+    if !buf.len().is_multiple_of(2) {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Palette data ends partway through a color"));
+    }
+
+    let (greens, bluereds) = buf.split_at(buf.len() / 2);
+
+    Ok(greens.iter().zip(bluereds.iter()).map(|(g, br)| {
+        Rgba([expand_channel(*br as u16, 4), expand_channel(*g as u16, 4), expand_channel((br >> 4) as u16, 4), 255])
+    }).collect())
+}
+
+/// Encoder for Suzy sprite data in literal (uncompressed) packet form.
+///
+/// Every scanline of the image is stored as an offset byte, giving the number
+/// of bytes from the start of this line to the start of the next, followed by
+/// the line's pixels packed most significant bit first. Each line ends with an
+/// extra zero byte to work around Suzy reading past the end of literal lines.
+/// The sprite is terminated with an offset byte of zero.
+///
+/// Sprites are 4bpp by default; use with_bpp to select 1, 2 or 3bpp data. The
+/// sprite control block must have the literal bit set in SPRCTL1 and a
+/// matching bits-per-pixel value in SPRCTL0.
+///
+/// Palettes are written as Lynx palette register data unless another format
+/// is given with with_palette_format.
+pub struct LynxSpriteEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    bpp: u8,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> LynxSpriteEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> LynxSpriteEncoder<'a, F> {
+        LynxSpriteEncoder {
+            f: file,
+            bpp: 4,
+            palette_format: PaletteColorFormat::Lynx
+        }
+    }
+
+    pub fn with_bpp(self, bpp: u8) -> LynxSpriteEncoder<'a, F> {
+        LynxSpriteEncoder {
+            bpp,
+            ..self
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as Lynx palette
+    /// register data.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> LynxSpriteEncoder<'a, F> {
+        LynxSpriteEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for LynxSpriteEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        //Depths above 4bpp are rejected by encode_indexes; clamp here so that
+        //the shift can't overflow before we get there.
+        (1 << self.bpp.min(4)) - 1
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for LynxSpriteEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, width: u32, _height: u32) -> io::Result<()> {
+        if self.bpp < 1 || self.bpp > 4 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Lynx sprites must be 1 to 4 bits per pixel."));
+        }
+
+        let bpp = self.bpp as u32;
+        let line_bytes = (width * bpp).div_ceil(8) as usize;
+
+        if line_bytes + 2 > 0xFF {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Sprite is too wide to encode as literal packets."));
+        }
+
+        let mask = (1 << bpp) - 1;
+        let mut out: Vec<u8> = Vec::with_capacity(line_bytes + 2);
+
+        for row in data.chunks(width as usize) {
+            out.clear();
+            out.push((line_bytes + 2) as u8);
+            out.resize(line_bytes + 2, 0);
+
+            for (x, index) in row.iter().enumerate() {
+                let bits = (index.to_u32().unwrap() & mask) << (16 - bpp);
+                let bitpos = x as u32 * bpp;
+                let byte = 1 + (bitpos / 8) as usize;
+                let shifted = bits >> (bitpos % 8);
+
+                out[byte] |= (shifted >> 8) as u8;
+                out[byte + 1] |= (shifted & 0xFF) as u8;
+            }
+
+            self.f.write_all(&out)?;
+        }
+
+        self.f.write_all(&[0])
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for LynxSpriteEncoder<'a, F> where F: Read {
+    /// Decode literal sprite lines up to the terminating zero offset.
+    ///
+    /// Every bit of each line is decoded, so lines are only free of padding
+    /// pixels if the sprite's rows fill whole bytes.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        if self.bpp < 1 || self.bpp > 4 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Lynx sprites must be 1 to 4 bits per pixel."));
+        }
+
+        let bpp = self.bpp as usize;
+        let mut buf = Vec::new();
+        let mut out = Vec::new();
+        let mut pos = 0;
+
+        self.f.by_ref().take(size as u64).read_to_end(&mut buf)?;
+
+        loop {
+            let offset = match buf.get(pos) {
+                Some(0) => return Ok(out),
+                Some(&offset) if offset >= 2 => offset as usize,
+                Some(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Literal sprite line is too short to hold its own offset")),
+                None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Sprite data ends before its terminating zero offset"))
+            };
+
+            if pos + offset > buf.len() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Sprite data ends partway through a line"));
+            }
+
+            let line = &buf[pos + 1..pos + offset - 1];
+
+            for x in 0..line.len() * 8 / bpp {
+                let bitpos = x * bpp;
+                let bits = (line[bitpos / 8] as u16) << 8 | *line.get(bitpos / 8 + 1).unwrap_or(&0) as u16;
+
+                out.push(P::from((bits >> (16 - bpp - bitpos % 8)) & ((1 << bpp) - 1)).unwrap());
+            }
+
+            pos += offset;
+        }
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::formats::IndexedGraphicsProperties;
+    use awsmimg::formats::lynx::LynxSpriteEncoder;
+
+    #[test]
+    fn literal4_encode() {
+        let src : Vec<u8> = vec![0x1, 0x2, 0x3,
+                                 0xF, 0x0, 0xA];
+        let mut test_out = Cursor::new(Vec::with_capacity(11));
+
+        {
+            let mut lynx = LynxSpriteEncoder::new(&mut test_out);
+
+            lynx.encode_indexes(src, 3, 2).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x04, 0x12, 0x30, 0x00,
+                                       0x04, 0xF0, 0xA0, 0x00,
+                                       0x00];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn literal3_encode() {
+        let src : Vec<u8> = vec![7, 0, 5, 1, 2, 3];
+        let mut test_out = Cursor::new(Vec::with_capacity(5));
+
+        {
+            let mut lynx = LynxSpriteEncoder::new(&mut test_out).with_bpp(3);
+
+            lynx.encode_indexes(src, 6, 1).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x05, 0xE2, 0x94, 0xC0, 0x00, 0x00];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn invalid_bpp() {
+        let mut test_out = Cursor::new(Vec::new());
+        let mut lynx = LynxSpriteEncoder::new(&mut test_out).with_bpp(16);
+
+        assert_eq!(lynx.palette_maxcol(), 15);
+        assert!(lynx.encode_indexes(vec![0u8], 1, 1).is_err());
+    }
+
+    #[test]
+    fn palette_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]),
+                           image::Rgba([0u8, 255, 0, 255]),
+                           image::Rgba([0u8, 0, 255, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(6));
+
+        {
+            let mut lynx = LynxSpriteEncoder::new(&mut test_out);
+
+            lynx.encode_palette(palette).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x00u8, 0x0F, 0x00, 0x0F, 0x00, 0xF0])
+    }
+}
@@ -0,0 +1,237 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat};
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::{IndexedGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{Primitive, Rgba};
+
+/// Encode index data as 4bpp pixels, packed two to a byte with the leftmost
+/// pixel in the high nibble. An odd pixel out is paired with index 0.
+///
+/// Other platforms which pack pixels the same way, such as the N64, share
+/// this function.
+pub fn encode_packed4<'a, P: Primitive, W: Write + 'a>(w: &'a mut W, data: &[P]) -> io::Result<()> {
+    let out: Vec<u8> = data.chunks(2).map(|pair| {
+        let hi = pair[0].to_u8().unwrap() & 0x0F;
+        let lo = pair.get(1).map_or(0, |i| i.to_u8().unwrap() & 0x0F);
+
+        hi << 4 | lo
+    }).collect();
+
+    w.write_all(&out)
+}
+
+/// Decode up to size bytes of index data packed by encode_packed4.
+///
+/// The data must hold a whole number of tiles of the given length in bytes;
+/// pass 1 for untiled data.
+pub fn decode_packed4<R: Read, P: Primitive>(r: &mut R, size: usize, tile_bytes: usize) -> io::Result<Vec<P>> {
+    let buf = read_units(r, size, tile_bytes, "4bpp data ends partway through a tile")?;
+    let mut out = Vec::with_capacity(buf.len() * 2);
+
+    for byte in buf {
+        out.push(P::from(byte >> 4).unwrap());
+        out.push(P::from(byte & 0x0F).unwrap());
+    }
+
+    Ok(out)
+}
+
+/// Encode a series of RGBA colors as MD CRAM palette data.
+///
+/// Colors are written as big-endian 9-bit BGR words in the 0000BBB0GGG0RRR0
+/// layout expected by the VDP. Alpha is ignored.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 2] = [0, 0];
+
+    for rgba in palette {
+        let r : u16 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let g : u16 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let b : u16 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+
+        let enc_color: u16 = (b & 0xE0) << 4 | (g & 0xE0) | (r & 0xE0) >> 4;
+
+        out[0] = ((enc_color >> 8) & 0xFF) as u8;
+        out[1] = (enc_color & 0xFF) as u8;
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode MD CRAM palette data written by encode_palette into RGBA colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. Each
+/// 3-bit channel is expanded to 8 bits by repeating its bits, so that
+/// re-encoding the palette yields the original data. Bits outside of the
+/// color channels are ignored.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    let mut buf = Vec::new();
+
+    r.take(size as u64).read_to_end(&mut buf)?;
+
+    if !buf.len().is_multiple_of(2) {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Palette data ends partway through a color"));
+    }
+
+    let expand = |v: u16| {
+        let v = (v & 0x07) as u8;
+        v << 5 | v << 2 | v >> 1
+    };
+
+    Ok(buf.chunks(2).map(|word| {
+        let value = (word[0] as u16) << 8 | word[1] as u16;
+
+        Rgba([expand(value >> 1), expand(value >> 5), expand(value >> 9), 255])
+    }).collect())
+}
+
+/// Encoder/decoder for 4bpp tile patterns for the MD platform.
+///
+/// Pixels are packed two to a byte, arranged row-major in 8x8 tiles. Unlike
+/// the AGB 4bpp format, the leftmost pixel of each pair is stored in the high
+/// nibble.
+///
+/// Palettes are written as MD CRAM data unless another format is given with
+/// with_palette_format.
+pub struct MD4Encoder<'a, F: 'a> {
+    f: &'a mut F,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> MD4Encoder<'a, F> {
+    pub fn new(file: &'a mut F) -> MD4Encoder<'a, F> {
+        MD4Encoder {
+            f: file,
+            palette_format: PaletteColorFormat::MD
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as MD CRAM data.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> MD4Encoder<'a, F> {
+        MD4Encoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for MD4Encoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        15
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for MD4Encoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        encode_packed4(self.f, &data)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for MD4Encoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        decode_packed4(self.f, size, 32)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate num;
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::decoder::IndexedGraphicsDecoder;
+    use awsmimg::formats::md::{encode_palette, decode_palette, MD4Encoder};
+
+    #[test]
+    fn data4_encode() {
+        let src = num::range(0, 64).collect();
+        let mut test_out = Cursor::new(Vec::with_capacity(32));
+
+        {
+            let mut md4 = MD4Encoder::new(&mut test_out);
+
+            md4.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF,
+                                       0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF,
+                                       0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF,
+                                       0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn palette_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]),
+                           image::Rgba([0u8, 255, 0, 255]),
+                           image::Rgba([0u8, 0, 255, 255]),
+                           image::Rgba([0x20u8, 0x40, 0x60, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(8));
+
+        {
+            let mut md4 = MD4Encoder::new(&mut test_out);
+
+            md4.encode_palette(palette).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x00, 0x0E, 0x00, 0xE0, 0x0E, 0x00, 0x06, 0x42];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn data4_decode() {
+        let src : Vec<u8> = num::range(0, 128).map(|i: u32| (i * 7 % 16) as u8).collect();
+        let mut test_out = Cursor::new(Vec::with_capacity(64));
+
+        MD4Encoder::new(&mut test_out).encode_indexes(src.clone(), 16, 8).unwrap();
+        test_out.set_position(0);
+        let decoded : Vec<u8> = MD4Encoder::new(&mut test_out).decode_indexes(usize::MAX).unwrap();
+
+        assert_eq!(decoded, src);
+        assert!(MD4Encoder::new(&mut Cursor::new(vec![0u8; 33])).decode_indexes::<u8>(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn palette_decode() {
+        let src : Vec<u8> = vec![0x00, 0x0E, 0x00, 0xE0, 0x0E, 0x00, 0x06, 0x42];
+        let palette = decode_palette(&mut Cursor::new(&src), usize::MAX).unwrap();
+        let mut test_out = Cursor::new(Vec::with_capacity(8));
+
+        assert_eq!(palette, vec![image::Rgba([255u8, 0, 0, 255]),
+                                 image::Rgba([0u8, 255, 0, 255]),
+                                 image::Rgba([0u8, 0, 255, 255]),
+                                 image::Rgba([0x24u8, 0x49, 0x6D, 255])]);
+
+        encode_palette(&mut test_out, palette.into_iter()).unwrap();
+
+        assert_eq!(test_out.get_ref(), &src);
+        assert!(decode_palette(&mut Cursor::new(&src), 3).is_err());
+    }
+}
@@ -1,5 +1,44 @@
+/// The table of constructors for every named format.
+///
+/// Encoders and decoders are generic over their data source and have generic
+/// methods, so they cannot be boxed and looked up at runtime. Instead, each
+/// macro here expands to a match over a format enum that builds the format's
+/// encoder or decoder around the given reader or writer, binds it to the
+/// given name, and evaluates the body with it. Every *_with_format function
+/// dispatches through these, so a format added here is available to all of
+/// them.
+#[macro_use]
+mod registry;
 //TODO: Can we unpublish agb and provide encoders through boxed access only?
 pub mod agb;
+pub mod amiga;
+pub mod atarist;
+pub mod c64;
+pub mod ctr;
+pub mod dc;
+pub mod dmg;
+pub mod generic;
+pub mod lynx;
+pub mod md;
+pub mod msx;
+pub mod n64;
+pub mod neogeo;
+pub mod nes;
+pub mod ngp;
+pub mod ntr;
+pub mod palette;
+pub mod pc;
+pub mod pce;
+pub mod pico8;
+pub mod psx;
+pub mod saturn;
+pub mod sfc;
+pub mod sms;
+pub mod tic80;
+pub mod ws;
+pub mod x16;
+pub mod x68k;
+pub mod zx;
 
 /// Supertrait for encoders and decoders of indexed-color image formats.
 pub trait IndexedGraphicsProperties {
@@ -38,34 +77,253 @@ pub trait IndexedGraphicsProperties {
     fn palette_maxcol(&self) -> u16;
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum IndexedFormat {
     AGB4,       //4 bits per pixel, packed, arranged row-major in 8x8 tiles
     AGB8Tiled,  //8 bits per pixel, packed, arranged row-major in 8x8 tiles
-    AGB8Chunky  //8 bits per pixel, packed, arranged row-major
+    AGB8Chunky, //8 bits per pixel, packed, arranged row-major
+    GB2,        //2 bits per pixel, interleaved planes, arranged row-major in 8x8 tiles
+    CGB2,       //2 bits per pixel, interleaved planes, arranged row-major in 8x8 tiles, BGR555 palettes
+    SFC2,       //2 bits per pixel, interleaved planes, arranged row-major in 8x8 tiles
+    SFC4,       //4 bits per pixel, interleaved plane pairs, arranged row-major in 8x8 tiles
+    SFC8,       //8 bits per pixel, interleaved plane pairs, arranged row-major in 8x8 tiles
+    SFCMode7,   //8 bits per pixel, packed, arranged row-major in 8x8 tiles (character bytes only)
+    MD4,        //4 bits per pixel, packed high nibble first, arranged row-major in 8x8 tiles
+    SMS4,       //4 bits per pixel, byte-interleaved planes, arranged row-major in 8x8 tiles
+    NesChr,     //2 bits per pixel, plane-sequential, arranged row-major in 8x8 tiles
+    PCEBG,      //4 bits per pixel, word-interleaved plane pairs, arranged row-major in 8x8 tiles
+    PCESprite,  //4 bits per pixel, plane-sequential words, arranged row-major in 16x16 tiles
+    NeoGeoSprite, //4 bits per pixel, byte-interleaved planes, arranged column-major in 16x16 tiles of 8x8 blocks
+    NeoGeoFix,  //4 bits per pixel, packed low nibble first, arranged column-major in 8x8 tiles
+    NGP2,       //2 bits per pixel, packed high bits first in little-endian words, arranged row-major in 8x8 tiles
+    WS2,        //2 bits per pixel, interleaved planes, arranged row-major in 8x8 tiles
+    WSC4,       //4 bits per pixel, packed high nibble first, arranged row-major in 8x8 tiles
+    LynxSprite, //4 bits per pixel, packed high bits first, literal sprite packets with per-line offsets
+    N64CI4,     //4 bits per pixel, packed high nibble first, arranged row-major
+    N64CI8,     //8 bits per pixel, packed, arranged row-major
+    TIM4,       //4 bits per pixel, packed low nibble first, arranged row-major in a TIM container
+    TIM8,       //8 bits per pixel, packed, arranged row-major in a TIM container
+    Saturn4,    //4 bits per pixel, packed high nibble first, arranged row-major in 8x8 tiles
+    Saturn8,    //8 bits per pixel, packed, arranged row-major in 8x8 tiles
+    SaturnSprite, //4 bits per pixel, packed high nibble first, arranged row-major
+    NTRA3I5,    //8 bits per texel, 3 bits alpha and 5 bits index, arranged row-major
+    NTRA5I3,    //8 bits per texel, 5 bits alpha and 3 bits index, arranged row-major
+    AmigaSprite, //2 bits per pixel, word-interleaved planes, 16 pixel wide strips with control words
+    AmigaAttachedSprite, //4 bits per pixel, attached pairs of 2 bit sprites, 16 pixel wide strips with control words
+    AtariSTLow, //4 bits per pixel, word-interleaved planes, arranged row-major in 16 pixel groups
+    AtariSTMedium, //2 bits per pixel, word-interleaved planes, arranged row-major in 16 pixel groups
+    C64Charset, //1 bit per pixel, C64 hires character set
+    CGA,        //2 bits per pixel, CGA 320x200 packed pixels
+    EGA,        //4 bits per pixel, EGA planes one after another
+    VGA13h,     //8 bits per pixel, VGA mode 13h linear pixels
+    ModeX,      //8 bits per pixel, VGA mode X planes one after another
+    VERA2,      //2 bits per pixel, VERA 8x8 tiles
+    VERA4,      //4 bits per pixel, VERA 8x8 tiles
+    VERA8,      //8 bits per pixel, VERA 8x8 tiles
+    AGBMode4,   //8 bits per pixel, AGB mode 4 frame buffer pages
+    X68KGraphic, //X68000 16-color graphic VRAM
+    X68KPCG     //X68000 PCG sprite patterns
 }
 
+/// Every name an IndexedFormat can be given, and the format it names.
+pub const INDEXED_FORMAT_NAMES: &[(&str, IndexedFormat)] = &[
+    ("agb4", IndexedFormat::AGB4),
+    ("agb8t", IndexedFormat::AGB8Tiled),
+    ("agb8c", IndexedFormat::AGB8Chunky),
+    ("gb2", IndexedFormat::GB2),
+    ("cgb2", IndexedFormat::CGB2),
+    ("sfc2", IndexedFormat::SFC2),
+    ("sfc4", IndexedFormat::SFC4),
+    ("sfc8", IndexedFormat::SFC8),
+    ("sfc7", IndexedFormat::SFCMode7),
+    ("md4", IndexedFormat::MD4),
+    ("sms4", IndexedFormat::SMS4),
+    ("chr", IndexedFormat::NesChr),
+    ("pcebg", IndexedFormat::PCEBG),
+    ("pcespr", IndexedFormat::PCESprite),
+    ("neogeo", IndexedFormat::NeoGeoSprite),
+    ("neogeofix", IndexedFormat::NeoGeoFix),
+    ("ngp2", IndexedFormat::NGP2),
+    ("ws2", IndexedFormat::WS2),
+    ("wsc4", IndexedFormat::WSC4),
+    ("lynx", IndexedFormat::LynxSprite),
+    ("n64ci4", IndexedFormat::N64CI4),
+    ("n64ci8", IndexedFormat::N64CI8),
+    ("tim4", IndexedFormat::TIM4),
+    ("tim8", IndexedFormat::TIM8),
+    ("sat4", IndexedFormat::Saturn4),
+    ("sat8", IndexedFormat::Saturn8),
+    ("satspr", IndexedFormat::SaturnSprite),
+    ("a3i5", IndexedFormat::NTRA3I5),
+    ("a5i3", IndexedFormat::NTRA5I3),
+    ("amigaspr", IndexedFormat::AmigaSprite),
+    ("amigaspr4", IndexedFormat::AmigaAttachedSprite),
+    ("stlow", IndexedFormat::AtariSTLow),
+    ("stmed", IndexedFormat::AtariSTMedium),
+    ("c64char", IndexedFormat::C64Charset),
+    ("cga", IndexedFormat::CGA),
+    ("ega", IndexedFormat::EGA),
+    ("vga13h", IndexedFormat::VGA13h),
+    ("modex", IndexedFormat::ModeX),
+    ("vera2", IndexedFormat::VERA2),
+    ("vera4", IndexedFormat::VERA4),
+    ("vera8", IndexedFormat::VERA8),
+    ("agbm4", IndexedFormat::AGBMode4),
+    ("x68kgvram", IndexedFormat::X68KGraphic),
+    ("x68kpcg", IndexedFormat::X68KPCG)
+];
+
 pub fn interpret_indexed_format_name(fmt_given: &str) -> Option<IndexedFormat> {
     let fmt = fmt_given.to_ascii_lowercase();
     
-    match fmt.as_ref() {
-        "agb4" => Some(IndexedFormat::AGB4),
-        "agb8t" => Some(IndexedFormat::AGB8Tiled),
-        "agb8c" => Some(IndexedFormat::AGB8Chunky),
-        _ => None
-    }
+    INDEXED_FORMAT_NAMES.iter().find(|(name, _)| *name == fmt).map(|(_, format)| *format)
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum DirectFormat {
     AGB16, //16 bits per pixel, packed, RGB5N1, arragned row-major
-    NTR16  //16 bits per pixel, packed, RGB5A1, arragned row-major
+    NTR16, //16 bits per pixel, packed, RGB5A1, arragned row-major
+    N64RGBA16, //16 bits per pixel, big-endian RGBA5551, arranged row-major
+    N64RGBA32, //32 bits per pixel, RGBA8888, arranged row-major
+    N64IA4, //4 bits per pixel, 3 bits intensity and 1 bit alpha, arranged row-major
+    N64IA8, //8 bits per pixel, 4 bits intensity and 4 bits alpha, arranged row-major
+    N64I4, //4 bits per pixel, intensity only, arranged row-major
+    N64I8, //8 bits per pixel, intensity only, arranged row-major
+    TIM16, //16 bits per pixel, packed, RGB5 with STP bit, arranged row-major in a TIM container
+    SaturnRGB, //16 bits per pixel, big-endian, RGB5 with MSB set, arranged row-major
+    NTRTex4x4, //2 bits per texel, 4x4 blocks with per-block palette index data and palette
+    NTRBitmapOBJ, //16 bits per pixel, packed, RGB5 with alpha bit set, arranged row-major
+    CTRRGBA4444, //16 bits per pixel, little-endian RGBA4444, Morton order in 8x8 tiles
+    CTRRGB565, //16 bits per pixel, little-endian RGB565, Morton order in 8x8 tiles
+    CTRRGBA8, //32 bits per pixel, ABGR8888, Morton order in 8x8 tiles
+    DCARGB1555, //16 bits per pixel, little-endian ARGB1555, twiddled
+    DCRGB565, //16 bits per pixel, little-endian RGB565, twiddled
+    DCARGB4444, //16 bits per pixel, little-endian ARGB4444, twiddled
+    DCVQARGB1555, //VQ compressed ARGB1555, twiddled 2x2 codebook entries and indexes
+    DCVQRGB565, //VQ compressed RGB565, twiddled 2x2 codebook entries and indexes
+    DCVQARGB4444, //VQ compressed ARGB4444, twiddled 2x2 codebook entries and indexes
+    ZXScreen, //1 bit per pixel, Spectrum screen order bitmap followed by 32x24 attributes
+    C64Multicolor, //2 bits per pixel, 4x8 cells with screen RAM, color RAM and background color
+    MSXScreen2, //1 bit per pixel, pattern table followed by color table with 2 colors per 8x1 line
+    Pico8Gfx, //4 bits per pixel, PICO-8 __gfx__ section as hex text
+    TIC80Sprites, //4 bits per pixel, TIC-80 <SPRITES> chunk as hex text
+    RGB888, //24 bits per pixel, RGB888, arranged row-major
+    RGBA8888, //32 bits per pixel, RGBA8888, arranged row-major
+    AGBMode3, //16 bits per pixel, packed, RGB5N1, 240x160 frame buffer
+    AGBMode5, //16 bits per pixel, packed, RGB5N1, 160x128 frame buffer pages
+    SFCHDMAGradient //HDMA table of per-scanline CGRAM writes from a 1 pixel wide image
 }
 
+/// Every name a DirectFormat can be given, and the format it names.
+pub const DIRECT_FORMAT_NAMES: &[(&str, DirectFormat)] = &[
+    ("agb16", DirectFormat::AGB16),
+    ("ntr16", DirectFormat::NTR16),
+    ("n64rgba16", DirectFormat::N64RGBA16),
+    ("n64rgba32", DirectFormat::N64RGBA32),
+    ("n64ia4", DirectFormat::N64IA4),
+    ("n64ia8", DirectFormat::N64IA8),
+    ("n64i4", DirectFormat::N64I4),
+    ("n64i8", DirectFormat::N64I8),
+    ("tim16", DirectFormat::TIM16),
+    ("satrgb", DirectFormat::SaturnRGB),
+    ("tex4x4", DirectFormat::NTRTex4x4),
+    ("ntrobj", DirectFormat::NTRBitmapOBJ),
+    ("ctrrgba4", DirectFormat::CTRRGBA4444),
+    ("ctrrgb565", DirectFormat::CTRRGB565),
+    ("ctrrgba8", DirectFormat::CTRRGBA8),
+    ("dc1555", DirectFormat::DCARGB1555),
+    ("dc565", DirectFormat::DCRGB565),
+    ("dc4444", DirectFormat::DCARGB4444),
+    ("dcvq1555", DirectFormat::DCVQARGB1555),
+    ("dcvq565", DirectFormat::DCVQRGB565),
+    ("dcvq4444", DirectFormat::DCVQARGB4444),
+    ("scr", DirectFormat::ZXScreen),
+    ("c64mc", DirectFormat::C64Multicolor),
+    ("sc2", DirectFormat::MSXScreen2),
+    ("p8gfx", DirectFormat::Pico8Gfx),
+    ("ticspr", DirectFormat::TIC80Sprites),
+    ("rgb888", DirectFormat::RGB888),
+    ("rgba8888", DirectFormat::RGBA8888),
+    ("agbm3", DirectFormat::AGBMode3),
+    ("agbm5", DirectFormat::AGBMode5),
+    ("sfchdma", DirectFormat::SFCHDMAGradient)
+];
+
 pub fn interpret_direct_format_name(fmt_given: &str) -> Option<DirectFormat> {
     let fmt = fmt_given.to_ascii_lowercase();
     
-    match fmt.as_ref() {
-        "agb16" => Some(DirectFormat::AGB16),
-        "ntr16" => Some(DirectFormat::NTR16),
-        _ => None
+    DIRECT_FORMAT_NAMES.iter().find(|(name, _)| *name == fmt).map(|(_, format)| *format)
+}
+
+/// Either kind of image format, as named by interpret_format_name.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format {
+    Indexed(IndexedFormat),
+    Direct(DirectFormat)
+}
+
+/// Look up a format of either kind by name.
+/// 
+/// This is the single table of format names shared by the encoding and
+/// decoding entry points, so a format's name only needs to be listed in
+/// INDEXED_FORMAT_NAMES or DIRECT_FORMAT_NAMES for it to be usable in both
+/// directions.
+pub fn interpret_format_name(fmt_given: &str) -> Option<Format> {
+    match interpret_indexed_format_name(fmt_given) {
+        Some(f) => Some(Format::Indexed(f)),
+        None => interpret_direct_format_name(fmt_given).map(Format::Direct)
+    }
+}
+
+/// The 8-bit alpha at or above which pixels and colors count as opaque, where
+/// alpha is reduced to a single bit or decides which pixels to encode at all.
+///
+/// Index conversion, palette formats and direct color encoders all default to
+/// this, so that they agree on which colors are transparent.
+pub const DEFAULT_ALPHA_THRESHOLD: u8 = 0x80;
+
+/// How color channels are reduced from 8 bits to the fewer bits hardware
+/// palettes store.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum ChannelRounding {
+    #[default]
+    Truncate,    //Drop the low bits, as a shift does; darkens colors slightly
+    RoundHalfUp, //Round to the nearest step of the reduced channel, saturating at the top
+    Scale        //Map 0-255 evenly onto the reduced range, rounding to nearest
+}
+
+impl ChannelRounding {
+    /// Reduce an 8-bit channel value to the given number of bits.
+    pub fn reduce(self, value: u8, bits: u32) -> u16 {
+        let max = (1u32 << bits) - 1;
+        let shift = 8 - bits;
+        let value = value as u32;
+
+        (match self {
+            ChannelRounding::Truncate => value >> shift,
+            ChannelRounding::RoundHalfUp if shift == 0 => value,
+            ChannelRounding::RoundHalfUp => ((value + (1 << (shift - 1))) >> shift).min(max),
+            ChannelRounding::Scale => (value * max + 127) / 255
+        }) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use awsmimg::formats::ChannelRounding;
+
+    #[test]
+    fn channel_rounding_boundaries() {
+        let reduce = |mode: ChannelRounding, values: &[u8]| -> Vec<u16> {
+            values.iter().map(|v| mode.reduce(*v, 5)).collect()
+        };
+        let values = [0u8, 3, 4, 7, 8, 11, 12, 123, 124, 251, 252, 255];
+
+        assert_eq!(reduce(ChannelRounding::Truncate, &values), vec![0, 0, 0, 0, 1, 1, 1, 15, 15, 31, 31, 31]);
+        assert_eq!(reduce(ChannelRounding::RoundHalfUp, &values), vec![0, 0, 1, 1, 1, 1, 2, 15, 16, 31, 31, 31]);
+        assert_eq!(reduce(ChannelRounding::Scale, &values), vec![0, 0, 0, 1, 1, 1, 1, 15, 15, 31, 31, 31]);
+        assert_eq!(ChannelRounding::RoundHalfUp.reduce(200, 8), 200);
+        assert_eq!(ChannelRounding::Scale.reduce(128, 1), 1);
+        assert_eq!(ChannelRounding::default(), ChannelRounding::Truncate);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,261 @@
+use awsmimg::encoder::DirectGraphicsEncoder;
+use awsmimg::decoder::{DirectGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{GenericImage, ImageBuffer, Primitive, Pixel, Rgba};
+
+/// The RGB values of the 15 opaque TMS9918 colors. Color 0 is transparent.
+const MSX_PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x21, 0xC8, 0x42], [0x5E, 0xDC, 0x78],
+    [0x54, 0x55, 0xED], [0x7D, 0x76, 0xFC], [0xD4, 0x52, 0x4D], [0x42, 0xEB, 0xF5],
+    [0xFC, 0x55, 0x54], [0xFF, 0x79, 0x78], [0xD4, 0xC1, 0x54], [0xE6, 0xCE, 0x80],
+    [0x21, 0xB0, 0x3B], [0xC9, 0x5B, 0xBA], [0xCC, 0xCC, 0xCC], [0xFF, 0xFF, 0xFF]
+];
+
+/// Distance between a pixel and a TMS9918 color number. Transparent pixels,
+/// given as None, only match color 0.
+fn msx_distance(px: Option<[u8; 3]>, color: u8) -> u32 {
+    match (px, color) {
+        (None, 0) => 0,
+        (None, _) | (Some(_), 0) => u32::MAX / 16,
+        (Some(rgb), c) => (0..3).map(|i| (rgb[i] as i32 - MSX_PALETTE[c as usize][i] as i32).pow(2) as u32).sum()
+    }
+}
+
+/// Encoder for Screen 2 graphics for the MSX platform.
+///
+/// Image dimensions must be multiples of 8; a full screen is 256x192. The
+/// image is split into 8x8 tiles, left to right then top to bottom, matching
+/// the default sequential pattern name table. Each tile has eight bytes in the
+/// pattern generator table, one per row with the leftmost pixel in the most
+/// significant bit, and eight bytes in the color table, one per row with the
+/// foreground color in the high nibble and the background color in the low
+/// nibble. Set pattern bits select the foreground color.
+///
+/// Each 8x1 line can only hold two colors. For each line, the pair of TMS9918
+/// colors that represents it with the least error is chosen, and every pixel
+/// is mapped to the nearer of the pair. The more common color of the pair
+/// becomes the background color; lines of a single color use it for both.
+/// Pixels that are less than half opaque use the transparent color 0.
+///
+/// When constructed with new_split, the pattern and color tables are written
+/// to separate writers. When constructed with new, they are written to one
+/// writer in that order.
+pub struct MSXScreen2Encoder<'a, W: Write + 'a> {
+    w: &'a mut W,
+    color: Option<&'a mut W>
+}
+
+impl<'a, W: Write + 'a> MSXScreen2Encoder<'a, W> {
+    pub fn new(write: &'a mut W) -> MSXScreen2Encoder<'a, W> {
+        MSXScreen2Encoder {
+            w: write,
+            color: None
+        }
+    }
+
+    pub fn new_split(pattern: &'a mut W, color: &'a mut W) -> MSXScreen2Encoder<'a, W> {
+        MSXScreen2Encoder {
+            w: pattern,
+            color: Some(color)
+        }
+    }
+}
+
+impl<'a, W: Write> DirectGraphicsEncoder for MSXScreen2Encoder<'a, W> {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        let (width, height) = image.dimensions();
+
+        if !width.is_multiple_of(8) || !height.is_multiple_of(8) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "MSX Screen 2 dimensions must be multiples of 8."));
+        }
+
+        let imgmax = S::max_value().to_f32().unwrap();
+        let mut pattern_out: Vec<u8> = Vec::with_capacity((width * height / 8) as usize);
+        let mut color_out: Vec<u8> = Vec::with_capacity((width * height / 8) as usize);
+
+        for ty in 0..height / 8 {
+            for tx in 0..width / 8 {
+                for y in ty * 8..ty * 8 + 8 {
+                    let line: Vec<Option<[u8; 3]>> = (tx * 8..tx * 8 + 8).map(|x| {
+                        let rgba = image.get_pixel(x, y).to_rgba();
+                        let mut c = [0u8; 4];
+
+                        for (i, v) in c.iter_mut().enumerate() {
+                            *v = (rgba[i].to_f32().unwrap() / imgmax * 255f32) as u8;
+                        }
+
+                        match c[3] {
+                            a if a < 0x80 => None,
+                            _ => Some([c[0], c[1], c[2]])
+                        }
+                    }).collect();
+
+                    //Pick the pair of colors with the least error.
+                    let mut best: Option<(u32, u8, u8)> = None;
+
+                    for a in 0..16 {
+                        for b in a..16 {
+                            let error: u32 = line.iter().map(|px| msx_distance(*px, a).min(msx_distance(*px, b))).sum();
+
+                            if best.is_none_or(|(e, _, _)| error < e) {
+                                best = Some((error, a, b));
+                            }
+                        }
+                    }
+
+                    let (_, a, b) = best.unwrap();
+                    let is_b: Vec<bool> = line.iter().map(|px| msx_distance(*px, b) < msx_distance(*px, a)).collect();
+                    let (bg, fg) = match is_b.iter().filter(|v| **v).count() {
+                        0 => (a, a),
+                        8 => (b, b),
+                        n if n > 4 => (b, a),
+                        _ => (a, b)
+                    };
+                    let mut byte = 0u8;
+
+                    for (x, v) in is_b.iter().enumerate() {
+                        if fg != bg && (*v == (fg == b)) {
+                            byte |= 0x80 >> x;
+                        }
+                    }
+
+                    pattern_out.push(byte);
+                    color_out.push(fg << 4 | bg);
+                }
+            }
+        }
+
+        self.w.write_all(&pattern_out)?;
+
+        match self.color {
+            Some(ref mut color) => color.write_all(&color_out),
+            None => self.w.write_all(&color_out)
+        }
+    }
+}
+
+/// Decoder for Screen 2 graphics for the MSX platform.
+///
+/// This reads data written by MSXScreen2Encoder. When constructed with
+/// new_split, the pattern and color tables are read from separate readers, up
+/// to size bytes each. When constructed with new, they are read from one
+/// reader in that order and must be the same length. Color 0 decodes as fully
+/// transparent.
+pub struct MSXScreen2Decoder<'a, R: Read + 'a> {
+    r: &'a mut R,
+    color: Option<&'a mut R>
+}
+
+impl<'a, R: Read + 'a> MSXScreen2Decoder<'a, R> {
+    pub fn new(read: &'a mut R) -> MSXScreen2Decoder<'a, R> {
+        MSXScreen2Decoder {
+            r: read,
+            color: None
+        }
+    }
+
+    pub fn new_split(pattern: &'a mut R, color: &'a mut R) -> MSXScreen2Decoder<'a, R> {
+        MSXScreen2Decoder {
+            r: pattern,
+            color: Some(color)
+        }
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for MSXScreen2Decoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        if width == 0 || !width.is_multiple_of(8) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "MSX Screen 2 dimensions must be multiples of 8."));
+        }
+
+        let row_bytes = width as usize;
+        let (pattern, color) = match self.color {
+            Some(ref mut color) => (
+                read_units(self.r, size, row_bytes, "Pattern table ends partway through a row of tiles")?,
+                read_units(*color, size, row_bytes, "Color table ends partway through a row of tiles")?
+            ),
+            None => {
+                let buf = read_units(self.r, size, row_bytes * 2, "Screen 2 data ends partway through a row of tiles")?;
+                let (pattern, color) = buf.split_at(buf.len() / 2);
+
+                (pattern.to_vec(), color.to_vec())
+            }
+        };
+
+        if pattern.len() != color.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Pattern and color tables are not the same length."));
+        }
+
+        let row_tiles = (width / 8) as usize;
+
+        Ok(ImageBuffer::from_fn(width, (pattern.len() / row_bytes) as u32 * 8, |x, y| {
+            let line = ((y / 8) as usize * row_tiles + (x / 8) as usize) * 8 + (y % 8) as usize;
+            let c = match pattern[line] & (0x80 >> (x % 8)) {
+                0 => color[line] & 0x0F,
+                _ => color[line] >> 4
+            };
+            let rgb = MSX_PALETTE[c as usize];
+
+            match c {
+                0 => Rgba([0, 0, 0, 0]),
+                _ => Rgba([rgb[0], rgb[1], rgb[2], 255])
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::DirectGraphicsEncoder;
+    use awsmimg::formats::msx::MSXScreen2Encoder;
+
+    fn test_image() -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+        image::ImageBuffer::from_fn(8, 8, |x, y| {
+            match (x, y) {
+                (0, 0) => image::Rgba([0xFCu8, 0x55, 0x54, 255]),
+                (0, 1) | (1, 1) => image::Rgba([0xFFu8, 0xFF, 0xFF, 255]),
+                (2, 1) => image::Rgba([0x42u8, 0xEB, 0xF5, 255]),
+                (_, 2) => image::Rgba([0xFFu8, 0xFF, 0xFF, 255]),
+                (x, 3) if x < 6 => image::Rgba([0x54u8, 0x55, 0xED, 255]),
+                (_, 3) => image::Rgba([0u8, 0, 0, 0]),
+                _ => image::Rgba([0u8, 0, 0, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn screen2_encode() {
+        let mut test_out = Cursor::new(Vec::with_capacity(16));
+
+        {
+            let mut msx = MSXScreen2Encoder::new(&mut test_out);
+
+            msx.encode_colors(&test_image()).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x80, 0xE0, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00,
+                                       0x81, 0xF1, 0xFF, 0x04, 0x11, 0x11, 0x11, 0x11];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn screen2_split_encode() {
+        let mut pattern = Cursor::new(Vec::with_capacity(8));
+        let mut color = Cursor::new(Vec::with_capacity(8));
+
+        {
+            let mut msx = MSXScreen2Encoder::new_split(&mut pattern, &mut color);
+
+            msx.encode_colors(&test_image()).unwrap();
+        }
+
+        assert_eq!(pattern.get_ref(), &vec![0x80u8, 0xE0, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(color.get_ref(), &vec![0x81u8, 0xF1, 0xFF, 0x04, 0x11, 0x11, 0x11, 0x11]);
+    }
+}
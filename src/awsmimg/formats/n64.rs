@@ -0,0 +1,324 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::md::{encode_packed4, decode_packed4};
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, RGBA5551, expand_channel};
+use awsmimg::formats::generic::image_from_rows;
+use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
+use awsmimg::decoder::{IndexedGraphicsDecoder, DirectGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{GenericImage, ImageBuffer, Primitive, Rgba, Pixel};
+
+/// Encode a series of RGBA colors as an N64 texture lookup table.
+///
+/// Colors are written as big-endian RGBA5551 words in the RRRRRGGGGGBBBBBA
+/// layout, as palette::RGBA5551 does by default.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    RGBA5551::default().encode_colors(w, palette)
+}
+
+/// Encoder for color-indexed (CI) textures for the N64 platform.
+///
+/// Textures are stored row-major without tiling. Use new_ci4 for 4bpp
+/// textures, packed two pixels to a byte with the leftmost pixel in the high
+/// nibble, or new_ci8 for 8bpp textures with one pixel per byte.
+///
+/// Palettes are written as a TLUT suitable for loading into TMEM, unless
+/// another format is given with with_palette_format.
+pub struct N64CIEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    ci8: bool,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> N64CIEncoder<'a, F> {
+    pub fn new_ci4(file: &'a mut F) -> N64CIEncoder<'a, F> {
+        N64CIEncoder {
+            f: file,
+            ci8: false,
+            palette_format: PaletteColorFormat::RGBA5551
+        }
+    }
+
+    pub fn new_ci8(file: &'a mut F) -> N64CIEncoder<'a, F> {
+        N64CIEncoder {
+            f: file,
+            ci8: true,
+            palette_format: PaletteColorFormat::RGBA5551
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as a TLUT.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> N64CIEncoder<'a, F> {
+        N64CIEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for N64CIEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        match self.ci8 {
+            true => 255,
+            false => 15
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for N64CIEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        if !self.ci8 {
+            return encode_packed4(self.f, &data);
+        }
+
+        let mut out: [u8; 1] = [0];
+
+        for index in data {
+            out[0] = index.to_u8().unwrap();
+            self.f.write_all(&out)?;
+        }
+
+        Ok(())
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for N64CIEncoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        match self.ci8 {
+            true => Ok(read_units(self.f, size, 1, "CI8 data ends partway through a pixel")?.into_iter().map(|index| P::from(index).unwrap()).collect()),
+            false => decode_packed4(self.f, size, 1)
+        }
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        match self.ci8 {
+            true => Some(1),
+            false => None
+        }
+    }
+}
+
+/// The direct-color texture formats supported by N64DirectEncoder.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum N64TextureFormat {
+    RGBA16, //16 bits per pixel, big-endian RGBA5551
+    RGBA32, //32 bits per pixel, RGBA8888
+    IA4,    //4 bits per pixel, 3 bits intensity and 1 bit alpha, packed high nibble first
+    IA8,    //8 bits per pixel, 4 bits intensity and 4 bits alpha
+    I4,     //4 bits per pixel, intensity only, packed high nibble first
+    I8      //8 bits per pixel, intensity only
+}
+
+/// Encoder for direct-color textures for the N64 platform.
+///
+/// Textures are stored row-major without tiling. The RGBA formats store color
+/// data directly, while the intensity formats store the luma of each pixel as
+/// computed by the image crate; I4 and I8 discard alpha entirely.
+pub struct N64DirectEncoder<'a, W: Write + 'a> {
+    w: &'a mut W,
+    format: N64TextureFormat
+}
+
+impl<'a, W: Write + 'a> N64DirectEncoder<'a, W> {
+    pub fn new(write: &'a mut W, format: N64TextureFormat) -> N64DirectEncoder<'a, W> {
+        N64DirectEncoder {
+            w: write,
+            format
+        }
+    }
+}
+
+impl<'a, W: Write> DirectGraphicsEncoder for N64DirectEncoder<'a, W> {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        let imgmax = S::max_value().to_f32().unwrap();
+        let mut out: Vec<u8> = Vec::new();
+
+        for (_, _, pixel) in image.pixels() {
+            match self.format {
+                N64TextureFormat::RGBA16 => {
+                    encode_palette(&mut out, Some(pixel.to_rgba()).into_iter())?;
+                },
+                N64TextureFormat::RGBA32 => {
+                    let rgba = pixel.to_rgba();
+
+                    for c in 0..4 {
+                        out.push((rgba[c].to_f32().unwrap() / imgmax * 255f32) as u8);
+                    }
+                },
+                _ => {
+                    let la = pixel.to_luma_alpha();
+                    let i = (la[0].to_f32().unwrap() / imgmax * 255f32) as u8;
+                    let a = (la[1].to_f32().unwrap() / imgmax * 255f32) as u8;
+
+                    match self.format {
+                        N64TextureFormat::IA4 => out.push((i & 0xE0) >> 4 | a >> 7),
+                        N64TextureFormat::IA8 => out.push((i & 0xF0) | a >> 4),
+                        N64TextureFormat::I4 => out.push(i >> 4),
+                        _ => out.push(i)
+                    }
+                }
+            }
+        }
+
+        match self.format {
+            N64TextureFormat::IA4 | N64TextureFormat::I4 => encode_packed4(self.w, &out),
+            _ => self.w.write_all(&out)
+        }
+    }
+}
+
+/// Decoder for direct-color textures for the N64 platform.
+///
+/// This reads textures written by an N64DirectEncoder in the same format.
+/// Intensity formats are decoded as gray pixels, which are opaque unless the
+/// format has alpha. 4-bit formats pack two pixels to a byte, so textures of
+/// odd width can only be decoded if they have an even number of rows.
+pub struct N64DirectDecoder<'a, R: Read + 'a> {
+    r: &'a mut R,
+    format: N64TextureFormat
+}
+
+impl<'a, R: Read + 'a> N64DirectDecoder<'a, R> {
+    pub fn new(read: &'a mut R, format: N64TextureFormat) -> N64DirectDecoder<'a, R> {
+        N64DirectDecoder {
+            r: read,
+            format
+        }
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for N64DirectDecoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        let gray = |i: u8, a: u8| Rgba([i, i, i, a]);
+        let colors: Vec<Rgba<u8>> = match self.format {
+            N64TextureFormat::RGBA16 => RGBA5551::default().decode_colors(self.r, size)?,
+            N64TextureFormat::RGBA32 => read_units(self.r, size, 4, "RGBA32 data ends partway through a pixel")?.chunks(4).map(|c| Rgba([c[0], c[1], c[2], c[3]])).collect(),
+            N64TextureFormat::IA4 => decode_packed4::<_, u16>(self.r, size, 1)?.into_iter().map(|v| gray(expand_channel(v >> 1, 3), (v & 0x01) as u8 * 255)).collect(),
+            N64TextureFormat::IA8 => read_units(self.r, size, 1, "Texture data ends partway through a pixel")?.into_iter().map(|v| gray(expand_channel((v >> 4) as u16, 4), expand_channel(v as u16, 4))).collect(),
+            N64TextureFormat::I4 => decode_packed4::<_, u16>(self.r, size, 1)?.into_iter().map(|v| gray(expand_channel(v, 4), 255)).collect(),
+            N64TextureFormat::I8 => read_units(self.r, size, 1, "Texture data ends partway through a pixel")?.into_iter().map(|v| gray(v, 255)).collect()
+        };
+
+        image_from_rows(colors, width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate num;
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
+    use awsmimg::formats::n64::{N64CIEncoder, N64DirectEncoder, N64TextureFormat};
+
+    fn test_image() -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+        image::ImageBuffer::from_fn(2, 2, |x, y| {
+            match (x, y) {
+                (0, 0) => image::Rgba([255u8, 255, 255, 255]),
+                (1, 0) => image::Rgba([0u8, 0, 0, 0]),
+                (0, 1) => image::Rgba([255u8, 0, 0, 128]),
+                _ => image::Rgba([0x80u8, 0x80, 0x80, 255])
+            }
+        })
+    }
+
+    fn encode_direct(format: N64TextureFormat) -> Vec<u8> {
+        let mut test_out = Cursor::new(Vec::new());
+
+        {
+            let mut n64 = N64DirectEncoder::new(&mut test_out, format);
+
+            n64.encode_colors(&test_image()).unwrap();
+        }
+
+        test_out.into_inner()
+    }
+
+    #[test]
+    fn ci4_encode() {
+        let src = num::range(0, 16).collect();
+        let mut test_out = Cursor::new(Vec::with_capacity(8));
+
+        {
+            let mut ci4 = N64CIEncoder::new_ci4(&mut test_out);
+
+            ci4.encode_indexes(src, 4, 4).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn ci8_encode() {
+        let src = num::range(0, 16).collect();
+        let mut test_out = Cursor::new(Vec::with_capacity(16));
+
+        {
+            let mut ci8 = N64CIEncoder::new_ci8(&mut test_out);
+
+            ci8.encode_indexes(src, 4, 4).unwrap();
+        }
+
+        let valid_out : Vec<u8> = num::range(0, 16).collect();
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn tlut_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]),
+                           image::Rgba([0u8, 255, 0, 255]),
+                           image::Rgba([0u8, 0, 255, 0]),
+                           image::Rgba([0x08u8, 0x10, 0x18, 0x80])];
+        let mut test_out = Cursor::new(Vec::with_capacity(8));
+
+        {
+            let mut ci4 = N64CIEncoder::new_ci4(&mut test_out);
+
+            ci4.encode_palette(palette).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0xF8, 0x01, 0x07, 0xC1, 0x00, 0x3E, 0x08, 0x87];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn rgba16_encode() {
+        assert_eq!(encode_direct(N64TextureFormat::RGBA16), vec![0xFF, 0xFF, 0x00, 0x00, 0xF8, 0x01, 0x84, 0x21]);
+    }
+
+    #[test]
+    fn rgba32_encode() {
+        assert_eq!(encode_direct(N64TextureFormat::RGBA32), vec![0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00,
+                                                                 0xFF, 0x00, 0x00, 0x80, 0x80, 0x80, 0x80, 0xFF]);
+    }
+
+    #[test]
+    fn intensity_encode() {
+        assert_eq!(encode_direct(N64TextureFormat::IA4), vec![0xF0, 0x39]);
+        assert_eq!(encode_direct(N64TextureFormat::IA8), vec![0xFF, 0x00, 0x38, 0x8F]);
+        assert_eq!(encode_direct(N64TextureFormat::I4), vec![0xF0, 0x38]);
+        assert_eq!(encode_direct(N64TextureFormat::I8), vec![0xFF, 0x00, 0x36, 0x80]);
+    }
+}
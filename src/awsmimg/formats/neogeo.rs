@@ -0,0 +1,424 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, read_color_words, expand_channel};
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::{IndexedGraphicsDecoder, read_units};
+use awsmimg::tiles::TileChunkIterator;
+
+use std::io;
+use std::io::{Write, Read};
+use image::{Primitive, Rgba};
+
+/// Encode a series of RGBA colors as Neo Geo palette RAM data.
+///
+/// Colors are written as big-endian words in the DRGBRRRRGGGGBBBB layout,
+/// where the lowest bit of each 5-bit channel is stored separately in bits
+/// 12-14. The dark bit is never set. Alpha is ignored.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 2] = [0, 0];
+
+    for rgba in palette {
+        let r : u16 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16 >> 3;
+        let g : u16 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16 >> 3;
+        let b : u16 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16 >> 3;
+
+        let enc_color: u16 = (r & 0x01) << 14 | (g & 0x01) << 13 | (b & 0x01) << 12 |
+                             (r >> 1) << 8 | (g >> 1) << 4 | b >> 1;
+
+        out[0] = ((enc_color >> 8) & 0xFF) as u8;
+        out[1] = (enc_color & 0xFF) as u8;
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode Neo Geo palette RAM data written by encode_palette into RGBA
+/// colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. Each
+/// channel's shared low bit is recombined with its four high bits. The dark
+/// bit is ignored, and every color is opaque.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    let channel = |v: u16, high: u32, low: u32| expand_channel(((v >> high) & 0x0F) << 1 | (v >> low) & 0x01, 5);
+
+    Ok(read_color_words(r, size, true)?.into_iter().map(|v| {
+        Rgba([channel(v, 8, 14), channel(v, 4, 13), channel(v, 0, 12), 255])
+    }).collect())
+}
+
+/// Encoder for 4bpp sprite tiles stored in Neo Geo C-ROMs.
+///
+/// Sprite tiles are 16x16 and made of four 8x8 blocks, stored in the order
+/// upper right, lower right, upper left, lower left. Each row of a block is
+/// four bytes, one per bitplane, with the leftmost pixel in the least
+/// significant bit. Bitplanes 0 and 1 are stored in the odd C-ROM of a pair
+/// (C1, C3...) and bitplanes 2 and 3 in the even C-ROM (C2, C4...).
+///
+/// When constructed with new_split, the two halves are written to separate
+/// writers. When constructed with new, both halves are written to one writer
+/// with the odd C-ROM in even bytes and the even C-ROM in odd bytes, as they
+/// appear on the 16-bit bus.
+///
+/// Palettes are written as Neo Geo palette RAM data unless another format is
+/// given with with_palette_format.
+pub struct NeoGeoSpriteEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    c2: Option<&'a mut F>,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> NeoGeoSpriteEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> NeoGeoSpriteEncoder<'a, F> {
+        NeoGeoSpriteEncoder {
+            f: file,
+            c2: None,
+            palette_format: PaletteColorFormat::NeoGeo
+        }
+    }
+
+    pub fn new_split(c1: &'a mut F, c2: &'a mut F) -> NeoGeoSpriteEncoder<'a, F> {
+        NeoGeoSpriteEncoder {
+            f: c1,
+            c2: Some(c2),
+            palette_format: PaletteColorFormat::NeoGeo
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as Neo Geo palette
+    /// RAM data.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> NeoGeoSpriteEncoder<'a, F> {
+        NeoGeoSpriteEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for NeoGeoSpriteEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (16, 16)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (16, 16)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        15
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for NeoGeoSpriteEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        let mut c1_out: Vec<u8> = Vec::with_capacity(64);
+        let mut c2_out: Vec<u8> = Vec::with_capacity(64);
+
+        for tile in TileChunkIterator::new(&data, self.tile_size()) {
+            c1_out.clear();
+            c2_out.clear();
+
+            for &(bx, by) in &[(8, 0), (8, 8), (0, 0), (0, 8)] {
+                for y in by..by + 8 {
+                    let mut planes: [u8; 4] = [0; 4];
+
+                    for x in 0..8 {
+                        let index = tile[y * 16 + bx + x].to_u8().unwrap();
+
+                        for (plane, byte) in planes.iter_mut().enumerate() {
+                            *byte |= ((index >> plane) & 0x01) << x;
+                        }
+                    }
+
+                    c1_out.extend_from_slice(&planes[0..2]);
+                    c2_out.extend_from_slice(&planes[2..4]);
+                }
+            }
+
+            match self.c2 {
+                Some(ref mut c2) => {
+                    self.f.write_all(&c1_out)?;
+                    c2.write_all(&c2_out)?;
+                },
+                None => {
+                    let interleaved: Vec<u8> = c1_out.iter().zip(c2_out.iter()).flat_map(|(a, b)| vec![*a, *b]).collect();
+
+                    self.f.write_all(&interleaved)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for NeoGeoSpriteEncoder<'a, F> where F: Read {
+    /// Decode sprite tiles.
+    ///
+    /// Decoders constructed with new_split read up to half of size bytes from
+    /// each C-ROM, which must hold the same number of tiles.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let (c1, c2): (Vec<u8>, Vec<u8>) = match self.c2 {
+            Some(ref mut c2) => {
+                let c1 = read_units(self.f, size / 2, 64, "Neo Geo sprite data ends partway through a tile")?;
+                let c2 = read_units(*c2, size / 2, 64, "Neo Geo sprite data ends partway through a tile")?;
+
+                if c1.len() != c2.len() {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Neo Geo C-ROM pair holds different numbers of tiles"));
+                }
+
+                (c1, c2)
+            },
+            None => {
+                let buf = read_units(self.f, size, 128, "Neo Geo sprite data ends partway through a tile")?;
+
+                (buf.iter().step_by(2).cloned().collect(), buf.iter().skip(1).step_by(2).cloned().collect())
+            }
+        };
+        let mut out = Vec::with_capacity(c1.len() * 4);
+
+        for (c1_tile, c2_tile) in c1.chunks(64).zip(c2.chunks(64)) {
+            let mut tile = [0u8; 256];
+
+            for (b, &(bx, by)) in [(8, 0), (8, 8), (0, 0), (0, 8)].iter().enumerate() {
+                for y in 0..8 {
+                    let row = (b * 8 + y) * 2;
+                    let planes = [c1_tile[row], c1_tile[row + 1], c2_tile[row], c2_tile[row + 1]];
+
+                    for x in 0..8 {
+                        tile[(by + y) * 16 + bx + x] = planes.iter().enumerate().fold(0, |index, (plane, byte)| index | ((byte >> x) & 0x01) << plane);
+                    }
+                }
+            }
+
+            out.extend(tile.iter().map(|index| P::from(*index).unwrap()));
+        }
+
+        Ok(out)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        match self.c2 {
+            Some(_) => None,
+            None => Some(128)
+        }
+    }
+}
+
+/// Encoder for 4bpp fix layer tiles stored in the Neo Geo S-ROM.
+///
+/// Fix tiles are 8x8 and packed two pixels to a byte, with the left pixel of
+/// each pair in the low nibble. Bytes are ordered by column rather than by
+/// row: each tile is four runs of eight bytes, one byte per row, covering
+/// pixel columns 4-5, 6-7, 0-1 and 2-3 in that order.
+///
+/// Palettes are written as Neo Geo palette RAM data unless another format is
+/// given with with_palette_format.
+pub struct NeoGeoFixEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> NeoGeoFixEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> NeoGeoFixEncoder<'a, F> {
+        NeoGeoFixEncoder {
+            f: file,
+            palette_format: PaletteColorFormat::NeoGeo
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as Neo Geo palette
+    /// RAM data.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> NeoGeoFixEncoder<'a, F> {
+        NeoGeoFixEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for NeoGeoFixEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        15
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for NeoGeoFixEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        let mut out: Vec<u8> = Vec::with_capacity(32);
+
+        for tile in TileChunkIterator::new(&data, self.tile_size()) {
+            out.clear();
+
+            for &x in &[4, 6, 0, 2] {
+                for y in 0..8 {
+                    let left = tile[y * 8 + x].to_u8().unwrap() & 0x0F;
+                    let right = tile[y * 8 + x + 1].to_u8().unwrap() & 0x0F;
+
+                    out.push(right << 4 | left);
+                }
+            }
+
+            self.f.write_all(&out)?;
+        }
+
+        Ok(())
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for NeoGeoFixEncoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let buf = read_units(self.f, size, 32, "Neo Geo fix data ends partway through a tile")?;
+        let mut out = Vec::with_capacity(buf.len() * 2);
+
+        for data in buf.chunks(32) {
+            let mut tile = [0u8; 64];
+
+            for (column, &x) in [4, 6, 0, 2].iter().enumerate() {
+                for y in 0..8 {
+                    let byte = data[column * 8 + y];
+
+                    tile[y * 8 + x] = byte & 0x0F;
+                    tile[y * 8 + x + 1] = byte >> 4;
+                }
+            }
+
+            out.extend(tile.iter().map(|index| P::from(*index).unwrap()));
+        }
+
+        Ok(out)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::formats::neogeo::{NeoGeoSpriteEncoder, NeoGeoFixEncoder};
+
+    fn test_tile() -> Vec<u8> {
+        let mut src : Vec<u8> = vec![0; 256];
+        src[8] = 0x01;
+        src[15] = 0x04;
+        src[16 * 8] = 0x02;
+        src[16 * 15 + 7] = 0x08;
+
+        src
+    }
+
+    #[test]
+    fn sprite_split_encode() {
+        let mut c1 = Cursor::new(Vec::with_capacity(64));
+        let mut c2 = Cursor::new(Vec::with_capacity(64));
+
+        {
+            let mut spr = NeoGeoSpriteEncoder::new_split(&mut c1, &mut c2);
+
+            spr.encode_indexes(test_tile(), 16, 16).unwrap();
+        }
+
+        let mut valid_c1 : Vec<u8> = vec![0; 64];
+        let mut valid_c2 : Vec<u8> = vec![0; 64];
+        valid_c1[0] = 0x01;
+        valid_c2[0] = 0x80;
+        valid_c1[49] = 0x01;
+        valid_c2[63] = 0x80;
+
+        assert_eq!(c1.get_ref(), &valid_c1);
+        assert_eq!(c2.get_ref(), &valid_c2);
+    }
+
+    #[test]
+    fn sprite_interleaved_encode() {
+        let mut test_out = Cursor::new(Vec::with_capacity(128));
+
+        {
+            let mut spr = NeoGeoSpriteEncoder::new(&mut test_out);
+
+            spr.encode_indexes(test_tile(), 16, 16).unwrap();
+        }
+
+        let mut valid_out : Vec<u8> = vec![0; 128];
+        valid_out[0] = 0x01;
+        valid_out[1] = 0x80;
+        valid_out[98] = 0x01;
+        valid_out[127] = 0x80;
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn palette_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]),
+                           image::Rgba([0u8, 255, 0, 255]),
+                           image::Rgba([0u8, 0, 255, 255]),
+                           image::Rgba([0x10u8, 0x08, 0x00, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(8));
+
+        {
+            let mut spr = NeoGeoSpriteEncoder::new(&mut test_out);
+
+            spr.encode_palette(palette).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x4F, 0x00, 0x20, 0xF0, 0x10, 0x0F, 0x21, 0x00];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn fix_encode() {
+        let src : Vec<u8> = vec![0,1,2,3,4,5,6,7,
+                                 8,9,10,11,12,13,14,15,
+                                 0,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,0,
+                                 1,0,0,0,0,0,0,2];
+        let mut test_out = Cursor::new(Vec::with_capacity(32));
+
+        {
+            let mut fix = NeoGeoFixEncoder::new(&mut test_out);
+
+            fix.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x54, 0xDC, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                                       0x76, 0xFE, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20,
+                                       0x10, 0x98, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+                                       0x32, 0xBA, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+}
@@ -0,0 +1,371 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat};
+use awsmimg::formats::dmg::{decode_2bpp_tiles, GB2PlaneLayout};
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::IndexedGraphicsDecoder;
+use awsmimg::conversion::luma_from_indexes;
+
+use std::io;
+use std::io::{Write, Read};
+use image::{ImageBuffer, LumaA, Primitive, Rgba};
+
+/// The 2C02 master palette, as commonly measured from NTSC hardware.
+const RP2C02_PALETTE: [[u8; 3]; 64] = [
+    [ 84,  84,  84], [  0,  30, 116], [  8,  16, 144], [ 48,   0, 136], [ 68,   0, 100], [ 92,   0,  48], [ 84,   4,   0], [ 60,  24,   0],
+    [ 32,  42,   0], [  8,  58,   0], [  0,  64,   0], [  0,  60,   0], [  0,  50,  60], [  0,   0,   0], [  0,   0,   0], [  0,   0,   0],
+    [152, 150, 152], [  8,  76, 196], [ 48,  50, 236], [ 92,  30, 228], [136,  20, 176], [160,  20, 100], [152,  34,  32], [120,  60,   0],
+    [ 84,  90,   0], [ 40, 114,   0], [  8, 124,   0], [  0, 118,  40], [  0, 102, 120], [  0,   0,   0], [  0,   0,   0], [  0,   0,   0],
+    [236, 238, 236], [ 76, 154, 236], [120, 124, 236], [176,  98, 236], [228,  84, 236], [236,  88, 180], [236, 106, 100], [212, 136,  32],
+    [160, 170,   0], [116, 196,   0], [ 76, 208,  32], [ 56, 204, 108], [ 56, 180, 204], [ 60,  60,  60], [  0,   0,   0], [  0,   0,   0],
+    [236, 238, 236], [168, 204, 236], [188, 188, 236], [212, 178, 236], [236, 174, 236], [236, 174, 212], [236, 180, 176], [228, 196, 144],
+    [204, 210, 120], [180, 222, 120], [168, 226, 144], [152, 226, 180], [160, 214, 228], [160, 162, 160], [  0,   0,   0], [  0,   0,   0]
+];
+
+/// FirebrandX's "Smooth" master palette.
+const FBX_PALETTE: [[u8; 3]; 64] = [
+    [0x6A, 0x6D, 0x6A], [0x00, 0x13, 0x80], [0x1E, 0x00, 0x8A], [0x39, 0x00, 0x7A], [0x55, 0x00, 0x56], [0x5A, 0x00, 0x18], [0x4F, 0x10, 0x00], [0x3D, 0x1C, 0x00],
+    [0x25, 0x32, 0x00], [0x00, 0x3D, 0x00], [0x00, 0x40, 0x00], [0x00, 0x39, 0x24], [0x00, 0x2E, 0x55], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xB9, 0xBC, 0xB9], [0x18, 0x50, 0xC7], [0x4B, 0x30, 0xE3], [0x73, 0x22, 0xD6], [0x95, 0x1F, 0xA9], [0x9D, 0x28, 0x5C], [0x98, 0x37, 0x00], [0x7F, 0x4C, 0x00],
+    [0x5E, 0x64, 0x00], [0x22, 0x77, 0x00], [0x02, 0x7E, 0x02], [0x00, 0x76, 0x45], [0x00, 0x6E, 0x8A], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF], [0x68, 0xA6, 0xFF], [0x8C, 0x9C, 0xFF], [0xB5, 0x86, 0xFF], [0xD9, 0x75, 0xFD], [0xE3, 0x77, 0xB9], [0xE5, 0x8D, 0x68], [0xD4, 0x9D, 0x29],
+    [0xB3, 0xAF, 0x0C], [0x7B, 0xC2, 0x11], [0x55, 0xCA, 0x47], [0x46, 0xCB, 0x81], [0x47, 0xC1, 0xC5], [0x4A, 0x4D, 0x4A], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF], [0xCC, 0xEA, 0xFF], [0xDD, 0xDE, 0xFF], [0xEC, 0xDA, 0xFF], [0xF8, 0xD7, 0xFE], [0xFC, 0xD6, 0xF5], [0xFD, 0xDB, 0xCF], [0xF9, 0xE7, 0xB5],
+    [0xF1, 0xF0, 0xAA], [0xDA, 0xFA, 0xA9], [0xC9, 0xFF, 0xBC], [0xC3, 0xFB, 0xD7], [0xC4, 0xF6, 0xF6], [0xBE, 0xC1, 0xBE], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00]
+];
+
+/// Selects which measurement of the NES master palette is used when mapping
+/// RGB colors to hardware palette indexes.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum NesMasterPalette {
+    RP2C02, //Commonly used NTSC 2C02 measurements
+    FBX     //FirebrandX "Smooth" palette
+}
+
+impl NesMasterPalette {
+    /// Retrieve the RGB values of all 64 master palette entries.
+    pub fn colors(&self) -> &'static [[u8; 3]; 64] {
+        match *self {
+            NesMasterPalette::RP2C02 => &RP2C02_PALETTE,
+            NesMasterPalette::FBX => &FBX_PALETTE
+        }
+    }
+
+    /// Find the master palette index closest to a given RGB color.
+    ///
+    /// Color $0D and the unused $xE/$xF columns are never selected, with the
+    /// exception of $0F, which is the canonical black. Ties are resolved in
+    /// favor of the lowest index.
+    pub fn nearest_index(&self, r: u8, g: u8, b: u8) -> u8 {
+        let mut best_index = 0x0F;
+        let mut best_distance = u32::MAX;
+
+        for (i, color) in self.colors().iter().enumerate() {
+            let column = i & 0x0F;
+
+            if i == 0x0D || column == 0x0E || (column == 0x0F && i != 0x0F) {
+                continue;
+            }
+
+            let dr = (color[0] as i32 - r as i32).pow(2);
+            let dg = (color[1] as i32 - g as i32).pow(2);
+            let db = (color[2] as i32 - b as i32).pow(2);
+            let distance = (dr + dg + db) as u32;
+
+            if distance < best_distance {
+                best_index = i as u8;
+                best_distance = distance;
+            }
+        }
+
+        best_index
+    }
+}
+
+/// Encode a series of RGBA colors as NES palette RAM data.
+///
+/// The NES has no RGB palette; each color is instead mapped to the nearest
+/// entry of the given master palette and written as a one-byte index. Every
+/// four colors written form one subpalette set. Alpha is ignored.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I, master: NesMasterPalette) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 1] = [0];
+
+    for rgba in palette {
+        let r : u8 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u8;
+        let g : u8 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u8;
+        let b : u8 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u8;
+
+        out[0] = master.nearest_index(r, g, b);
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode NES palette RAM data written by encode_palette into RGBA colors.
+///
+/// Up to size bytes are read, one color per byte, and each is looked up in
+/// the given master palette. Every color is opaque.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize, master: NesMasterPalette) -> io::Result<Vec<Rgba<u8>>> {
+    let mut buf = Vec::new();
+    let colors = master.colors();
+
+    r.take(size as u64).read_to_end(&mut buf)?;
+
+    Ok(buf.iter().map(|index| {
+        let rgb = colors[(index & 0x3F) as usize];
+
+        Rgba([rgb[0], rgb[1], rgb[2], 255])
+    }).collect())
+}
+
+/// Encoder/decoder for 2bpp CHR tile patterns for the NES platform.
+///
+/// Each 8x8 tile is stored as eight bytes of bitplane 0 followed by eight
+/// bytes of bitplane 1. The leftmost pixel of a row is stored in the most
+/// significant bit of each byte.
+///
+/// When constructed with new_padded, the encoded tile data is padded with
+/// zeroes up to the next multiple of the given bank size. Pass 0x1000 for 4KB
+/// pattern tables or 0x2000 for 8KB CHR banks.
+///
+/// Palettes are mapped against the 2C02 master palette unless another one is
+/// selected with with_master_palette. Decoded palettes take their colors from
+/// the same master palette. Use with_palette_format to write palettes in
+/// another color format instead.
+pub struct NesChrEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    bank_size: usize,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> NesChrEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> NesChrEncoder<'a, F> {
+        NesChrEncoder {
+            f: file,
+            bank_size: 0,
+            palette_format: PaletteColorFormat::NES(NesMasterPalette::RP2C02)
+        }
+    }
+
+    pub fn new_padded(file: &'a mut F, bank_size: usize) -> NesChrEncoder<'a, F> {
+        NesChrEncoder {
+            f: file,
+            bank_size,
+            palette_format: PaletteColorFormat::NES(NesMasterPalette::RP2C02)
+        }
+    }
+
+    pub fn with_master_palette(self, master_palette: NesMasterPalette) -> NesChrEncoder<'a, F> {
+        NesChrEncoder {
+            palette_format: PaletteColorFormat::NES(master_palette),
+            ..self
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as indexes into
+    /// the master palette.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> NesChrEncoder<'a, F> {
+        NesChrEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for NesChrEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (16, 16)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        3
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for NesChrEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        let mut out: Vec<u8> = Vec::with_capacity(data.len() / 4);
+
+        for tile in data.chunks(64) {
+            let mut planes: [u8; 16] = [0; 16];
+
+            for (i, index) in tile.iter().enumerate() {
+                let index = index.to_u8().unwrap();
+                let (y, x) = (i / 8, i % 8);
+
+                planes[y] |= (index & 0x01) << (7 - x);
+                planes[y + 8] |= ((index >> 1) & 0x01) << (7 - x);
+            }
+
+            out.extend_from_slice(&planes);
+        }
+
+        if self.bank_size > 0 && !out.len().is_multiple_of(self.bank_size) {
+            let padded_len = (out.len() / self.bank_size + 1) * self.bank_size;
+
+            out.resize(padded_len, 0);
+        }
+
+        self.f.write_all(&out)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for NesChrEncoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        decode_2bpp_tiles(self.f, size, GB2PlaneLayout::Separated)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(16)
+    }
+}
+
+/// Decode CHR data as a sheet image laid out as pattern tables.
+///
+/// The sheet is 16 tiles wide, so that each 256-tile pattern table forms a
+/// 128x128 square. Successive pattern tables are stacked vertically, and a
+/// partial pattern table at the end is padded with transparent pixels.
+pub fn decode_chr_sheet<R: Read>(r: &mut R, size: usize) -> io::Result<Box<ImageBuffer<LumaA<u8>, Vec<u8>>>> {
+    let mut chr = NesChrEncoder::new(r);
+    let indexes : Vec<u8> = chr.decode_indexes(size)?;
+    let tables = (indexes.len() / 64).div_ceil(256).max(1) as u32;
+
+    match luma_from_indexes(indexes, chr.palette_maxcol(), chr.tile_size(), Some((128, 128 * tables))) {
+        Some(i) => Ok(i),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, "CHR data does not hold a whole number of tiles."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::decoder::IndexedGraphicsDecoder;
+    use awsmimg::formats::nes::{decode_chr_sheet, NesChrEncoder, NesMasterPalette};
+
+    #[test]
+    fn chr_encode() {
+        let src : Vec<u8> = vec![0,1,2,3,0,1,2,3,
+                                 3,3,3,3,0,0,0,0,
+                                 1,1,1,1,1,1,1,1,
+                                 2,2,2,2,2,2,2,2,
+                                 0,0,0,0,0,0,0,0,
+                                 3,2,1,0,3,2,1,0,
+                                 0,0,0,0,0,0,0,3,
+                                 3,0,0,0,0,0,0,0];
+        let mut test_out = Cursor::new(Vec::with_capacity(16));
+
+        {
+            let mut chr = NesChrEncoder::new(&mut test_out);
+
+            chr.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x55, 0xF0, 0xFF, 0x00, 0x00, 0xAA, 0x01, 0x80,
+                                       0x33, 0xF0, 0x00, 0xFF, 0x00, 0xCC, 0x01, 0x80];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn chr_padded_encode() {
+        let src : Vec<u8> = vec![3; 64 * 3];
+        let mut test_out = Cursor::new(Vec::with_capacity(0x1000));
+
+        {
+            let mut chr = NesChrEncoder::new_padded(&mut test_out, 0x1000);
+
+            chr.encode_indexes(src, 24, 8).unwrap();
+        }
+
+        let out = test_out.get_ref();
+
+        assert_eq!(out.len(), 0x1000);
+        assert!(out[..48].iter().all(|b| *b == 0xFF));
+        assert!(out[48..].iter().all(|b| *b == 0x00));
+    }
+
+    #[test]
+    fn chr_decode() {
+        let src : Vec<u8> = vec![0x55, 0xF0, 0xFF, 0x00, 0x00, 0xAA, 0x01, 0x80,
+                                 0x33, 0xF0, 0x00, 0xFF, 0x00, 0xCC, 0x01, 0x80];
+        let mut test_in = Cursor::new(&src);
+
+        let test_out : Vec<u8> = NesChrEncoder::new(&mut test_in).decode_indexes(usize::MAX).unwrap();
+        let valid_out : Vec<u8> = vec![0,1,2,3,0,1,2,3,
+                                       3,3,3,3,0,0,0,0,
+                                       1,1,1,1,1,1,1,1,
+                                       2,2,2,2,2,2,2,2,
+                                       0,0,0,0,0,0,0,0,
+                                       3,2,1,0,3,2,1,0,
+                                       0,0,0,0,0,0,0,3,
+                                       3,0,0,0,0,0,0,0];
+
+        assert_eq!(test_out, valid_out)
+    }
+
+    #[test]
+    fn chr_decode_sheet() {
+        let mut src : Vec<u8> = vec![0; 0x1000 + 16];
+        src[16 * 17] = 0x80;
+        src[16 * 17 + 8] = 0x80;
+        src[0x1000] = 0x80;
+
+        let sheet = decode_chr_sheet(&mut Cursor::new(&src), usize::MAX).unwrap();
+
+        assert_eq!(sheet.dimensions(), (128, 256));
+        assert_eq!(sheet.get_pixel(8, 8), &image::LumaA([255u8, 255]));
+        assert_eq!(sheet.get_pixel(0, 128), &image::LumaA([85u8, 255]));
+        assert_eq!(sheet.get_pixel(8, 128), &image::LumaA([0u8, 0]));
+    }
+
+    #[test]
+    fn palette_decode() {
+        let mut test_in = Cursor::new(vec![0x0Fu8, 0x30, 0x01]);
+        let palette = NesChrEncoder::new(&mut test_in).with_master_palette(NesMasterPalette::FBX).decode_palette(usize::MAX).unwrap();
+
+        assert_eq!(palette, vec![image::Rgba([0u8, 0, 0, 255]),
+                                 image::Rgba([0xFFu8, 0xFF, 0xFF, 255]),
+                                 image::Rgba([0x00u8, 0x13, 0x80, 255])]);
+    }
+
+    #[test]
+    fn palette_encode() {
+        let palette = vec![image::Rgba([0u8, 0, 0, 255]),
+                           image::Rgba([255u8, 255, 255, 255]),
+                           image::Rgba([0u8, 30, 116, 255]),
+                           image::Rgba([200u8, 10, 10, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(4));
+
+        {
+            let mut chr = NesChrEncoder::new(&mut test_out);
+
+            chr.encode_palette(palette).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x0Fu8, 0x20, 0x01, 0x16])
+    }
+
+    #[test]
+    fn palette_selectable() {
+        assert_eq!(NesMasterPalette::RP2C02.nearest_index(0x6A, 0x6D, 0x6A), 0x00);
+        assert_eq!(NesMasterPalette::FBX.nearest_index(0x6A, 0x6D, 0x6A), 0x00);
+        assert_eq!(NesMasterPalette::RP2C02.nearest_index(0x4A, 0x4D, 0x4A), 0x00);
+        assert_eq!(NesMasterPalette::FBX.nearest_index(0x4A, 0x4D, 0x4A), 0x2D);
+    }
+}
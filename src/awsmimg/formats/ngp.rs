@@ -0,0 +1,186 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, read_color_words, expand_channel};
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::{IndexedGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{Primitive, Rgba};
+
+/// Encode a series of RGBA colors as NGPC palette RAM data.
+///
+/// Colors are written as little-endian 12-bit words in the 0000BBBBGGGGRRRR
+/// layout. Alpha is ignored.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 2] = [0, 0];
+
+    for rgba in palette {
+        let r : u16 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let g : u16 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let b : u16 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+
+        let enc_color: u16 = (b & 0xF0) << 4 | (g & 0xF0) | r >> 4;
+
+        out[0] = (enc_color & 0xFF) as u8;
+        out[1] = ((enc_color >> 8) & 0xFF) as u8;
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode NGPC palette data written by encode_palette into RGBA colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. Every
+/// color is opaque.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    Ok(read_color_words(r, size, false)?.into_iter().map(|v| {
+        Rgba([expand_channel(v, 4), expand_channel(v >> 4, 4), expand_channel(v >> 8, 4), 255])
+    }).collect())
+}
+
+/// Encoder for 2bpp tile patterns for the NGP and NGPC platforms.
+///
+/// Each row of an 8x8 tile is stored as a little-endian 16-bit word with two
+/// bits per pixel. The leftmost pixel of a row is stored in the most
+/// significant bits of the word, so the first byte of each row holds the
+/// right half of the row.
+///
+/// Palettes are written as NGPC palette RAM data unless another format is given
+/// with with_palette_format.
+pub struct NGP2Encoder<'a, F: 'a> {
+    f: &'a mut F,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> NGP2Encoder<'a, F> {
+    pub fn new(file: &'a mut F) -> NGP2Encoder<'a, F> {
+        NGP2Encoder {
+            f: file,
+            palette_format: PaletteColorFormat::NGP
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as NGPC palette
+    /// RAM data.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> NGP2Encoder<'a, F> {
+        NGP2Encoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for NGP2Encoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        3
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for NGP2Encoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        let mut out: [u8; 2] = [0, 0];
+
+        for row in data.chunks(8) {
+            let mut word: u16 = 0;
+
+            for (x, index) in row.iter().enumerate() {
+                word |= (index.to_u16().unwrap() & 0x03) << (14 - x * 2);
+            }
+
+            out[0] = (word & 0xFF) as u8;
+            out[1] = ((word >> 8) & 0xFF) as u8;
+            self.f.write_all(&out)?;
+        }
+
+        Ok(())
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for NGP2Encoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let buf = read_units(self.f, size, 16, "NGP 2bpp data ends partway through a tile")?;
+        let mut out = Vec::with_capacity(buf.len() * 4);
+
+        for row in buf.chunks(2) {
+            let word = row[0] as u16 | (row[1] as u16) << 8;
+
+            for x in 0..8 {
+                out.push(P::from((word >> (14 - x * 2)) & 0x03).unwrap());
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::formats::ngp::NGP2Encoder;
+
+    #[test]
+    fn data2_encode() {
+        let src : Vec<u8> = vec![0,1,2,3,0,1,2,3,
+                                 3,3,3,3,0,0,0,0,
+                                 0,0,0,0,3,3,3,3,
+                                 1,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,2,
+                                 0,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,0];
+        let mut test_out = Cursor::new(Vec::with_capacity(16));
+
+        {
+            let mut ngp2 = NGP2Encoder::new(&mut test_out);
+
+            ngp2.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x1B, 0x1B, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0x40,
+                                       0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn palette_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]),
+                           image::Rgba([0u8, 255, 0, 255]),
+                           image::Rgba([0u8, 0, 255, 255]),
+                           image::Rgba([0x11u8, 0x22, 0x33, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(8));
+
+        {
+            let mut ngp2 = NGP2Encoder::new(&mut test_out);
+
+            ngp2.encode_palette(palette).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x0Fu8, 0x00, 0xF0, 0x00, 0x00, 0x0F, 0x21, 0x03])
+    }
+}
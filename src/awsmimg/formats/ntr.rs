@@ -0,0 +1,729 @@
+use awsmimg::formats::{IndexedGraphicsProperties, DEFAULT_ALPHA_THRESHOLD};
+use awsmimg::formats::agb::encode_palette;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, expand_channel};
+use awsmimg::formats::generic::DirectColor16Decoder;
+use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
+use awsmimg::decoder::{IndexedGraphicsDecoder, DirectGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{GenericImage, ImageBuffer, Primitive, Rgba, Pixel};
+
+/// Encoder for translucent 3D textures for the NTR platform.
+///
+/// Each texel is one byte holding a color index in the low bits and an alpha
+/// value in the high bits. Use new_a3i5 for 3 bits of alpha and 32 colors, or
+/// new_a5i3 for 5 bits of alpha and 8 colors. Textures are stored row-major
+/// without tiling.
+///
+/// Alpha is not part of the index data and must be provided separately with
+/// with_alpha, as 8-bit values in the same order as the index data (see
+/// conversion::alpha_from_image). Texels without alpha data are opaque.
+///
+/// Palettes are written as BGR555 by default; use with_palette_format to select
+/// another color format.
+pub struct NTRTranslucentEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    alpha_bits: u8,
+    alpha: Vec<u8>,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> NTRTranslucentEncoder<'a, F> {
+    pub fn new_a3i5(file: &'a mut F) -> NTRTranslucentEncoder<'a, F> {
+        NTRTranslucentEncoder {
+            f: file,
+            alpha_bits: 3,
+            alpha: Vec::new(),
+            palette_format: PaletteColorFormat::BGR555
+        }
+    }
+
+    pub fn new_a5i3(file: &'a mut F) -> NTRTranslucentEncoder<'a, F> {
+        NTRTranslucentEncoder {
+            f: file,
+            alpha_bits: 5,
+            alpha: Vec::new(),
+            palette_format: PaletteColorFormat::BGR555
+        }
+    }
+
+    pub fn with_alpha(self, alpha: Vec<u8>) -> NTRTranslucentEncoder<'a, F> {
+        NTRTranslucentEncoder {
+            alpha,
+            ..self
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as BGR555.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> NTRTranslucentEncoder<'a, F> {
+        NTRTranslucentEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for NTRTranslucentEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        (1 << (8 - self.alpha_bits)) - 1
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for NTRTranslucentEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        let index_bits = 8 - self.alpha_bits;
+        let index_mask = (1 << index_bits) - 1;
+        let mut out: Vec<u8> = Vec::with_capacity(data.len());
+
+        for (i, index) in data.iter().enumerate() {
+            let alpha = self.alpha.get(i).map_or(0xFF, |a| *a);
+
+            out.push((alpha >> index_bits) << index_bits | (index.to_u8().unwrap() & index_mask));
+        }
+
+        self.f.write_all(&out)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for NTRTranslucentEncoder<'a, F> where F: Read {
+    /// Decode the color indexes of a translucent texture.
+    ///
+    /// The alpha bits of each texel are discarded.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let index_mask = (1u8 << (8 - self.alpha_bits)) - 1;
+        let buf = read_units(self.f, size, 1, "Texture data ends partway through a texel")?;
+
+        Ok(buf.into_iter().map(|texel| P::from(texel & index_mask).unwrap()).collect())
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(1)
+    }
+}
+
+/// Convert a pixel to a 15-bit BGR color, or None if its 8-bit alpha is below
+/// the given threshold.
+fn ntr_color<P: Pixel<Subpixel=S>, S: Primitive>(pixel: P, alpha_threshold: u8) -> Option<u16> {
+    let imgmax = S::max_value().to_f32().unwrap();
+    let rgba = pixel.to_rgba();
+    let c: Vec<u16> = (0..4).map(|i| (rgba[i].to_f32().unwrap() / imgmax * 255f32) as u16).collect();
+
+    match c[3] >= alpha_threshold as u16 {
+        false => None,
+        true => Some((c[2] & 0xF8) << 7 | (c[1] & 0xF8) << 2 | c[0] >> 3)
+    }
+}
+
+/// Split a 15-bit color into its red, green and blue components.
+fn components(color: u16) -> [u32; 3] {
+    [(color & 0x1F) as u32, ((color >> 5) & 0x1F) as u32, ((color >> 10) & 0x1F) as u32]
+}
+
+/// Squared distance between two 15-bit colors.
+fn distance(c0: u16, c1: u16) -> u32 {
+    let (a, b) = (components(c0), components(c1));
+
+    (0..3).map(|i| (a[i] as i32 - b[i] as i32).pow(2) as u32).sum()
+}
+
+/// Weighted blend of two 15-bit colors, as computed by the texture unit.
+fn blend(c0: u16, c1: u16, w0: u32, w1: u32) -> u16 {
+    let (a, b) = (components(c0), components(c1));
+    let mix: Vec<u16> = (0..3).map(|i| ((a[i] * w0 + b[i] * w1) / (w0 + w1)) as u16).collect();
+
+    mix[2] << 10 | mix[1] << 5 | mix[0]
+}
+
+/// Encoder for 4x4 texel compressed textures for the NTR platform.
+///
+/// The texture is split into 4x4 blocks, stored left to right then top to
+/// bottom. Each block is stored as a 32-bit texel word with two bits per
+/// texel, one byte per row, leftmost texel in the least significant bits.
+/// Each block also has a 16-bit palette index word, stored separately in the
+/// texture slot 1 region, which selects a palette offset (in units of two
+/// colors) and one of four modes:
+///
+///  * Mode 0: three palette colors, index 3 is transparent.
+///  * Mode 1: two palette colors, index 2 is their average, index 3 is
+///    transparent.
+///  * Mode 2: four palette colors.
+///  * Mode 3: two palette colors, indexes 2 and 3 are 5:3 and 3:5 blends.
+///
+/// Blocks whose colors fit in the palette modes store their exact colors.
+/// Other blocks are reduced to the two most distant colors in the block plus
+/// the blended colors between them. Identical palette runs are shared between
+/// blocks. Texels whose 8-bit alpha is below formats::DEFAULT_ALPHA_THRESHOLD
+/// are transparent; use with_alpha_threshold to choose another threshold.
+///
+/// When constructed with new_split, texel data, palette index data and the
+/// palette are written to separate writers. When constructed with new, they
+/// are written to one writer in that order.
+pub struct NTRTex4x4Encoder<'a, W: Write + 'a> {
+    w: &'a mut W,
+    pidx: Option<&'a mut W>,
+    palette: Option<&'a mut W>,
+    alpha_threshold: u8
+}
+
+impl<'a, W: Write + 'a> NTRTex4x4Encoder<'a, W> {
+    pub fn new(write: &'a mut W) -> NTRTex4x4Encoder<'a, W> {
+        NTRTex4x4Encoder {
+            w: write,
+            pidx: None,
+            palette: None,
+            alpha_threshold: DEFAULT_ALPHA_THRESHOLD
+        }
+    }
+
+    pub fn new_split(texels: &'a mut W, pidx: &'a mut W, palette: &'a mut W) -> NTRTex4x4Encoder<'a, W> {
+        NTRTex4x4Encoder {
+            w: texels,
+            pidx: Some(pidx),
+            palette: Some(palette),
+            alpha_threshold: DEFAULT_ALPHA_THRESHOLD
+        }
+    }
+
+    pub fn with_alpha_threshold(self, alpha_threshold: u8) -> NTRTex4x4Encoder<'a, W> {
+        NTRTex4x4Encoder {
+            alpha_threshold,
+            ..self
+        }
+    }
+}
+
+impl<'a, W: Write> DirectGraphicsEncoder for NTRTex4x4Encoder<'a, W> {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        let (width, height) = image.dimensions();
+
+        if !width.is_multiple_of(4) || !height.is_multiple_of(4) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "4x4 compressed textures must be a multiple of 4 texels in each dimension."));
+        }
+
+        let mut texel_out: Vec<u8> = Vec::new();
+        let mut pidx_out: Vec<u8> = Vec::new();
+        let mut palette: Vec<u16> = Vec::new();
+
+        for by in 0..height / 4 {
+            for bx in 0..width / 4 {
+                let block: Vec<Option<u16>> = (0..16).map(|i| ntr_color(image.get_pixel(bx * 4 + i % 4, by * 4 + i / 4), self.alpha_threshold)).collect();
+                let transparent = block.iter().any(|c| c.is_none());
+                let mut colors: Vec<u16> = Vec::new();
+
+                for c in block.iter().flatten() {
+                    if !colors.contains(c) {
+                        colors.push(*c);
+                    }
+                }
+
+                let (mode, mut entries, choices): (u16, Vec<u16>, Vec<u16>) = if colors.is_empty() {
+                    (0, vec![], vec![])
+                } else if (transparent && colors.len() <= 3) || (!transparent && colors.len() <= 4) {
+                    (if transparent { 0 } else { 2 }, colors.clone(), colors)
+                } else {
+                    let mut ends = (colors[0], colors[1]);
+
+                    for (i, c0) in colors.iter().enumerate() {
+                        for c1 in colors.iter().skip(i + 1) {
+                            if distance(*c0, *c1) > distance(ends.0, ends.1) {
+                                ends = (*c0, *c1);
+                            }
+                        }
+                    }
+
+                    let (c0, c1) = ends;
+
+                    match transparent {
+                        true => (1, vec![c0, c1], vec![c0, c1, blend(c0, c1, 1, 1)]),
+                        false => (3, vec![c0, c1], vec![c0, c1, blend(c0, c1, 5, 3), blend(c0, c1, 3, 5)])
+                    }
+                };
+
+                if entries.len() % 2 == 1 {
+                    entries.push(0);
+                }
+
+                let offset = if entries.is_empty() {
+                    0
+                } else {
+                    match (0..palette.len()).step_by(2).find(|&k| palette[k..].starts_with(&entries)) {
+                        Some(k) => k / 2,
+                        None => {
+                            palette.extend_from_slice(&entries);
+                            (palette.len() - entries.len()) / 2
+                        }
+                    }
+                };
+
+                if offset > 0x3FFF {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "Texture needs more palette colors than 4x4 compression can address."));
+                }
+
+                let pidx = mode << 14 | offset as u16;
+
+                pidx_out.push((pidx & 0xFF) as u8);
+                pidx_out.push((pidx >> 8) as u8);
+
+                for row in block.chunks(4) {
+                    let mut byte = 0u8;
+
+                    for (x, texel) in row.iter().enumerate() {
+                        let index = match *texel {
+                            None => 3,
+                            Some(c) => (0..choices.len()).min_by_key(|&i| distance(c, choices[i])).unwrap() as u8
+                        };
+
+                        byte |= index << (x * 2);
+                    }
+
+                    texel_out.push(byte);
+                }
+            }
+        }
+
+        let palette_out: Vec<u8> = palette.iter().flat_map(|c| vec![(c & 0xFF) as u8, (c >> 8) as u8]).collect();
+
+        self.w.write_all(&texel_out)?;
+
+        match self.pidx {
+            Some(ref mut pidx) => pidx.write_all(&pidx_out)?,
+            None => self.w.write_all(&pidx_out)?
+        }
+
+        match self.palette {
+            Some(ref mut pal) => pal.write_all(&palette_out),
+            None => self.w.write_all(&palette_out)
+        }
+    }
+}
+
+/// Decoder for 4x4 texel compressed textures for the NTR platform.
+///
+/// This reads data written by NTRTex4x4Encoder. When constructed with
+/// new_split, texel data, palette index data and the palette are read from
+/// separate readers, up to size bytes each. When constructed with new, they
+/// are read from one reader in that order; the number of blocks is then
+/// inferred as the only one whose palette references end exactly at the end
+/// of the data. Transparent texels decode as fully transparent black.
+pub struct NTRTex4x4Decoder<'a, R: Read + 'a> {
+    r: &'a mut R,
+    pidx: Option<&'a mut R>,
+    palette: Option<&'a mut R>
+}
+
+impl<'a, R: Read + 'a> NTRTex4x4Decoder<'a, R> {
+    pub fn new(read: &'a mut R) -> NTRTex4x4Decoder<'a, R> {
+        NTRTex4x4Decoder {
+            r: read,
+            pidx: None,
+            palette: None
+        }
+    }
+
+    pub fn new_split(texels: &'a mut R, pidx: &'a mut R, palette: &'a mut R) -> NTRTex4x4Decoder<'a, R> {
+        NTRTex4x4Decoder {
+            r: texels,
+            pidx: Some(pidx),
+            palette: Some(palette)
+        }
+    }
+}
+
+/// The number of palette colors a 4x4 block needs, counted from the start of
+/// the palette.
+fn tex4x4_palette_end(texels: &[u8], pidx: u16) -> usize {
+    let mode = pidx >> 14;
+    let offset = (pidx & 0x3FFF) as usize * 2;
+    let used = match mode {
+        1 | 3 => 2,
+        _ => texels.iter()
+            .flat_map(|row| (0..4).map(move |x| (row >> (x * 2)) & 3))
+            .filter(|&index| mode == 2 || index != 3)
+            .map(|index| index as usize + 1)
+            .max().map_or(0, |n| n + n % 2)
+    };
+
+    match used {
+        0 => 0,
+        _ => offset + used
+    }
+}
+
+/// Decode a 15-bit BGR color.
+fn ntr_rgba(color: u16) -> Rgba<u8> {
+    Rgba([expand_channel(color, 5), expand_channel(color >> 5, 5), expand_channel(color >> 10, 5), 255])
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for NTRTex4x4Decoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        if width == 0 || !width.is_multiple_of(4) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "4x4 compressed textures must be a multiple of 4 texels in each dimension."));
+        }
+
+        let row_blocks = (width / 4) as usize;
+        let texel_data = read_units(self.r, size, 4, "Texel data ends partway through a block")?;
+        let (texel_data, pidx_data, palette_data) = match (self.pidx.as_mut(), self.palette.as_mut()) {
+            (Some(pidx), Some(palette)) => {
+                let pidx_data = read_units(*pidx, size, 2, "Palette index data ends partway through a word")?;
+                let palette_data = read_units(*palette, size, 2, "Palette data ends partway through a color")?;
+
+                (texel_data, pidx_data, palette_data)
+            },
+            _ => {
+                let blocks = (1..=texel_data.len() / 6).filter(|n| n % row_blocks == 0).find(|&n| {
+                    let pidx = &texel_data[n * 4..n * 6];
+
+                    (0..n).map(|i| tex4x4_palette_end(&texel_data[i * 4..i * 4 + 4], pidx[i * 2] as u16 | (pidx[i * 2 + 1] as u16) << 8))
+                        .max().unwrap_or(0) * 2 == texel_data.len() - n * 6
+                }).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Data does not hold a whole 4x4 compressed texture of the given width"))?;
+
+                (texel_data[..blocks * 4].to_vec(), texel_data[blocks * 4..blocks * 6].to_vec(), texel_data[blocks * 6..].to_vec())
+            }
+        };
+
+        let blocks = texel_data.len() / 4;
+
+        if pidx_data.len() != blocks * 2 || !blocks.is_multiple_of(row_blocks) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Palette index data does not match the texel data"));
+        }
+
+        let palette: Vec<u16> = palette_data.chunks(2).map(|c| c[0] as u16 | (c[1] as u16) << 8).collect();
+        let height = (blocks / row_blocks) as u32 * 4;
+        let mut image = ImageBuffer::new(width, height);
+
+        for (i, texels) in texel_data.chunks(4).enumerate() {
+            let pidx = pidx_data[i * 2] as u16 | (pidx_data[i * 2 + 1] as u16) << 8;
+            let offset = (pidx & 0x3FFF) as usize * 2;
+
+            if tex4x4_palette_end(texels, pidx) > palette.len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "4x4 block refers to colors past the end of the palette"));
+            }
+
+            let color = |index: usize| palette[offset + index];
+            let (bx, by) = ((i % row_blocks) as u32 * 4, (i / row_blocks) as u32 * 4);
+
+            for (y, row) in texels.iter().enumerate() {
+                for x in 0..4 {
+                    let index = ((row >> (x * 2)) & 3) as usize;
+                    let rgba = match (pidx >> 14, index) {
+                        (0, 3) | (1, 3) => Rgba([0, 0, 0, 0]),
+                        (1, 2) => ntr_rgba(blend(color(0), color(1), 1, 1)),
+                        (3, 2) => ntr_rgba(blend(color(0), color(1), 5, 3)),
+                        (3, 3) => ntr_rgba(blend(color(0), color(1), 3, 5)),
+                        _ => ntr_rgba(color(index))
+                    };
+
+                    image.put_pixel(bx + x as u32, by + y as u32, rgba);
+                }
+            }
+        }
+
+        Ok(image)
+    }
+}
+
+/// Encoder for direct-color bitmap OBJs for the NTR platform.
+///
+/// Pixels are written as little-endian ABGR1555 words using the same color
+/// packing as AGB16Encoder, with the alpha bit always set so that every pixel
+/// of the sprite is visible.
+///
+/// When constructed with new, the sprite is written for 1D bitmap OBJ mapping:
+/// rows are stored contiguously. When constructed with new_2d, rows are
+/// written for 2D bitmap OBJ mapping, where each row starts a fixed number of
+/// pixels after the previous one; stride must be 128 or 256 to match the
+/// DISPCNT bitmap OBJ dimension setting, and the remainder of each row is
+/// padded with transparent pixels.
+pub struct NTRBitmapOBJEncoder<'a, W: Write + 'a> {
+    w: &'a mut W,
+    stride: Option<u32>
+}
+
+impl<'a, W: Write + 'a> NTRBitmapOBJEncoder<'a, W> {
+    pub fn new(write: &'a mut W) -> NTRBitmapOBJEncoder<'a, W> {
+        NTRBitmapOBJEncoder {
+            w: write,
+            stride: None
+        }
+    }
+
+    pub fn new_2d(write: &'a mut W, stride: u32) -> NTRBitmapOBJEncoder<'a, W> {
+        NTRBitmapOBJEncoder {
+            w: write,
+            stride: Some(stride)
+        }
+    }
+}
+
+impl<'a, W: Write> DirectGraphicsEncoder for NTRBitmapOBJEncoder<'a, W> {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        let (width, height) = image.dimensions();
+        let row_len = match self.stride {
+            Some(stride) if stride != 128 && stride != 256 => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "2D bitmap OBJ stride must be 128 or 256 pixels."));
+            },
+            Some(stride) if width > stride => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Bitmap OBJ is wider than the 2D mapping stride."));
+            },
+            Some(stride) => stride as usize * 2,
+            None => width as usize * 2
+        };
+        let mut row: Vec<u8> = Vec::with_capacity(row_len);
+
+        for y in 0..height {
+            row.clear();
+            encode_palette(&mut row, (0..width).map(|x| image.get_pixel(x, y).to_rgba()), false)?;
+
+            for hi in row.iter_mut().skip(1).step_by(2) {
+                *hi |= 0x80;
+            }
+
+            row.resize(row_len, 0);
+            self.w.write_all(&row)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Decoder for direct-color bitmap OBJs for the NTR platform.
+///
+/// This reads data written by NTRBitmapOBJEncoder. Pixels without the alpha
+/// bit are fully transparent. When constructed with new_2d, each row is read
+/// from the start of a stride of 128 or 256 pixels and the padding after it
+/// is discarded.
+pub struct NTRBitmapOBJDecoder<'a, R: Read + 'a> {
+    r: &'a mut R,
+    stride: Option<u32>
+}
+
+impl<'a, R: Read + 'a> NTRBitmapOBJDecoder<'a, R> {
+    pub fn new(read: &'a mut R) -> NTRBitmapOBJDecoder<'a, R> {
+        NTRBitmapOBJDecoder {
+            r: read,
+            stride: None
+        }
+    }
+
+    pub fn new_2d(read: &'a mut R, stride: u32) -> NTRBitmapOBJDecoder<'a, R> {
+        NTRBitmapOBJDecoder {
+            r: read,
+            stride: Some(stride)
+        }
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for NTRBitmapOBJDecoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        let stride = match self.stride {
+            None => return DirectColor16Decoder::new_ntr(self.r).decode_colors(width, size),
+            Some(stride) if stride != 128 && stride != 256 => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "2D bitmap OBJ stride must be 128 or 256 pixels."));
+            },
+            Some(stride) if width > stride => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Bitmap OBJ is wider than the 2D mapping stride."));
+            },
+            Some(stride) => stride as usize * 2
+        };
+
+        let buf = read_units(self.r, size, stride, "Bitmap OBJ data ends partway through a row")?;
+        let rows: Vec<u8> = buf.chunks(stride).flat_map(|row| row[..width as usize * 2].to_vec()).collect();
+
+        DirectColor16Decoder::new_ntr(&mut &rows[..]).decode_colors(width, rows.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
+    use awsmimg::decoder::DirectGraphicsDecoder;
+    use awsmimg::formats::ntr::{NTRTranslucentEncoder, NTRTex4x4Encoder, NTRTex4x4Decoder, NTRBitmapOBJEncoder, NTRBitmapOBJDecoder};
+
+    #[test]
+    fn a3i5_encode() {
+        let src : Vec<u8> = vec![0, 1, 31, 17];
+        let alpha : Vec<u8> = vec![0x00, 0x20, 0xFF];
+        let mut test_out = Cursor::new(Vec::with_capacity(4));
+
+        {
+            let mut a3i5 = NTRTranslucentEncoder::new_a3i5(&mut test_out).with_alpha(alpha);
+
+            a3i5.encode_indexes(src, 2, 2).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x00, 0x21, 0xFF, 0xF1];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn a5i3_encode() {
+        let src : Vec<u8> = vec![0, 1, 7, 9];
+        let alpha : Vec<u8> = vec![0x00, 0x08, 0x80, 0xFF];
+        let mut test_out = Cursor::new(Vec::with_capacity(4));
+
+        {
+            let mut a5i3 = NTRTranslucentEncoder::new_a5i3(&mut test_out).with_alpha(alpha);
+
+            a5i3.encode_indexes(src, 2, 2).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x00, 0x09, 0x87, 0xF9];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn tex4x4_shared_palette_encode() {
+        let img = image::ImageBuffer::from_fn(8, 4, |x, _| {
+            match x % 4 {
+                0 | 1 => image::Rgba([255u8, 0, 0, 255]),
+                _ => image::Rgba([0u8, 0, 255, 255])
+            }
+        });
+        let mut texels = Cursor::new(Vec::new());
+        let mut pidx = Cursor::new(Vec::new());
+        let mut palette = Cursor::new(Vec::new());
+
+        {
+            let mut tex = NTRTex4x4Encoder::new_split(&mut texels, &mut pidx, &mut palette);
+
+            tex.encode_colors(&img).unwrap();
+        }
+
+        assert_eq!(texels.get_ref(), &vec![0x50u8; 8]);
+        assert_eq!(pidx.get_ref(), &vec![0x00u8, 0x80, 0x00, 0x80]);
+        assert_eq!(palette.get_ref(), &vec![0x1Fu8, 0x00, 0x00, 0x7C]);
+    }
+
+    #[test]
+    fn tex4x4_alpha_threshold() {
+        let img = image::ImageBuffer::from_fn(4, 4, |x, _| match x {
+            0 => image::Rgba([255u8, 0, 0, 0x40]),
+            _ => image::Rgba([255u8, 0, 0, 255])
+        });
+        let mut default_out = Vec::new();
+        let mut custom_out = Vec::new();
+
+        NTRTex4x4Encoder::new(&mut default_out).encode_colors(&img).unwrap();
+        NTRTex4x4Encoder::new(&mut custom_out).with_alpha_threshold(0x01).encode_colors(&img).unwrap();
+
+        assert_eq!(default_out, vec![0x03u8, 0x03, 0x03, 0x03, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]);
+        assert_eq!(custom_out, vec![0x00u8, 0x00, 0x00, 0x00, 0x00, 0x80, 0x1F, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn tex4x4_interpolated_encode() {
+        let img = image::ImageBuffer::from_fn(4, 4, |x, y| {
+            match y * 4 + x {
+                0 => image::Rgba([0u8, 0, 0, 0]),
+                2 => image::Rgba([64u8, 64, 64, 255]),
+                3 => image::Rgba([128u8, 128, 128, 255]),
+                4 => image::Rgba([192u8, 192, 192, 255]),
+                5 => image::Rgba([255u8, 255, 255, 255]),
+                _ => image::Rgba([0u8, 0, 0, 255])
+            }
+        });
+        let mut test_out = Cursor::new(Vec::new());
+
+        {
+            let mut tex = NTRTex4x4Encoder::new(&mut test_out);
+
+            tex.encode_colors(&img).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0xA3, 0x05, 0x00, 0x00,
+                                       0x00, 0x40,
+                                       0x00, 0x00, 0xFF, 0x7F];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    fn bitmap_obj_image() -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+        image::ImageBuffer::from_fn(2, 2, |x, y| {
+            match (x, y) {
+                (0, 0) => image::Rgba([255u8, 0, 0, 255]),
+                (1, 0) => image::Rgba([0u8, 255, 0, 255]),
+                (0, 1) => image::Rgba([0u8, 0, 255, 255]),
+                _ => image::Rgba([0u8, 0, 0, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn bitmap_obj_1d_encode() {
+        let mut test_out = Cursor::new(Vec::with_capacity(8));
+
+        {
+            let mut obj = NTRBitmapOBJEncoder::new(&mut test_out);
+
+            obj.encode_colors(&bitmap_obj_image()).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x1F, 0x80, 0xE0, 0x83, 0x00, 0xFC, 0x00, 0x80];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn bitmap_obj_2d_encode() {
+        let mut test_out = Cursor::new(Vec::with_capacity(512));
+
+        {
+            let mut obj = NTRBitmapOBJEncoder::new_2d(&mut test_out, 128);
+
+            obj.encode_colors(&bitmap_obj_image()).unwrap();
+        }
+
+        let mut valid_out : Vec<u8> = vec![0; 512];
+        valid_out[0..4].copy_from_slice(&[0x1F, 0x80, 0xE0, 0x83]);
+        valid_out[256..260].copy_from_slice(&[0x00, 0xFC, 0x00, 0x80]);
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn tex4x4_decode() {
+        let img = image::ImageBuffer::from_fn(8, 4, |x, y| match (x / 4, (x + y) % 2) {
+            (0, 0) => image::Rgba([255u8, 0, 0, 255]),
+            (0, _) => image::Rgba([0u8, 0, 255, 255]),
+            (_, 0) => image::Rgba([0u8, 255, 0, 255]),
+            _ => image::Rgba([0u8, 0, 0, 0])
+        });
+        let mut tex = Vec::new();
+
+        NTRTex4x4Encoder::new(&mut tex).encode_colors(&img).unwrap();
+
+        assert_eq!(NTRTex4x4Decoder::new(&mut &tex[..]).decode_colors(8, tex.len()).unwrap().into_raw(), img.into_raw());
+    }
+
+    #[test]
+    fn bitmap_obj_decode() {
+        let img = image::ImageBuffer::from_fn(2, 2, |x, y| image::Rgba([(x * 0xFF) as u8, (y * 0xFF) as u8, 0xFFu8, 255u8]));
+        let mut obj = Vec::new();
+
+        NTRBitmapOBJEncoder::new(&mut obj).encode_colors(&img).unwrap();
+
+        assert_eq!(NTRBitmapOBJDecoder::new(&mut &obj[..]).decode_colors(2, obj.len()).unwrap().into_raw(), img.into_raw());
+    }
+}
@@ -0,0 +1,426 @@
+use awsmimg::formats::{ChannelRounding, DEFAULT_ALPHA_THRESHOLD, agb, amiga, atarist, c64, dmg, lynx, md, neogeo, ngp, pc, pce, psx, saturn, sms, ws, x16, x68k};
+use awsmimg::formats::nes::{self, NesMasterPalette};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{Primitive, Rgba};
+
+/// A hardware color format that palettes can be written in.
+///
+/// Palette formats are independent of the index data they color, so that
+/// index data for one platform can be paired with palettes for another, as
+/// hardware that shares a video chip with a different color DAC requires.
+/// Every index encoder accepts a PaletteColorFormat through
+/// with_palette_format for this purpose, and writes palettes in the format
+/// native to its platform otherwise.
+pub trait PaletteFormat {
+    /// Encode a series of RGBA colors as palette data in this format.
+    fn encode_colors<'a, I, T: Primitive, W: Write + 'a>(&self, w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>>;
+
+    /// Decode palette data written by encode_colors back into RGBA colors.
+    ///
+    /// Up to size bytes are read, and must hold a whole number of colors.
+    /// Channels are expanded to 8 bits such that re-encoding the palette
+    /// yields the original data.
+    fn decode_colors<R: Read>(&self, r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>>;
+}
+
+/// Read up to size bytes of palette data as 16-bit words, for palette formats
+/// that store one word per color.
+pub fn read_color_words<R: Read>(r: &mut R, size: usize, big_endian: bool) -> io::Result<Vec<u16>> {
+    let mut buf = Vec::new();
+
+    r.take(size as u64).read_to_end(&mut buf)?;
+
+    if !buf.len().is_multiple_of(2) {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Palette data ends partway through a color"));
+    }
+
+    Ok(buf.chunks(2).map(|word| match big_endian {
+        true => (word[0] as u16) << 8 | word[1] as u16,
+        false => word[0] as u16 | (word[1] as u16) << 8
+    }).collect())
+}
+
+/// Expand the low bits of a color channel to 8 bits by repeating them, so
+/// that dropping the low bits again yields the original value.
+pub fn expand_channel(value: u16, bits: u32) -> u8 {
+    let value = (value & ((1 << bits) - 1)) as u32;
+    let mut out = 0;
+    let mut shift = 8 - bits as i32;
+
+    while shift > -(bits as i32) {
+        out |= match shift {
+            s if s >= 0 => value << s,
+            s => value >> -s
+        };
+        shift -= bits as i32;
+    }
+
+    out as u8
+}
+
+fn channels8<T: Primitive>(rgba: &Rgba<T>) -> [u8; 4] {
+    let imgmax = T::max_value().to_f32().unwrap();
+    let mut out = [0u8; 4];
+
+    for (i, channel) in out.iter_mut().enumerate() {
+        *channel = (rgba[i].to_f32().unwrap() / imgmax * 255f32) as u8;
+    }
+
+    out
+}
+
+/// Little-endian 15-bit BGR words, as used by the AGB, CGB, SFC and NTR.
+///
+/// If use_alpha is true, the top bit of each word is set for colors whose
+/// alpha is at least alpha_threshold, as in the NTR direct color format. The
+/// default threshold is formats::DEFAULT_ALPHA_THRESHOLD.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BGR555 {
+    pub use_alpha: bool,
+    pub alpha_threshold: u8,
+    pub rounding: ChannelRounding
+}
+
+impl Default for BGR555 {
+    fn default() -> BGR555 {
+        BGR555 {
+            use_alpha: false,
+            alpha_threshold: DEFAULT_ALPHA_THRESHOLD,
+            rounding: ChannelRounding::Truncate
+        }
+    }
+}
+
+impl PaletteFormat for BGR555 {
+    fn encode_colors<'a, I, T: Primitive, W: Write + 'a>(&self, w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+        for rgba in palette {
+            let [r, g, b, a] = channels8(&rgba);
+            let r = self.rounding.reduce(r, 5);
+            let g = self.rounding.reduce(g, 5);
+            let b = self.rounding.reduce(b, 5);
+            let a : u16 = (self.use_alpha && a >= self.alpha_threshold) as u16;
+
+            let enc_color: u16 = a << 15 | b << 10 | g << 5 | r;
+
+            w.write_all(&enc_color.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn decode_colors<R: Read>(&self, r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        agb::decode_palette(r, size, self.use_alpha)
+    }
+}
+
+/// Little-endian 16-bit RGB565 words, as used by CTR and DC textures. Alpha
+/// is ignored.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct RGB565 {
+    pub rounding: ChannelRounding
+}
+
+impl PaletteFormat for RGB565 {
+    fn encode_colors<'a, I, T: Primitive, W: Write + 'a>(&self, w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+        for rgba in palette {
+            let [r, g, b, _] = channels8(&rgba);
+            let enc_color: u16 = self.rounding.reduce(r, 5) << 11 | self.rounding.reduce(g, 6) << 5 | self.rounding.reduce(b, 5);
+
+            w.write_all(&enc_color.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn decode_colors<R: Read>(&self, r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        Ok(read_color_words(r, size, false)?.into_iter().map(|v| {
+            Rgba([expand_channel(v >> 11, 5), expand_channel(v >> 5, 6), expand_channel(v, 5), 255])
+        }).collect())
+    }
+}
+
+/// Big-endian 9-bit BGR words, as written to MD CRAM.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct MDColor;
+
+impl PaletteFormat for MDColor {
+    fn encode_colors<'a, I, T: Primitive, W: Write + 'a>(&self, w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+        md::encode_palette(w, palette)
+    }
+
+    fn decode_colors<R: Read>(&self, r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        md::decode_palette(r, size)
+    }
+}
+
+/// Three bytes of 6-bit red, green and blue per color, as written to the VGA
+/// DAC.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct VGADAC;
+
+impl PaletteFormat for VGADAC {
+    fn encode_colors<'a, I, T: Primitive, W: Write + 'a>(&self, w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+        pc::encode_vga_palette(w, palette)
+    }
+
+    fn decode_colors<R: Read>(&self, r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        pc::decode_vga_palette(r, size)
+    }
+}
+
+/// Big-endian 12-bit RGB words, as written to OCS/ECS color registers.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct AmigaColor;
+
+impl PaletteFormat for AmigaColor {
+    fn encode_colors<'a, I, T: Primitive, W: Write + 'a>(&self, w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+        amiga::encode_palette(w, palette)
+    }
+
+    fn decode_colors<R: Read>(&self, r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        amiga::decode_palette(r, size)
+    }
+}
+
+/// Little-endian 12-bit RGB words, as written to the VERA palette.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct VERAColor;
+
+impl PaletteFormat for VERAColor {
+    fn encode_colors<'a, I, T: Primitive, W: Write + 'a>(&self, w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+        x16::encode_palette(w, palette)
+    }
+
+    fn decode_colors<R: Read>(&self, r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        x16::decode_palette(r, size)
+    }
+}
+
+/// Big-endian RGBA5551 words, as used by N64 texture lookup tables.
+///
+/// The alpha bit is set for colors whose alpha is at least alpha_threshold,
+/// which defaults to formats::DEFAULT_ALPHA_THRESHOLD.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct RGBA5551 {
+    pub alpha_threshold: u8,
+    pub rounding: ChannelRounding
+}
+
+impl Default for RGBA5551 {
+    fn default() -> RGBA5551 {
+        RGBA5551 {
+            alpha_threshold: DEFAULT_ALPHA_THRESHOLD,
+            rounding: ChannelRounding::Truncate
+        }
+    }
+}
+
+impl PaletteFormat for RGBA5551 {
+    fn encode_colors<'a, I, T: Primitive, W: Write + 'a>(&self, w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+        for rgba in palette {
+            let [r, g, b, a] = channels8(&rgba);
+            let a : u16 = (a >= self.alpha_threshold) as u16;
+            let enc_color: u16 = self.rounding.reduce(r, 5) << 11 | self.rounding.reduce(g, 5) << 6 | self.rounding.reduce(b, 5) << 1 | a;
+
+            w.write_all(&enc_color.to_be_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn decode_colors<R: Read>(&self, r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        Ok(read_color_words(r, size, true)?.into_iter().map(|v| {
+            let a = match v & 0x01 {
+                0 => 0,
+                _ => 255
+            };
+
+            Rgba([expand_channel(v >> 11, 5), expand_channel(v >> 6, 5), expand_channel(v >> 1, 5), a])
+        }).collect())
+    }
+}
+
+/// Every palette format, for choosing one by name at runtime.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PaletteColorFormat {
+    BGR555,     //15 bits per color, little-endian BGR
+    RGB565,     //16 bits per color, little-endian RGB
+    MD,         //9 bits per color, big-endian BGR in MD CRAM layout
+    VGADAC,     //18 bits per color, one byte per 6-bit channel
+    Amiga,      //12 bits per color, big-endian RGB
+    VERA,       //12 bits per color, little-endian RGB
+    RGBA5551,   //16 bits per color, big-endian RGBA
+    DMG,        //2 bits per color, DMG shades packed four to a palette register
+    SMS,        //6 bits per color, one byte of BGR
+    GameGear,   //12 bits per color, little-endian BGR
+    NES(NesMasterPalette), //One byte per color, the nearest entry of a master palette
+    PCE,        //9 bits per color, little-endian GRB
+    NeoGeo,     //16 bits per color, big-endian RGB444 with shared low bits
+    NGP,        //12 bits per color, little-endian BGR
+    WS,         //12 bits per color, little-endian RGB
+    Lynx,       //12 bits per color, all green bytes followed by all blue/red bytes
+    PSX,        //15 bits per color, little-endian BGR with the STP bit marking black
+    Saturn,     //15 bits per color, big-endian BGR in VDP2 CRAM
+    SaturnVDP1, //15 bits per color, big-endian BGR with the MSB set on opaque colors
+    X68K,       //16 bits per color, big-endian GRB with an intensity bit
+    AtariST,    //9 bits per color, big-endian RGB
+    AtariSTE,   //12 bits per color, big-endian RGB with the low bit of each channel on top
+    CGA,        //One color select register byte per palette, matched against mode 4
+    CGAMode5,   //One color select register byte per palette, matched against mode 5
+    EGA,        //6 bits per color, one rgbRGB palette register byte
+    C64         //One byte per color, the nearest of the 16 C64 colors
+}
+
+impl PaletteColorFormat {
+    /// Encode colors as encode_colors does, treating colors whose alpha is
+    /// below the given threshold as transparent in formats that store alpha.
+    pub fn encode_colors_with_alpha_threshold<'a, I, T: Primitive, W: Write + 'a>(&self, w: &'a mut W, palette: I, alpha_threshold: u8) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+        match self {
+            PaletteColorFormat::BGR555 => BGR555 { alpha_threshold, ..BGR555::default() }.encode_colors(w, palette),
+            PaletteColorFormat::RGB565 => RGB565::default().encode_colors(w, palette),
+            PaletteColorFormat::MD => MDColor.encode_colors(w, palette),
+            PaletteColorFormat::VGADAC => VGADAC.encode_colors(w, palette),
+            PaletteColorFormat::Amiga => AmigaColor.encode_colors(w, palette),
+            PaletteColorFormat::VERA => VERAColor.encode_colors(w, palette),
+            PaletteColorFormat::RGBA5551 => RGBA5551 { alpha_threshold, ..RGBA5551::default() }.encode_colors(w, palette),
+            PaletteColorFormat::DMG => dmg::encode_palette(w, palette),
+            PaletteColorFormat::SMS => sms::encode_sms_palette(w, palette),
+            PaletteColorFormat::GameGear => sms::encode_gg_palette(w, palette),
+            PaletteColorFormat::NES(master) => nes::encode_palette(w, palette, *master),
+            PaletteColorFormat::PCE => pce::encode_colors(w, palette),
+            PaletteColorFormat::NeoGeo => neogeo::encode_palette(w, palette),
+            PaletteColorFormat::NGP => ngp::encode_palette(w, palette),
+            PaletteColorFormat::WS => ws::encode_palette(w, palette),
+            PaletteColorFormat::Lynx => lynx::encode_palette(w, palette),
+            PaletteColorFormat::PSX => psx::encode_palette(w, palette),
+            PaletteColorFormat::Saturn => saturn::encode_palette(w, palette),
+            PaletteColorFormat::SaturnVDP1 => saturn::encode_vdp1_palette(w, palette),
+            PaletteColorFormat::X68K => x68k::encode_palette(w, palette),
+            PaletteColorFormat::AtariST => atarist::encode_palette(w, palette, false),
+            PaletteColorFormat::AtariSTE => atarist::encode_palette(w, palette, true),
+            PaletteColorFormat::CGA => pc::encode_cga_palette(w, palette, false),
+            PaletteColorFormat::CGAMode5 => pc::encode_cga_palette(w, palette, true),
+            PaletteColorFormat::EGA => pc::encode_ega_palette(w, palette),
+            PaletteColorFormat::C64 => c64::encode_palette(w, palette)
+        }
+    }
+}
+
+impl PaletteFormat for PaletteColorFormat {
+    fn encode_colors<'a, I, T: Primitive, W: Write + 'a>(&self, w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+        self.encode_colors_with_alpha_threshold(w, palette, DEFAULT_ALPHA_THRESHOLD)
+    }
+
+    fn decode_colors<R: Read>(&self, r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        match self {
+            PaletteColorFormat::BGR555 => BGR555::default().decode_colors(r, size),
+            PaletteColorFormat::RGB565 => RGB565::default().decode_colors(r, size),
+            PaletteColorFormat::MD => MDColor.decode_colors(r, size),
+            PaletteColorFormat::VGADAC => VGADAC.decode_colors(r, size),
+            PaletteColorFormat::Amiga => AmigaColor.decode_colors(r, size),
+            PaletteColorFormat::VERA => VERAColor.decode_colors(r, size),
+            PaletteColorFormat::RGBA5551 => RGBA5551::default().decode_colors(r, size),
+            PaletteColorFormat::DMG => dmg::decode_palette(r, size),
+            PaletteColorFormat::SMS => sms::decode_sms_palette(r, size),
+            PaletteColorFormat::GameGear => sms::decode_gg_palette(r, size),
+            PaletteColorFormat::NES(master) => nes::decode_palette(r, size, *master),
+            PaletteColorFormat::PCE => pce::decode_colors(r, size),
+            PaletteColorFormat::NeoGeo => neogeo::decode_palette(r, size),
+            PaletteColorFormat::NGP => ngp::decode_palette(r, size),
+            PaletteColorFormat::WS => ws::decode_palette(r, size),
+            PaletteColorFormat::Lynx => lynx::decode_palette(r, size),
+            PaletteColorFormat::PSX => psx::decode_palette(r, size),
+            PaletteColorFormat::Saturn => saturn::decode_palette(r, size),
+            PaletteColorFormat::SaturnVDP1 => saturn::decode_vdp1_palette(r, size),
+            PaletteColorFormat::X68K => x68k::decode_palette(r, size),
+            PaletteColorFormat::AtariST => atarist::decode_palette(r, size, false),
+            PaletteColorFormat::AtariSTE => atarist::decode_palette(r, size, true),
+            PaletteColorFormat::CGA => pc::decode_cga_palette(r, size, false),
+            PaletteColorFormat::CGAMode5 => pc::decode_cga_palette(r, size, true),
+            PaletteColorFormat::EGA => pc::decode_ega_palette(r, size),
+            PaletteColorFormat::C64 => c64::decode_palette(r, size)
+        }
+    }
+}
+
+pub fn interpret_palette_format_name(fmt_given: &str) -> Option<PaletteColorFormat> {
+    let fmt = fmt_given.to_ascii_lowercase();
+
+    match fmt.as_ref() {
+        "bgr555" => Some(PaletteColorFormat::BGR555),
+        "rgb565" => Some(PaletteColorFormat::RGB565),
+        "md" => Some(PaletteColorFormat::MD),
+        "vgadac" => Some(PaletteColorFormat::VGADAC),
+        "amiga" => Some(PaletteColorFormat::Amiga),
+        "vera" => Some(PaletteColorFormat::VERA),
+        "rgba5551" => Some(PaletteColorFormat::RGBA5551),
+        "dmg" => Some(PaletteColorFormat::DMG),
+        "sms" => Some(PaletteColorFormat::SMS),
+        "gg" => Some(PaletteColorFormat::GameGear),
+        "nes" => Some(PaletteColorFormat::NES(NesMasterPalette::RP2C02)),
+        "nesfbx" => Some(PaletteColorFormat::NES(NesMasterPalette::FBX)),
+        "pce" => Some(PaletteColorFormat::PCE),
+        "neogeo" => Some(PaletteColorFormat::NeoGeo),
+        "ngp" => Some(PaletteColorFormat::NGP),
+        "ws" => Some(PaletteColorFormat::WS),
+        "lynx" => Some(PaletteColorFormat::Lynx),
+        "psx" => Some(PaletteColorFormat::PSX),
+        "saturn" => Some(PaletteColorFormat::Saturn),
+        "vdp1" => Some(PaletteColorFormat::SaturnVDP1),
+        "x68k" => Some(PaletteColorFormat::X68K),
+        "st" => Some(PaletteColorFormat::AtariST),
+        "ste" => Some(PaletteColorFormat::AtariSTE),
+        "cga" => Some(PaletteColorFormat::CGA),
+        "cga5" => Some(PaletteColorFormat::CGAMode5),
+        "ega" => Some(PaletteColorFormat::EGA),
+        "c64" => Some(PaletteColorFormat::C64),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use image::Rgba;
+    use awsmimg::formats::ChannelRounding;
+    use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, BGR555, RGB565, RGBA5551, interpret_palette_format_name};
+
+    fn encode<F: PaletteFormat>(format: F, palette: &[Rgba<u8>]) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        format.encode_colors(&mut out, palette.iter().cloned()).unwrap();
+
+        out
+    }
+
+    #[test]
+    fn palette_formats() {
+        let palette = [Rgba([255u8, 0, 0, 255]), Rgba([0u8, 255, 0, 128]), Rgba([0u8, 0, 255, 0])];
+
+        assert_eq!(encode(BGR555::default(), &palette), vec![0x1F, 0x00, 0xE0, 0x03, 0x00, 0x7C]);
+        assert_eq!(encode(BGR555 { use_alpha: true, ..BGR555::default() }, &palette), vec![0x1F, 0x80, 0xE0, 0x83, 0x00, 0x7C]);
+        assert_eq!(encode(BGR555 { use_alpha: true, alpha_threshold: 0xFF, rounding: ChannelRounding::Truncate }, &palette), vec![0x1F, 0x80, 0xE0, 0x03, 0x00, 0x7C]);
+        assert_eq!(encode(RGB565::default(), &palette), vec![0x00, 0xF8, 0xE0, 0x07, 0x1F, 0x00]);
+        assert_eq!(encode(PaletteColorFormat::MD, &palette), vec![0x00, 0x0E, 0x00, 0xE0, 0x0E, 0x00]);
+        assert_eq!(encode(PaletteColorFormat::VGADAC, &palette), vec![63, 0, 0, 0, 63, 0, 0, 0, 63]);
+        assert_eq!(encode(PaletteColorFormat::VERA, &palette[..1]), vec![0x00, 0x0F]);
+        assert_eq!(encode(PaletteColorFormat::Amiga, &palette[..1]), vec![0x0F, 0x00]);
+        assert_eq!(encode(PaletteColorFormat::RGBA5551, &palette), vec![0xF8, 0x01, 0x07, 0xC1, 0x00, 0x3E]);
+        assert_eq!(encode(RGBA5551 { alpha_threshold: 0xFF, ..RGBA5551::default() }, &palette), vec![0xF8, 0x01, 0x07, 0xC0, 0x00, 0x3E]);
+        assert_eq!(encode(RGBA5551 { rounding: ChannelRounding::RoundHalfUp, ..RGBA5551::default() }, &[Rgba([0x7Cu8, 0x04, 0xFF, 255])]), vec![0x80, 0x7F]);
+        assert_eq!(encode(PaletteColorFormat::SMS, &palette), vec![0x03, 0x0C, 0x30]);
+        assert_eq!(encode(PaletteColorFormat::GameGear, &palette[..1]), vec![0x0F, 0x00]);
+        assert_eq!(encode(PaletteColorFormat::PCE, &palette[..1]), vec![0x38, 0x00]);
+
+        let mut low = Vec::new();
+
+        PaletteColorFormat::RGBA5551.encode_colors_with_alpha_threshold(&mut low, palette.iter().cloned(), 0).unwrap();
+        assert_eq!(low, vec![0xF8, 0x01, 0x07, 0xC1, 0x00, 0x3F]);
+        assert_eq!(interpret_palette_format_name("RGB565"), Some(PaletteColorFormat::RGB565));
+        assert_eq!(interpret_palette_format_name("gg"), Some(PaletteColorFormat::GameGear));
+        assert_eq!(interpret_palette_format_name("cmyk"), None);
+    }
+}
@@ -0,0 +1,764 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, expand_channel};
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::{IndexedGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{Primitive, Rgba};
+
+/// The RGB values of the 16 RGBI colors.
+const RGBI_PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00], [0x00, 0x00, 0xAA], [0x00, 0xAA, 0x00], [0x00, 0xAA, 0xAA],
+    [0xAA, 0x00, 0x00], [0xAA, 0x00, 0xAA], [0xAA, 0x55, 0x00], [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55], [0x55, 0x55, 0xFF], [0x55, 0xFF, 0x55], [0x55, 0xFF, 0xFF],
+    [0xFF, 0x55, 0x55], [0xFF, 0x55, 0xFF], [0xFF, 0xFF, 0x55], [0xFF, 0xFF, 0xFF]
+];
+
+/// The RGBI colors of CGA palette indexes 1 through 3 in mode 4, by color
+/// select register bits 5 (palette) and 4 (intensity).
+const CGA_MODE4_PALETTES: [[u8; 3]; 4] = [[2, 4, 6], [10, 12, 14], [3, 5, 7], [11, 13, 15]];
+
+/// The RGBI colors of CGA palette indexes 1 through 3 in mode 5, by color
+/// select register bit 4 (intensity).
+const CGA_MODE5_PALETTES: [[u8; 3]; 2] = [[3, 4, 7], [11, 12, 15]];
+
+fn rgb_distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    (0..3).map(|i| (a[i] as i32 - b[i] as i32).pow(2) as u32).sum()
+}
+
+fn rgb8<T: Primitive>(rgba: &Rgba<T>) -> [u8; 3] {
+    let imgmax = T::max_value();
+    let mut rgb = [0u8; 3];
+
+    for (c, v) in rgb.iter_mut().enumerate() {
+        *v = (rgba[c].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u8;
+    }
+
+    rgb
+}
+
+/// Read up to size bytes of palette data.
+fn read_palette_bytes<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+
+    r.take(size as u64).read_to_end(&mut buf)?;
+
+    Ok(buf)
+}
+
+fn rgbi(index: u8) -> Rgba<u8> {
+    let rgb = RGBI_PALETTE[(index & 0x0F) as usize];
+
+    Rgba([rgb[0], rgb[1], rgb[2], 255])
+}
+
+/// Encode a series of RGBA colors as EGA palette register values.
+///
+/// Each color is written as one byte in the 00rgbRGB layout, where the
+/// uppercase bits select two thirds intensity and the lowercase bits one
+/// third intensity of each channel. Alpha is ignored.
+pub fn encode_ega_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let mut out: Vec<u8> = Vec::new();
+
+    for rgba in palette {
+        let rgb = rgb8(&rgba);
+        let mut enc_color = 0u8;
+
+        for (c, v) in rgb.iter().enumerate() {
+            let level = ((*v as u16 + 42) / 85) as u8;
+
+            enc_color |= ((level >> 1) << (2 - c)) | ((level & 0x01) << (5 - c));
+        }
+
+        out.push(enc_color);
+    }
+
+    w.write_all(&out)
+}
+
+/// Decode EGA palette register values written by encode_ega_palette into
+/// RGBA colors.
+///
+/// Up to size bytes are read, one color per byte. Every color is opaque.
+pub fn decode_ega_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    Ok(read_palette_bytes(r, size)?.iter().map(|b| {
+        let channel = |c: u8| (((b >> (2 - c)) & 0x01) << 1 | ((b >> (5 - c)) & 0x01)) * 85;
+
+        Rgba([channel(0), channel(1), channel(2), 255])
+    }).collect())
+}
+
+/// Encode a series of RGBA colors as VGA DAC palette data.
+///
+/// Each color is written as three bytes, red, green and blue, each holding a
+/// 6-bit DAC value. Alpha is ignored.
+pub fn encode_vga_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let out: Vec<u8> = palette.flat_map(|rgba| rgb8(&rgba).iter().map(|v| v >> 2).collect::<Vec<u8>>()).collect();
+
+    w.write_all(&out)
+}
+
+/// Decode VGA DAC palette data written by encode_vga_palette into RGBA
+/// colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. Every
+/// color is opaque.
+pub fn decode_vga_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    let buf = read_palette_bytes(r, size)?;
+
+    if !buf.len().is_multiple_of(3) {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Palette data ends partway through a color"));
+    }
+
+    Ok(buf.chunks(3).map(|rgb| {
+        Rgba([expand_channel(rgb[0] as u16, 6), expand_channel(rgb[1] as u16, 6), expand_channel(rgb[2] as u16, 6), 255])
+    }).collect())
+}
+
+/// Encode a palette as all 256 VGA DAC entries in the given color format,
+/// padding it with black.
+fn encode_vga_dac<'a, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: Vec<Rgba<T>>, format: PaletteColorFormat) -> io::Result<()> {
+    if palette.len() > 256 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "VGA palettes cannot have more than 256 colors."));
+    }
+
+    let padding = 256 - palette.len();
+
+    format.encode_colors(w, palette.into_iter().chain((0..padding).map(|_| Rgba([T::zero(), T::zero(), T::zero(), T::max_value()]))))
+}
+
+/// Encode a series of RGBA colors as a CGA color select register value.
+///
+/// CGA colors are fixed, so the first color becomes the nearest of the 16
+/// background colors, and the next three select the palette and intensity
+/// bits of the hardware palette that matches them best. If mode5 is set, they
+/// are matched against the cyan, red and white palettes of mode 5 rather than
+/// the mode 4 palettes. Any further colors are ignored.
+pub fn encode_cga_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I, mode5: bool) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let colors: Vec<[u8; 3]> = palette.map(|rgba| rgb8(&rgba)).collect();
+    let background = match colors.first() {
+        Some(c) => (0..16).min_by_key(|&i| rgb_distance(*c, RGBI_PALETTE[i])).unwrap() as u8,
+        None => 0
+    };
+    let candidates: &[[u8; 3]] = if mode5 { &CGA_MODE5_PALETTES } else { &CGA_MODE4_PALETTES };
+    let select = (0..candidates.len()).min_by_key(|&p| {
+        colors.iter().skip(1).take(3).enumerate().map(|(i, c)| rgb_distance(*c, RGBI_PALETTE[candidates[p][i] as usize])).sum::<u32>()
+    }).unwrap() as u8;
+
+    w.write_all(&[select << 4 | background])
+}
+
+/// Decode CGA color select register values written by encode_cga_palette
+/// into RGBA colors.
+///
+/// Up to size bytes are read, and each yields the four colors it selects: the
+/// background color, then the three colors of the mode 4 palette, or of the
+/// mode 5 palette if mode5 is set. Every color is opaque.
+pub fn decode_cga_palette<R: Read>(r: &mut R, size: usize, mode5: bool) -> io::Result<Vec<Rgba<u8>>> {
+    Ok(read_palette_bytes(r, size)?.iter().flat_map(|b| {
+        let foreground = match mode5 {
+            true => CGA_MODE5_PALETTES[((b >> 4) & 0x01) as usize],
+            false => CGA_MODE4_PALETTES[((b >> 4) & 0x03) as usize]
+        };
+
+        Some(rgbi(*b)).into_iter().chain(foreground.iter().map(|c| rgbi(*c))).collect::<Vec<_>>()
+    }).collect())
+}
+
+/// Encoder for 2bpp graphics for the CGA 320x200 modes.
+///
+/// Pixels are packed four to a byte, leftmost pixel in the most significant
+/// bits, one row after another. If with_banks is used, even rows and odd rows
+/// are split into the two interleaved banks of CGA video memory: the even
+/// bank is padded to 8KB so that the output can be copied directly to B800h.
+///
+/// CGA colors are fixed, so palettes are written as a single color select
+/// register value. Index 0 becomes the nearest of the 16 background colors,
+/// and indexes 1 through 3 select the palette and intensity bits of the
+/// hardware palette that matches them best. Encoders constructed with new
+/// match against the mode 4 palettes; encoders constructed with new_mode5
+/// match against the cyan, red and white palettes of mode 5. Use
+/// with_palette_format to write palettes in another color format instead.
+pub struct CGAEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    palette_format: PaletteColorFormat,
+    banks: bool
+}
+
+impl<'a, F: 'a> CGAEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> CGAEncoder<'a, F> {
+        CGAEncoder {
+            f: file,
+            palette_format: PaletteColorFormat::CGA,
+            banks: false
+        }
+    }
+
+    pub fn new_mode5(file: &'a mut F) -> CGAEncoder<'a, F> {
+        CGAEncoder {
+            palette_format: PaletteColorFormat::CGAMode5,
+            ..CGAEncoder::new(file)
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as a color
+    /// select register value.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> CGAEncoder<'a, F> {
+        CGAEncoder {
+            palette_format,
+            ..self
+        }
+    }
+
+    pub fn with_banks(self, banks: bool) -> CGAEncoder<'a, F> {
+        CGAEncoder {
+            banks,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for CGAEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        3
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for CGAEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, width: u32, _height: u32) -> io::Result<()> {
+        let rows: Vec<Vec<u8>> = data.chunks(width as usize).map(|row| {
+            row.chunks(4).map(|group| {
+                group.iter().enumerate().fold(0u8, |byte, (x, index)| byte | (index.to_u8().unwrap() & 0x03) << (6 - x * 2))
+            }).collect()
+        }).collect();
+
+        if !self.banks {
+            for row in rows.iter() {
+                self.f.write_all(row)?;
+            }
+
+            return Ok(());
+        }
+
+        let mut even: Vec<u8> = rows.iter().step_by(2).flatten().cloned().collect();
+
+        if even.len() > 0x2000 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image is too large for CGA video memory banks."));
+        }
+
+        even.resize(0x2000, 0);
+        self.f.write_all(&even)?;
+
+        for row in rows.iter().skip(1).step_by(2) {
+            self.f.write_all(row)?;
+        }
+
+        Ok(())
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for CGAEncoder<'a, F> where F: Read {
+    /// Decode packed 2bpp data into color indexes.
+    ///
+    /// Banked data cannot be decoded, as the bank layout depends on the width
+    /// of the image.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        if self.banks {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Banked CGA data cannot be decoded."));
+        }
+
+        let buf = read_units(self.f, size, 1, "CGA data ends partway through a byte")?;
+
+        Ok(buf.iter().flat_map(|byte| (0..4).map(move |x| P::from((byte >> (6 - x * 2)) & 0x03).unwrap())).collect())
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+}
+
+/// Encoder for 4bpp planar graphics for the EGA 16-color modes.
+///
+/// Each bitplane holds one bit of every pixel's index, packed eight pixels to
+/// a byte with the leftmost pixel in the most significant bit, one row after
+/// another. Rows are padded to a whole number of bytes.
+///
+/// When constructed with new, the four planes are written to one writer one
+/// after another, lowest plane first. When constructed with new_split, each
+/// plane is written to its own writer, ready to be copied to video memory
+/// with the matching map mask.
+///
+/// Palettes are written as 16 EGA palette register values unless another color
+/// format is selected with with_palette_format.
+pub struct EGAEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    split: Option<[&'a mut F; 3]>,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> EGAEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> EGAEncoder<'a, F> {
+        EGAEncoder {
+            f: file,
+            split: None,
+            palette_format: PaletteColorFormat::EGA
+        }
+    }
+
+    pub fn new_split(plane0: &'a mut F, plane1: &'a mut F, plane2: &'a mut F, plane3: &'a mut F) -> EGAEncoder<'a, F> {
+        EGAEncoder {
+            f: plane0,
+            split: Some([plane1, plane2, plane3]),
+            palette_format: PaletteColorFormat::EGA
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as EGA palette
+    /// register values.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> EGAEncoder<'a, F> {
+        EGAEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for EGAEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        15
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for EGAEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, width: u32, _height: u32) -> io::Result<()> {
+        for plane in 0..4 {
+            let out: Vec<u8> = data.chunks(width as usize).flat_map(|row| {
+                row.chunks(8).map(|group| {
+                    group.iter().enumerate().fold(0u8, |byte, (x, index)| byte | ((index.to_u8().unwrap() >> plane) & 0x01) << (7 - x))
+                }).collect::<Vec<u8>>()
+            }).collect();
+
+            match self.split {
+                Some(ref mut planes) if plane > 0 => planes[plane - 1].write_all(&out)?,
+                _ => self.f.write_all(&out)?
+            }
+        }
+
+        Ok(())
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+/// Read the four planes of an EGA or mode X image, up to size bytes each when
+/// split, or up to size bytes in total when not.
+fn read_planes<F: Read>(f: &mut F, split: &mut Option<[&mut F; 3]>, size: usize) -> io::Result<Vec<Vec<u8>>> {
+    match *split {
+        Some(ref mut planes) => {
+            let mut out = vec![read_units(f, size, 1, "Plane data ends partway through a byte")?];
+
+            for plane in planes.iter_mut() {
+                out.push(read_units(*plane, size, 1, "Plane data ends partway through a byte")?);
+            }
+
+            if out.iter().any(|plane| plane.len() != out[0].len()) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Planes are not all the same length."));
+            }
+
+            Ok(out)
+        },
+        None => {
+            let buf = read_units(f, size, 4, "Plane data ends partway through a plane")?;
+            let plane_len = buf.len() / 4;
+
+            Ok((0..4).map(|plane| buf[plane * plane_len..(plane + 1) * plane_len].to_vec()).collect())
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for EGAEncoder<'a, F> where F: Read {
+    /// Decode planar data into color indexes.
+    ///
+    /// Every bit of each plane is decoded, so images whose width is not a
+    /// multiple of 8 decode with the padding of each row intact.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let planes = read_planes(self.f, &mut self.split, size)?;
+
+        Ok((0..planes[0].len() * 8).map(|i| {
+            let index = (0..4).fold(0u8, |index, plane| index | ((planes[plane][i / 8] >> (7 - i % 8)) & 0x01) << plane);
+
+            P::from(index).unwrap()
+        }).collect())
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+}
+
+/// Encoder for 8bpp graphics for VGA mode 13h.
+///
+/// Pixels are stored one byte per pixel, one row after another, exactly as
+/// they appear in the linear frame buffer at A000h.
+///
+/// Palettes are written as all 256 DAC entries, three bytes per entry, so
+/// that the output can be sent directly to the DAC data port. Palettes with
+/// fewer than 256 colors are padded with black. Use with_palette_format to
+/// write the padded palette in another color format instead.
+pub struct VGA13hEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> VGA13hEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> VGA13hEncoder<'a, F> {
+        VGA13hEncoder {
+            f: file,
+            palette_format: PaletteColorFormat::VGADAC
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as VGA DAC values.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> VGA13hEncoder<'a, F> {
+        VGA13hEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for VGA13hEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        255
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for VGA13hEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        let out: Vec<u8> = data.iter().map(|index| index.to_u8().unwrap()).collect();
+
+        self.f.write_all(&out)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        encode_vga_dac(self.f, palette, self.palette_format)
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for VGA13hEncoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let buf = read_units(self.f, size, 1, "Frame buffer data ends partway through a pixel")?;
+
+        Ok(buf.into_iter().map(|index| P::from(index).unwrap()).collect())
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(1)
+    }
+}
+
+/// Encoder for 8bpp graphics for VGA mode X.
+///
+/// In the unchained 256-color modes, each of the four VGA memory planes holds
+/// every fourth column of the screen: plane 0 holds columns 0, 4, 8 and so on,
+/// plane 1 holds columns 1, 5, 9 and so on. Each plane is stored one byte per
+/// pixel, one row after another, with rows a quarter of the image width long.
+/// Image widths must be a multiple of 4.
+///
+/// When constructed with new, the four planes are written to one writer one
+/// after another, plane 0 first. When constructed with new_split, each plane
+/// is written to its own writer, ready to be copied to video memory with the
+/// matching map mask.
+///
+/// Palettes are written as all 256 DAC entries, as with VGA13hEncoder, unless
+/// another color format is selected with with_palette_format.
+pub struct ModeXEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    split: Option<[&'a mut F; 3]>,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> ModeXEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> ModeXEncoder<'a, F> {
+        ModeXEncoder {
+            f: file,
+            split: None,
+            palette_format: PaletteColorFormat::VGADAC
+        }
+    }
+
+    pub fn new_split(plane0: &'a mut F, plane1: &'a mut F, plane2: &'a mut F, plane3: &'a mut F) -> ModeXEncoder<'a, F> {
+        ModeXEncoder {
+            f: plane0,
+            split: Some([plane1, plane2, plane3]),
+            palette_format: PaletteColorFormat::VGADAC
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as VGA DAC values.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> ModeXEncoder<'a, F> {
+        ModeXEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for ModeXEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        255
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for ModeXEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, width: u32, _height: u32) -> io::Result<()> {
+        if !width.is_multiple_of(4) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Mode X image width must be a multiple of 4."));
+        }
+
+        for plane in 0..4 {
+            let out: Vec<u8> = data.iter().skip(plane).step_by(4).map(|index| index.to_u8().unwrap()).collect();
+
+            match self.split {
+                Some(ref mut planes) if plane > 0 => planes[plane - 1].write_all(&out)?,
+                _ => self.f.write_all(&out)?
+            }
+        }
+
+        Ok(())
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        encode_vga_dac(self.f, palette, self.palette_format)
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for ModeXEncoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let planes = read_planes(self.f, &mut self.split, size)?;
+
+        Ok((0..planes[0].len() * 4).map(|i| P::from(planes[i % 4][i / 4]).unwrap()).collect())
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::formats::pc::{CGAEncoder, EGAEncoder, VGA13hEncoder, ModeXEncoder};
+
+    #[test]
+    fn cga_encode() {
+        let src : Vec<u8> = vec![0, 1, 2, 3, 3, 2, 1, 0,
+                                 1, 1, 1, 1, 2, 2, 2, 2];
+        let mut test_out = Cursor::new(Vec::with_capacity(4));
+
+        {
+            let mut cga = CGAEncoder::new(&mut test_out);
+
+            cga.encode_indexes(src, 8, 2).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x1Bu8, 0xE4, 0x55, 0xAA])
+    }
+
+    #[test]
+    fn cga_banked_encode() {
+        let src : Vec<u8> = vec![3, 0, 0, 0,
+                                 0, 3, 0, 0,
+                                 0, 0, 3, 0];
+        let mut test_out = Cursor::new(Vec::with_capacity(0x2001));
+
+        {
+            let mut cga = CGAEncoder::new(&mut test_out).with_banks(true);
+
+            cga.encode_indexes(src, 4, 3).unwrap();
+        }
+
+        let mut valid_out : Vec<u8> = vec![0; 0x2001];
+        valid_out[0] = 0xC0;
+        valid_out[1] = 0x0C;
+        valid_out[0x2000] = 0x30;
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn cga_palette_encode() {
+        let palette = vec![image::Rgba([0u8, 0, 0xAA, 255]),
+                           image::Rgba([0x55u8, 0xFF, 0xFF, 255]),
+                           image::Rgba([0xFFu8, 0x55, 0xFF, 255]),
+                           image::Rgba([0xFFu8, 0xFF, 0xFF, 255])];
+        let mut mode4_out = Cursor::new(Vec::with_capacity(1));
+        let mut mode5_out = Cursor::new(Vec::with_capacity(1));
+
+        CGAEncoder::new(&mut mode4_out).encode_palette(palette.clone()).unwrap();
+        CGAEncoder::new_mode5(&mut mode5_out).encode_palette(palette).unwrap();
+
+        assert_eq!(mode4_out.get_ref(), &vec![0x31u8]);
+        assert_eq!(mode5_out.get_ref(), &vec![0x11u8]);
+    }
+
+    #[test]
+    fn ega_encode() {
+        let src : Vec<u8> = vec![1, 2, 4, 8, 15, 0, 0, 0, 3];
+        let mut test_out = Cursor::new(Vec::with_capacity(8));
+
+        {
+            let mut ega = EGAEncoder::new(&mut test_out);
+
+            ega.encode_indexes(src, 9, 1).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x88u8, 0x80, 0x48, 0x80, 0x28, 0x00, 0x18, 0x00])
+    }
+
+    #[test]
+    fn ega_split_encode() {
+        let src : Vec<u8> = vec![1, 2, 4, 8, 0, 0, 0, 15];
+        let mut planes = [Cursor::new(Vec::new()), Cursor::new(Vec::new()), Cursor::new(Vec::new()), Cursor::new(Vec::new())];
+
+        {
+            let [ref mut p0, ref mut p1, ref mut p2, ref mut p3] = planes;
+            let mut ega = EGAEncoder::new_split(p0, p1, p2, p3);
+
+            ega.encode_indexes(src, 8, 1).unwrap();
+        }
+
+        assert_eq!(planes[0].get_ref(), &vec![0x81u8]);
+        assert_eq!(planes[1].get_ref(), &vec![0x41u8]);
+        assert_eq!(planes[2].get_ref(), &vec![0x21u8]);
+        assert_eq!(planes[3].get_ref(), &vec![0x11u8]);
+    }
+
+    #[test]
+    fn ega_palette_encode() {
+        let palette = vec![image::Rgba([0xAAu8, 0x55, 0x00, 255]),
+                           image::Rgba([0x55u8, 0x55, 0xFF, 255]),
+                           image::Rgba([0xFFu8, 0xFF, 0xFF, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(3));
+
+        {
+            let mut ega = EGAEncoder::new(&mut test_out);
+
+            ega.encode_palette(palette).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x14u8, 0x39, 0x3F])
+    }
+
+    #[test]
+    fn vga13h_encode() {
+        let src : Vec<u16> = vec![0, 1, 0x7F, 0xFF];
+        let mut test_out = Cursor::new(Vec::with_capacity(4));
+
+        {
+            let mut vga = VGA13hEncoder::new(&mut test_out);
+
+            vga.encode_indexes(src, 2, 2).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0u8, 1, 0x7F, 0xFF])
+    }
+
+    #[test]
+    fn vga_palette_encode() {
+        let palette = vec![image::Rgba([0xFFu8, 0x80, 0x04, 255]),
+                           image::Rgba([0x00u8, 0xFF, 0x03, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(768));
+
+        {
+            let mut vga = VGA13hEncoder::new(&mut test_out);
+
+            vga.encode_palette(palette).unwrap();
+        }
+
+        let mut valid_out : Vec<u8> = vec![0; 768];
+        valid_out[0..6].copy_from_slice(&[0x3F, 0x20, 0x01, 0x00, 0x3F, 0x00]);
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn modex_encode() {
+        let src : Vec<u8> = (0..16).collect();
+        let mut test_out = Cursor::new(Vec::with_capacity(16));
+
+        {
+            let mut vga = ModeXEncoder::new(&mut test_out);
+
+            vga.encode_indexes(src, 8, 2).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0, 4, 8, 12, 1, 5, 9, 13, 2, 6, 10, 14, 3, 7, 11, 15];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn modex_split_encode() {
+        let src : Vec<u8> = (0..8).collect();
+        let mut planes = [Cursor::new(Vec::new()), Cursor::new(Vec::new()), Cursor::new(Vec::new()), Cursor::new(Vec::new())];
+
+        {
+            let [ref mut p0, ref mut p1, ref mut p2, ref mut p3] = planes;
+            let mut vga = ModeXEncoder::new_split(p0, p1, p2, p3);
+
+            vga.encode_indexes(src, 8, 1).unwrap();
+        }
+
+        assert_eq!(planes[0].get_ref(), &vec![0u8, 4]);
+        assert_eq!(planes[1].get_ref(), &vec![1u8, 5]);
+        assert_eq!(planes[2].get_ref(), &vec![2u8, 6]);
+        assert_eq!(planes[3].get_ref(), &vec![3u8, 7]);
+    }
+}
@@ -0,0 +1,376 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, read_color_words, expand_channel};
+use awsmimg::formats::sfc::{encode_planar, decode_planar};
+use awsmimg::formats::generic::{PlanarEncoder, PlaneInterleave, PlaneUnit};
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::IndexedGraphicsDecoder;
+use awsmimg::tiles::TileChunkIterator;
+
+use std::io;
+use std::io::{Write, Read};
+use image::{Primitive, Rgba};
+
+/// Identifies one of the 16-entry palette blocks in the PCE color table.
+///
+/// The VCE color table holds 512 colors: sixteen background palettes followed
+/// by sixteen sprite palettes.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PCEPaletteBlock {
+    Background(u8),
+    Sprite(u8)
+}
+
+impl PCEPaletteBlock {
+    /// The VCE color table address of the first entry of this block.
+    pub fn vce_address(&self) -> u16 {
+        match *self {
+            PCEPaletteBlock::Background(b) => b as u16 * 16,
+            PCEPaletteBlock::Sprite(b) => 256 + b as u16 * 16
+        }
+    }
+
+    /// The number of colors that can be written starting at this block before
+    /// running out of blocks of the same kind.
+    pub fn capacity(&self) -> usize {
+        match *self {
+            PCEPaletteBlock::Background(b) | PCEPaletteBlock::Sprite(b) => 256usize.saturating_sub(b as usize * 16)
+        }
+    }
+}
+
+/// Encode a series of RGBA colors as PCE color table data.
+///
+/// Colors are written as little-endian 9-bit words in the 0000000GGGRRRBBB
+/// layout expected by the VCE. Alpha is ignored.
+pub fn encode_colors<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 2] = [0, 0];
+
+    for rgba in palette {
+        let r : u16 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let g : u16 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let b : u16 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+
+        let enc_color: u16 = (g & 0xE0) << 1 | (r & 0xE0) >> 2 | b >> 5;
+
+        out[0] = (enc_color & 0xFF) as u8;
+        out[1] = ((enc_color >> 8) & 0xFF) as u8;
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode PCE color table data written by encode_colors into RGBA colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. Every
+/// color is opaque.
+pub fn decode_colors<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    Ok(read_color_words(r, size, false)?.into_iter().map(|v| {
+        Rgba([expand_channel(v >> 3, 3), expand_channel(v >> 6, 3), expand_channel(v, 3), 255])
+    }).collect())
+}
+
+/// Check that a palette of the given length fits in the PCE color table when
+/// uploaded starting at the given palette block.
+///
+/// Palettes too large to fit between that block and the end of its half of the
+/// color table (e.g. background palettes that would spill into the sprite
+/// palettes) are rejected.
+fn check_palette_block(len: usize, block: PCEPaletteBlock) -> io::Result<()> {
+    if len > block.capacity() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Palette does not fit in the PCE color table from the selected palette block."));
+    }
+
+    Ok(())
+}
+
+/// Encode a series of RGBA colors as PCE color table data, to be uploaded
+/// starting at the given palette block.
+///
+/// Colors are written as with encode_colors. Palettes that do not fit in the
+/// color table from the given block are rejected.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I, block: PCEPaletteBlock) -> io::Result<()> where I: ExactSizeIterator<Item=Rgba<T>> {
+    check_palette_block(palette.len(), block)?;
+    encode_colors(w, palette)
+}
+
+/// Encoder for 4bpp background character patterns for the PCE platform.
+///
+/// Each 8x8 character is stored as sixteen words. The first eight words hold
+/// bitplanes 0 and 1 of each row, and the next eight hold bitplanes 2 and 3,
+/// with the lower plane of each pair in the low byte. This is the same layout
+/// as SFC 4bpp tiles, and is written using the same planar encoder.
+///
+/// Palettes are validated against background palette block 0 unless another
+/// block is selected with with_palette_block. They are written as PCE color
+/// table words unless another color format is selected with
+/// with_palette_format.
+pub struct PCEBGEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    palette_block: PCEPaletteBlock,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> PCEBGEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> PCEBGEncoder<'a, F> {
+        PCEBGEncoder {
+            f: file,
+            palette_block: PCEPaletteBlock::Background(0),
+            palette_format: PaletteColorFormat::PCE
+        }
+    }
+
+    pub fn with_palette_block(self, palette_block: PCEPaletteBlock) -> PCEBGEncoder<'a, F> {
+        PCEBGEncoder {
+            palette_block,
+            ..self
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as PCE color table
+    /// words.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> PCEBGEncoder<'a, F> {
+        PCEBGEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for PCEBGEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        15
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for PCEBGEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        encode_planar(self.f, &data, 4)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        check_palette_block(palette.len(), self.palette_block)?;
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for PCEBGEncoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        decode_planar(self.f, size, 4)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(32)
+    }
+}
+
+/// Encoder for 4bpp sprite patterns for the PCE platform.
+///
+/// Sprites are 16x16 cells. Each cell is stored as four groups of sixteen
+/// little-endian words, one group per bitplane starting with plane 0. Each
+/// word holds one row of its plane, with the leftmost pixel in the most
+/// significant bit.
+///
+/// Palettes are validated against sprite palette block 0 unless another block
+/// is selected with with_palette_block. They are written as PCE color table
+/// words unless another color format is selected with with_palette_format.
+pub struct PCESpriteEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    palette_block: PCEPaletteBlock,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> PCESpriteEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> PCESpriteEncoder<'a, F> {
+        PCESpriteEncoder {
+            f: file,
+            palette_block: PCEPaletteBlock::Sprite(0),
+            palette_format: PaletteColorFormat::PCE
+        }
+    }
+
+    pub fn with_palette_block(self, palette_block: PCEPaletteBlock) -> PCESpriteEncoder<'a, F> {
+        PCESpriteEncoder {
+            palette_block,
+            ..self
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as PCE color table
+    /// words.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> PCESpriteEncoder<'a, F> {
+        PCESpriteEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for PCESpriteEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (16, 16)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (16, 16)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        15
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for PCESpriteEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        let mut out: Vec<u8> = Vec::with_capacity(128);
+
+        for cell in TileChunkIterator::new(&data, self.tile_size()) {
+            out.clear();
+
+            for plane in 0..4 {
+                for row in cell.chunks(16) {
+                    let mut word: u16 = 0;
+
+                    for (x, index) in row.iter().enumerate() {
+                        word |= (((index.to_u8().unwrap() >> plane) & 0x01) as u16) << (15 - x);
+                    }
+
+                    out.push((word & 0xFF) as u8);
+                    out.push((word >> 8) as u8);
+                }
+            }
+
+            self.f.write_all(&out)?;
+        }
+
+        Ok(())
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        check_palette_block(palette.len(), self.palette_block)?;
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for PCESpriteEncoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        PlanarEncoder::new(self.f, 4, PlaneInterleave::Tile)?.with_unit(PlaneUnit::WordLE).with_tile_size((16, 16)).decode_indexes(size)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(128)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::formats::pce::{PCEBGEncoder, PCESpriteEncoder, PCEPaletteBlock};
+    use awsmimg::formats::palette::PaletteColorFormat;
+
+    #[test]
+    fn bg_encode() {
+        let src : Vec<u8> = vec![0,1,2,3,4,5,6,7,
+                                 8,9,10,11,12,13,14,15,
+                                 0,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,0,
+                                 15,0,0,0,0,0,0,15];
+        let mut test_out = Cursor::new(Vec::with_capacity(32));
+
+        {
+            let mut bg = PCEBGEncoder::new(&mut test_out);
+
+            bg.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x55, 0x33, 0x55, 0x33, 0x00, 0x00, 0x00, 0x00,
+                                       0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x81, 0x81,
+                                       0x0F, 0x00, 0x0F, 0xFF, 0x00, 0x00, 0x00, 0x00,
+                                       0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x81, 0x81];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn sprite_encode() {
+        let mut src : Vec<u8> = vec![0; 256];
+        src[0] = 0x01;
+        src[15] = 0x02;
+        src[16 * 15 + 8] = 0x0C;
+        let mut test_out = Cursor::new(Vec::with_capacity(128));
+
+        {
+            let mut spr = PCESpriteEncoder::new(&mut test_out);
+
+            spr.encode_indexes(src, 16, 16).unwrap();
+        }
+
+        let mut valid_out : Vec<u8> = vec![0; 128];
+        valid_out[1] = 0x80;
+        valid_out[32] = 0x01;
+        valid_out[64 + 30] = 0x80;
+        valid_out[96 + 30] = 0x80;
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn palette_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]),
+                           image::Rgba([0u8, 255, 0, 255]),
+                           image::Rgba([0u8, 0, 255, 255]),
+                           image::Rgba([0x20u8, 0x40, 0x60, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(8));
+
+        {
+            let mut bg = PCEBGEncoder::new(&mut test_out);
+
+            bg.encode_palette(palette).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x38, 0x00, 0xC0, 0x01, 0x07, 0x00, 0x8B, 0x00];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn palette_block_overflow() {
+        let palette = vec![image::Rgba([0u8, 0, 0, 255]); 32];
+        let mut test_out = Cursor::new(Vec::with_capacity(64));
+        let mut spr = PCESpriteEncoder::new(&mut test_out).with_palette_block(PCEPaletteBlock::Sprite(15));
+
+        assert_eq!(PCEPaletteBlock::Sprite(15).vce_address(), 0x1F0);
+        assert!(spr.encode_palette(palette.clone()).is_err());
+        assert!(spr.encode_palette(palette[..16].to_vec()).is_ok());
+
+        let mut bgr_out = Cursor::new(Vec::with_capacity(32));
+        let mut bgr_spr = PCESpriteEncoder::new(&mut bgr_out).with_palette_block(PCEPaletteBlock::Sprite(15)).with_palette_format(PaletteColorFormat::BGR555);
+
+        assert!(bgr_spr.encode_palette(palette.clone()).is_err());
+        assert!(bgr_spr.encode_palette(vec![image::Rgba([255u8, 0, 0, 255])]).is_ok());
+        assert_eq!(bgr_out.get_ref(), &vec![0x1Fu8, 0x00]);
+    }
+}
@@ -0,0 +1,173 @@
+use awsmimg::encoder::DirectGraphicsEncoder;
+use awsmimg::decoder::DirectGraphicsDecoder;
+
+use std::io;
+use std::io::{Write, Read};
+use image::{GenericImage, ImageBuffer, Primitive, Pixel, Rgba};
+
+/// The RGB values of the 16 PICO-8 colors.
+const PICO8_PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00], [0x1D, 0x2B, 0x53], [0x7E, 0x25, 0x53], [0x00, 0x87, 0x51],
+    [0xAB, 0x52, 0x36], [0x5F, 0x57, 0x4F], [0xC2, 0xC3, 0xC7], [0xFF, 0xF1, 0xE8],
+    [0xFF, 0x00, 0x4D], [0xFF, 0xA3, 0x00], [0xFF, 0xEC, 0x27], [0x00, 0xE4, 0x36],
+    [0x29, 0xAD, 0xFF], [0x83, 0x76, 0x9C], [0xFF, 0x77, 0xA8], [0xFF, 0xCC, 0xAA]
+];
+
+/// The nearest color number to a pixel, out of a fixed 16-color palette.
+///
+/// Pixels that are less than half opaque become color 0, which both PICO-8
+/// and TIC-80 draw as transparent by default.
+pub fn nearest_fixed_color<P, S>(pixel: P, palette: &[[u8; 3]; 16]) -> u8 where P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+    let imgmax = S::max_value().to_f32().unwrap();
+    let rgba = pixel.to_rgba();
+    let mut c = [0i32; 4];
+
+    for (i, v) in c.iter_mut().enumerate() {
+        *v = (rgba[i].to_f32().unwrap() / imgmax * 255f32) as i32;
+    }
+
+    if c[3] < 0x80 {
+        return 0;
+    }
+
+    (0..16).min_by_key(|&i| (0..3).map(|ch| (c[ch] - palette[i][ch] as i32).pow(2)).sum::<i32>()).unwrap() as u8
+}
+
+/// Read up to size bytes of a text cart as text.
+pub fn read_cart_text<R: Read>(r: &mut R, size: usize) -> io::Result<String> {
+    let mut text = String::new();
+
+    r.take(size as u64).read_to_string(&mut text)?;
+
+    Ok(text)
+}
+
+/// Parse one hex digit per pixel into fixed palette colors.
+pub fn fixed_colors_from_hex(digits: &str, palette: &[[u8; 3]; 16]) -> io::Result<Vec<Rgba<u8>>> {
+    digits.chars().map(|d| match d.to_digit(16) {
+        Some(c) => Ok(Rgba([palette[c as usize][0], palette[c as usize][1], palette[c as usize][2], 255])),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, "Cart pixel data holds a character that is not a hex digit."))
+    }).collect()
+}
+
+/// Encoder for the spritesheet section of PICO-8 text carts.
+///
+/// The image is written as a `__gfx__` section: one line per row, with one
+/// lowercase hex digit per pixel holding its PICO-8 color number. Colors are
+/// mapped to the nearest color of the fixed PICO-8 palette. The spritesheet
+/// is 128x128; narrower images are padded with color 0 to the full width.
+pub struct Pico8GfxEncoder<'a, W: Write + 'a> {
+    w: &'a mut W
+}
+
+impl<'a, W: Write + 'a> Pico8GfxEncoder<'a, W> {
+    pub fn new(write: &'a mut W) -> Pico8GfxEncoder<'a, W> {
+        Pico8GfxEncoder {
+            w: write
+        }
+    }
+}
+
+impl<'a, W: Write> DirectGraphicsEncoder for Pico8GfxEncoder<'a, W> {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        let (width, height) = image.dimensions();
+
+        if width > 128 || height > 128 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "PICO-8 spritesheets cannot be larger than 128x128."));
+        }
+
+        let mut out = String::from("__gfx__\n");
+
+        for y in 0..height {
+            for x in 0..128 {
+                let color = match x {
+                    x if x < width => nearest_fixed_color(image.get_pixel(x, y), &PICO8_PALETTE),
+                    _ => 0
+                };
+
+                out.push_str(&format!("{:x}", color));
+            }
+
+            out.push('\n');
+        }
+
+        self.w.write_all(out.as_bytes())
+    }
+}
+
+/// Decoder for the spritesheet section of PICO-8 text carts.
+///
+/// The text read must start with a `__gfx__` section; it ends at the next
+/// section or the end of the text. Each row must be 128 pixels, and is
+/// cropped to the given width. Every color decodes as opaque.
+pub struct Pico8GfxDecoder<'a, R: Read + 'a> {
+    r: &'a mut R
+}
+
+impl<'a, R: Read + 'a> Pico8GfxDecoder<'a, R> {
+    pub fn new(read: &'a mut R) -> Pico8GfxDecoder<'a, R> {
+        Pico8GfxDecoder {
+            r: read
+        }
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for Pico8GfxDecoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        if width == 0 || width > 128 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "PICO-8 spritesheets cannot be larger than 128x128."));
+        }
+
+        let text = read_cart_text(self.r, size)?;
+        let mut lines = text.lines();
+
+        if lines.next() != Some("__gfx__") {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "PICO-8 spritesheet does not start with a __gfx__ section."));
+        }
+
+        let mut pixels = Vec::new();
+
+        for line in lines.take_while(|line| !line.starts_with("__")) {
+            if line.len() != 128 || !line.is_ascii() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "PICO-8 spritesheet row is not 128 pixels wide."));
+            }
+
+            pixels.extend(fixed_colors_from_hex(&line[..width as usize], &PICO8_PALETTE)?);
+        }
+
+        Ok(ImageBuffer::from_fn(width, (pixels.len() / width as usize) as u32, |x, y| pixels[(y * width + x) as usize]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::DirectGraphicsEncoder;
+    use awsmimg::formats::pico8::Pico8GfxEncoder;
+
+    #[test]
+    fn gfx_encode() {
+        let img = image::ImageBuffer::from_fn(4, 2, |x, y| {
+            match (x, y) {
+                (0, 0) => image::Rgba([0xFFu8, 0x00, 0x4D, 255]),
+                (1, 0) => image::Rgba([0xF0u8, 0xF0, 0xF0, 255]),
+                (3, 1) => image::Rgba([0x29u8, 0xAD, 0xFF, 0]),
+                (_, 1) => image::Rgba([0x29u8, 0xAD, 0xFF, 255]),
+                _ => image::Rgba([0u8, 0, 0, 255])
+            }
+        });
+        let mut test_out = Cursor::new(Vec::new());
+
+        {
+            let mut p8 = Pico8GfxEncoder::new(&mut test_out);
+
+            p8.encode_colors(&img).unwrap();
+        }
+
+        let valid_out = format!("__gfx__\n87{}\nccc{}\n", "0".repeat(126), "0".repeat(125));
+
+        assert_eq!(test_out.get_ref(), valid_out.as_bytes())
+    }
+}
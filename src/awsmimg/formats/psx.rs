@@ -0,0 +1,664 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, read_color_words};
+use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
+use awsmimg::decoder::{IndexedGraphicsDecoder, DirectGraphicsDecoder};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{GenericImage, ImageBuffer, Primitive, Rgba, Pixel};
+
+/// Encode a series of RGBA colors as PSX VRAM color data.
+///
+/// Colors are written as little-endian 15-bit BGR words with the STP bit in
+/// the most significant bit. The GPU treats 0x0000 as transparent, so fully
+/// transparent colors are written as 0x0000 and opaque black is written with
+/// STP set to keep it visible.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 2] = [0, 0];
+
+    for rgba in palette {
+        let r : u16 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let g : u16 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let b : u16 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let a : u16 = (rgba[3].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+
+        let mut enc_color: u16 = (b & 0xF8) << 7 | (g & 0xF8) << 2 | r >> 3;
+
+        if a == 0 {
+            enc_color = 0;
+        } else if enc_color == 0 {
+            enc_color = 0x8000;
+        }
+
+        out[0] = (enc_color & 0xFF) as u8;
+        out[1] = ((enc_color >> 8) & 0xFF) as u8;
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode PSX VRAM color data written by encode_palette into RGBA colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. Colors
+/// are decoded as decode_color does.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    Ok(read_color_words(r, size, false)?.into_iter().map(decode_color).collect())
+}
+
+/// Write a TIM block header: block length, VRAM position and size in
+/// halfwords.
+fn write_block_header<W: Write>(w: &mut W, data_len: usize, pos: (u16, u16), size: (u16, u16)) -> io::Result<()> {
+    let len = (data_len + 12) as u32;
+    let mut out: Vec<u8> = Vec::with_capacity(12);
+
+    for byte in 0..4 {
+        out.push((len >> (byte * 8) & 0xFF) as u8);
+    }
+
+    for half in &[pos.0, pos.1, size.0, size.1] {
+        out.push((half & 0xFF) as u8);
+        out.push((half >> 8) as u8);
+    }
+
+    w.write_all(&out)
+}
+
+/// Encoder for PSX TIM image files.
+///
+/// A TIM file consists of a header, an optional CLUT block, and a pixel block.
+/// Both blocks carry the VRAM position they are intended to be uploaded to,
+/// which can be set with with_image_position and with_clut_position.
+///
+/// The 4bpp and 8bpp modes are written through IndexedGraphicsEncoder. Because
+/// the CLUT block precedes the pixel block, encode_palette does not write
+/// anything; the palette is instead held until encode_indexes writes the whole
+/// file. If no palette was provided, the TIM is written without a CLUT. The
+/// 16bpp mode is written through DirectGraphicsEncoder.
+///
+/// CLUT colors are written as PSX VRAM colors unless another color format is
+/// selected with with_palette_format. The CLUT block is padded to a whole
+/// number of VRAM halfwords.
+///
+/// Pixel data is not tiled. Image widths must fill whole VRAM halfwords, i.e.
+/// be a multiple of 4 pixels at 4bpp or 2 pixels at 8bpp.
+pub struct TIMEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    bpp: u8,
+    image_pos: (u16, u16),
+    clut_pos: (u16, u16),
+    clut: Option<(u16, Vec<u8>)>,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> TIMEncoder<'a, F> {
+    pub fn new_4bpp(file: &'a mut F) -> TIMEncoder<'a, F> {
+        TIMEncoder {
+            f: file,
+            bpp: 4,
+            image_pos: (0, 0),
+            clut_pos: (0, 0),
+            clut: None,
+            palette_format: PaletteColorFormat::PSX
+        }
+    }
+
+    pub fn new_8bpp(file: &'a mut F) -> TIMEncoder<'a, F> {
+        TIMEncoder {
+            bpp: 8,
+            ..TIMEncoder::new_4bpp(file)
+        }
+    }
+
+    pub fn new_16bpp(file: &'a mut F) -> TIMEncoder<'a, F> {
+        TIMEncoder {
+            bpp: 16,
+            ..TIMEncoder::new_4bpp(file)
+        }
+    }
+
+    pub fn with_image_position(self, x: u16, y: u16) -> TIMEncoder<'a, F> {
+        TIMEncoder {
+            image_pos: (x, y),
+            ..self
+        }
+    }
+
+    pub fn with_clut_position(self, x: u16, y: u16) -> TIMEncoder<'a, F> {
+        TIMEncoder {
+            clut_pos: (x, y),
+            ..self
+        }
+    }
+
+    fn pmode(&self) -> u32 {
+        match self.bpp {
+            4 => 0,
+            8 => 1,
+            _ => 2
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as PSX VRAM
+    /// colors.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> TIMEncoder<'a, F> {
+        TIMEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> TIMEncoder<'a, F> where F: Write {
+    /// Write the complete TIM file given already-encoded pixel data.
+    fn write_tim(&mut self, pixels: &[u8], width: u32, height: u32) -> io::Result<()> {
+        let px_per_half = 16 / self.bpp as u32;
+
+        if !width.is_multiple_of(px_per_half) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "TIM image width must fill whole VRAM halfwords."));
+        }
+
+        let flags = self.pmode() | if self.clut.is_some() { 0x08 } else { 0x00 };
+
+        self.f.write_all(&[0x10, 0x00, 0x00, 0x00])?;
+        self.f.write_all(&[flags as u8, 0x00, 0x00, 0x00])?;
+
+        if let Some((colors, ref clut)) = self.clut {
+            write_block_header(self.f, clut.len(), self.clut_pos, (colors, 1))?;
+            self.f.write_all(clut)?;
+        }
+
+        write_block_header(self.f, pixels.len(), self.image_pos, ((width / px_per_half) as u16, height as u16))?;
+        self.f.write_all(pixels)
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for TIMEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        match self.bpp {
+            4 => 15,
+            _ => 255
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for TIMEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, width: u32, height: u32) -> io::Result<()> {
+        let mut pixels: Vec<u8> = Vec::with_capacity(data.len());
+
+        match self.bpp {
+            4 => for byte in data.chunks(2) {
+                let lo = byte[0].to_u8().unwrap() & 0x0F;
+                let hi = byte.get(1).map_or(0, |i| i.to_u8().unwrap() & 0x0F);
+
+                pixels.push(hi << 4 | lo);
+            },
+            8 => for index in data {
+                pixels.push(index.to_u8().unwrap());
+            },
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "16bpp TIM files do not contain color indexes."))
+        }
+
+        self.write_tim(&pixels, width, height)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        let mut clut: Vec<u8> = Vec::with_capacity(palette.len() * 2);
+
+        self.palette_format.encode_colors(&mut clut, palette.into_iter())?;
+
+        if !clut.len().is_multiple_of(2) {
+            clut.push(0);
+        }
+
+        self.clut = Some(((clut.len() / 2) as u16, clut));
+
+        Ok(())
+    }
+}
+
+impl<'a, F: 'a> DirectGraphicsEncoder for TIMEncoder<'a, F> where F: Write {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        if self.bpp != 16 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Only 16bpp TIM files contain direct color data."));
+        }
+
+        let (width, height) = image.dimensions();
+        let mut pixels: Vec<u8> = Vec::with_capacity((width * height * 2) as usize);
+
+        encode_palette(&mut pixels, image.pixels().map(|(_, _, p)| p.to_rgba()))?;
+        self.clut = None;
+        self.write_tim(&pixels, width, height)
+    }
+}
+
+impl<'a, F: 'a> TIMEncoder<'a, F> where F: Read {
+    /// Read up to size bytes of a TIM file of this encoder's color depth,
+    /// returning its CLUT block, if any, and its pixel block.
+    fn read_tim(&mut self, size: usize) -> io::Result<(Option<TIMBlock>, TIMBlock)> {
+        let mut r = self.f.by_ref().take(size as u64);
+        let (bpp, has_clut) = read_header(&mut r)?;
+
+        if bpp != self.bpp as u32 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "TIM file is not of the color depth being decoded."));
+        }
+
+        let clut = match has_clut {
+            true => Some(read_block(&mut r)?),
+            false => None
+        };
+
+        Ok((clut, read_block(&mut r)?))
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for TIMEncoder<'a, F> where F: Read {
+    /// Decode the pixel block of a 4bpp or 8bpp TIM file.
+    ///
+    /// Indexes are returned row by row; the width of the image is not.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        if self.bpp == 16 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "16bpp TIM files do not contain color indexes."));
+        }
+
+        let (_, pixels) = self.read_tim(size)?;
+
+        Ok(match self.bpp {
+            4 => pixels.data.iter().flat_map(|byte| vec![byte & 0x0F, byte >> 4]).map(|index| P::from(index).unwrap()).collect(),
+            _ => pixels.data.iter().map(|index| P::from(*index).unwrap()).collect()
+        })
+    }
+
+    /// Decode every row of the CLUT block of a TIM file.
+    ///
+    /// Files without a CLUT decode as an empty palette.
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        match self.read_tim(size)? {
+            (Some(clut), _) => self.palette_format.decode_colors(&mut &clut.data[..], clut.data.len()),
+            (None, _) => Ok(Vec::new())
+        }
+    }
+}
+
+/// Decode a PSX VRAM color, as written by encode_palette.
+///
+/// 0x0000 is decoded as fully transparent and every other color as opaque,
+/// matching how the GPU draws them with the STP bit clear.
+fn decode_color(value: u16) -> Rgba<u8> {
+    let expand = |v: u16| ((v & 0x1F) << 3 | (v & 0x1F) >> 2) as u8;
+
+    match value {
+        0 => Rgba([0, 0, 0, 0]),
+        _ => Rgba([expand(value), expand(value >> 5), expand(value >> 10), 255])
+    }
+}
+
+/// Read a little-endian 16-bit value at the given byte offset.
+fn read_half(data: &[u8], offset: usize) -> u16 {
+    data[offset] as u16 | (data[offset + 1] as u16) << 8
+}
+
+/// Read the header of a TIM file, returning its bits per pixel and whether
+/// it has a CLUT block.
+fn read_header<R: Read>(r: &mut R) -> io::Result<(u32, bool)> {
+    let mut header = [0u8; 8];
+
+    r.read_exact(&mut header)?;
+
+    if header[0..4] != [0x10, 0x00, 0x00, 0x00] {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a TIM file."));
+    }
+
+    let bpp: u32 = match header[4] & 0x07 {
+        0 => 4,
+        1 => 8,
+        2 => 16,
+        3 => 24,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown TIM pixel mode."))
+    };
+
+    Ok((bpp, header[4] & 0x08 != 0))
+}
+
+/// A block of a TIM file: its VRAM position, its size in halfwords, and its
+/// data.
+struct TIMBlock {
+    pos: (u16, u16),
+    size: (u16, u16),
+    data: Vec<u8>
+}
+
+/// Read a TIM block, checking that its length matches its size and that it
+/// fits in the 1024x512 halfwords of VRAM.
+fn read_block<R: Read>(r: &mut R) -> io::Result<TIMBlock> {
+    let mut header = [0u8; 12];
+
+    r.read_exact(&mut header)?;
+
+    let len = header[0] as usize | (header[1] as usize) << 8 | (header[2] as usize) << 16 | (header[3] as usize) << 24;
+    let pos = (read_half(&header, 4), read_half(&header, 6));
+    let size = (read_half(&header, 8), read_half(&header, 10));
+    let data_len = size.0 as usize * size.1 as usize * 2;
+
+    if size.0 > 1024 || size.1 > 512 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "TIM block is larger than VRAM."));
+    }
+
+    if len != data_len + 12 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "TIM block length does not match its size."));
+    }
+
+    let mut data = Vec::new();
+
+    r.take(data_len as u64).read_to_end(&mut data)?;
+
+    if data.len() != data_len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "TIM block ends early."));
+    }
+
+    Ok(TIMBlock { pos, size, data })
+}
+
+/// Decoder for PSX TIM image files.
+///
+/// TIM files of any color depth are decoded into RGBA images. Indexed images
+/// are colored by one row of their CLUT, the first unless another is selected
+/// with with_clut_row. Indexed images without a CLUT are decoded with a
+/// grayscale ramp standing in for the palette, as their colors are not known.
+///
+/// The VRAM positions of the pixel and CLUT blocks of the most recently
+/// decoded file are available from image_position and clut_position.
+pub struct TIMDecoder<'a, R: Read + 'a> {
+    r: &'a mut R,
+    clut_row: u16,
+    image_pos: (u16, u16),
+    clut_pos: Option<(u16, u16)>
+}
+
+impl<'a, R: Read + 'a> TIMDecoder<'a, R> {
+    pub fn new(read: &'a mut R) -> TIMDecoder<'a, R> {
+        TIMDecoder {
+            r: read,
+            clut_row: 0,
+            image_pos: (0, 0),
+            clut_pos: None
+        }
+    }
+
+    pub fn with_clut_row(self, clut_row: u16) -> TIMDecoder<'a, R> {
+        TIMDecoder {
+            clut_row,
+            ..self
+        }
+    }
+
+    pub fn image_position(&self) -> (u16, u16) {
+        self.image_pos
+    }
+
+    pub fn clut_position(&self) -> Option<(u16, u16)> {
+        self.clut_pos
+    }
+
+    /// Decode a complete TIM file into an RGBA image.
+    pub fn decode(&mut self) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        let (bpp, has_clut) = read_header(self.r)?;
+
+        let clut: Option<Vec<Rgba<u8>>> = match has_clut {
+            false => {
+                self.clut_pos = None;
+                None
+            },
+            true => {
+                let TIMBlock { pos, size: (w, h), data } = read_block(self.r)?;
+
+                if self.clut_row >= h {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "TIM file does not have the selected CLUT row."));
+                }
+
+                let row = self.clut_row as usize * w as usize * 2;
+
+                self.clut_pos = Some(pos);
+                Some((0..w as usize).map(|i| decode_color(read_half(&data, row + i * 2))).collect())
+            }
+        };
+
+        let TIMBlock { pos, size: (w, h), data } = read_block(self.r)?;
+        let width = w as u32 * 16 / bpp;
+
+        self.image_pos = pos;
+
+        let indexed = |index: u8| match clut {
+            Some(ref colors) => colors.get(index as usize).cloned().unwrap_or(Rgba([0, 0, 0, 0])),
+            None => {
+                let gray = (index as u32 * 255 / ((1 << bpp) - 1)) as u8;
+                Rgba([gray, gray, gray, 255])
+            }
+        };
+
+        Ok(ImageBuffer::from_fn(width, h as u32, |x, y| {
+            let row = y as usize * w as usize * 2;
+
+            match bpp {
+                4 => indexed((data[row + x as usize / 2] >> ((x % 2) * 4)) & 0x0F),
+                8 => indexed(data[row + x as usize]),
+                16 => decode_color(read_half(&data, row + x as usize * 2)),
+                _ => {
+                    let i = row + x as usize * 3;
+                    Rgba([data[i], data[i + 1], data[i + 2], 255])
+                }
+            }
+        }))
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for TIMDecoder<'a, R> {
+    /// Decode a TIM file of up to size bytes, as decode does.
+    ///
+    /// The width of the image is given by the file, and must match the given
+    /// width.
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        let mut data = Vec::new();
+
+        self.r.by_ref().take(size as u64).read_to_end(&mut data)?;
+
+        let mut file = &data[..];
+        let mut decoder = TIMDecoder::new(&mut file).with_clut_row(self.clut_row);
+        let image = decoder.decode()?;
+
+        self.image_pos = decoder.image_pos;
+        self.clut_pos = decoder.clut_pos;
+
+        if image.width() != width {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "TIM file is not of the given width."));
+        }
+
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
+    use awsmimg::decoder::{IndexedGraphicsDecoder, DirectGraphicsDecoder};
+    use awsmimg::formats::psx::{TIMEncoder, TIMDecoder};
+
+    #[test]
+    fn tim4_encode() {
+        let src : Vec<u8> = vec![0, 1, 2, 3,
+                                 4, 5, 6, 7];
+        let palette = vec![image::Rgba([0u8, 0, 0, 0]),
+                           image::Rgba([0u8, 0, 0, 255]),
+                           image::Rgba([255u8, 0, 0, 255])];
+        let mut test_out = Cursor::new(Vec::new());
+
+        {
+            let mut tim = TIMEncoder::new_4bpp(&mut test_out).with_image_position(320, 0).with_clut_position(0, 480);
+
+            tim.encode_palette(palette).unwrap();
+            tim.encode_indexes(src, 4, 2).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x10, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00,
+                                       0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0xE0, 0x01, 0x03, 0x00, 0x01, 0x00,
+                                       0x00, 0x00, 0x00, 0x80, 0x1F, 0x00,
+                                       0x10, 0x00, 0x00, 0x00, 0x40, 0x01, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00,
+                                       0x10, 0x32, 0x54, 0x76];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn tim8_without_clut_encode() {
+        let src : Vec<u8> = vec![0, 1, 2, 3];
+        let mut test_out = Cursor::new(Vec::new());
+
+        {
+            let mut tim = TIMEncoder::new_8bpp(&mut test_out);
+
+            tim.encode_indexes(src, 2, 2).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x10, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+                                       0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00,
+                                       0x00, 0x01, 0x02, 0x03];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn tim4_bad_width() {
+        let mut test_out = Cursor::new(Vec::new());
+        let mut tim = TIMEncoder::new_4bpp(&mut test_out);
+
+        assert!(tim.encode_indexes(vec![0u8; 6], 3, 2).is_err());
+    }
+
+    #[test]
+    fn tim16_encode() {
+        let img = image::ImageBuffer::from_fn(2, 1, |x, _| {
+            match x {
+                0 => image::Rgba([255u8, 255, 255, 255]),
+                _ => image::Rgba([0u8, 0, 255, 255])
+            }
+        });
+        let mut test_out = Cursor::new(Vec::new());
+
+        {
+            let mut tim = TIMEncoder::new_16bpp(&mut test_out);
+
+            tim.encode_colors(&img).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x10, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+                                       0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01, 0x00,
+                                       0xFF, 0x7F, 0x00, 0x7C];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn tim4_decode() {
+        let src : Vec<u8> = vec![0x10, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00,
+                                 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0xE0, 0x01, 0x03, 0x00, 0x02, 0x00,
+                                 0x00, 0x00, 0x00, 0x80, 0x1F, 0x00,
+                                 0x00, 0x00, 0x00, 0x7C, 0xE0, 0x03,
+                                 0x10, 0x00, 0x00, 0x00, 0x40, 0x01, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00,
+                                 0x10, 0x32, 0x21, 0x00];
+        let mut test_in = Cursor::new(&src);
+        let mut tim = TIMDecoder::new(&mut test_in);
+        let img = tim.decode().unwrap();
+
+        assert_eq!(img.dimensions(), (4, 2));
+        assert_eq!(img.get_pixel(0, 0), &image::Rgba([0u8, 0, 0, 0]));
+        assert_eq!(img.get_pixel(1, 0), &image::Rgba([0u8, 0, 0, 255]));
+        assert_eq!(img.get_pixel(2, 0), &image::Rgba([255u8, 0, 0, 255]));
+        assert_eq!(img.get_pixel(3, 0), &image::Rgba([0u8, 0, 0, 0]));
+        assert_eq!(img.get_pixel(1, 1), &image::Rgba([255u8, 0, 0, 255]));
+        assert_eq!(tim.image_position(), (320, 0));
+        assert_eq!(tim.clut_position(), Some((0, 480)));
+
+        let second = TIMDecoder::new(&mut Cursor::new(&src)).with_clut_row(1).decode().unwrap();
+
+        assert_eq!(second.get_pixel(1, 0), &image::Rgba([0u8, 0, 255, 255]));
+        assert_eq!(second.get_pixel(1, 1), &image::Rgba([0u8, 255, 0, 255]));
+        assert!(TIMDecoder::new(&mut Cursor::new(&src)).with_clut_row(2).decode().is_err());
+    }
+
+    #[test]
+    fn tim_roundtrip() {
+        let img = image::ImageBuffer::from_fn(2, 1, |x, _| {
+            match x {
+                0 => image::Rgba([255u8, 255, 255, 255]),
+                _ => image::Rgba([0u8, 0, 255, 255])
+            }
+        });
+        let mut tim16 = Cursor::new(Vec::new());
+        let mut tim8 = Cursor::new(Vec::new());
+
+        TIMEncoder::new_16bpp(&mut tim16).encode_colors(&img).unwrap();
+        TIMEncoder::new_8bpp(&mut tim8).encode_indexes(vec![0u8, 1, 2, 255], 2, 2).unwrap();
+        tim16.set_position(0);
+        tim8.set_position(0);
+
+        assert_eq!(TIMDecoder::new(&mut tim16).decode().unwrap().into_raw(), img.into_raw());
+
+        let gray = TIMDecoder::new(&mut tim8).decode().unwrap();
+
+        assert_eq!(gray.get_pixel(1, 0), &image::Rgba([1u8, 1, 1, 255]));
+        assert_eq!(gray.get_pixel(1, 1), &image::Rgba([255u8, 255, 255, 255]));
+        assert!(TIMDecoder::new(&mut Cursor::new(vec![0x11u8, 0, 0, 0, 0, 0, 0, 0])).decode().is_err());
+    }
+
+    #[test]
+    fn tim_bad_block_size() {
+        //A 2048x512 block has a consistent length, but is larger than VRAM.
+        let oversized : Vec<u8> = vec![0x10, 0, 0, 0, 0x02, 0, 0, 0,
+                                       0x0C, 0x00, 0x20, 0x00, 0, 0, 0, 0, 0x00, 0x08, 0x00, 0x02];
+        let truncated : Vec<u8> = vec![0x10, 0, 0, 0, 0x02, 0, 0, 0,
+                                       0x14, 0x00, 0x00, 0x00, 0, 0, 0, 0, 0x04, 0x00, 0x01, 0x00, 0xFF, 0x7F];
+
+        assert!(TIMDecoder::new(&mut Cursor::new(oversized)).decode().is_err());
+        assert!(TIMDecoder::new(&mut Cursor::new(truncated)).decode().is_err());
+    }
+
+    #[test]
+    fn tim4_indexes_roundtrip() {
+        let src : Vec<u8> = vec![0, 1, 2, 3,
+                                 4, 5, 6, 7];
+        let palette = vec![image::Rgba([0u8, 0, 0, 0]),
+                           image::Rgba([0u8, 0, 0, 255]),
+                           image::Rgba([255u8, 0, 0, 255])];
+        let mut tim4 = Vec::new();
+
+        {
+            let mut tim = TIMEncoder::new_4bpp(&mut tim4);
+
+            tim.encode_palette(palette.clone()).unwrap();
+            tim.encode_indexes(src.clone(), 4, 2).unwrap();
+        }
+
+        let indexes : Vec<u8> = TIMEncoder::new_4bpp(&mut &tim4[..]).decode_indexes(tim4.len()).unwrap();
+
+        assert_eq!(indexes, src);
+        assert_eq!(TIMEncoder::new_4bpp(&mut &tim4[..]).decode_palette(tim4.len()).unwrap(), palette);
+        assert!(TIMEncoder::new_16bpp(&mut &tim4[..]).decode_indexes::<u8>(tim4.len()).is_err());
+
+        let direct = TIMDecoder::new(&mut &tim4[..]).decode_colors(4, tim4.len()).unwrap();
+
+        assert_eq!(direct.get_pixel(2, 0), &image::Rgba([255u8, 0, 0, 255]));
+        assert!(TIMDecoder::new(&mut &tim4[..]).decode_colors(8, tim4.len()).is_err());
+    }
+}
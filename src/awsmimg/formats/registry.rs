@@ -0,0 +1,557 @@
+/// Construct the codec of an IndexedFormat around a reader or writer.
+///
+/// Indexed formats are encoded and decoded by the same type, so the codec
+/// implements IndexedGraphicsEncoder or IndexedGraphicsDecoder depending on
+/// whether it was given a writer or a reader.
+macro_rules! with_indexed_codec {
+    ($format:expr, $io:expr, |$codec:ident| $body:expr) => {
+        match $format {
+            $crate::awsmimg::formats::IndexedFormat::AGB4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::agb::AGB4Encoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::AGB8Tiled => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::agb::AGB8Encoder::new_tiled($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::AGB8Chunky => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::agb::AGB8Encoder::new_chunky($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::GB2 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::dmg::GB2Encoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::CGB2 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::dmg::CGB2Encoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::SFC2 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::sfc::SFC2Encoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::SFC4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::sfc::SFC4Encoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::SFC8 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::sfc::SFC8Encoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::SFCMode7 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::sfc::SFCMode7Encoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::MD4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::md::MD4Encoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::SMS4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::sms::SMS4Encoder::new_sms($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::NesChr => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::nes::NesChrEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::PCEBG => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::pce::PCEBGEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::PCESprite => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::pce::PCESpriteEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::NeoGeoSprite => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::neogeo::NeoGeoSpriteEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::NeoGeoFix => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::neogeo::NeoGeoFixEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::NGP2 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ngp::NGP2Encoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::WS2 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ws::WSEncoder::new_planar2($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::WSC4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ws::WSEncoder::new_packed4($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::LynxSprite => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::lynx::LynxSpriteEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::N64CI4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::n64::N64CIEncoder::new_ci4($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::N64CI8 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::n64::N64CIEncoder::new_ci8($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::TIM4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::psx::TIMEncoder::new_4bpp($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::TIM8 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::psx::TIMEncoder::new_8bpp($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::Saturn4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::saturn::SaturnCellEncoder::new_4bpp($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::Saturn8 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::saturn::SaturnCellEncoder::new_8bpp($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::SaturnSprite => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::saturn::SaturnSpriteEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::NTRA3I5 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ntr::NTRTranslucentEncoder::new_a3i5($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::NTRA5I3 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ntr::NTRTranslucentEncoder::new_a5i3($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::AmigaSprite => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::amiga::AmigaSpriteEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::AmigaAttachedSprite => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::amiga::AmigaSpriteEncoder::new_attached($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::AtariSTLow => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::atarist::AtariSTEncoder::new_low($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::AtariSTMedium => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::atarist::AtariSTEncoder::new_medium($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::C64Charset => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::c64::C64CharsetEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::CGA => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::pc::CGAEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::EGA => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::pc::EGAEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::VGA13h => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::pc::VGA13hEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::ModeX => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::pc::ModeXEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::VERA2 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::x16::VERATileEncoder::new_2bpp($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::VERA4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::x16::VERATileEncoder::new_4bpp($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::VERA8 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::x16::VERATileEncoder::new_8bpp($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::AGBMode4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::agb::AGBMode4Encoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::X68KGraphic => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::x68k::X68KGraphicEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::IndexedFormat::X68KPCG => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::x68k::X68KPCGEncoder::new($io);
+                $body
+            }
+        }
+    }
+}
+
+/// Construct the DirectGraphicsEncoder of a DirectFormat around a writer.
+macro_rules! with_direct_encoder {
+    ($format:expr, $io:expr, |$codec:ident| $body:expr) => {
+        match $format {
+            $crate::awsmimg::formats::DirectFormat::AGB16 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::agb::AGB16Encoder::new_agb($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::NTR16 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::agb::AGB16Encoder::new_ntr($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::N64RGBA16 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::n64::N64DirectEncoder::new($io, $crate::awsmimg::formats::n64::N64TextureFormat::RGBA16);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::N64RGBA32 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::n64::N64DirectEncoder::new($io, $crate::awsmimg::formats::n64::N64TextureFormat::RGBA32);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::N64IA4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::n64::N64DirectEncoder::new($io, $crate::awsmimg::formats::n64::N64TextureFormat::IA4);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::N64IA8 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::n64::N64DirectEncoder::new($io, $crate::awsmimg::formats::n64::N64TextureFormat::IA8);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::N64I4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::n64::N64DirectEncoder::new($io, $crate::awsmimg::formats::n64::N64TextureFormat::I4);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::N64I8 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::n64::N64DirectEncoder::new($io, $crate::awsmimg::formats::n64::N64TextureFormat::I8);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::TIM16 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::psx::TIMEncoder::new_16bpp($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::SaturnRGB => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::saturn::SaturnRGBSpriteEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::NTRTex4x4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ntr::NTRTex4x4Encoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::NTRBitmapOBJ => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ntr::NTRBitmapOBJEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::CTRRGBA4444 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ctr::CTRTextureEncoder::new($io, $crate::awsmimg::formats::ctr::CTRTextureFormat::RGBA4444);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::CTRRGB565 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ctr::CTRTextureEncoder::new($io, $crate::awsmimg::formats::ctr::CTRTextureFormat::RGB565);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::CTRRGBA8 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ctr::CTRTextureEncoder::new($io, $crate::awsmimg::formats::ctr::CTRTextureFormat::RGBA8);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::DCARGB1555 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::dc::DCTextureEncoder::new($io, $crate::awsmimg::formats::dc::DCTextureFormat::ARGB1555);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::DCRGB565 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::dc::DCTextureEncoder::new($io, $crate::awsmimg::formats::dc::DCTextureFormat::RGB565);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::DCARGB4444 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::dc::DCTextureEncoder::new($io, $crate::awsmimg::formats::dc::DCTextureFormat::ARGB4444);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::DCVQARGB1555 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::dc::DCTextureEncoder::new($io, $crate::awsmimg::formats::dc::DCTextureFormat::ARGB1555).with_vq(true);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::DCVQRGB565 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::dc::DCTextureEncoder::new($io, $crate::awsmimg::formats::dc::DCTextureFormat::RGB565).with_vq(true);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::DCVQARGB4444 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::dc::DCTextureEncoder::new($io, $crate::awsmimg::formats::dc::DCTextureFormat::ARGB4444).with_vq(true);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::ZXScreen => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::zx::ZXScreenEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::C64Multicolor => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::c64::C64MulticolorEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::MSXScreen2 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::msx::MSXScreen2Encoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::Pico8Gfx => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::pico8::Pico8GfxEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::TIC80Sprites => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::tic80::TIC80SpriteEncoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::RGB888 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::generic::DirectColor32Encoder::new_rgb888($io, $crate::awsmimg::formats::generic::ByteOrder::BigEndian);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::RGBA8888 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::generic::DirectColor32Encoder::new_rgba8888($io, $crate::awsmimg::formats::generic::ByteOrder::BigEndian);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::AGBMode3 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::agb::AGBBitmapEncoder::new_mode3($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::AGBMode5 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::agb::AGBBitmapEncoder::new_mode5($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::SFCHDMAGradient => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::sfc::SFCHDMAGradientEncoder::new($io);
+                $body
+            }
+        }
+    }
+}
+
+/// Construct the DirectGraphicsDecoder of a DirectFormat around a reader.
+macro_rules! with_direct_decoder {
+    ($format:expr, $io:expr, |$codec:ident| $body:expr) => {
+        match $format {
+            $crate::awsmimg::formats::DirectFormat::AGB16 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::agb::AGB16Decoder::new_agb($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::NTR16 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::agb::AGB16Decoder::new_ntr($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::N64RGBA16 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::n64::N64DirectDecoder::new($io, $crate::awsmimg::formats::n64::N64TextureFormat::RGBA16);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::N64RGBA32 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::n64::N64DirectDecoder::new($io, $crate::awsmimg::formats::n64::N64TextureFormat::RGBA32);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::N64IA4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::n64::N64DirectDecoder::new($io, $crate::awsmimg::formats::n64::N64TextureFormat::IA4);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::N64IA8 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::n64::N64DirectDecoder::new($io, $crate::awsmimg::formats::n64::N64TextureFormat::IA8);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::N64I4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::n64::N64DirectDecoder::new($io, $crate::awsmimg::formats::n64::N64TextureFormat::I4);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::N64I8 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::n64::N64DirectDecoder::new($io, $crate::awsmimg::formats::n64::N64TextureFormat::I8);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::TIM16 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::psx::TIMDecoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::SaturnRGB => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::saturn::SaturnRGBSpriteDecoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::NTRTex4x4 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ntr::NTRTex4x4Decoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::NTRBitmapOBJ => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ntr::NTRBitmapOBJDecoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::CTRRGBA4444 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ctr::CTRTextureDecoder::new($io, $crate::awsmimg::formats::ctr::CTRTextureFormat::RGBA4444);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::CTRRGB565 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ctr::CTRTextureDecoder::new($io, $crate::awsmimg::formats::ctr::CTRTextureFormat::RGB565);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::CTRRGBA8 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::ctr::CTRTextureDecoder::new($io, $crate::awsmimg::formats::ctr::CTRTextureFormat::RGBA8);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::DCARGB1555 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::dc::DCTextureDecoder::new($io, $crate::awsmimg::formats::dc::DCTextureFormat::ARGB1555);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::DCRGB565 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::dc::DCTextureDecoder::new($io, $crate::awsmimg::formats::dc::DCTextureFormat::RGB565);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::DCARGB4444 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::dc::DCTextureDecoder::new($io, $crate::awsmimg::formats::dc::DCTextureFormat::ARGB4444);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::DCVQARGB1555 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::dc::DCTextureDecoder::new($io, $crate::awsmimg::formats::dc::DCTextureFormat::ARGB1555).with_vq(true);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::DCVQRGB565 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::dc::DCTextureDecoder::new($io, $crate::awsmimg::formats::dc::DCTextureFormat::RGB565).with_vq(true);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::DCVQARGB4444 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::dc::DCTextureDecoder::new($io, $crate::awsmimg::formats::dc::DCTextureFormat::ARGB4444).with_vq(true);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::ZXScreen => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::zx::ZXScreenDecoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::C64Multicolor => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::c64::C64MulticolorDecoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::MSXScreen2 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::msx::MSXScreen2Decoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::Pico8Gfx => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::pico8::Pico8GfxDecoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::TIC80Sprites => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::tic80::TIC80SpriteDecoder::new($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::RGB888 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::generic::DirectColor32Decoder::new_rgb888($io, $crate::awsmimg::formats::generic::ByteOrder::BigEndian);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::RGBA8888 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::generic::DirectColor32Decoder::new_rgba8888($io, $crate::awsmimg::formats::generic::ByteOrder::BigEndian);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::AGBMode3 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::agb::AGBBitmapDecoder::new_mode3($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::AGBMode5 => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::agb::AGBBitmapDecoder::new_mode5($io);
+                $body
+            },
+            $crate::awsmimg::formats::DirectFormat::SFCHDMAGradient => {
+                #[allow(unused_mut)]
+                let mut $codec = $crate::awsmimg::formats::sfc::SFCHDMAGradientDecoder::new($io);
+                $body
+            }
+        }
+    }
+}
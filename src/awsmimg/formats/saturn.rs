@@ -0,0 +1,432 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, read_color_words, expand_channel};
+use awsmimg::formats::md::{encode_packed4, decode_packed4};
+use awsmimg::formats::generic::image_from_rows;
+use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
+use awsmimg::decoder::{IndexedGraphicsDecoder, DirectGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{GenericImage, ImageBuffer, Primitive, Rgba, Pixel};
+
+/// Encode a series of RGBA colors as Saturn color RAM data.
+///
+/// Colors are written as big-endian 15-bit BGR words in the 0BBBBBGGGGGRRRRR
+/// layout used by color RAM modes 0 and 1. Alpha is ignored.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 2] = [0, 0];
+
+    for rgba in palette {
+        let r : u16 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let g : u16 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let b : u16 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+
+        let enc_color: u16 = (b & 0xF8) << 7 | (g & 0xF8) << 2 | r >> 3;
+
+        out[0] = ((enc_color >> 8) & 0xFF) as u8;
+        out[1] = (enc_color & 0xFF) as u8;
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode VDP2 color RAM data written by encode_palette into RGBA colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. Every
+/// color is opaque.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    Ok(read_color_words(r, size, true)?.into_iter().map(|v| {
+        Rgba([expand_channel(v, 5), expand_channel(v >> 5, 5), expand_channel(v >> 10, 5), 255])
+    }).collect())
+}
+
+/// Encode a series of RGBA colors as VDP1 RGB color words.
+///
+/// Colors are written as big-endian 15-bit BGR words in the 1BBBBBGGGGGRRRRR
+/// layout, with the MSB set to mark the word as RGB rather than a color bank
+/// code. Fully transparent colors are written as 0x0000, which VDP1 treats as
+/// transparent. This is the format of both RGB sprite pixels and 4bpp lookup
+/// tables.
+pub fn encode_vdp1_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 2] = [0, 0];
+
+    for rgba in palette {
+        let r : u16 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let g : u16 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let b : u16 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let a : u16 = (rgba[3].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+
+        let enc_color: u16 = match a {
+            0 => 0,
+            _ => 0x8000 | (b & 0xF8) << 7 | (g & 0xF8) << 2 | r >> 3
+        };
+
+        out[0] = ((enc_color >> 8) & 0xFF) as u8;
+        out[1] = (enc_color & 0xFF) as u8;
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode VDP1 RGB color words written by encode_vdp1_palette into RGBA
+/// colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. 0x0000
+/// is decoded as fully transparent and every other color as opaque.
+pub fn decode_vdp1_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    Ok(read_color_words(r, size, true)?.into_iter().map(|v| match v {
+        0 => Rgba([0, 0, 0, 0]),
+        _ => Rgba([expand_channel(v, 5), expand_channel(v >> 5, 5), expand_channel(v >> 10, 5), 255])
+    }).collect())
+}
+
+/// VDP1 sprites must be a multiple of 8 pixels wide.
+fn check_sprite_width(width: u32) -> io::Result<()> {
+    if !width.is_multiple_of(8) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "VDP1 sprite width must be a multiple of 8."));
+    }
+
+    Ok(())
+}
+
+/// Encoder for VDP2 character patterns (cells) for the Saturn platform.
+///
+/// Cells are 8x8 and stored row-major. Use new_4bpp for 16-color cells, packed
+/// two pixels to a byte with the leftmost pixel in the high nibble, or
+/// new_8bpp for 256-color cells with one pixel per byte.
+///
+/// Palettes are written as VDP2 color RAM words by default; use
+/// with_palette_format to select another color format.
+pub struct SaturnCellEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    bpp8: bool,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> SaturnCellEncoder<'a, F> {
+    pub fn new_4bpp(file: &'a mut F) -> SaturnCellEncoder<'a, F> {
+        SaturnCellEncoder {
+            f: file,
+            bpp8: false,
+            palette_format: PaletteColorFormat::Saturn
+        }
+    }
+
+    pub fn new_8bpp(file: &'a mut F) -> SaturnCellEncoder<'a, F> {
+        SaturnCellEncoder {
+            f: file,
+            bpp8: true,
+            palette_format: PaletteColorFormat::Saturn
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as VDP2 color RAM
+    /// words.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> SaturnCellEncoder<'a, F> {
+        SaturnCellEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for SaturnCellEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        match self.bpp8 {
+            true => 255,
+            false => 15
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for SaturnCellEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        if !self.bpp8 {
+            return encode_packed4(self.f, &data);
+        }
+
+        let mut out: [u8; 1] = [0];
+
+        for index in data {
+            out[0] = index.to_u8().unwrap();
+            self.f.write_all(&out)?;
+        }
+
+        Ok(())
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for SaturnCellEncoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        if !self.bpp8 {
+            return decode_packed4(self.f, size, 32);
+        }
+
+        let buf = read_units(self.f, size, 64, "8bpp cell data ends partway through a cell")?;
+
+        Ok(buf.into_iter().map(|index| P::from(index).unwrap()).collect())
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        match self.bpp8 {
+            true => Some(64),
+            false => Some(32)
+        }
+    }
+}
+
+/// Encoder for 4bpp lookup table sprites for the Saturn VDP1.
+///
+/// Sprites are stored row-major without tiling, packed two pixels to a byte
+/// with the leftmost pixel in the high nibble. Sprites must be a multiple of 8
+/// pixels wide. Palettes are written as a 16-entry lookup table of VDP1 RGB
+/// color words by default; use with_palette_format to select another color
+/// format.
+pub struct SaturnSpriteEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> SaturnSpriteEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> SaturnSpriteEncoder<'a, F> {
+        SaturnSpriteEncoder {
+            f: file,
+            palette_format: PaletteColorFormat::SaturnVDP1
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as VDP1 RGB color
+    /// words.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> SaturnSpriteEncoder<'a, F> {
+        SaturnSpriteEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for SaturnSpriteEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        15
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for SaturnSpriteEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, width: u32, _height: u32) -> io::Result<()> {
+        check_sprite_width(width)?;
+        encode_packed4(self.f, &data)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for SaturnSpriteEncoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        decode_packed4(self.f, size, 4)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+}
+
+/// Encoder for 15-bit RGB sprites for the Saturn VDP1.
+///
+/// Sprites are stored row-major without tiling as VDP1 RGB color words.
+/// Sprites must be a multiple of 8 pixels wide.
+pub struct SaturnRGBSpriteEncoder<'a, W: Write + 'a> {
+    w: &'a mut W
+}
+
+impl<'a, W: Write + 'a> SaturnRGBSpriteEncoder<'a, W> {
+    pub fn new(write: &'a mut W) -> SaturnRGBSpriteEncoder<'a, W> {
+        SaturnRGBSpriteEncoder {
+            w: write
+        }
+    }
+}
+
+impl<'a, W: Write> DirectGraphicsEncoder for SaturnRGBSpriteEncoder<'a, W> {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        check_sprite_width(image.width())?;
+
+        encode_vdp1_palette(self.w, image.pixels().map(|(_, _, p)| p.to_rgba()))
+    }
+}
+
+/// Decoder for 15-bit RGB sprites for the Saturn VDP1.
+pub struct SaturnRGBSpriteDecoder<'a, R: Read + 'a> {
+    r: &'a mut R
+}
+
+impl<'a, R: Read + 'a> SaturnRGBSpriteDecoder<'a, R> {
+    pub fn new(read: &'a mut R) -> SaturnRGBSpriteDecoder<'a, R> {
+        SaturnRGBSpriteDecoder {
+            r: read
+        }
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for SaturnRGBSpriteDecoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        check_sprite_width(width)?;
+
+        image_from_rows(decode_vdp1_palette(self.r, size)?, width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate num;
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
+    use awsmimg::formats::saturn::{SaturnCellEncoder, SaturnSpriteEncoder, SaturnRGBSpriteEncoder};
+
+    #[test]
+    fn cell4_encode() {
+        let src = num::range(0, 64).collect();
+        let mut test_out = Cursor::new(Vec::with_capacity(32));
+
+        {
+            let mut sat4 = SaturnCellEncoder::new_4bpp(&mut test_out);
+
+            sat4.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let valid_out : Vec<u8> = [0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF].repeat(4);
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn cell8_encode() {
+        let src = num::range(0, 64).collect();
+        let mut test_out = Cursor::new(Vec::with_capacity(64));
+
+        {
+            let mut sat8 = SaturnCellEncoder::new_8bpp(&mut test_out);
+
+            sat8.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let valid_out : Vec<u8> = num::range(0, 64).collect();
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn palette_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]),
+                           image::Rgba([0u8, 255, 0, 255]),
+                           image::Rgba([0u8, 0, 255, 255]),
+                           image::Rgba([0x08u8, 0x10, 0x18, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(8));
+
+        {
+            let mut sat4 = SaturnCellEncoder::new_4bpp(&mut test_out);
+
+            sat4.encode_palette(palette).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x00, 0x1F, 0x03, 0xE0, 0x7C, 0x00, 0x0C, 0x41];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn sprite4_encode() {
+        let src = num::range(0, 32).collect();
+        let mut test_out = Cursor::new(Vec::with_capacity(16));
+
+        {
+            let mut spr = SaturnSpriteEncoder::new(&mut test_out);
+
+            spr.encode_indexes(src, 16, 2).unwrap();
+        }
+
+        let valid_out : Vec<u8> = [0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF].repeat(2);
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn sprite4_bad_width() {
+        let mut test_out = Cursor::new(Vec::new());
+        let mut spr = SaturnSpriteEncoder::new(&mut test_out);
+
+        assert!(spr.encode_indexes(vec![0u8; 12], 12, 1).is_err());
+    }
+
+    #[test]
+    fn sprite_lut_encode() {
+        let palette = vec![image::Rgba([0u8, 0, 0, 0]),
+                           image::Rgba([0u8, 0, 0, 255]),
+                           image::Rgba([255u8, 0, 0, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(6));
+
+        {
+            let mut spr = SaturnSpriteEncoder::new(&mut test_out);
+
+            spr.encode_palette(palette).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x00, 0x00, 0x80, 0x00, 0x80, 0x1F];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn sprite_rgb_encode() {
+        let img = image::ImageBuffer::from_fn(8, 1, |x, _| {
+            match x {
+                0 => image::Rgba([0u8, 255, 0, 255]),
+                1 => image::Rgba([0u8, 0, 255, 255]),
+                _ => image::Rgba([0u8, 0, 0, 0])
+            }
+        });
+        let mut test_out = Cursor::new(Vec::with_capacity(16));
+
+        {
+            let mut spr = SaturnRGBSpriteEncoder::new(&mut test_out);
+
+            spr.encode_colors(&img).unwrap();
+        }
+
+        let mut valid_out : Vec<u8> = vec![0x83, 0xE0, 0xFC, 0x00];
+        valid_out.resize(16, 0);
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+}
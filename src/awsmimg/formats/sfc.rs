@@ -0,0 +1,678 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::agb::{encode_palette, decode_palette};
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat};
+use awsmimg::formats::generic::image_from_rows;
+use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
+use awsmimg::decoder::{IndexedGraphicsDecoder, DirectGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use std::cmp::max;
+use image::{GenericImage, ImageBuffer, Primitive, Pixel, Rgba};
+
+/// Encode index data as SFC planar tiles of the given bit depth.
+///
+/// SFC tiles store bitplanes in pairs. Each pair is eight rows of two bytes,
+/// the first byte holding the lower plane of the pair and the second holding
+/// the upper plane. All eight rows of one pair are written before the next
+/// pair begins. The leftmost pixel of a row is stored in the most significant
+/// bit of each byte.
+///
+/// Other platforms which use the same planar layout, such as the PCE, share
+/// this function.
+pub fn encode_planar<'a, P: Primitive, W: Write + 'a>(w: &'a mut W, data: &[P], bpp: u8) -> io::Result<()> {
+    let mut out = Vec::with_capacity(bpp as usize * 8);
+
+    for tile in data.chunks(64) {
+        out.clear();
+
+        for pair in 0..bpp / 2 {
+            for row in 0..8 {
+                let mut lo = 0u8;
+                let mut hi = 0u8;
+
+                for x in 0..8 {
+                    let index = tile.get(row * 8 + x).map_or(0, |i| i.to_u8().unwrap());
+
+                    lo |= ((index >> (pair * 2)) & 0x01) << (7 - x);
+                    hi |= ((index >> (pair * 2 + 1)) & 0x01) << (7 - x);
+                }
+
+                out.push(lo);
+                out.push(hi);
+            }
+        }
+
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode SFC planar tiles of the given bit depth into index data.
+///
+/// This is the inverse of encode_planar. Up to size bytes are read, and must
+/// hold a whole number of tiles.
+pub fn decode_planar<R: Read, P: Primitive>(r: &mut R, size: usize, bpp: u8) -> io::Result<Vec<P>> {
+    let mut buf = Vec::new();
+    let tbytes = bpp as usize * 8;
+
+    r.take(size as u64).read_to_end(&mut buf)?;
+
+    if !buf.len().is_multiple_of(tbytes) {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Planar data ends partway through a tile"));
+    }
+
+    let mut out = Vec::with_capacity(buf.len() * 8 / bpp as usize);
+
+    for tile in buf.chunks(tbytes) {
+        for row in 0..8 {
+            for x in 0..8 {
+                let index = (0..bpp as usize).fold(0u8, |index, plane| {
+                    let byte = tile[(plane / 2) * 16 + row * 2 + plane % 2];
+
+                    index | ((byte >> (7 - x)) & 0x01) << plane
+                });
+
+                out.push(P::from(index).unwrap());
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Encoder/decoder for 2bpp planar tile patterns for the SFC platform.
+///
+/// This is the tile format of Mode 0 backgrounds and is identical to the DMG
+/// tile format.
+pub struct SFC2Encoder<'a, F: 'a> {
+    f: &'a mut F,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> SFC2Encoder<'a, F> {
+    pub fn new(file: &'a mut F) -> SFC2Encoder<'a, F> {
+        SFC2Encoder {
+            f: file,
+            palette_format: PaletteColorFormat::BGR555
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as BGR555.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> SFC2Encoder<'a, F> {
+        SFC2Encoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for SFC2Encoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        3
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for SFC2Encoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        encode_planar(self.f, &data, 2)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for SFC2Encoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        decode_planar(self.f, size, 2)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(16)
+    }
+}
+
+/// Encoder/decoder for 4bpp planar tile patterns for the SFC platform.
+///
+/// This is the tile format of sprites and of most backgrounds in Modes 1
+/// through 3. The four bitplanes are stored as two interleaved plane pairs.
+pub struct SFC4Encoder<'a, F: 'a> {
+    f: &'a mut F,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> SFC4Encoder<'a, F> {
+    pub fn new(file: &'a mut F) -> SFC4Encoder<'a, F> {
+        SFC4Encoder {
+            f: file,
+            palette_format: PaletteColorFormat::BGR555
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as BGR555.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> SFC4Encoder<'a, F> {
+        SFC4Encoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for SFC4Encoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        15
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for SFC4Encoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        encode_planar(self.f, &data, 4)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for SFC4Encoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        decode_planar(self.f, size, 4)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(32)
+    }
+}
+
+/// Encoder/decoder for 8bpp planar tile patterns for the SFC platform.
+///
+/// This is the tile format of Mode 3 and Mode 4 256-color backgrounds. All
+/// eight bitplanes are stored as four interleaved plane pairs.
+pub struct SFC8Encoder<'a, F: 'a> {
+    f: &'a mut F,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> SFC8Encoder<'a, F> {
+    pub fn new(file: &'a mut F) -> SFC8Encoder<'a, F> {
+        SFC8Encoder {
+            f: file,
+            palette_format: PaletteColorFormat::BGR555
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as BGR555.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> SFC8Encoder<'a, F> {
+        SFC8Encoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for SFC8Encoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        255
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for SFC8Encoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        encode_planar(self.f, &data, 8)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for SFC8Encoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        decode_planar(self.f, size, 8)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(64)
+    }
+}
+
+/// Encoder for Mode 7 character data for the SFC platform.
+///
+/// Mode 7 characters are 8bpp chunky 8x8 tiles. In VRAM, character data
+/// occupies the odd (high) bytes of each word, while the even (low) bytes hold
+/// the Mode 7 tilemap. When constructed with new, only the character bytes are
+/// written, suitable for a VRAM upload that only targets the high bytes. When
+/// constructed with new_interleaved, each character byte is preceded by the
+/// corresponding byte of the given tilemap, producing a stream that can be
+/// uploaded to VRAM with word writes. If the map and character data differ in
+/// length, the shorter of the two is padded with zeroes.
+pub struct SFCMode7Encoder<'a, F: 'a> {
+    f: &'a mut F,
+    map: Option<Vec<u8>>,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> SFCMode7Encoder<'a, F> {
+    pub fn new(file: &'a mut F) -> SFCMode7Encoder<'a, F> {
+        SFCMode7Encoder {
+            f: file,
+            map: None,
+            palette_format: PaletteColorFormat::BGR555
+        }
+    }
+
+    pub fn new_interleaved(file: &'a mut F, map: Vec<u8>) -> SFCMode7Encoder<'a, F> {
+        SFCMode7Encoder {
+            f: file,
+            map: Some(map),
+            palette_format: PaletteColorFormat::BGR555
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as BGR555.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> SFCMode7Encoder<'a, F> {
+        SFCMode7Encoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for SFCMode7Encoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        255
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for SFCMode7Encoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        let chars: Vec<u8> = data.iter().map(|i| i.to_u8().unwrap()).collect();
+
+        match self.map {
+            None => self.f.write_all(&chars),
+            Some(ref map) => {
+                let len = max(map.len(), chars.len());
+                let mut out = Vec::with_capacity(len * 2);
+
+                for i in 0..len {
+                    out.push(*map.get(i).unwrap_or(&0));
+                    out.push(*chars.get(i).unwrap_or(&0));
+                }
+
+                self.f.write_all(&out)
+            }
+        }
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for SFCMode7Encoder<'a, F> where F: Read {
+    /// Decode character data.
+    /// 
+    /// Decoders constructed with new_interleaved read whole VRAM words and
+    /// skip the tilemap bytes; their map is not used.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let buf = match self.map {
+            None => read_units(self.f, size, 64, "Mode 7 data ends partway through a tile")?,
+            Some(_) => read_units(self.f, size, 128, "Mode 7 data ends partway through a tile")?.into_iter().skip(1).step_by(2).collect()
+        };
+
+        Ok(buf.into_iter().map(|byte| P::from(byte).unwrap()).collect())
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        match self.map {
+            None => Some(64),
+            Some(_) => Some(128)
+        }
+    }
+}
+
+/// Encoder for HDMA gradient tables for the SFC platform.
+///
+/// The image must be one pixel wide, with one row per scanline. It is written
+/// as an HDMA table for transfer mode 3 targeting CGADD ($2121), which writes
+/// the CGRAM address twice and then both bytes of a color to CGDATA ($2122)
+/// on every line of an entry. Each entry is a line count followed by the color
+/// index, written twice, and the 15-bit BGR color. Runs of identical rows are
+/// merged into one entry of up to 127 lines. The table ends with a zero byte.
+///
+/// The gradient changes the backdrop color, color index 0, unless another
+/// index is selected with with_color_index.
+pub struct SFCHDMAGradientEncoder<'a, W: Write + 'a> {
+    w: &'a mut W,
+    color_index: u8
+}
+
+impl<'a, W: Write + 'a> SFCHDMAGradientEncoder<'a, W> {
+    pub fn new(write: &'a mut W) -> SFCHDMAGradientEncoder<'a, W> {
+        SFCHDMAGradientEncoder {
+            w: write,
+            color_index: 0
+        }
+    }
+
+    pub fn with_color_index(self, color_index: u8) -> SFCHDMAGradientEncoder<'a, W> {
+        SFCHDMAGradientEncoder {
+            color_index,
+            ..self
+        }
+    }
+}
+
+impl<'a, W: Write> DirectGraphicsEncoder for SFCHDMAGradientEncoder<'a, W> {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        let (width, height) = image.dimensions();
+
+        if width != 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "HDMA gradient images must be one pixel wide."));
+        }
+
+        let mut colors: Vec<u8> = Vec::with_capacity(height as usize * 2);
+        encode_palette(&mut colors, (0..height).map(|y| image.get_pixel(0, y).to_rgba()), false)?;
+
+        let mut out: Vec<u8> = Vec::new();
+        let mut lines = colors.chunks(2).peekable();
+
+        while let Some(color) = lines.next() {
+            let mut count = 1;
+
+            while count < 127 && lines.peek() == Some(&color) {
+                lines.next();
+                count += 1;
+            }
+
+            out.extend_from_slice(&[count, self.color_index, self.color_index, color[0], color[1]]);
+        }
+
+        out.push(0);
+        self.w.write_all(&out)
+    }
+}
+
+/// Decoder for HDMA gradient tables for the SFC platform.
+///
+/// This reads a table written by SFCHDMAGradientEncoder, up to its zero
+/// terminator, as a one pixel wide image. Every entry must write the same
+/// color index; repeat mode entries are not supported.
+pub struct SFCHDMAGradientDecoder<'a, R: Read + 'a> {
+    r: &'a mut R
+}
+
+impl<'a, R: Read + 'a> SFCHDMAGradientDecoder<'a, R> {
+    pub fn new(read: &'a mut R) -> SFCHDMAGradientDecoder<'a, R> {
+        SFCHDMAGradientDecoder {
+            r: read
+        }
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for SFCHDMAGradientDecoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        if width != 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "HDMA gradient images must be one pixel wide."));
+        }
+
+        let mut table = self.r.take(size as u64);
+        let mut count = [0u8; 1];
+        let mut entry = [0u8; 4];
+        let mut color_index: Option<u8> = None;
+        let mut lines: Vec<u8> = Vec::new();
+
+        loop {
+            table.read_exact(&mut count)?;
+
+            match count[0] {
+                0 => break,
+                n if n & 0x80 != 0 => return Err(io::Error::new(io::ErrorKind::InvalidData, "HDMA repeat mode entries are not supported.")),
+                n => {
+                    table.read_exact(&mut entry)?;
+
+                    if entry[0] != entry[1] || color_index.is_some_and(|index| index != entry[0]) {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "HDMA gradient entries write different color indexes."));
+                    }
+
+                    color_index = Some(entry[0]);
+
+                    for _ in 0..n {
+                        lines.extend_from_slice(&entry[2..4]);
+                    }
+                }
+            }
+        }
+
+        image_from_rows(decode_palette(&mut &lines[..], lines.len(), false)?, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate num;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::{IndexedGraphicsEncoder, DirectGraphicsEncoder};
+    use awsmimg::decoder::IndexedGraphicsDecoder;
+    use awsmimg::formats::palette::PaletteColorFormat;
+    use awsmimg::formats::sfc::{SFC2Encoder, SFC4Encoder, SFC8Encoder, SFCMode7Encoder, SFCHDMAGradientEncoder};
+
+    #[test]
+    fn data2_encode() {
+        let src : Vec<u8> = vec![0,1,2,3,0,1,2,3,
+                                 3,3,3,3,0,0,0,0,
+                                 1,1,1,1,1,1,1,1,
+                                 2,2,2,2,2,2,2,2,
+                                 0,0,0,0,0,0,0,0,
+                                 3,2,1,0,3,2,1,0,
+                                 0,0,0,0,0,0,0,3,
+                                 3,0,0,0,0,0,0,0];
+        let mut test_out = Cursor::new(Vec::with_capacity(16));
+
+        {
+            let mut sfc2 = SFC2Encoder::new(&mut test_out);
+
+            sfc2.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x55, 0x33, 0xF0, 0xF0, 0xFF, 0x00, 0x00, 0xFF,
+                                       0x00, 0x00, 0xAA, 0xCC, 0x01, 0x01, 0x80, 0x80];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn data2_decode() {
+        let src : Vec<u8> = vec![0x55, 0x33, 0xF0, 0xF0, 0xFF, 0x00, 0x00, 0xFF,
+                                 0x00, 0x00, 0xAA, 0xCC, 0x01, 0x01, 0x80, 0x80];
+        let mut test_in = Cursor::new(&src);
+
+        let test_out : Vec<u8> = SFC2Encoder::new(&mut test_in).decode_indexes(usize::MAX).unwrap();
+        let valid_out : Vec<u8> = vec![0,1,2,3,0,1,2,3,
+                                       3,3,3,3,0,0,0,0,
+                                       1,1,1,1,1,1,1,1,
+                                       2,2,2,2,2,2,2,2,
+                                       0,0,0,0,0,0,0,0,
+                                       3,2,1,0,3,2,1,0,
+                                       0,0,0,0,0,0,0,3,
+                                       3,0,0,0,0,0,0,0];
+
+        assert_eq!(test_out, valid_out);
+        assert!(SFC2Encoder::new(&mut Cursor::new(&src)).decode_indexes::<u8>(15).is_err());
+    }
+
+    #[test]
+    fn data4_roundtrip() {
+        let src : Vec<u8> = (0..128).map(|i| (i * 7 % 16) as u8).collect();
+        let mut test_out = Cursor::new(Vec::with_capacity(64));
+
+        SFC4Encoder::new(&mut test_out).encode_indexes(src.clone(), 16, 8).unwrap();
+
+        assert_eq!(test_out.get_ref().len(), 64);
+        assert_eq!(test_out.get_ref()[0..2], [0x55, 0x66]);
+
+        test_out.set_position(0);
+        let decoded : Vec<u8> = SFC4Encoder::new(&mut test_out).decode_indexes(usize::MAX).unwrap();
+
+        assert_eq!(decoded, src);
+    }
+
+    #[test]
+    fn data8_encode() {
+        let mut src : Vec<u8> = vec![0; 64];
+        src[0] = 0xFF;
+        src[9] = 0x05;
+        src[63] = 0x80;
+        let mut test_out = Cursor::new(Vec::with_capacity(64));
+
+        {
+            let mut sfc8 = SFC8Encoder::new(&mut test_out);
+
+            sfc8.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let mut valid_out : Vec<u8> = vec![0; 64];
+        for pair in 0..4 {
+            valid_out[pair * 16] = 0x80;
+            valid_out[pair * 16 + 1] = 0x80;
+        }
+        valid_out[2] = 0x40;
+        valid_out[18] = 0x40;
+        valid_out[63] = 0x01;
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn data8_roundtrip() {
+        let src : Vec<u8> = num::range(0, 128).map(|i: u32| (i * 37 % 256) as u8).collect();
+        let mut test_out = Cursor::new(Vec::with_capacity(128));
+
+        SFC8Encoder::new(&mut test_out).encode_indexes(src.clone(), 16, 8).unwrap();
+        test_out.set_position(0);
+        let decoded : Vec<u8> = SFC8Encoder::new(&mut test_out).decode_indexes(usize::MAX).unwrap();
+
+        assert_eq!(decoded, src);
+    }
+
+    #[test]
+    fn palette_format_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255])];
+        let mut bgr_out = Vec::new();
+        let mut vga_out = Vec::new();
+
+        SFC4Encoder::new(&mut bgr_out).encode_palette(palette.clone()).unwrap();
+        SFC4Encoder::new(&mut vga_out).with_palette_format(PaletteColorFormat::VGADAC).encode_palette(palette).unwrap();
+
+        assert_eq!(bgr_out, vec![0x1F, 0x00]);
+        assert_eq!(vga_out, vec![63, 0, 0]);
+    }
+
+    #[test]
+    fn mode7_encode() {
+        let src : Vec<u8> = num::range(0, 64).collect();
+        let mut test_out = Cursor::new(Vec::with_capacity(64));
+
+        {
+            let mut mode7 = SFCMode7Encoder::new(&mut test_out);
+
+            mode7.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let valid_out : Vec<u8> = num::range(0, 64).collect();
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn mode7_interleaved_encode() {
+        let src : Vec<u8> = vec![0x10, 0x11, 0x12, 0x13];
+        let map : Vec<u8> = vec![0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5];
+        let mut test_out = Cursor::new(Vec::with_capacity(12));
+
+        {
+            let mut mode7 = SFCMode7Encoder::new_interleaved(&mut test_out, map);
+
+            mode7.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0xA0, 0x10, 0xA1, 0x11, 0xA2, 0x12, 0xA3, 0x13, 0xA4, 0x00, 0xA5, 0x00];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn hdma_gradient_encode() {
+        let img = image::ImageBuffer::from_fn(1, 131, |_, y| {
+            match y {
+                0 | 1 => image::Rgba([255u8, 0, 0, 255]),
+                _ => image::Rgba([0u8, 0, 255, 255])
+            }
+        });
+        let mut test_out = Cursor::new(Vec::new());
+
+        {
+            let mut hdma = SFCHDMAGradientEncoder::new(&mut test_out).with_color_index(0x11);
+
+            hdma.encode_colors(&img).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![2, 0x11, 0x11, 0x1F, 0x00,
+                                       127, 0x11, 0x11, 0x00, 0x7C,
+                                       2, 0x11, 0x11, 0x00, 0x7C,
+                                       0];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+}
@@ -0,0 +1,252 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, read_color_words, expand_channel};
+use awsmimg::formats::generic::{PlanarEncoder, PlaneInterleave};
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::IndexedGraphicsDecoder;
+
+use std::io;
+use std::io::{Write, Read};
+use image::{Primitive, Rgba};
+
+/// Encode a series of RGBA colors as SMS CRAM palette data.
+///
+/// Colors are written as one byte each in the 00BBGGRR layout. Alpha is
+/// ignored.
+pub fn encode_sms_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 1] = [0];
+
+    for rgba in palette {
+        let r : u8 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u8;
+        let g : u8 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u8;
+        let b : u8 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u8;
+
+        out[0] = (b & 0xC0) >> 2 | (g & 0xC0) >> 4 | r >> 6;
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode SMS CRAM data written by encode_sms_palette into RGBA colors.
+///
+/// Up to size bytes are read, one color per byte. Every color is opaque.
+pub fn decode_sms_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    let mut buf = Vec::new();
+
+    r.take(size as u64).read_to_end(&mut buf)?;
+
+    Ok(buf.iter().map(|v| {
+        let v = *v as u16;
+
+        Rgba([expand_channel(v, 2), expand_channel(v >> 2, 2), expand_channel(v >> 4, 2), 255])
+    }).collect())
+}
+
+/// Encode a series of RGBA colors as GG CRAM palette data.
+///
+/// Colors are written as little-endian 12-bit words in the 0000BBBBGGGGRRRR
+/// layout. Alpha is ignored.
+pub fn encode_gg_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 2] = [0, 0];
+
+    for rgba in palette {
+        let r : u16 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let g : u16 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let b : u16 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+
+        let enc_color: u16 = (b & 0xF0) << 4 | (g & 0xF0) | r >> 4;
+
+        out[0] = (enc_color & 0xFF) as u8;
+        out[1] = ((enc_color >> 8) & 0xFF) as u8;
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode GG CRAM data written by encode_gg_palette into RGBA colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. Every
+/// color is opaque.
+pub fn decode_gg_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    Ok(read_color_words(r, size, false)?.into_iter().map(|v| {
+        Rgba([expand_channel(v, 4), expand_channel(v >> 4, 4), expand_channel(v >> 8, 4), 255])
+    }).collect())
+}
+
+/// Encoder for 4bpp planar tile patterns for the SMS and GG platforms.
+///
+/// Each row of an 8x8 tile is stored as four bytes, one per bitplane, starting
+/// with the lowest plane. The leftmost pixel of a row is stored in the most
+/// significant bit of each byte.
+///
+/// Tile data is identical between both platforms; only the palette format
+/// differs. Use new_sms or new_gg to select the palette format, or
+/// with_palette_format to select any other.
+pub struct SMS4Encoder<'a, F: 'a> {
+    f: &'a mut F,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> SMS4Encoder<'a, F> {
+    pub fn new_sms(file: &'a mut F) -> SMS4Encoder<'a, F> {
+        SMS4Encoder {
+            f: file,
+            palette_format: PaletteColorFormat::SMS
+        }
+    }
+
+    pub fn new_gg(file: &'a mut F) -> SMS4Encoder<'a, F> {
+        SMS4Encoder {
+            f: file,
+            palette_format: PaletteColorFormat::GameGear
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as SMS or GG
+    /// CRAM data.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> SMS4Encoder<'a, F> {
+        SMS4Encoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for SMS4Encoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        15
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for SMS4Encoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        for row in data.chunks(8) {
+            let mut out: [u8; 4] = [0; 4];
+
+            for (x, index) in row.iter().enumerate() {
+                let index = index.to_u8().unwrap();
+
+                for (plane, byte) in out.iter_mut().enumerate() {
+                    *byte |= ((index >> plane) & 0x01) << (7 - x);
+                }
+            }
+
+            self.f.write_all(&out)?;
+        }
+
+        Ok(())
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for SMS4Encoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        PlanarEncoder::new(self.f, 4, PlaneInterleave::Row)?.decode_indexes(size)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::formats::sms::SMS4Encoder;
+    use awsmimg::formats::palette::PaletteColorFormat;
+
+    #[test]
+    fn data4_encode() {
+        let src : Vec<u8> = vec![0,1,2,3,4,5,6,7,
+                                 8,9,10,11,12,13,14,15,
+                                 15,15,15,15,15,15,15,15,
+                                 0,0,0,0,0,0,0,0,
+                                 1,0,0,0,0,0,0,0,
+                                 0,2,0,0,0,0,0,0,
+                                 0,0,4,0,0,0,0,0,
+                                 0,0,0,8,0,0,0,0];
+        let mut test_out = Cursor::new(Vec::with_capacity(32));
+
+        {
+            let mut sms4 = SMS4Encoder::new_sms(&mut test_out);
+
+            sms4.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x55, 0x33, 0x0F, 0x00, 0x55, 0x33, 0x0F, 0xFF,
+                                       0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00,
+                                       0x80, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00,
+                                       0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x10];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn sms_palette_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]),
+                           image::Rgba([0u8, 255, 0, 255]),
+                           image::Rgba([0u8, 0, 255, 255]),
+                           image::Rgba([0x55u8, 0xAA, 0xFF, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(4));
+
+        {
+            let mut sms4 = SMS4Encoder::new_sms(&mut test_out);
+
+            sms4.encode_palette(palette).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x03u8, 0x0C, 0x30, 0x39])
+    }
+
+    #[test]
+    fn gg_palette_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]),
+                           image::Rgba([0u8, 255, 0, 255]),
+                           image::Rgba([0u8, 0, 255, 255]),
+                           image::Rgba([0x11u8, 0x22, 0x33, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(8));
+
+        {
+            let mut gg4 = SMS4Encoder::new_gg(&mut test_out);
+
+            gg4.encode_palette(palette).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x0Fu8, 0x00, 0xF0, 0x00, 0x00, 0x0F, 0x21, 0x03])
+    }
+
+    #[test]
+    fn palette_format_override() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(2));
+
+        {
+            let mut sms4 = SMS4Encoder::new_sms(&mut test_out).with_palette_format(PaletteColorFormat::GameGear);
+
+            sms4.encode_palette(palette).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x0Fu8, 0x00])
+    }
+}
@@ -0,0 +1,194 @@
+use awsmimg::encoder::DirectGraphicsEncoder;
+use awsmimg::decoder::DirectGraphicsDecoder;
+use awsmimg::formats::pico8::{nearest_fixed_color, read_cart_text, fixed_colors_from_hex};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{GenericImage, ImageBuffer, Primitive, Pixel, Rgba};
+
+/// The RGB values of the default TIC-80 palette, Sweetie 16.
+const TIC80_PALETTE: [[u8; 3]; 16] = [
+    [0x1A, 0x1C, 0x2C], [0x5D, 0x27, 0x5D], [0xB1, 0x3E, 0x53], [0xEF, 0x7D, 0x57],
+    [0xFF, 0xCD, 0x75], [0xA7, 0xF0, 0x70], [0x38, 0xB7, 0x64], [0x25, 0x71, 0x79],
+    [0x29, 0x36, 0x6F], [0x3B, 0x5D, 0xC9], [0x41, 0xA6, 0xF6], [0x73, 0xEF, 0xF7],
+    [0xF4, 0xF4, 0xF4], [0x94, 0xB0, 0xC2], [0x56, 0x6C, 0x86], [0x33, 0x3C, 0x57]
+];
+
+/// Encoder for the sprite chunks of TIC-80 text carts.
+///
+/// The image is treated as a spritesheet 16 sprites wide: sprite numbers
+/// count left to right then top to bottom in 8x8 units. Dimensions must be
+/// multiples of 8, no wider than 128 pixels and no taller than 128 pixels.
+/// Each sprite that is not entirely color 0 is written as one comment line
+/// holding its three-digit number and 64 lowercase hex digits, one per pixel,
+/// in rows. Colors are mapped to the nearest color of the default palette.
+///
+/// Encoders constructed with new write a `<SPRITES>` chunk; encoders
+/// constructed with new_tiles write a `<TILES>` chunk instead.
+pub struct TIC80SpriteEncoder<'a, W: Write + 'a> {
+    w: &'a mut W,
+    chunk: &'static str
+}
+
+impl<'a, W: Write + 'a> TIC80SpriteEncoder<'a, W> {
+    pub fn new(write: &'a mut W) -> TIC80SpriteEncoder<'a, W> {
+        TIC80SpriteEncoder {
+            w: write,
+            chunk: "SPRITES"
+        }
+    }
+
+    pub fn new_tiles(write: &'a mut W) -> TIC80SpriteEncoder<'a, W> {
+        TIC80SpriteEncoder {
+            w: write,
+            chunk: "TILES"
+        }
+    }
+}
+
+impl<'a, W: Write> DirectGraphicsEncoder for TIC80SpriteEncoder<'a, W> {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        let (width, height) = image.dimensions();
+
+        if !width.is_multiple_of(8) || !height.is_multiple_of(8) || width > 128 || height > 128 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "TIC-80 spritesheets must be multiples of 8 and no larger than 128x128."));
+        }
+
+        let mut out = format!("-- <{}>\n", self.chunk);
+
+        for sy in 0..height / 8 {
+            for sx in 0..width / 8 {
+                let sprite: Vec<u8> = (0..64).map(|i| nearest_fixed_color(image.get_pixel(sx * 8 + i % 8, sy * 8 + i / 8), &TIC80_PALETTE)).collect();
+
+                if sprite.iter().all(|c| *c == 0) {
+                    continue;
+                }
+
+                out.push_str(&format!("-- {:03}:", sy * 16 + sx));
+
+                for c in sprite {
+                    out.push_str(&format!("{:x}", c));
+                }
+
+                out.push('\n');
+            }
+        }
+
+        out.push_str(&format!("-- </{}>\n", self.chunk));
+        self.w.write_all(out.as_bytes())
+    }
+}
+
+/// Decoder for the sprite chunks of TIC-80 text carts.
+///
+/// This reads a `<SPRITES>` or `<TILES>` chunk as written by
+/// TIC80SpriteEncoder. Sprites missing from the chunk are color 0. The image
+/// is as tall as the last row of sprites in the chunk, and every sprite must
+/// fit within the given width.
+pub struct TIC80SpriteDecoder<'a, R: Read + 'a> {
+    r: &'a mut R
+}
+
+impl<'a, R: Read + 'a> TIC80SpriteDecoder<'a, R> {
+    pub fn new(read: &'a mut R) -> TIC80SpriteDecoder<'a, R> {
+        TIC80SpriteDecoder {
+            r: read
+        }
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for TIC80SpriteDecoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        if width == 0 || !width.is_multiple_of(8) || width > 128 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "TIC-80 spritesheets must be multiples of 8 and no larger than 128x128."));
+        }
+
+        let text = read_cart_text(self.r, size)?;
+        let mut lines = text.lines();
+        let chunk = match lines.next() {
+            Some("-- <SPRITES>") => "-- </SPRITES>",
+            Some("-- <TILES>") => "-- </TILES>",
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "TIC-80 cart data does not start with a sprite chunk."))
+        };
+        let mut sprites: Vec<(u32, Vec<Rgba<u8>>)> = Vec::new();
+        let mut closed = false;
+
+        for line in lines {
+            if line == chunk {
+                closed = true;
+                break;
+            }
+
+            let num = line.get(3..6).and_then(|num| num.parse::<u32>().ok());
+
+            match (num, line.starts_with("-- ") && line.get(6..7) == Some(":") && line.len() == 71) {
+                (Some(num), true) if num < 256 && num % 16 < width / 8 => sprites.push((num, fixed_colors_from_hex(&line[7..], &TIC80_PALETTE)?)),
+                (Some(_), true) => return Err(io::Error::new(io::ErrorKind::InvalidData, "TIC-80 sprite does not fit within the given width.")),
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "TIC-80 sprite chunk holds a line that is not a sprite."))
+            }
+        }
+
+        if !closed {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "TIC-80 sprite chunk is not closed."));
+        }
+
+        let height = sprites.iter().map(|(num, _)| (num / 16 + 1) * 8).max().unwrap_or(0);
+        let color0 = TIC80_PALETTE[0];
+        let mut image = ImageBuffer::from_pixel(width, height, Rgba([color0[0], color0[1], color0[2], 255]));
+
+        for (num, pixels) in sprites {
+            for (i, rgba) in pixels.into_iter().enumerate() {
+                image.put_pixel((num % 16) * 8 + i as u32 % 8, (num / 16) * 8 + i as u32 / 8, rgba);
+            }
+        }
+
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::DirectGraphicsEncoder;
+    use awsmimg::formats::tic80::TIC80SpriteEncoder;
+
+    fn test_image() -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+        image::ImageBuffer::from_fn(16, 16, |x, y| {
+            match (x, y) {
+                (8, 0) => image::Rgba([0xF4u8, 0xF4, 0xF4, 255]),
+                (15, 15) => image::Rgba([0xB0u8, 0x40, 0x50, 255]),
+                _ => image::Rgba([0x1Au8, 0x1C, 0x2C, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn sprites_encode() {
+        let mut test_out = Cursor::new(Vec::new());
+
+        {
+            let mut tic = TIC80SpriteEncoder::new(&mut test_out);
+
+            tic.encode_colors(&test_image()).unwrap();
+        }
+
+        let valid_out = format!("-- <SPRITES>\n-- 001:c{}\n-- 017:{}2\n-- </SPRITES>\n", "0".repeat(63), "0".repeat(63));
+
+        assert_eq!(String::from_utf8_lossy(test_out.get_ref()), valid_out)
+    }
+
+    #[test]
+    fn tiles_encode() {
+        let mut test_out = Cursor::new(Vec::new());
+
+        {
+            let mut tic = TIC80SpriteEncoder::new_tiles(&mut test_out);
+
+            tic.encode_colors(&test_image()).unwrap();
+        }
+
+        assert!(String::from_utf8_lossy(test_out.get_ref()).starts_with("-- <TILES>\n-- 001:c"));
+    }
+}
@@ -0,0 +1,205 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, read_color_words, expand_channel};
+use awsmimg::formats::md::{encode_packed4, decode_packed4};
+use awsmimg::formats::sfc::{encode_planar, decode_planar};
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::IndexedGraphicsDecoder;
+
+use std::io;
+use std::io::{Write, Read};
+use image::{Primitive, Rgba};
+
+/// Encode a series of RGBA colors as WSC palette RAM data.
+///
+/// Colors are written as little-endian 12-bit words in the 0000RRRRGGGGBBBB
+/// layout. Alpha is ignored.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 2] = [0, 0];
+
+    for rgba in palette {
+        let r : u16 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let g : u16 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let b : u16 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+
+        let enc_color: u16 = (r & 0xF0) << 4 | (g & 0xF0) | b >> 4;
+
+        out[0] = (enc_color & 0xFF) as u8;
+        out[1] = ((enc_color >> 8) & 0xFF) as u8;
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode WSC palette RAM data written by encode_palette into RGBA colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. Every
+/// color is opaque.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    Ok(read_color_words(r, size, false)?.into_iter().map(|v| {
+        Rgba([expand_channel(v >> 8, 4), expand_channel(v >> 4, 4), expand_channel(v, 4), 255])
+    }).collect())
+}
+
+/// Encoder for tile patterns for the WS and WSC platforms.
+///
+/// Constructed with new_planar2, tiles are stored in the 2bpp planar format
+/// shared by both platforms: each row of an 8x8 tile is two bytes, one per
+/// bitplane, with the leftmost pixel in the most significant bit.
+///
+/// Constructed with new_packed4, tiles are stored in the WSC 4bpp packed
+/// format: each row is four bytes of two pixels each, with the leftmost pixel
+/// of each pair in the high nibble.
+///
+/// Palettes are written as WonderSwan palette words by default; use
+/// with_palette_format to select another color format.
+pub struct WSEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    packed4: bool,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> WSEncoder<'a, F> {
+    pub fn new_planar2(file: &'a mut F) -> WSEncoder<'a, F> {
+        WSEncoder {
+            f: file,
+            packed4: false,
+            palette_format: PaletteColorFormat::WS
+        }
+    }
+
+    pub fn new_packed4(file: &'a mut F) -> WSEncoder<'a, F> {
+        WSEncoder {
+            f: file,
+            packed4: true,
+            palette_format: PaletteColorFormat::WS
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as WonderSwan
+    /// palette words.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> WSEncoder<'a, F> {
+        WSEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for WSEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        match self.packed4 {
+            true => 15,
+            false => 3
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for WSEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        match self.packed4 {
+            true => encode_packed4(self.f, &data),
+            false => encode_planar(self.f, &data, 2)
+        }
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for WSEncoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        match self.packed4 {
+            true => decode_packed4(self.f, size, 32),
+            false => decode_planar(self.f, size, 2)
+        }
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        match self.packed4 {
+            true => Some(32),
+            false => Some(16)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate num;
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::formats::ws::WSEncoder;
+
+    #[test]
+    fn planar2_encode() {
+        let src : Vec<u8> = vec![0,1,2,3,0,1,2,3,
+                                 3,3,3,3,0,0,0,0,
+                                 0,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,0,
+                                 0,0,0,0,0,0,0,0,
+                                 1,0,0,0,0,0,0,2];
+        let mut test_out = Cursor::new(Vec::with_capacity(16));
+
+        {
+            let mut ws2 = WSEncoder::new_planar2(&mut test_out);
+
+            ws2.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let valid_out : Vec<u8> = vec![0x55, 0x33, 0xF0, 0xF0, 0x00, 0x00, 0x00, 0x00,
+                                       0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x01];
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn packed4_encode() {
+        let src = num::range(0, 64).collect();
+        let mut test_out = Cursor::new(Vec::with_capacity(32));
+
+        {
+            let mut ws4 = WSEncoder::new_packed4(&mut test_out);
+
+            ws4.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let valid_out : Vec<u8> = [0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF].repeat(4);
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn palette_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]),
+                           image::Rgba([0u8, 255, 0, 255]),
+                           image::Rgba([0u8, 0, 255, 255]),
+                           image::Rgba([0x11u8, 0x22, 0x33, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(8));
+
+        {
+            let mut ws = WSEncoder::new_packed4(&mut test_out);
+
+            ws.encode_palette(palette).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x00u8, 0x0F, 0xF0, 0x00, 0x0F, 0x00, 0x23, 0x01])
+    }
+}
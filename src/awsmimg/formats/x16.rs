@@ -0,0 +1,237 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, read_color_words, expand_channel};
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::{IndexedGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{Primitive, Rgba};
+
+/// Encode a series of RGBA colors as VERA palette data.
+///
+/// Colors are written as little-endian 12-bit words in the 0000RRRRGGGGBBBB
+/// layout. Alpha is ignored.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 2] = [0, 0];
+
+    for rgba in palette {
+        let r : u16 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let g : u16 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let b : u16 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+
+        let enc_color: u16 = (r & 0xF0) << 4 | (g & 0xF0) | b >> 4;
+
+        out[0] = (enc_color & 0xFF) as u8;
+        out[1] = ((enc_color >> 8) & 0xFF) as u8;
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode VERA palette data written by encode_palette into RGBA colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. Every
+/// color is opaque.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    Ok(read_color_words(r, size, false)?.into_iter().map(|v| {
+        Rgba([expand_channel(v >> 8, 4), expand_channel(v >> 4, 4), expand_channel(v, 4), 255])
+    }).collect())
+}
+
+/// Encoder for tile graphics for the VERA video chip of the X16 platform.
+///
+/// Pixels are packed into bytes, one row after another within each tile,
+/// with the leftmost pixel in the most significant bits. Use new_2bpp,
+/// new_4bpp or new_8bpp to select the color depth.
+///
+/// Tiles are 8x8 by default. VERA layers also support 16 pixel tile widths
+/// and heights, which can be selected with with_tile_size.
+///
+/// Palettes are written as VERA palette words by default; use
+/// with_palette_format to select another color format.
+pub struct VERATileEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    bpp: u8,
+    tsize: (u32, u32),
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> VERATileEncoder<'a, F> {
+    pub fn new_2bpp(file: &'a mut F) -> VERATileEncoder<'a, F> {
+        VERATileEncoder {
+            f: file,
+            bpp: 2,
+            tsize: (8, 8),
+            palette_format: PaletteColorFormat::VERA
+        }
+    }
+
+    pub fn new_4bpp(file: &'a mut F) -> VERATileEncoder<'a, F> {
+        VERATileEncoder {
+            bpp: 4,
+            ..VERATileEncoder::new_2bpp(file)
+        }
+    }
+
+    pub fn new_8bpp(file: &'a mut F) -> VERATileEncoder<'a, F> {
+        VERATileEncoder {
+            bpp: 8,
+            ..VERATileEncoder::new_2bpp(file)
+        }
+    }
+
+    pub fn with_tile_size(self, tsize: (u32, u32)) -> VERATileEncoder<'a, F> {
+        VERATileEncoder {
+            tsize,
+            ..self
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as VERA palette
+    /// words.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> VERATileEncoder<'a, F> {
+        VERATileEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for VERATileEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        self.tsize
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        self.tsize
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        (1 << self.bpp) - 1
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for VERATileEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        match self.tsize {
+            (8, 8) | (8, 16) | (16, 8) | (16, 16) => {},
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "VERA tiles must be 8 or 16 pixels wide and tall."))
+        }
+
+        let bpp = self.bpp as usize;
+        let mask = ((1u16 << bpp) - 1) as u8;
+        let out: Vec<u8> = data.chunks(8 / bpp).map(|group| {
+            group.iter().enumerate().fold(0u8, |byte, (x, index)| byte | (index.to_u8().unwrap() & mask) << (8 - bpp * (x + 1)))
+        }).collect();
+
+        self.f.write_all(&out)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for VERATileEncoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let tile_bytes = match self.tile_bytes() {
+            Some(tile_bytes) => tile_bytes,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "VERA tiles must be 8 or 16 pixels wide and tall."))
+        };
+        let bpp = self.bpp as usize;
+        let mask = ((1u16 << bpp) - 1) as u8;
+        let buf = read_units(self.f, size, tile_bytes, "VERA tile data ends partway through a tile")?;
+
+        Ok(buf.iter().flat_map(|byte| (0..8 / bpp).map(move |x| P::from((byte >> (8 - bpp * (x + 1))) & mask).unwrap())).collect())
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        match self.tsize {
+            (8, 8) | (8, 16) | (16, 8) | (16, 16) => Some((self.tsize.0 * self.tsize.1) as usize * self.bpp as usize / 8),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::formats::x16::VERATileEncoder;
+
+    #[test]
+    fn tile2_encode() {
+        let mut src : Vec<u8> = vec![0; 64];
+        src[0..4].copy_from_slice(&[0, 1, 2, 3]);
+        src[63] = 3;
+        let mut test_out = Cursor::new(Vec::with_capacity(16));
+
+        {
+            let mut vera = VERATileEncoder::new_2bpp(&mut test_out);
+
+            vera.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let mut valid_out : Vec<u8> = vec![0; 16];
+        valid_out[0] = 0x1B;
+        valid_out[15] = 0x03;
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn tile4_encode() {
+        let mut src : Vec<u8> = vec![0; 64];
+        src[0..4].copy_from_slice(&[0x1, 0x2, 0xF, 0x0]);
+        let mut test_out = Cursor::new(Vec::with_capacity(32));
+
+        {
+            let mut vera = VERATileEncoder::new_4bpp(&mut test_out);
+
+            vera.encode_indexes(src, 8, 8).unwrap();
+        }
+
+        let mut valid_out : Vec<u8> = vec![0; 32];
+        valid_out[0..2].copy_from_slice(&[0x12, 0xF0]);
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn tile8_encode() {
+        let src : Vec<u8> = (0..=255).collect();
+        let mut test_out = Cursor::new(Vec::with_capacity(256));
+
+        {
+            let mut vera = VERATileEncoder::new_8bpp(&mut test_out).with_tile_size((16, 16));
+
+            vera.encode_indexes(src.clone(), 16, 16).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &src);
+        assert!(VERATileEncoder::new_8bpp(&mut Cursor::new(Vec::new())).with_tile_size((32, 8)).encode_indexes(src, 32, 8).is_err());
+    }
+
+    #[test]
+    fn palette_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]),
+                           image::Rgba([0x12u8, 0x34, 0x56, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(4));
+
+        {
+            let mut vera = VERATileEncoder::new_4bpp(&mut test_out);
+
+            vera.encode_palette(palette).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x00u8, 0x0F, 0x35, 0x01])
+    }
+}
@@ -0,0 +1,321 @@
+use awsmimg::formats::IndexedGraphicsProperties;
+use awsmimg::formats::palette::{PaletteFormat, PaletteColorFormat, read_color_words};
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::{IndexedGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{Primitive, Rgba};
+
+/// Encode a series of RGBA colors as palette data for the X68K platform.
+///
+/// Colors are written as big-endian words in the GGGGGRRRRRBBBBBI layout. The
+/// intensity bit is set when the low bits discarded from all three channels
+/// are at least half of the next step, which approximates the extra level of
+/// brightness it provides. Alpha is ignored.
+pub fn encode_palette<'a, I, T: Primitive, W: Write + 'a>(w: &'a mut W, palette: I) -> io::Result<()> where I: Iterator<Item=Rgba<T>> {
+    let imgmax = T::max_value();
+    let mut out: [u8; 2] = [0, 0];
+
+    for rgba in palette {
+        let r : u16 = (rgba[0].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let g : u16 = (rgba[1].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let b : u16 = (rgba[2].to_f32().unwrap() / imgmax.to_f32().unwrap() * 255f32) as u16;
+        let i : u16 = ((r & 0x04) & (g & 0x04) & (b & 0x04)) >> 2;
+
+        let enc_color: u16 = (g & 0xF8) << 8 | (r & 0xF8) << 3 | (b & 0xF8) >> 2 | i;
+
+        out[0] = ((enc_color >> 8) & 0xFF) as u8;
+        out[1] = (enc_color & 0xFF) as u8;
+        w.write_all(&out)?;
+    }
+
+    Ok(())
+}
+
+/// Decode X68K palette data written by encode_palette into RGBA colors.
+///
+/// Up to size bytes are read, and must hold a whole number of colors. The
+/// intensity bit becomes the third bit of every channel, below the five bits
+/// the channel stores, so that re-encoding the palette yields the original
+/// data. Every color is opaque.
+pub fn decode_palette<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+    Ok(read_color_words(r, size, true)?.into_iter().map(|v| {
+        let channel = |c: u16| ((c & 0x1F) << 3 | (v & 0x01) << 2 | (c & 0x1F) >> 3) as u8;
+
+        Rgba([channel(v >> 6), channel(v >> 11), channel(v >> 1), 255])
+    }).collect())
+}
+
+/// Encoder for 16-color graphic screen data for the X68K platform.
+///
+/// In the 16-color graphic modes, each pixel of graphic VRAM is a big-endian
+/// word, and the four graphic pages are four planes of that word, each using
+/// one nibble: page 0 the lowest, page 3 the highest. Pixels are written one
+/// row after another with their index in the nibble of the page selected by
+/// with_page, or page 0 by default, and the other nibbles clear so that pages
+/// can be combined with a bitwise OR.
+///
+/// Palettes are written as X68000 palette words by default; use
+/// with_palette_format to select another color format.
+pub struct X68KGraphicEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    page: u8,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> X68KGraphicEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> X68KGraphicEncoder<'a, F> {
+        X68KGraphicEncoder {
+            f: file,
+            page: 0,
+            palette_format: PaletteColorFormat::X68K
+        }
+    }
+
+    pub fn with_page(self, page: u8) -> X68KGraphicEncoder<'a, F> {
+        X68KGraphicEncoder {
+            page,
+            ..self
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as X68000 palette
+    /// words.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> X68KGraphicEncoder<'a, F> {
+        X68KGraphicEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for X68KGraphicEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        15
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for X68KGraphicEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        if self.page > 3 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "X68K 16-color graphic pages are numbered 0 to 3."));
+        }
+
+        let mut out: Vec<u8> = Vec::with_capacity(data.len() * 2);
+
+        for index in data {
+            let word = (index.to_u16().unwrap() & 0x0F) << (self.page * 4);
+
+            out.push((word >> 8) as u8);
+            out.push((word & 0xFF) as u8);
+        }
+
+        self.f.write_all(&out)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for X68KGraphicEncoder<'a, F> where F: Read {
+    /// Decode graphic VRAM words into the color indexes of the selected page.
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        if self.page > 3 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "X68K 16-color graphic pages are numbered 0 to 3."));
+        }
+
+        let buf = read_units(self.f, size, 2, "Graphic VRAM data ends partway through a pixel")?;
+
+        Ok(buf.chunks(2).map(|word| {
+            let word = (word[0] as u16) << 8 | word[1] as u16;
+
+            P::from((word >> (self.page * 4)) & 0x0F).unwrap()
+        }).collect())
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(2)
+    }
+}
+
+/// Encoder for PCG sprite patterns for the X68K platform.
+///
+/// Sprites are 16x16, made of four 8x8 blocks stored top left, bottom left,
+/// top right, then bottom right. Each block is 32 bytes of 4bpp pixels, two
+/// per byte with the leftmost pixel in the high nibble.
+///
+/// Palettes are written as X68000 palette words by default; use
+/// with_palette_format to select another color format.
+pub struct X68KPCGEncoder<'a, F: 'a> {
+    f: &'a mut F,
+    palette_format: PaletteColorFormat
+}
+
+impl<'a, F: 'a> X68KPCGEncoder<'a, F> {
+    pub fn new(file: &'a mut F) -> X68KPCGEncoder<'a, F> {
+        X68KPCGEncoder {
+            f: file,
+            palette_format: PaletteColorFormat::X68K
+        }
+    }
+
+    /// Write palettes in the given color format, rather than as X68000 palette
+    /// words.
+    pub fn with_palette_format(self, palette_format: PaletteColorFormat) -> X68KPCGEncoder<'a, F> {
+        X68KPCGEncoder {
+            palette_format,
+            ..self
+        }
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsProperties for X68KPCGEncoder<'a, F> {
+    fn tile_size(&self) -> (u32, u32) {
+        (16, 16)
+    }
+
+    fn attribute_size(&self) -> (u32, u32) {
+        (16, 16)
+    }
+
+    fn palette_maxcol(&self) -> u16 {
+        15
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsEncoder for X68KPCGEncoder<'a, F> where F: Write {
+    fn encode_indexes<P: Primitive>(&mut self, data: Vec<P>, _width: u32, _height: u32) -> io::Result<()> {
+        let mut out: Vec<u8> = Vec::with_capacity(data.len() / 2);
+
+        for sprite in data.chunks(256) {
+            for &(bx, by) in &[(0, 0), (0, 8), (8, 0), (8, 8)] {
+                for y in by..by + 8 {
+                    for x in (bx..bx + 8).step_by(2) {
+                        let left = sprite.get(y * 16 + x).map_or(0, |i| i.to_u8().unwrap() & 0x0F);
+                        let right = sprite.get(y * 16 + x + 1).map_or(0, |i| i.to_u8().unwrap() & 0x0F);
+
+                        out.push(left << 4 | right);
+                    }
+                }
+            }
+        }
+
+        self.f.write_all(&out)
+    }
+
+    fn encode_palette<T: Primitive>(&mut self, palette: Vec<Rgba<T>>) -> io::Result<()> {
+        self.palette_format.encode_colors(self.f, palette.into_iter())
+    }
+}
+
+impl<'a, F: 'a> IndexedGraphicsDecoder for X68KPCGEncoder<'a, F> where F: Read {
+    fn decode_indexes<P: Primitive>(&mut self, size: usize) -> io::Result<Vec<P>> {
+        let buf = read_units(self.f, size, 128, "PCG data ends partway through a sprite")?;
+        let mut out: Vec<P> = Vec::with_capacity(buf.len() * 2);
+
+        for sprite in buf.chunks(128) {
+            let mut indexes = [0u8; 256];
+            let mut bytes = sprite.iter();
+
+            for &(bx, by) in &[(0, 0), (0, 8), (8, 0), (8, 8)] {
+                for y in by..by + 8 {
+                    for x in (bx..bx + 8).step_by(2) {
+                        let byte = bytes.next().unwrap();
+
+                        indexes[y * 16 + x] = byte >> 4;
+                        indexes[y * 16 + x + 1] = byte & 0x0F;
+                    }
+                }
+            }
+
+            out.extend(indexes.iter().map(|index| P::from(*index).unwrap()));
+        }
+
+        Ok(out)
+    }
+
+    fn decode_palette(&mut self, size: usize) -> io::Result<Vec<Rgba<u8>>> {
+        self.palette_format.decode_colors(self.f, size)
+    }
+
+    fn tile_bytes(&self) -> Option<usize> {
+        Some(128)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::formats::x68k::{X68KGraphicEncoder, X68KPCGEncoder};
+
+    #[test]
+    fn graphic_encode() {
+        let src : Vec<u8> = vec![0x1, 0xF, 0x7];
+        let mut page0_out = Cursor::new(Vec::with_capacity(6));
+        let mut page2_out = Cursor::new(Vec::with_capacity(6));
+
+        X68KGraphicEncoder::new(&mut page0_out).encode_indexes(src.clone(), 3, 1).unwrap();
+        X68KGraphicEncoder::new(&mut page2_out).with_page(2).encode_indexes(src, 3, 1).unwrap();
+
+        assert_eq!(page0_out.get_ref(), &vec![0x00u8, 0x01, 0x00, 0x0F, 0x00, 0x07]);
+        assert_eq!(page2_out.get_ref(), &vec![0x01u8, 0x00, 0x0F, 0x00, 0x07, 0x00]);
+    }
+
+    #[test]
+    fn pcg_encode() {
+        let mut src : Vec<u8> = vec![0; 256];
+        src[0] = 0x1;
+        src[1] = 0x2;
+        src[8 * 16] = 0x3;
+        src[8] = 0x4;
+        src[255] = 0x5;
+        let mut test_out = Cursor::new(Vec::with_capacity(128));
+
+        {
+            let mut pcg = X68KPCGEncoder::new(&mut test_out);
+
+            pcg.encode_indexes(src, 16, 16).unwrap();
+        }
+
+        let mut valid_out : Vec<u8> = vec![0; 128];
+        valid_out[0] = 0x12;
+        valid_out[32] = 0x30;
+        valid_out[64] = 0x40;
+        valid_out[127] = 0x05;
+
+        assert_eq!(test_out.get_ref(), &valid_out)
+    }
+
+    #[test]
+    fn palette_encode() {
+        let palette = vec![image::Rgba([255u8, 0, 0, 255]),
+                           image::Rgba([0u8, 255, 0, 255]),
+                           image::Rgba([0x0Cu8, 0x0C, 0x0C, 255])];
+        let mut test_out = Cursor::new(Vec::with_capacity(6));
+
+        {
+            let mut pcg = X68KPCGEncoder::new(&mut test_out);
+
+            pcg.encode_palette(palette).unwrap();
+        }
+
+        assert_eq!(test_out.get_ref(), &vec![0x07u8, 0xC0, 0xF8, 0x00, 0x08, 0x43])
+    }
+}
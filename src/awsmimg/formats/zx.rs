@@ -0,0 +1,253 @@
+use awsmimg::encoder::DirectGraphicsEncoder;
+use awsmimg::decoder::{DirectGraphicsDecoder, read_units};
+
+use std::io;
+use std::io::{Write, Read};
+use image::{GenericImage, ImageBuffer, Primitive, Pixel, Rgba};
+
+/// Intensity of a color channel that is on, without and with BRIGHT set.
+const ZX_LEVELS: [u8; 2] = [0xD7, 0xFF];
+
+/// The RGB value of a Spectrum color number (GRB bit order) at a brightness.
+fn zx_rgb(color: u8, bright: usize) -> [u8; 3] {
+    let level = ZX_LEVELS[bright];
+    let on = |bit: u8| if color & bit != 0 { level } else { 0 };
+
+    [on(0x02), on(0x04), on(0x01)]
+}
+
+fn zx_distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    (0..3).map(|i| (a[i] as i32 - b[i] as i32).pow(2) as u32).sum()
+}
+
+/// The nearest Spectrum color number to an RGB value at a brightness, out of
+/// a given set of color numbers.
+fn zx_nearest(rgb: [u8; 3], bright: usize, colors: &[u8]) -> u8 {
+    *colors.iter().min_by_key(|&&c| zx_distance(rgb, zx_rgb(c, bright))).unwrap()
+}
+
+/// Encoder for ZX Spectrum screen (SCR) dumps.
+///
+/// Images must be 256x192. The bitmap is written first, in the Spectrum's
+/// non-linear row order: the screen is split into three thirds, each third
+/// stores the first pixel row of all eight character rows, then the second
+/// pixel row, and so on. Each byte holds eight pixels with the leftmost in
+/// the most significant bit, set for INK and clear for PAPER. The 32x24 grid
+/// of attribute bytes follows, in the FBPPPIII layout.
+///
+/// Colors are matched against the 15 colors of the Spectrum palette. Each 8x8
+/// cell can only hold two colors of the same brightness; for each cell the
+/// brightness and the pair of colors that represent the cell with the least
+/// error are chosen, with the most common color becoming PAPER. By default,
+/// pixels in cells with more colors than that are mapped to the nearest of
+/// the chosen pair. If with_strict is used, such attribute clash is instead
+/// reported as an error naming the first cell that clashes.
+pub struct ZXScreenEncoder<'a, W: Write + 'a> {
+    w: &'a mut W,
+    strict: bool
+}
+
+impl<'a, W: Write + 'a> ZXScreenEncoder<'a, W> {
+    pub fn new(write: &'a mut W) -> ZXScreenEncoder<'a, W> {
+        ZXScreenEncoder {
+            w: write,
+            strict: false
+        }
+    }
+
+    pub fn with_strict(self, strict: bool) -> ZXScreenEncoder<'a, W> {
+        ZXScreenEncoder {
+            strict,
+            ..self
+        }
+    }
+}
+
+impl<'a, W: Write> DirectGraphicsEncoder for ZXScreenEncoder<'a, W> {
+    fn encode_colors<I, P, S>(&mut self, image: &I) -> io::Result<()> where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+        if image.dimensions() != (256, 192) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "ZX Spectrum screens must be 256x192."));
+        }
+
+        let imgmax = S::max_value().to_f32().unwrap();
+        let all_colors: Vec<u8> = (0..8).collect();
+        let mut bitmap: Vec<u8> = vec![0; 6144];
+        let mut attrs: Vec<u8> = Vec::with_capacity(768);
+
+        for cy in 0..24 {
+            for cx in 0..32 {
+                let cell: Vec<[u8; 3]> = (0..64).map(|i| {
+                    let rgba = image.get_pixel(cx * 8 + i % 8, cy * 8 + i / 8).to_rgba();
+                    let mut rgb = [0u8; 3];
+
+                    for (c, v) in rgb.iter_mut().enumerate() {
+                        *v = (rgba[c].to_f32().unwrap() / imgmax * 255f32) as u8;
+                    }
+
+                    rgb
+                }).collect();
+
+                //Pick the brightness and color pair with the least error.
+                let mut best: Option<(u32, usize, u8, u8)> = None;
+
+                for bright in 0..2 {
+                    let mut counts = [0u32; 8];
+
+                    for rgb in &cell {
+                        counts[zx_nearest(*rgb, bright, &all_colors) as usize] += 1;
+                    }
+
+                    let mut ranked: Vec<u8> = (0..8).collect();
+                    ranked.sort_by_key(|&c| std::cmp::Reverse(counts[c as usize]));
+
+                    let paper = ranked[0];
+                    let ink = if counts[ranked[1] as usize] > 0 { ranked[1] } else { paper };
+                    let error: u32 = cell.iter().map(|rgb| {
+                        zx_distance(*rgb, zx_rgb(zx_nearest(*rgb, bright, &[paper, ink]), bright))
+                    }).sum();
+
+                    if best.is_none_or(|(e, _, _, _)| error < e) {
+                        best = Some((error, bright, paper, ink));
+                    }
+                }
+
+                let (_, bright, paper, ink) = best.unwrap();
+
+                if self.strict {
+                    let exact = cell.iter().all(|rgb| *rgb == zx_rgb(paper, bright) || *rgb == zx_rgb(ink, bright));
+
+                    if !exact {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Attribute clash in character cell ({}, {}).", cx, cy)));
+                    }
+                }
+
+                for (i, rgb) in cell.iter().enumerate() {
+                    let y = (cy * 8 + i as u32 / 8) as usize;
+                    let addr = (y & 0xC0) << 5 | (y & 0x07) << 8 | (y & 0x38) << 2 | cx as usize;
+
+                    if ink != paper && zx_nearest(*rgb, bright, &[paper, ink]) == ink {
+                        bitmap[addr] |= 0x80 >> (i % 8);
+                    }
+                }
+
+                attrs.push((bright as u8) << 6 | paper << 3 | ink);
+            }
+        }
+
+        self.w.write_all(&bitmap)?;
+        self.w.write_all(&attrs)
+    }
+}
+
+/// Decoder for ZX Spectrum screen (SCR) dumps.
+///
+/// Screens are always 256x192, and must be read in full. FLASH is ignored.
+pub struct ZXScreenDecoder<'a, R: Read + 'a> {
+    r: &'a mut R
+}
+
+impl<'a, R: Read + 'a> ZXScreenDecoder<'a, R> {
+    pub fn new(read: &'a mut R) -> ZXScreenDecoder<'a, R> {
+        ZXScreenDecoder {
+            r: read
+        }
+    }
+}
+
+impl<'a, R: Read> DirectGraphicsDecoder for ZXScreenDecoder<'a, R> {
+    fn decode_colors(&mut self, width: u32, size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        if width != 256 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "ZX Spectrum screens must be 256x192."));
+        }
+
+        let buf = read_units(self.r, size.min(6912), 6912, "ZX Spectrum screen ends partway through")?;
+
+        if buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "ZX Spectrum screen ends partway through"));
+        }
+
+        let (bitmap, attrs) = buf.split_at(6144);
+
+        Ok(ImageBuffer::from_fn(256, 192, |x, y| {
+            let y = y as usize;
+            let addr = (y & 0xC0) << 5 | (y & 0x07) << 8 | (y & 0x38) << 2 | (x as usize / 8);
+            let attr = attrs[(y / 8) * 32 + x as usize / 8];
+            let color = match bitmap[addr] & (0x80 >> (x % 8)) {
+                0 => (attr >> 3) & 0x07,
+                _ => attr & 0x07
+            };
+            let rgb = zx_rgb(color, ((attr >> 6) & 1) as usize);
+
+            Rgba([rgb[0], rgb[1], rgb[2], 255])
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use awsmimg::encoder::DirectGraphicsEncoder;
+    use awsmimg::decoder::DirectGraphicsDecoder;
+    use awsmimg::formats::zx::{ZXScreenEncoder, ZXScreenDecoder};
+
+    fn test_image(clash: bool) -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+        image::ImageBuffer::from_fn(256, 192, |x, y| {
+            match (x, y) {
+                (0, 0) => image::Rgba([0xFFu8, 0xFF, 0x00, 255]),
+                (9, 8) => image::Rgba([0xD7u8, 0x00, 0x00, 255]),
+                (10, 8) if clash => image::Rgba([0x00u8, 0xD7, 0x00, 255]),
+                (x, y) if x >= 248 && y >= 184 => image::Rgba([0x00u8, 0x00, 0xD7, 255]),
+                _ => image::Rgba([0u8, 0, 0, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn scr_encode() {
+        let mut test_out = Cursor::new(Vec::with_capacity(6912));
+
+        {
+            let mut zx = ZXScreenEncoder::new(&mut test_out);
+
+            zx.encode_colors(&test_image(false)).unwrap();
+        }
+
+        let out = test_out.get_ref();
+        let mut valid_bitmap : Vec<u8> = vec![0; 6144];
+        valid_bitmap[0] = 0x80;
+        valid_bitmap[0x21] = 0x40;
+
+        let mut valid_attrs : Vec<u8> = vec![0; 768];
+        valid_attrs[0] = 0x46;
+        valid_attrs[33] = 0x02;
+        valid_attrs[767] = 0x09;
+
+        assert_eq!(&out[0..6144], &valid_bitmap[..]);
+        assert_eq!(&out[6144..], &valid_attrs[..]);
+    }
+
+    #[test]
+    fn scr_clash() {
+        let mut lenient_out = Cursor::new(Vec::with_capacity(6912));
+        let mut strict_out = Cursor::new(Vec::with_capacity(6912));
+
+        ZXScreenEncoder::new(&mut lenient_out).encode_colors(&test_image(true)).unwrap();
+
+        assert!(ZXScreenEncoder::new(&mut strict_out).with_strict(true).encode_colors(&test_image(true)).is_err());
+        assert!(ZXScreenEncoder::new(&mut strict_out).with_strict(true).encode_colors(&test_image(false)).is_ok());
+    }
+
+    #[test]
+    fn scr_decode() {
+        let img = test_image(false);
+        let mut scr = Vec::with_capacity(6912);
+
+        ZXScreenEncoder::new(&mut scr).encode_colors(&img).unwrap();
+
+        assert_eq!(ZXScreenDecoder::new(&mut &scr[..]).decode_colors(256, scr.len()).unwrap().into_raw(), img.into_raw());
+        assert!(ZXScreenDecoder::new(&mut &scr[..]).decode_colors(128, scr.len()).is_err());
+        assert!(ZXScreenDecoder::new(&mut &scr[..6000]).decode_colors(256, 6000).is_err());
+    }
+}
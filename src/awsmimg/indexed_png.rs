@@ -0,0 +1,152 @@
+use std::io;
+use std::io::{Read, Write};
+use image::{ImageBuffer, Luma, Rgba};
+use png;
+use png::HasParameters;
+
+/// An image of color indexes, each pixel holding its index as-is.
+pub type IndexImage = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+/// Write an image of color indexes and its palette as an indexed-color PNG.
+///
+/// Unlike saving the grayscale images produced by luma_from_indexes, this
+/// preserves the exact index of every pixel along with the color it maps to,
+/// so that the image can be edited with a palette-aware image editor. The
+/// palette is written as a PLTE chunk, and a tRNS chunk is added if any of
+/// its colors are not fully opaque.
+///
+/// PNG palettes hold at most 256 colors. Indexes beyond the end of the given
+/// palette are permitted, but will be displayed as black by most software.
+pub fn write_indexed_png<W: Write>(w: W, image: &IndexImage, palette: &[Rgba<u8>]) -> io::Result<()> {
+    if palette.is_empty() || palette.len() > 256 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Indexed PNG palettes must hold between 1 and 256 colors."));
+    }
+
+    let (width, height) = image.dimensions();
+    let mut enc = png::Encoder::new(w, width, height);
+
+    enc.set(png::ColorType::Indexed).set(png::BitDepth::Eight);
+
+    let mut writer = enc.write_header()?;
+    let plte : Vec<u8> = palette.iter().flat_map(|c| vec![c[0], c[1], c[2]]).collect();
+
+    writer.write_chunk(png::chunk::PLTE, &plte)?;
+
+    if let Some(last_translucent) = palette.iter().rposition(|c| c[3] != 255) {
+        let trns : Vec<u8> = palette[..=last_translucent].iter().map(|c| c[3]).collect();
+
+        writer.write_chunk(png::chunk::tRNS, &trns)?;
+    }
+
+    writer.write_image_data(image)?;
+
+    Ok(())
+}
+
+/// Write a palette as an indexed-color PNG of swatches, for checking palette
+/// conversions by eye.
+///
+/// Swatches are laid out as by swatches_from_palette, but as an indexed-color
+/// image, so that each swatch holds exactly its own index and color. Parts of
+/// the last row past the end of the palette use index zero.
+pub fn write_palette_swatch_png<W: Write>(w: W, palette: &[Rgba<u8>], columns: u32, size: u32) -> io::Result<()> {
+    if columns == 0 || size == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Swatch grids must have at least one column of swatches at least one pixel across."));
+    }
+
+    let rows = (palette.len() as u32).div_ceil(columns);
+    let image : IndexImage = ImageBuffer::from_fn(columns * size, rows * size, |x, y| {
+        let index = (y / size) * columns + x / size;
+
+        Luma([if (index as usize) < palette.len() { index as u8 } else { 0 }])
+    });
+
+    write_indexed_png(w, &image, palette)
+}
+
+/// Read an indexed-color PNG as an image of color indexes and its palette.
+///
+/// This is the inverse of write_indexed_png; indexes are read back exactly
+/// as stored, at any PNG bit depth. Non-indexed PNGs are rejected, as they do
+/// not hold index data.
+pub fn read_indexed_png<R: Read>(r: R) -> io::Result<(IndexImage, Vec<Rgba<u8>>)> {
+    let mut dec = png::Decoder::new(r);
+
+    dec.set(png::Transformations::IDENTITY);
+
+    let (info, mut reader) = dec.read_info().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+
+    if info.color_type != png::ColorType::Indexed {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "PNG file is not indexed-color."));
+    }
+
+    let mut raw = vec![0u8; info.line_size * info.height as usize];
+
+    reader.next_frame(&mut raw).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+
+    let palette = {
+        let plte = match reader.info().palette {
+            Some(ref p) => p.clone(),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "Indexed PNG file has no palette."))
+        };
+        let trns = reader.info().trns.clone().unwrap_or_default();
+
+        plte.chunks(3).enumerate().map(|(i, c)| Rgba([c[0], c[1], c[2], trns.get(i).cloned().unwrap_or(255)])).collect()
+    };
+
+    let bits = info.bit_depth as usize;
+    let mask = ((1u16 << bits) - 1) as u8;
+    let image = ImageBuffer::from_fn(info.width, info.height, |x, y| {
+        let bit = x as usize * bits;
+        let byte = raw[y as usize * info.line_size + bit / 8];
+
+        Luma([(byte >> (8 - bits - bit % 8)) & mask])
+    });
+
+    Ok((image, palette))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use std::io::Cursor;
+    use image::{ImageBuffer, Luma, Rgba};
+    use awsmimg::indexed_png::{write_indexed_png, read_indexed_png, write_palette_swatch_png};
+
+    #[test]
+    fn indexed_png_roundtrip() {
+        let img : ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(5, 3, |x, y| Luma([((x + y) % 3) as u8]));
+        let palette = vec![Rgba([0u8, 0, 0, 0]), Rgba([255u8, 0, 0, 255]), Rgba([0u8, 255, 0, 255])];
+        let mut out = Cursor::new(Vec::new());
+
+        write_indexed_png(&mut out, &img, &palette).unwrap();
+
+        let (test_img, test_palette) = read_indexed_png(&out.get_ref()[..]).unwrap();
+
+        assert_eq!(test_img.into_raw(), img.into_raw());
+        assert_eq!(test_palette, palette);
+    }
+
+    #[test]
+    fn indexed_png_bad_palette() {
+        let img : ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::new(1, 1);
+
+        assert!(write_indexed_png(Cursor::new(Vec::new()), &img, &[]).is_err());
+        assert!(write_indexed_png(Cursor::new(Vec::new()), &img, &vec![Rgba([0u8; 4]); 257]).is_err());
+    }
+
+    #[test]
+    fn palette_swatch_png() {
+        let palette = vec![Rgba([255u8, 0, 0, 255]), Rgba([0u8, 255, 0, 255]), Rgba([0u8, 0, 255, 255])];
+        let mut out = Cursor::new(Vec::new());
+
+        write_palette_swatch_png(&mut out, &palette, 2, 2).unwrap();
+
+        let (img, test_palette) = read_indexed_png(&out.get_ref()[..]).unwrap();
+
+        assert_eq!(img.into_raw(), vec![0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 0, 0, 2, 2, 0, 0]);
+        assert_eq!(test_palette, palette);
+        assert!(write_palette_swatch_png(Cursor::new(Vec::new()), &palette, 0, 2).is_err());
+    }
+}
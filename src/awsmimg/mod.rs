@@ -1,6 +1,16 @@
+#[macro_use]
+pub mod formats;
 pub mod conversion;
 pub mod encoder;
 pub mod decoder;
+pub mod dump;
+pub mod indexed_png;
+pub mod palette_cycle;
+pub mod palette_file;
+pub mod palette_usage;
+pub mod quantize;
+pub mod ripper;
+pub mod tilemap;
 pub mod tiles;
-pub mod formats;
+pub mod verify;
 pub mod compression;
\ No newline at end of file
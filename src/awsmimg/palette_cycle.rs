@@ -0,0 +1,277 @@
+use std::io;
+use std::io::Write;
+use image::{GenericImage, Pixel, Primitive, Rgba};
+use awsmimg::formats::IndexedFormat;
+use awsmimg::encoder::encode_palette_with_format;
+use awsmimg::indexed_png::IndexImage;
+
+/// A range of palette entries that rotate by one place each frame, as in a
+/// classic palette-cycling effect.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PaletteRotation {
+    pub start: usize,
+    pub length: usize,
+    pub reverse: bool
+}
+
+/// Generate every frame of a palette rotation, starting with the palette as
+/// given.
+///
+/// Each frame moves the colors of the rotating range one entry later, or one
+/// entry earlier if the rotation is reversed, with the color at the end
+/// wrapping around. The rotation repeats after as many frames as the range is
+/// long, so that many frames are returned.
+pub fn rotate_palette(palette: &[Rgba<u8>], rotation: PaletteRotation) -> io::Result<Vec<Vec<Rgba<u8>>>> {
+    let end = rotation.start + rotation.length;
+
+    if rotation.length == 0 || end > palette.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Palette rotation does not lie within the palette."));
+    }
+
+    let mut frames = Vec::with_capacity(rotation.length);
+    let mut frame = palette.to_vec();
+
+    for _ in 0..rotation.length {
+        frames.push(frame.clone());
+
+        match rotation.reverse {
+            true => frame[rotation.start..end].rotate_left(1),
+            false => frame[rotation.start..end].rotate_right(1)
+        }
+    }
+
+    Ok(frames)
+}
+
+/// Given an image of color indexes and several images of how it should look
+/// over time, such as the layers of an image editor, recover the palette of
+/// each layer.
+///
+/// Every layer must be the same size as the index image, and must show every
+/// pixel of the same index in the same color. Each palette is as long as the
+/// largest index used; entries for indexes that are never used are fully
+/// transparent.
+pub fn palettes_from_layers<I, P, S>(indexes: &IndexImage, layers: &[&I]) -> io::Result<Vec<Vec<Rgba<u8>>>>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    let count = indexes.pixels().map(|p| p[0] as usize + 1).max().unwrap_or(0);
+    let mut palettes = Vec::with_capacity(layers.len());
+
+    for layer in layers {
+        if layer.dimensions() != indexes.dimensions() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Palette layers must be the same size as their index image."));
+        }
+
+        let imgmax = S::max_value().to_f32().unwrap();
+        let mut palette : Vec<Option<Rgba<u8>>> = vec![None; count];
+
+        for (x, y, pixel) in layer.pixels() {
+            let rgba = pixel.to_rgba();
+            let mut color = Rgba([0u8; 4]);
+
+            for i in 0..4 {
+                color[i] = (rgba[i].to_f32().unwrap() / imgmax * 255f32) as u8;
+            }
+
+            let index = indexes.get_pixel(x, y)[0] as usize;
+
+            match palette[index] {
+                Some(existing) if existing != color => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Palette layer shows index {} in more than one color.", index)));
+                },
+                _ => palette[index] = Some(color)
+            }
+        }
+
+        palettes.push(palette.into_iter().map(|c| c.unwrap_or(Rgba([0, 0, 0, 0]))).collect());
+    }
+
+    Ok(palettes)
+}
+
+/// Encode several palettes one after another in the given format, writing
+/// a table of where each palette starts.
+///
+/// Palette data is written to data, and the byte offset of each palette
+/// within it is written to table as a little-endian 32-bit word. The offsets
+/// are also returned.
+pub fn encode_palette_animation<'a, W, T>(format: IndexedFormat, data: &mut W, table: &mut T, palettes: &[Vec<Rgba<u8>>]) -> io::Result<Vec<u32>> where W: Write + 'a, T: Write + 'a {
+    let mut encoded = Vec::new();
+    let mut offsets = Vec::with_capacity(palettes.len());
+
+    for palette in palettes {
+        offsets.push(encoded.len() as u32);
+        encode_palette_with_format(format, &mut encoded, palette.clone())?;
+    }
+
+    data.write_all(&encoded)?;
+
+    for offset in &offsets {
+        table.write_all(&offset.to_le_bytes())?;
+    }
+
+    Ok(offsets)
+}
+
+/// The color a palette fades toward.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FadeTarget {
+    Black,
+    White,
+    Color(Rgba<u8>)
+}
+
+impl FadeTarget {
+    pub fn color(self) -> Rgba<u8> {
+        match self {
+            FadeTarget::Black => Rgba([0, 0, 0, 255]),
+            FadeTarget::White => Rgba([255, 255, 255, 255]),
+            FadeTarget::Color(color) => color
+        }
+    }
+}
+
+/// Generate the palettes of a fade, starting with the palette as given and
+/// ending with every color replaced by the target color.
+///
+/// Colors are interpolated in even steps between the two, rounding to the
+/// nearest 8-bit value, so that a fade of a given number of steps can be
+/// played forward to fade out or backward to fade in. Alpha is kept as it is.
+/// At least two steps are needed to hold both ends of the fade.
+pub fn fade_palettes(palette: &[Rgba<u8>], target: FadeTarget, steps: usize) -> io::Result<Vec<Vec<Rgba<u8>>>> {
+    if steps < 2 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Palette fades need at least two steps."));
+    }
+
+    let to = target.color();
+    let last = (steps - 1) as f32;
+
+    Ok((0..steps).map(|step| {
+        let t = step as f32 / last;
+
+        palette.iter().map(|from| {
+            let mut color = *from;
+
+            for i in 0..3 {
+                color[i] = (from[i] as f32 + (to[i] as f32 - from[i] as f32) * t).round() as u8;
+            }
+
+            color
+        }).collect()
+    }).collect())
+}
+
+/// Encode the palettes of a fade one after another in the given format, and
+/// write a C header describing them.
+///
+/// Palette data is written to data with no padding between palettes. The
+/// header defines the number of palettes and the size of each in bytes, as
+/// NAME_COUNT and NAME_STRIDE, and the size of the whole table as NAME_SIZE,
+/// where NAME is the given name in upper case. Names must be valid C
+/// identifiers, and every palette must be the same length.
+pub fn encode_fade_table<'a, W, H>(format: IndexedFormat, data: &mut W, header: &mut H, name: &str, palettes: &[Vec<Rgba<u8>>]) -> io::Result<()> where W: Write + 'a, H: Write + 'a {
+    let is_identifier = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if !is_identifier {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Fade table names must be valid C identifiers."));
+    }
+
+    if palettes.windows(2).any(|pair| pair[0].len() != pair[1].len()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Every palette of a fade table must be the same length."));
+    }
+
+    let mut encoded = Vec::new();
+    let mut stride = 0;
+
+    for palette in palettes {
+        let start = encoded.len();
+
+        encode_palette_with_format(format, &mut encoded, palette.clone())?;
+        stride = encoded.len() - start;
+    }
+
+    data.write_all(&encoded)?;
+
+    let prefix = name.to_ascii_uppercase();
+
+    write!(header, "#pragma once\n\n#define {}_COUNT {}\n#define {}_STRIDE {}\n#define {}_SIZE {}\n",
+        prefix, palettes.len(), prefix, stride, prefix, encoded.len())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use image::{ImageBuffer, Luma, Rgba};
+    use awsmimg::formats::IndexedFormat;
+    use awsmimg::palette_cycle::{PaletteRotation, rotate_palette, palettes_from_layers, encode_palette_animation, FadeTarget, fade_palettes, encode_fade_table};
+
+    #[test]
+    fn palette_rotation() {
+        let colors : Vec<Rgba<u8>> = (0..4).map(|i| Rgba([i as u8, 0, 0, 255])).collect();
+        let frames = rotate_palette(&colors, PaletteRotation { start: 1, length: 3, reverse: false }).unwrap();
+        let reds = |frame: &Vec<Rgba<u8>>| -> Vec<u8> { frame.iter().map(|c| c[0]).collect() };
+
+        assert_eq!(frames.iter().map(reds).collect::<Vec<_>>(), vec![vec![0, 1, 2, 3], vec![0, 3, 1, 2], vec![0, 2, 3, 1]]);
+
+        let reversed = rotate_palette(&colors, PaletteRotation { start: 0, length: 2, reverse: true }).unwrap();
+
+        assert_eq!(reversed.iter().map(reds).collect::<Vec<_>>(), vec![vec![0, 1, 2, 3], vec![1, 0, 2, 3]]);
+        assert!(rotate_palette(&colors, PaletteRotation { start: 2, length: 3, reverse: false }).is_err());
+    }
+
+    #[test]
+    fn layer_palettes() {
+        let indexes : ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(3, 1, |x, _| Luma([[0u8, 2, 2][x as usize]]));
+        let day : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(3, 1, |x, _| if x == 0 { Rgba([0, 0, 255, 255]) } else { Rgba([255, 255, 0, 255]) });
+        let night : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(3, 1, |x, _| if x == 0 { Rgba([0, 0, 64, 255]) } else { Rgba([64, 64, 0, 255]) });
+        let palettes = palettes_from_layers(&indexes, &[&day, &night]).unwrap();
+
+        assert_eq!(palettes, vec![
+            vec![Rgba([0u8, 0, 255, 255]), Rgba([0u8, 0, 0, 0]), Rgba([255u8, 255, 0, 255])],
+            vec![Rgba([0u8, 0, 64, 255]), Rgba([0u8, 0, 0, 0]), Rgba([64u8, 64, 0, 255])]
+        ]);
+
+        let muddled : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(3, 1, |x, _| Rgba([x as u8, 0, 0, 255]));
+
+        assert!(palettes_from_layers(&indexes, &[&muddled]).is_err());
+    }
+
+    #[test]
+    fn animation_table() {
+        let palettes = vec![vec![Rgba([255u8, 0, 0, 255]); 2], vec![Rgba([0u8, 0, 255, 255]); 3]];
+        let mut data = Vec::new();
+        let mut table = Vec::new();
+
+        let offsets = encode_palette_animation(IndexedFormat::AGB4, &mut data, &mut table, &palettes).unwrap();
+
+        assert_eq!(offsets, vec![0, 4]);
+        assert_eq!(table, vec![0, 0, 0, 0, 4, 0, 0, 0]);
+        assert_eq!(data, vec![0x1F, 0x00, 0x1F, 0x00, 0x00, 0x7C, 0x00, 0x7C, 0x00, 0x7C]);
+    }
+
+    #[test]
+    fn fades() {
+        let palette = vec![Rgba([200u8, 100, 0, 255]), Rgba([0u8, 0, 0, 0])];
+        let out = fade_palettes(&palette, FadeTarget::Black, 3).unwrap();
+
+        assert_eq!(out, vec![
+            palette.clone(),
+            vec![Rgba([100u8, 50, 0, 255]), Rgba([0u8, 0, 0, 0])],
+            vec![Rgba([0u8, 0, 0, 255]), Rgba([0u8, 0, 0, 0])]
+        ]);
+        assert_eq!(fade_palettes(&palette, FadeTarget::White, 2).unwrap()[1][0], Rgba([255u8, 255, 255, 255]));
+        assert!(fade_palettes(&palette, FadeTarget::White, 1).is_err());
+
+        let mut data = Vec::new();
+        let mut header = Vec::new();
+
+        encode_fade_table(IndexedFormat::AGB4, &mut data, &mut header, "title_fade", &out).unwrap();
+
+        assert_eq!(data.len(), 12);
+        assert_eq!(String::from_utf8(header).unwrap(), "#pragma once\n\n#define TITLE_FADE_COUNT 3\n#define TITLE_FADE_STRIDE 4\n#define TITLE_FADE_SIZE 12\n");
+        assert!(encode_fade_table(IndexedFormat::AGB4, &mut data, &mut Vec::new(), "3fade", &out).is_err());
+        assert!(encode_fade_table(IndexedFormat::AGB4, &mut data, &mut Vec::new(), "fade", &[palette.clone(), palette[..1].to_vec()]).is_err());
+    }
+}
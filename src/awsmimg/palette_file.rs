@@ -0,0 +1,521 @@
+use std::io;
+use std::io::{Read, Write};
+use image::Rgba;
+
+/// Read a whole palette file as text.
+fn read_text<R: Read>(r: &mut R) -> io::Result<String> {
+    let mut data = Vec::new();
+
+    r.read_to_end(&mut data)?;
+
+    match String::from_utf8(data) {
+        Ok(text) => Ok(text),
+        Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "Palette file is not valid text."))
+    }
+}
+
+/// Parse a line of whitespace-separated color channels.
+///
+/// Three channels are read as an opaque color; a fourth, if present, is taken
+/// as alpha. Any other number of channels, or channels beyond 255, are
+/// rejected.
+fn parse_channels<'a, I: Iterator<Item=&'a str>>(fields: I) -> io::Result<Rgba<u8>> {
+    let mut color = Rgba([0, 0, 0, 255]);
+    let mut count = 0;
+
+    for field in fields {
+        if count >= 4 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Palette color has too many channels."));
+        }
+
+        color[count] = match field.parse() {
+            Ok(v) => v,
+            Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Palette color channel is not a number from 0 to 255."))
+        };
+        count += 1;
+    }
+
+    if count < 3 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Palette color has too few channels."));
+    }
+
+    Ok(color)
+}
+
+/// Read a JASC-PAL text palette, as written by Paint Shop Pro, Pro Motion and
+/// many other art tools.
+///
+/// Colors are returned in file order, ready to be passed to encode_palette.
+/// Lines holding a fourth value, as written by some tools, have it read as
+/// alpha; all other colors are opaque. Either line ending is accepted.
+pub fn read_jasc_pal<R: Read>(r: &mut R) -> io::Result<Vec<Rgba<u8>>> {
+    let text = read_text(r)?;
+    let mut lines = text.lines().map(|l| l.trim());
+
+    if lines.next() != Some("JASC-PAL") {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Palette file is not a JASC-PAL file."));
+    }
+
+    if lines.next() != Some("0100") {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "JASC-PAL file version is not supported."));
+    }
+
+    let count : usize = match lines.next().map(|l| l.parse()) {
+        Some(Ok(count)) => count,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "JASC-PAL color count is malformed."))
+    };
+
+    let palette = lines.filter(|l| !l.is_empty()).take(count).map(|l| parse_channels(l.split_whitespace())).collect::<io::Result<Vec<_>>>()?;
+
+    if palette.len() < count {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "JASC-PAL file ends before its last color."));
+    }
+
+    Ok(palette)
+}
+
+/// Write a palette as a JASC-PAL text palette.
+///
+/// Alpha is not written, as most tools reading these files do not expect it.
+/// Lines end in CRLF, as Paint Shop Pro writes them.
+pub fn write_jasc_pal<W: Write>(w: &mut W, palette: &[Rgba<u8>]) -> io::Result<()> {
+    let mut out = format!("JASC-PAL\r\n0100\r\n{}\r\n", palette.len());
+
+    for color in palette {
+        out.push_str(&format!("{} {} {}\r\n", color[0], color[1], color[2]));
+    }
+
+    w.write_all(out.as_bytes())
+}
+
+/// A color of a GIMP palette, with its name.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GimpPaletteEntry {
+    pub color: Rgba<u8>,
+    pub name: String
+}
+
+/// A GIMP palette, as read from or written to a .gpl file.
+///
+/// Besides its colors, a GIMP palette carries a name for itself and for each
+/// of its colors, and optionally the number of columns to display it in.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GimpPalette {
+    pub name: String,
+    pub columns: Option<u32>,
+    pub entries: Vec<GimpPaletteEntry>
+}
+
+impl GimpPalette {
+    /// Create an unnamed GIMP palette from a list of colors, naming each
+    /// color "Untitled" as GIMP does.
+    pub fn from_colors(palette: &[Rgba<u8>]) -> GimpPalette {
+        GimpPalette {
+            name: String::new(),
+            columns: None,
+            entries: palette.iter().map(|c| GimpPaletteEntry { color: *c, name: "Untitled".to_string() }).collect()
+        }
+    }
+
+    /// The palette's colors in file order, ready to be passed to
+    /// encode_palette.
+    pub fn colors(&self) -> Vec<Rgba<u8>> {
+        self.entries.iter().map(|e| e.color).collect()
+    }
+}
+
+/// Read a GIMP .gpl palette.
+///
+/// Comments and blank lines are skipped. Palettes with a "Channels: RGBA"
+/// header have four values per color, the last read as alpha; otherwise
+/// colors are opaque. Whatever follows a color's values is its name.
+pub fn read_gimp_gpl<R: Read>(r: &mut R) -> io::Result<GimpPalette> {
+    let text = read_text(r)?;
+    let mut lines = text.lines();
+
+    if lines.next().map(|l| l.trim()) != Some("GIMP Palette") {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Palette file is not a GIMP palette."));
+    }
+
+    let mut palette = GimpPalette { name: String::new(), columns: None, entries: Vec::new() };
+    let mut channels = 3;
+
+    for line in lines {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix("Name:") {
+            palette.name = name.trim().to_string();
+            continue;
+        }
+
+        if let Some(columns) = line.strip_prefix("Columns:") {
+            palette.columns = columns.trim().parse().ok().filter(|c| *c > 0);
+            continue;
+        }
+
+        if let Some(format) = line.strip_prefix("Channels:") {
+            channels = match format.trim() {
+                "RGB" => 3,
+                "RGBA" => 4,
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "GIMP palette channel layout is not supported."))
+            };
+            continue;
+        }
+
+        //Values are separated by whitespace, and the name is everything after
+        //the last of them.
+        let mut rest = line;
+        let mut values = Vec::with_capacity(channels);
+
+        for _ in 0..channels {
+            let start = rest.trim_start();
+            let end = start.find(char::is_whitespace).unwrap_or(start.len());
+
+            values.push(&start[..end]);
+            rest = &start[end..];
+        }
+
+        palette.entries.push(GimpPaletteEntry {
+            color: parse_channels(values.into_iter().filter(|v| !v.is_empty()))?,
+            name: rest.trim().to_string()
+        });
+    }
+
+    Ok(palette)
+}
+
+/// Write a GIMP .gpl palette.
+///
+/// Palettes holding any color that is not fully opaque are written with a
+/// "Channels: RGBA" header so that alpha is kept; others are written as plain
+/// RGB palettes, readable by any version of GIMP.
+pub fn write_gimp_gpl<W: Write>(w: &mut W, palette: &GimpPalette) -> io::Result<()> {
+    let alpha = palette.entries.iter().any(|e| e.color[3] != 255);
+    let mut out = format!("GIMP Palette\nName: {}\n", palette.name);
+
+    if let Some(columns) = palette.columns {
+        out.push_str(&format!("Columns: {}\n", columns));
+    }
+
+    if alpha {
+        out.push_str("Channels: RGBA\n");
+    }
+
+    out.push_str("#\n");
+
+    for entry in &palette.entries {
+        let c = entry.color;
+
+        out.push_str(&format!("{:3} {:3} {:3}", c[0], c[1], c[2]));
+
+        if alpha {
+            out.push_str(&format!(" {:3}", c[3]));
+        }
+
+        out.push_str(&format!("\t{}\n", entry.name));
+    }
+
+    w.write_all(out.as_bytes())
+}
+
+/// Read an Adobe Color Table (.act) palette, as exported by Photoshop and
+/// Aseprite.
+///
+/// ACT files hold 256 RGB colors, optionally followed by a footer giving the
+/// number of colors actually used and the index of the transparent color.
+/// Without a footer, all 256 colors are returned. The transparent color, if
+/// any, is returned fully transparent; all others are opaque.
+pub fn read_adobe_act<R: Read>(r: &mut R) -> io::Result<Vec<Rgba<u8>>> {
+    let mut data = Vec::new();
+
+    r.read_to_end(&mut data)?;
+
+    let (count, transparent) = match data.len() {
+        768 => (256, None),
+        772 => {
+            let count = (data[768] as usize) << 8 | data[769] as usize;
+            let transparent = (data[770] as usize) << 8 | data[771] as usize;
+
+            if count > 256 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "ACT color count is out of range."));
+            }
+
+            (count, Some(transparent))
+        },
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "ACT files must be 768 or 772 bytes long."))
+    };
+
+    Ok(data[..count * 3].chunks(3).enumerate().map(|(i, c)| {
+        Rgba([c[0], c[1], c[2], if Some(i) == transparent { 0 } else { 255 }])
+    }).collect())
+}
+
+/// Write a palette as an Adobe Color Table (.act) palette.
+///
+/// The footer is always written, so that palettes of fewer than 256 colors
+/// keep their length. The first fully transparent color, if any, is recorded
+/// as the transparent color; alpha is otherwise not kept.
+pub fn write_adobe_act<W: Write>(w: &mut W, palette: &[Rgba<u8>]) -> io::Result<()> {
+    if palette.len() > 256 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "ACT palettes hold at most 256 colors."));
+    }
+
+    let mut out = vec![0u8; 772];
+
+    for (slot, color) in out.chunks_mut(3).zip(palette.iter()) {
+        slot.copy_from_slice(&color.data[..3]);
+    }
+
+    let transparent = palette.iter().position(|c| c[3] == 0).unwrap_or(0xFFFF);
+
+    out[768] = (palette.len() >> 8) as u8;
+    out[769] = (palette.len() & 0xFF) as u8;
+    out[770] = (transparent >> 8) as u8;
+    out[771] = (transparent & 0xFF) as u8;
+
+    w.write_all(&out)
+}
+
+/// Read a Microsoft RIFF palette (.pal), as written by Windows art tools.
+///
+/// The palette is taken from the file's data chunk; other chunks are skipped.
+/// Per-color flags are ignored and all colors are returned opaque.
+pub fn read_riff_pal<R: Read>(r: &mut R) -> io::Result<Vec<Rgba<u8>>> {
+    let mut data = Vec::new();
+
+    r.read_to_end(&mut data)?;
+
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"PAL " {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Palette file is not a RIFF palette."));
+    }
+
+    let le32 = |b: &[u8]| b[0] as usize | (b[1] as usize) << 8 | (b[2] as usize) << 16 | (b[3] as usize) << 24;
+    let mut pos = 12;
+
+    while pos + 8 <= data.len() {
+        let size = le32(&data[pos + 4..pos + 8]);
+        let body = pos + 8;
+
+        if body + size > data.len() {
+            break;
+        }
+
+        if &data[pos..pos + 4] == b"data" {
+            if size < 4 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "RIFF palette data chunk is malformed."));
+            }
+
+            let count = data[body + 2] as usize | (data[body + 3] as usize) << 8;
+
+            if 4 + count * 4 > size {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "RIFF palette ends before its last color."));
+            }
+
+            return Ok(data[body + 4..body + 4 + count * 4].chunks(4).map(|c| Rgba([c[0], c[1], c[2], 255])).collect());
+        }
+
+        //Chunks are padded to an even length.
+        pos = body + size + size % 2;
+    }
+
+    Err(io::Error::new(io::ErrorKind::InvalidData, "RIFF palette has no data chunk."))
+}
+
+/// Write a palette as a Microsoft RIFF palette (.pal).
+///
+/// Alpha is not kept, as RIFF palettes have no means to store it.
+pub fn write_riff_pal<W: Write>(w: &mut W, palette: &[Rgba<u8>]) -> io::Result<()> {
+    if palette.len() > 0xFFFF {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "RIFF palettes hold at most 65535 colors."));
+    }
+
+    let data_size = 4 + palette.len() as u32 * 4;
+    let mut out = Vec::with_capacity(data_size as usize + 20);
+
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(data_size + 12).to_le_bytes());
+    out.extend_from_slice(b"PAL data");
+    out.extend_from_slice(&data_size.to_le_bytes());
+    out.extend_from_slice(&0x0300u16.to_le_bytes());
+    out.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+
+    for color in palette {
+        out.extend_from_slice(&[color[0], color[1], color[2], 0]);
+    }
+
+    w.write_all(&out)
+}
+
+/// Read a color table mapping input colors to the palette indexes they must
+/// be converted to, for source art drawn with key colors.
+///
+/// Each line holds an index, an equals sign and the color's channels, such as
+/// "0 = 255 0 255" to put magenta at index zero. A fourth channel is read as
+/// alpha; otherwise colors are opaque. Anything after a '#' is a comment, and
+/// blank lines are skipped. Entries are returned in file order, as
+/// quantize_with_color_table expects.
+pub fn read_color_table<R: Read>(r: &mut R) -> io::Result<Vec<(usize, Rgba<u8>)>> {
+    let text = read_text(r)?;
+    let mut table = Vec::new();
+
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let (index, channels) = match line.find('=') {
+            Some(split) => (&line[..split], &line[split + 1..]),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "Color table entry is not of the form index = color."))
+        };
+        let index = match index.trim().parse() {
+            Ok(i) => i,
+            Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Color table index is not a number."))
+        };
+
+        table.push((index, parse_channels(channels.split_whitespace())?));
+    }
+
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use image::Rgba;
+    use awsmimg::palette_file::{read_jasc_pal, write_jasc_pal, GimpPalette, GimpPaletteEntry, read_gimp_gpl, write_gimp_gpl, read_adobe_act, write_adobe_act, read_riff_pal, write_riff_pal, read_color_table};
+
+    #[test]
+    fn jasc_pal_roundtrip() {
+        let palette = vec![Rgba([0u8, 0, 0, 255]), Rgba([255u8, 128, 7, 255])];
+        let mut out = Vec::new();
+
+        write_jasc_pal(&mut out, &palette).unwrap();
+
+        assert_eq!(out, b"JASC-PAL\r\n0100\r\n2\r\n0 0 0\r\n255 128 7\r\n".to_vec());
+        assert_eq!(read_jasc_pal(&mut &out[..]).unwrap(), palette);
+    }
+
+    #[test]
+    fn jasc_pal_parsing() {
+        let unix = b"JASC-PAL\n0100\n2\n1 2 3\n4 5 6 0\n".to_vec();
+
+        assert_eq!(read_jasc_pal(&mut &unix[..]).unwrap(), vec![Rgba([1u8, 2, 3, 255]), Rgba([4u8, 5, 6, 0])]);
+        assert!(read_jasc_pal(&mut &b"JASC-PAL\n0100\n2\n1 2 3\n"[..]).is_err());
+        assert!(read_jasc_pal(&mut &b"JASC-PAL\n0100\n1\n1 2 300\n"[..]).is_err());
+        assert!(read_jasc_pal(&mut &b"JASC-PAL\n0100\n1\n1 2\n"[..]).is_err());
+        assert!(read_jasc_pal(&mut &b"GIMP Palette\n"[..]).is_err());
+    }
+
+    #[test]
+    fn gimp_gpl_roundtrip() {
+        let mut palette = GimpPalette::from_colors(&[Rgba([0u8, 0, 0, 255]), Rgba([255u8, 128, 7, 255])]);
+
+        palette.name = "Test".to_string();
+        palette.columns = Some(16);
+        palette.entries[1].name = "Bright orange".to_string();
+
+        let mut out = Vec::new();
+
+        write_gimp_gpl(&mut out, &palette).unwrap();
+
+        assert_eq!(String::from_utf8(out.clone()).unwrap(), "GIMP Palette\nName: Test\nColumns: 16\n#\n  0   0   0\tUntitled\n255 128   7\tBright orange\n");
+        assert_eq!(read_gimp_gpl(&mut &out[..]).unwrap(), palette);
+
+        let translucent = GimpPalette::from_colors(&[Rgba([1u8, 2, 3, 0])]);
+        let mut out = Vec::new();
+
+        write_gimp_gpl(&mut out, &translucent).unwrap();
+
+        assert_eq!(read_gimp_gpl(&mut &out[..]).unwrap(), translucent);
+    }
+
+    #[test]
+    fn gimp_gpl_parsing() {
+        let text = b"GIMP Palette\r\nName: Hand made\r\n# a comment\r\n\r\n1 2 3\r\n 40  50  60   Two  words\r\n".to_vec();
+        let palette = read_gimp_gpl(&mut &text[..]).unwrap();
+
+        assert_eq!(palette.name, "Hand made");
+        assert_eq!(palette.columns, None);
+        assert_eq!(palette.entries, vec![
+            GimpPaletteEntry { color: Rgba([1u8, 2, 3, 255]), name: "".to_string() },
+            GimpPaletteEntry { color: Rgba([40u8, 50, 60, 255]), name: "Two  words".to_string() }
+        ]);
+        assert_eq!(palette.colors(), vec![Rgba([1u8, 2, 3, 255]), Rgba([40u8, 50, 60, 255])]);
+        assert!(read_gimp_gpl(&mut &b"GIMP Palette\n1 2\n"[..]).is_err());
+        assert!(read_gimp_gpl(&mut &b"JASC-PAL\n"[..]).is_err());
+    }
+
+    #[test]
+    fn adobe_act_roundtrip() {
+        let palette = vec![Rgba([0u8, 0, 0, 255]), Rgba([9u8, 8, 7, 0]), Rgba([255u8, 128, 7, 255])];
+        let mut out = Vec::new();
+
+        write_adobe_act(&mut out, &palette).unwrap();
+
+        assert_eq!(out.len(), 772);
+        assert_eq!(&out[3..9], &[9, 8, 7, 255, 128, 7]);
+        assert_eq!(&out[768..], &[0, 3, 0, 1]);
+        assert_eq!(read_adobe_act(&mut &out[..]).unwrap(), palette);
+        assert!(write_adobe_act(&mut Vec::new(), &vec![Rgba([0u8; 4]); 257]).is_err());
+    }
+
+    #[test]
+    fn adobe_act_parsing() {
+        let mut bare = vec![0u8; 768];
+
+        bare[767] = 255;
+
+        let palette = read_adobe_act(&mut &bare[..]).unwrap();
+
+        assert_eq!(palette.len(), 256);
+        assert_eq!(palette[255], Rgba([0u8, 0, 255, 255]));
+
+        let mut footer = bare.clone();
+
+        footer.extend_from_slice(&[0, 2, 0xFF, 0xFF]);
+
+        assert_eq!(read_adobe_act(&mut &footer[..]).unwrap(), vec![Rgba([0u8, 0, 0, 255]); 2]);
+        assert!(read_adobe_act(&mut &bare[..767]).is_err());
+        footer[768] = 2;
+        assert!(read_adobe_act(&mut &footer[..]).is_err());
+    }
+
+    #[test]
+    fn riff_pal_roundtrip() {
+        let palette = vec![Rgba([0u8, 0, 0, 255]), Rgba([255u8, 128, 7, 255])];
+        let mut out = Vec::new();
+
+        write_riff_pal(&mut out, &palette).unwrap();
+
+        assert_eq!(out, b"RIFF\x18\0\0\0PAL data\x0C\0\0\0\0\x03\x02\0\0\0\0\0\xFF\x80\x07\0".to_vec());
+        assert_eq!(read_riff_pal(&mut &out[..]).unwrap(), palette);
+    }
+
+    #[test]
+    fn riff_pal_parsing() {
+        //Unknown chunks before the data chunk are skipped, padding included.
+        let extra = b"RIFF\x1E\0\0\0PAL junk\x03\0\0\0abc\0data\x08\0\0\0\0\x03\x01\0\x01\x02\x03\x04".to_vec();
+
+        assert_eq!(read_riff_pal(&mut &extra[..]).unwrap(), vec![Rgba([1u8, 2, 3, 255])]);
+        assert!(read_riff_pal(&mut &extra[..extra.len() - 1]).is_err());
+        assert!(read_riff_pal(&mut &b"RIFF\x04\0\0\0WAVE"[..]).is_err());
+    }
+
+    #[test]
+    fn color_table() {
+        let text = "# Keys for the player sprite\n0 = 255 0 255  # magenta\n\n15=255 255 255\n3 = 0 0 0 0\n";
+        let table = read_color_table(&mut text.as_bytes()).unwrap();
+
+        assert_eq!(table, vec![(0, Rgba([255u8, 0, 255, 255])), (15, Rgba([255u8, 255, 255, 255])), (3, Rgba([0u8, 0, 0, 0]))]);
+        assert!(read_color_table(&mut "0 255 0 255\n".as_bytes()).is_err());
+        assert!(read_color_table(&mut "x = 255 0 255\n".as_bytes()).is_err());
+        assert!(read_color_table(&mut "1 = 256 0 0\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use image::{Primitive, Rgba};
+
+/// How a converted image uses the entries of its palette.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PaletteUsage {
+    /// How many pixels use each palette entry.
+    pub counts: Vec<usize>,
+
+    /// Pairs of entries holding the same color, each as the index of the
+    /// first entry with that color and the index of a later one.
+    pub duplicates: Vec<(usize, usize)>,
+
+    /// Entries no pixel uses.
+    pub unused: Vec<usize>,
+
+    /// How many pixels use indexes beyond the end of the palette.
+    pub out_of_range: usize
+}
+
+impl PaletteUsage {
+    /// Whether every palette entry is used and holds a distinct color, so
+    /// that no palette slots are wasted.
+    pub fn is_efficient(&self) -> bool {
+        self.duplicates.is_empty() && self.unused.is_empty()
+    }
+}
+
+/// Given index data and the palette it indexes, report how the palette is
+/// used, so that build scripts can warn about assets wasting palette slots.
+///
+/// Colors are compared exactly, including alpha; colors that differ only by
+/// bits the target hardware discards are not counted as duplicates.
+pub fn palette_usage<S: Primitive>(indexes: &[S], palette: &[Rgba<u8>]) -> PaletteUsage {
+    let mut counts = vec![0; palette.len()];
+    let mut out_of_range = 0;
+
+    for index in indexes {
+        match index.to_usize().and_then(|i| counts.get_mut(i)) {
+            Some(count) => *count += 1,
+            None => out_of_range += 1
+        }
+    }
+
+    let mut duplicates = Vec::new();
+
+    for (later, color) in palette.iter().enumerate() {
+        if let Some(first) = palette[..later].iter().position(|c| c == color) {
+            duplicates.push((first, later));
+        }
+    }
+
+    let unused = counts.iter().enumerate().filter(|(_, c)| **c == 0).map(|(i, _)| i).collect();
+
+    PaletteUsage { counts, duplicates, unused, out_of_range }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use image::Rgba;
+    use awsmimg::palette_usage::palette_usage;
+
+    #[test]
+    fn usage_report() {
+        let (red, blue) = (Rgba([255u8, 0, 0, 255]), Rgba([0u8, 0, 255, 255]));
+        let palette = vec![red, blue, red, Rgba([0u8, 255, 0, 255]), blue];
+        let usage = palette_usage(&[0u8, 0, 1, 4, 4, 4, 7], &palette);
+
+        assert_eq!(usage.counts, vec![2, 1, 0, 0, 3]);
+        assert_eq!(usage.duplicates, vec![(0, 2), (1, 4)]);
+        assert_eq!(usage.unused, vec![2, 3]);
+        assert_eq!(usage.out_of_range, 1);
+        assert!(!usage.is_efficient());
+        assert!(palette_usage(&[0u16, 1], &palette[..2]).is_efficient());
+    }
+}
@@ -0,0 +1,1282 @@
+use std::io;
+use std::cmp::{Ordering, Reverse};
+use image::{GenericImage, ImageBuffer, Pixel, Primitive, Rgba};
+use awsmimg::indexed_png::IndexImage;
+
+/// Algorithms for choosing a limited palette to represent an image.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum QuantizeMethod {
+    MedianCut, //Repeatedly split the box of colors with the widest range at its median
+    Octree     //Merge the least significant branches of an octree of colors
+}
+
+/// Read every pixel of an image as 8-bit RGBA.
+fn image_colors<I, P, S>(image: &I) -> Vec<Rgba<u8>>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    let imgmax = S::max_value().to_f32().unwrap();
+
+    image.pixels().map(|(_, _, pixel)| {
+        let rgba = pixel.to_rgba();
+        let mut out = Rgba([0u8; 4]);
+
+        for i in 0..4 {
+            out[i] = (rgba[i].to_f32().unwrap() / imgmax * 255f32) as u8;
+        }
+
+        out
+    }).collect()
+}
+
+/// Average a set of colors, as an opaque color.
+fn average(colors: &[[u8; 3]]) -> Rgba<u8> {
+    let mut sum = [0u64; 3];
+
+    for color in colors {
+        for (sum, c) in sum.iter_mut().zip(color.iter()) {
+            *sum += *c as u64;
+        }
+    }
+
+    let n = colors.len().max(1) as u64;
+
+    Rgba([(sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8, 255])
+}
+
+fn median_cut(colors: Vec<[u8; 3]>, count: usize) -> Vec<Rgba<u8>> {
+    let range = |b: &[[u8; 3]], c: usize| {
+        let (lo, hi) = b.iter().fold((255u8, 0u8), |(lo, hi), color| (lo.min(color[c]), hi.max(color[c])));
+        hi.saturating_sub(lo)
+    };
+
+    let mut boxes = vec![colors];
+
+    while boxes.len() < count {
+        //Split the box whose widest channel is widest of all.
+        let widest = boxes.iter().enumerate()
+            .map(|(i, b)| (i, (0..3).map(|c| range(b, c)).max().unwrap_or(0)))
+            .max_by_key(|&(_, r)| r);
+
+        let (i, r) = match widest {
+            Some(w) => w,
+            None => break
+        };
+
+        if r == 0 {
+            break;
+        }
+
+        let mut b = boxes.swap_remove(i);
+        let channel = (0..3).max_by_key(|&c| range(&b, c)).unwrap();
+
+        b.sort_unstable_by_key(|color| color[channel]);
+
+        //Split at the median, moved to the nearest change in value so that
+        //identical colors never end up in both halves.
+        let median = b[b.len() / 2][channel];
+        let split = match b.iter().position(|color| color[channel] == median) {
+            Some(0) | None => b.iter().position(|color| color[channel] > median).unwrap_or(b.len()),
+            Some(first) => first
+        };
+        let upper = b.split_off(split);
+
+        boxes.push(b);
+        boxes.push(upper);
+    }
+
+    boxes.iter().filter(|b| !b.is_empty()).map(|b| average(b)).collect()
+}
+
+struct OctreeNode {
+    children: [Option<usize>; 8],
+    sum: [u64; 3],
+    count: u64
+}
+
+impl OctreeNode {
+    fn new() -> OctreeNode {
+        OctreeNode {
+            children: [None; 8],
+            sum: [0; 3],
+            count: 0
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.iter().all(|c| c.is_none())
+    }
+}
+
+fn octree(colors: Vec<[u8; 3]>, count: usize) -> Vec<Rgba<u8>> {
+    const DEPTH: usize = 8;
+
+    let mut nodes = vec![OctreeNode::new()];
+    let mut levels: Vec<Vec<usize>> = vec![Vec::new(); DEPTH];
+    let mut leaves = 0;
+
+    for color in colors {
+        let mut node = 0;
+
+        for (level, nodes_at_level) in levels.iter_mut().enumerate() {
+            let shift = 7 - level;
+            let octant = ((color[0] >> shift) & 1) << 2 | ((color[1] >> shift) & 1) << 1 | ((color[2] >> shift) & 1);
+
+            node = match nodes[node].children[octant as usize] {
+                Some(child) => child,
+                None => {
+                    let child = nodes.len();
+
+                    nodes.push(OctreeNode::new());
+                    nodes[node].children[octant as usize] = Some(child);
+                    nodes_at_level.push(child);
+
+                    if level == DEPTH - 1 {
+                        leaves += 1;
+                    }
+
+                    child
+                }
+            };
+        }
+
+        for (sum, c) in nodes[node].sum.iter_mut().zip(color.iter()) {
+            *sum += *c as u64;
+        }
+
+        nodes[node].count += 1;
+    }
+
+    //Fold the children of the deepest branches into their parents until few
+    //enough leaves remain. Branches holding fewer pixels are folded first, so
+    //that detail is kept where most of the image is.
+    for level in (0..DEPTH - 1).rev() {
+        let mut branches: Vec<usize> = levels[level].clone();
+
+        branches.sort_by_key(|&n| subtree_count(&nodes, n));
+
+        for node in branches {
+            if leaves <= count {
+                break;
+            }
+
+            let children: Vec<usize> = nodes[node].children.iter().filter_map(|c| *c).collect();
+
+            for child in &children {
+                for c in 0..3 {
+                    nodes[node].sum[c] += nodes[*child].sum[c];
+                }
+
+                nodes[node].count += nodes[*child].count;
+            }
+
+            nodes[node].children = [None; 8];
+            leaves -= children.len() - 1;
+        }
+    }
+
+    //Folding can't go past the root's eight children, so for fewer colors
+    //than that, merge the least-populated of them into each other.
+    if leaves > count {
+        let mut octants: Vec<usize> = nodes[0].children.iter().filter_map(|c| *c).collect();
+
+        while octants.len() > count.max(1) {
+            octants.sort_by_key(|&n| nodes[n].count);
+
+            let (smallest, into) = (octants.remove(0), octants[0]);
+
+            for c in 0..3 {
+                nodes[into].sum[c] += nodes[smallest].sum[c];
+            }
+
+            nodes[into].count += nodes[smallest].count;
+
+            for child in nodes[0].children.iter_mut() {
+                if *child == Some(smallest) {
+                    *child = None;
+                }
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut stack = vec![0];
+
+    while let Some(n) = stack.pop() {
+        if n != 0 && nodes[n].is_leaf() {
+            let count = nodes[n].count.max(1);
+            let sum = nodes[n].sum;
+
+            out.push(Rgba([(sum[0] / count) as u8, (sum[1] / count) as u8, (sum[2] / count) as u8, 255]));
+        } else {
+            stack.extend(nodes[n].children.iter().rev().filter_map(|c| *c));
+        }
+    }
+
+    out
+}
+
+/// Count the pixels in every leaf below an octree node.
+fn subtree_count(nodes: &[OctreeNode], n: usize) -> u64 {
+    match nodes[n].is_leaf() {
+        true => nodes[n].count,
+        false => nodes[n].children.iter().filter_map(|c| *c).map(|c| subtree_count(nodes, c)).sum()
+    }
+}
+
+/// Given an image, choose a palette of at most the given number of colors to
+/// represent it with the given method.
+///
+/// If the image has any fully transparent pixels, the first color of the
+/// palette is reserved for them and is fully transparent, as on most hardware
+/// index zero is. All other colors of the palette are opaque; partial alpha is
+/// ignored.
+pub fn palette_from_image<I, P, S>(image: &I, count: usize, method: QuantizeMethod) -> Vec<Rgba<u8>>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    palette_from_colors(&image_colors(image), count, method)
+}
+
+/// Choose a palette for a set of 8-bit RGBA pixels, as palette_from_image.
+fn palette_from_colors(pixels: &[Rgba<u8>], count: usize, method: QuantizeMethod) -> Vec<Rgba<u8>> {
+    let mut palette = Vec::new();
+
+    if pixels.iter().any(|p| p[3] == 0) && count > 0 {
+        palette.push(Rgba([0, 0, 0, 0]));
+    }
+
+    let opaque: Vec<[u8; 3]> = pixels.iter().filter(|p| p[3] != 0).map(|p| [p[0], p[1], p[2]]).collect();
+    let remaining = count - palette.len();
+
+    if opaque.is_empty() || remaining == 0 {
+        return palette;
+    }
+
+    palette.extend(match method {
+        QuantizeMethod::MedianCut => median_cut(opaque, remaining),
+        QuantizeMethod::Octree => octree(opaque, remaining)
+    });
+
+    palette
+}
+
+/// Ways of measuring how different two colors are, for matching colors to a
+/// palette.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ColorMetric {
+    RGB,      //Euclidean distance between sRGB values
+    OKLab,    //Euclidean distance in the OKLab perceptual color space
+    CIEDE2000 //The CIE's 2000 color difference formula, over CIELAB
+}
+
+/// Convert an sRGB channel to linear light.
+fn linear(c: u8) -> f64 {
+    let c = c as f64 / 255.0;
+
+    match c <= 0.04045 {
+        true => c / 12.92,
+        false => ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn oklab(c: Rgba<u8>) -> [f64; 3] {
+    let (r, g, b) = (linear(c[0]), linear(c[1]), linear(c[2]));
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+
+    [0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+     1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+     0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s]
+}
+
+/// Convert an sRGB color to CIELAB, relative to the D65 white point.
+fn cielab(c: Rgba<u8>) -> [f64; 3] {
+    let (r, g, b) = (linear(c[0]), linear(c[1]), linear(c[2]));
+    let xyz = [(0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047,
+                0.2126729 * r + 0.7151522 * g + 0.0721750 * b,
+               (0.0193339 * r + 0.1191920 * g + 0.9503041 * b) / 1.08883];
+    let f = |t: f64| match t > 216.0 / 24389.0 {
+        true => t.cbrt(),
+        false => (24389.0 / 27.0 * t + 16.0) / 116.0
+    };
+    let (fx, fy, fz) = (f(xyz[0]), f(xyz[1]), f(xyz[2]));
+
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// The CIEDE2000 difference between two CIELAB colors.
+fn ciede2000(lab1: [f64; 3], lab2: [f64; 3]) -> f64 {
+    let [l1, a1, b1] = lab1;
+    let [l2, a2, b2] = lab2;
+    let pow7 = |x: f64| x.powi(7);
+    let cbar = ((a1 * a1 + b1 * b1).sqrt() + (a2 * a2 + b2 * b2).sqrt()) / 2.0;
+    let g = 0.5 * (1.0 - (pow7(cbar) / (pow7(cbar) + pow7(25.0))).sqrt());
+    let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+    let (c1, c2) = ((a1 * a1 + b1 * b1).sqrt(), (a2 * a2 + b2 * b2).sqrt());
+    let hue = |b: f64, a: f64| match a == 0.0 && b == 0.0 {
+        true => 0.0,
+        false => b.atan2(a).to_degrees().rem_euclid(360.0)
+    };
+    let (h1, h2) = (hue(b1, a1), hue(b2, a2));
+
+    let dl = l2 - l1;
+    let dc = c2 - c1;
+    let dh = match c1 * c2 == 0.0 {
+        true => 0.0,
+        false if (h2 - h1).abs() <= 180.0 => h2 - h1,
+        false if h2 - h1 > 180.0 => h2 - h1 - 360.0,
+        false => h2 - h1 + 360.0
+    };
+    let dh = 2.0 * (c1 * c2).sqrt() * (dh / 2.0).to_radians().sin();
+
+    let lbar = (l1 + l2) / 2.0;
+    let cbar = (c1 + c2) / 2.0;
+    let hbar = match c1 * c2 == 0.0 {
+        true => h1 + h2,
+        false if (h1 - h2).abs() <= 180.0 => (h1 + h2) / 2.0,
+        false if h1 + h2 < 360.0 => (h1 + h2 + 360.0) / 2.0,
+        false => (h1 + h2 - 360.0) / 2.0
+    };
+    let cos = |deg: f64| deg.to_radians().cos();
+    let t = 1.0 - 0.17 * cos(hbar - 30.0) + 0.24 * cos(2.0 * hbar) + 0.32 * cos(3.0 * hbar + 6.0) - 0.20 * cos(4.0 * hbar - 63.0);
+    let dtheta = 30.0 * (-((hbar - 275.0) / 25.0).powi(2)).exp();
+    let rc = 2.0 * (pow7(cbar) / (pow7(cbar) + pow7(25.0))).sqrt();
+    let sl = 1.0 + 0.015 * (lbar - 50.0).powi(2) / (20.0 + (lbar - 50.0).powi(2)).sqrt();
+    let sc = 1.0 + 0.045 * cbar;
+    let sh = 1.0 + 0.015 * cbar * t;
+    let rt = -(2.0 * dtheta).to_radians().sin() * rc;
+
+    ((dl / sl).powi(2) + (dc / sc).powi(2) + (dh / sh).powi(2) + rt * (dc / sc) * (dh / sh)).sqrt()
+}
+
+impl ColorMetric {
+    /// Measure the difference between two colors, ignoring alpha.
+    ///
+    /// Differences are only comparable with others measured by the same
+    /// metric; RGB differences are squared, for speed.
+    pub fn distance(self, a: Rgba<u8>, b: Rgba<u8>) -> f64 {
+        match self {
+            ColorMetric::RGB => (0..3).map(|c| (a[c] as f64 - b[c] as f64).powi(2)).sum(),
+            ColorMetric::OKLab => {
+                let (a, b) = (oklab(a), oklab(b));
+
+                (0..3).map(|c| (a[c] - b[c]).powi(2)).sum::<f64>().sqrt()
+            },
+            ColorMetric::CIEDE2000 => ciede2000(cielab(a), cielab(b))
+        }
+    }
+}
+
+/// Find the index of the color in a palette nearest to the given color.
+///
+/// Fully transparent colors match the first fully transparent palette color,
+/// if there is one. Otherwise, distance is measured in RGB space and
+/// transparent palette colors are never matched.
+pub fn nearest_color(palette: &[Rgba<u8>], color: Rgba<u8>) -> Option<usize> {
+    nearest_color_with_metric(palette, color, ColorMetric::RGB)
+}
+
+/// Find the index of the color in a palette nearest to the given color, as
+/// nearest_color does, measuring distance with the given metric.
+pub fn nearest_color_with_metric(palette: &[Rgba<u8>], color: Rgba<u8>, metric: ColorMetric) -> Option<usize> {
+    if color[3] == 0 {
+        if let Some(i) = palette.iter().position(|p| p[3] == 0) {
+            return Some(i);
+        }
+    }
+
+    palette.iter().enumerate().filter(|(_, p)| p[3] != 0).map(|(i, p)| (i, metric.distance(*p, color)))
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+        .map(|(i, _)| i)
+}
+
+/// Map each of a row-major run of pixels to its nearest palette color,
+/// diffusing the error of each match onto its neighbours.
+///
+/// Error is spread Floyd-Steinberg style, scaled by strength: zero maps each
+/// pixel independently and one diffuses the whole error. Transparent pixels
+/// neither receive nor spread error.
+fn dither(pixels: &[Rgba<u8>], width: u32, palette: &[Rgba<u8>], strength: f32, metric: ColorMetric) -> Vec<u8> {
+    let width = width as usize;
+    let mut error = vec![[0f32; 3]; pixels.len()];
+    let mut out = vec![0u8; pixels.len()];
+
+    for (i, pixel) in pixels.iter().enumerate() {
+        let mut wanted = *pixel;
+
+        if pixel[3] != 0 {
+            for (c, e) in error[i].iter().enumerate() {
+                wanted[c] = (pixel[c] as f32 + e).round().clamp(0f32, 255f32) as u8;
+            }
+        }
+
+        let index = nearest_color_with_metric(palette, wanted, metric).unwrap_or(0);
+
+        out[i] = index as u8;
+
+        if pixel[3] == 0 || strength == 0f32 {
+            continue;
+        }
+
+        let chosen = palette[index];
+        let (x, y) = (i % width, i / width);
+        let mut spread = |dx: isize, dy: usize, weight: f32| {
+            let tx = x as isize + dx;
+
+            if tx < 0 || tx as usize >= width {
+                return;
+            }
+
+            let target = (y + dy) * width + tx as usize;
+
+            if target >= pixels.len() || pixels[target][3] == 0 {
+                return;
+            }
+
+            for c in 0..3 {
+                error[target][c] += (wanted[c] as f32 - chosen[c] as f32) * weight * strength;
+            }
+        };
+
+        spread(1, 0, 7f32 / 16f32);
+        spread(-1, 1, 3f32 / 16f32);
+        spread(0, 1, 5f32 / 16f32);
+        spread(1, 1, 1f32 / 16f32);
+    }
+
+    out
+}
+
+/// Rearrange row-major index data into tile order.
+fn tile_order(indexes: &[u8], dimensions: (u32, u32), tsize: (u32, u32)) -> io::Result<Vec<u8>> {
+    let (width, height) = dimensions;
+    let (tw, th) = tsize;
+
+    if tw == 0 || th == 0 || !width.is_multiple_of(tw) || !height.is_multiple_of(th) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image size is not a multiple of the tile size."));
+    }
+
+    let mut out = vec![0u8; indexes.len()];
+
+    for (i, index) in indexes.iter().enumerate() {
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        let tile = (y / th) * (width / tw) + x / tw;
+        let outidx = (tile * tw * th + (y % th) * tw + x % tw) as usize;
+
+        out[outidx] = *index;
+    }
+
+    Ok(out)
+}
+
+/// Given an image, choose a palette for it with the given method and map each
+/// pixel to its nearest palette color.
+///
+/// Index data is returned in the same tile order as indexes_from_luma
+/// produces, ready to be encoded; the image's dimensions must be multiples of
+/// the tile size, and count may be at most 256.
+pub fn quantize<I, P, S>(image: &I, count: usize, method: QuantizeMethod, tsize: (u32, u32)) -> io::Result<(Vec<Rgba<u8>>, Vec<u8>)>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    quantize_dithered(image, count, method, 0f32, tsize)
+}
+
+/// Quantize an image as quantize does, dithering it with error diffusion.
+///
+/// Strength scales how much of each pixel's error is passed on, from 0.0
+/// (none, as quantize) to 1.0 (full Floyd-Steinberg). Smaller strengths trade
+/// smoother gradients for less noise in flat areas.
+pub fn quantize_dithered<I, P, S>(image: &I, count: usize, method: QuantizeMethod, strength: f32, tsize: (u32, u32)) -> io::Result<(Vec<Rgba<u8>>, Vec<u8>)>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    quantize_with_metric(image, count, method, ColorMetric::RGB, strength, tsize)
+}
+
+/// Quantize an image as quantize_dithered does, matching pixels to the
+/// chosen palette with the given metric.
+///
+/// Palettes are still chosen in RGB space; the metric decides only which
+/// palette color each pixel is given. Perceptual metrics are slower, but
+/// match more pleasingly when the palette is coarse.
+pub fn quantize_with_metric<I, P, S>(image: &I, count: usize, method: QuantizeMethod, metric: ColorMetric, strength: f32, tsize: (u32, u32)) -> io::Result<(Vec<Rgba<u8>>, Vec<u8>)>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    if count > 256 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Palettes hold at most 256 colors."));
+    }
+
+    let palette = palette_from_image(image, count, method);
+    let (width, height) = image.dimensions();
+    let indexes = dither(&image_colors(image), width, &palette, strength, metric);
+
+    Ok((palette, tile_order(&indexes, (width, height), tsize)?))
+}
+
+/// Quantize an image as quantize does, keeping some palette entries fixed.
+///
+/// Each reserved entry gives a palette index and the color it must hold,
+/// such as transparency at index zero or interface colors shared with other
+/// graphics. The remaining entries are chosen to suit the rest of the image,
+/// and fill the free indexes from lowest to highest. Pixels may be mapped to
+/// reserved colors as well as chosen ones. Free indexes below the highest
+/// reserved index that are left over are filled with opaque black, but never
+/// used.
+pub fn quantize_with_reserved<I, P, S>(image: &I, count: usize, method: QuantizeMethod, reserved: &[(usize, Rgba<u8>)], tsize: (u32, u32)) -> io::Result<(Vec<Rgba<u8>>, Vec<u8>)>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    if count > 256 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Palettes hold at most 256 colors."));
+    }
+
+    let mut slots : Vec<Option<Rgba<u8>>> = vec![None; count];
+
+    for (index, color) in reserved {
+        match slots.get_mut(*index) {
+            Some(slot @ &mut None) => *slot = Some(*color),
+            Some(_) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Palette index is reserved more than once.")),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Reserved palette index is beyond the end of the palette."))
+        }
+    }
+
+    //Colors are only chosen for pixels the reserved colors don't already
+    //cover exactly.
+    let pixels = image_colors(image);
+    let reserves_transparency = reserved.iter().any(|(_, c)| c[3] == 0);
+    let uncovered : Vec<Rgba<u8>> = pixels.iter().filter(|p| match p[3] == 0 {
+        true => !reserves_transparency,
+        false => !reserved.iter().any(|(_, c)| c[3] != 0 && c[0] == p[0] && c[1] == p[1] && c[2] == p[2])
+    }).cloned().collect();
+    let mut chosen = palette_from_colors(&uncovered, count - reserved.len(), method).into_iter();
+
+    for slot in slots.iter_mut().filter(|s| s.is_none()) {
+        match chosen.next() {
+            Some(color) => *slot = Some(color),
+            None => break
+        }
+    }
+
+    let used = slots.iter().rposition(|s| s.is_some()).map_or(0, |i| i + 1);
+    let candidates : Vec<(usize, Rgba<u8>)> = slots[..used].iter().enumerate().filter_map(|(i, s)| s.map(|c| (i, c))).collect();
+    let colors : Vec<Rgba<u8>> = candidates.iter().map(|(_, c)| *c).collect();
+    let (width, height) = image.dimensions();
+    let indexes : Vec<u8> = pixels.iter().map(|p| match reserved.iter().find(|(_, c)| same_color(*c, *p)) {
+        Some((index, _)) => *index as u8,
+        None => candidates[nearest_color(&colors, *p).unwrap_or(0)].0 as u8
+    }).collect();
+    let palette = slots[..used].iter().map(|s| s.unwrap_or(Rgba([0, 0, 0, 255]))).collect();
+
+    Ok((palette, tile_order(&indexes, (width, height), tsize)?))
+}
+
+/// Whether two colors look the same: both fully transparent, or both not
+/// and with the same RGB values.
+fn same_color(a: Rgba<u8>, b: Rgba<u8>) -> bool {
+    match (a[3] == 0, b[3] == 0) {
+        (true, true) => true,
+        (false, false) => a[0] == b[0] && a[1] == b[1] && a[2] == b[2],
+        _ => false
+    }
+}
+
+/// Quantize color-keyed art, converting each color listed in a color table
+/// to the index it is listed with, as quantize_with_reserved does.
+///
+/// The table is checked against the image first, so that mistakes in it are
+/// caught rather than silently quantized: every listed color must appear in
+/// the image, and no color may be listed at two indexes. Colors are matched
+/// by RGB alone, except that transparent colors match every fully
+/// transparent pixel.
+pub fn quantize_with_color_table<I, P, S>(image: &I, count: usize, method: QuantizeMethod, table: &[(usize, Rgba<u8>)], tsize: (u32, u32)) -> io::Result<(Vec<Rgba<u8>>, Vec<u8>)>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    let pixels = image_colors(image);
+
+    for (i, (index, color)) in table.iter().enumerate() {
+        if table[..i].iter().any(|(other, c)| other != index && same_color(*c, *color)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Color table lists color {:?} at more than one index.", color.data)));
+        }
+
+        if !pixels.iter().any(|p| same_color(*p, *color)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Color table lists color {:?}, which does not appear in the image.", color.data)));
+        }
+    }
+
+    quantize_with_reserved(image, count, method, table, tsize)
+}
+
+/// Given several images, choose one palette of at most the given number of
+/// colors to represent all of them with the given method.
+///
+/// Every pixel of every image is weighed equally, so larger images have a
+/// greater say in the palette. Transparency is handled as palette_from_image
+/// does: the first color is reserved for it if any image has transparent
+/// pixels.
+pub fn palette_from_images<I, P, S>(images: &[&I], count: usize, method: QuantizeMethod) -> Vec<Rgba<u8>>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    let pixels : Vec<Rgba<u8>> = images.iter().flat_map(|i| image_colors(*i)).collect();
+
+    palette_from_colors(&pixels, count, method)
+}
+
+/// A palette shared by several images, and each image's index data.
+pub type SharedQuantization = (Vec<Rgba<u8>>, Vec<Vec<u8>>);
+
+/// Given several images, such as the frames of an animation, choose one
+/// palette for all of them and map each image's pixels to it.
+///
+/// Index data for each image is returned in tile order, as with quantize, so
+/// that every image can be encoded against the same palette. Each image's
+/// dimensions must be multiples of the tile size.
+pub fn quantize_shared<I, P, S>(images: &[&I], count: usize, method: QuantizeMethod, tsize: (u32, u32)) -> io::Result<SharedQuantization>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    if count > 256 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Palettes hold at most 256 colors."));
+    }
+
+    let palette = palette_from_images(images, count, method);
+    let indexes = images.iter().map(|image| {
+        let (width, height) = image.dimensions();
+
+        tile_order(&dither(&image_colors(*image), width, &palette, 0f32, ColorMetric::RGB), (width, height), tsize)
+    }).collect::<io::Result<Vec<Vec<u8>>>>()?;
+
+    Ok((palette, indexes))
+}
+
+/// Given an image and a fixed palette, map each pixel to its nearest palette
+/// color.
+///
+/// This is for images that must conform to a palette chosen elsewhere, such
+/// as by a game engine, rather than one chosen to suit the image. The result
+/// is an image of color indexes, which can be encoded as-is with
+/// encode_index_image. Fully transparent pixels are mapped to the palette's
+/// first fully transparent color, if it has one.
+///
+/// The palette must hold between 1 and 256 colors, and at least one of them
+/// must be opaque.
+pub fn map_to_palette<I, P, S>(image: &I, palette: &[Rgba<u8>]) -> io::Result<IndexImage>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    map_to_palette_with_metric(image, palette, ColorMetric::RGB)
+}
+
+/// Map an image to a fixed palette as map_to_palette does, measuring
+/// distance with the given metric.
+pub fn map_to_palette_with_metric<I, P, S>(image: &I, palette: &[Rgba<u8>], metric: ColorMetric) -> io::Result<IndexImage>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    if palette.len() > 256 || !palette.iter().any(|c| c[3] != 0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Palettes must hold at most 256 colors, at least one of them opaque."));
+    }
+
+    let (width, height) = image.dimensions();
+    let indexes = dither(&image_colors(image), width, palette, 0f32, metric);
+
+    Ok(ImageBuffer::from_raw(width, height, indexes).unwrap())
+}
+
+/// Which pixels of an image are meant to be transparent.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TransparentKey {
+    Alpha,          //Fully transparent pixels
+    Color(Rgba<u8>) //Pixels of one designated color, ignoring alpha
+}
+
+/// Given an image with a designated transparent color, produce a copy with
+/// pixels of that color made fully transparent.
+///
+/// Quantizers reserve palette index zero for fully transparent pixels, so
+/// this allows images drawn with a key color, rather than with alpha, to have
+/// their transparent color kept at index zero.
+pub fn apply_color_key<I, P, S>(image: &I, key: Rgba<u8>) -> ImageBuffer<Rgba<u8>, Vec<u8>>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    let (width, _) = image.dimensions();
+    let pixels = image_colors(image);
+
+    ImageBuffer::from_fn(width, pixels.len() as u32 / width.max(1), |x, y| {
+        let pixel = pixels[(y * width + x) as usize];
+
+        match pixel[0] == key[0] && pixel[1] == key[1] && pixel[2] == key[2] {
+            true => Rgba([pixel[0], pixel[1], pixel[2], 0]),
+            false => pixel
+        }
+    })
+}
+
+/// Move a palette's transparent color to index zero, as most hardware with
+/// indexed color treats index zero as transparent, remapping index data to
+/// match.
+///
+/// The transparent color is the palette's first fully transparent color, or
+/// first color matching the designated key color. Colors before it shift up
+/// by one index. If the palette has no such color, one is inserted at index
+/// zero and every other color shifts up by one; the palette must then have
+/// room for another color.
+pub fn move_transparent_to_index_zero(palette: &mut Vec<Rgba<u8>>, indexes: &mut [u8], key: TransparentKey) -> io::Result<()> {
+    let found = palette.iter().position(|c| match key {
+        TransparentKey::Alpha => c[3] == 0,
+        TransparentKey::Color(k) => c[0] == k[0] && c[1] == k[1] && c[2] == k[2]
+    });
+
+    let old = match found {
+        Some(i) => i,
+        None => {
+            if palette.len() >= 256 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Palette has no room for a transparent color."));
+            }
+
+            palette.push(match key {
+                TransparentKey::Alpha => Rgba([0, 0, 0, 0]),
+                TransparentKey::Color(k) => k
+            });
+
+            palette.len() - 1
+        }
+    };
+
+    let color = palette.remove(old);
+
+    palette.insert(0, color);
+
+    for index in indexes.iter_mut() {
+        if (*index as usize) == old {
+            *index = 0;
+        } else if (*index as usize) < old {
+            *index += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// An image's tiles divided between several subpalettes, as for the 16-color
+/// palette banks of 4bpp backgrounds.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SubpaletteAssignment {
+    /// How many colors each subpalette occupies in hardware.
+    pub bank_size: usize,
+
+    /// The colors of each subpalette. The first color of every subpalette is
+    /// fully transparent; subpalettes may be shorter than the bank size.
+    pub palettes: Vec<Vec<Rgba<u8>>>,
+
+    /// The subpalette of each tile, in tile order.
+    pub tile_banks: Vec<u8>,
+
+    /// Index data, in tile order, indexing each tile's own subpalette.
+    pub indexes: Vec<u8>
+}
+
+impl SubpaletteAssignment {
+    /// All subpalettes as one palette, each padded to the bank size, ready
+    /// to be passed to encode_palette.
+    pub fn palette(&self) -> Vec<Rgba<u8>> {
+        let mut out = Vec::with_capacity(self.palettes.len() * self.bank_size);
+
+        for palette in &self.palettes {
+            out.extend(palette.iter().cloned());
+            out.resize(out.len() + self.bank_size - palette.len(), Rgba([0, 0, 0, 255]));
+        }
+
+        out
+    }
+}
+
+/// Sum of the squared distances from each color to its nearest palette color.
+fn palette_error(colors: &[[u8; 3]], palette: &[[u8; 3]]) -> u64 {
+    colors.iter().map(|c| {
+        palette.iter().map(|p| (0..3).map(|i| (p[i] as i64 - c[i] as i64).pow(2) as u64).sum::<u64>()).min().unwrap_or(3 * 255 * 255)
+    }).sum()
+}
+
+/// Given an image, divide its tiles between at most count subpalettes of
+/// bank_size colors each, choosing the colors of every subpalette.
+///
+/// The first color of every subpalette is reserved for transparency, as
+/// index zero is transparent on most hardware with palette banks. Tiles are
+/// first packed into as few subpalettes as will hold every one of their
+/// colors exactly; if that takes more than count subpalettes, tiles are
+/// clustered by color and each subpalette is quantized to fit its tiles as
+/// closely as possible.
+///
+/// The image's dimensions must be multiples of the tile size.
+pub fn assign_subpalettes<I, P, S>(image: &I, tsize: (u32, u32), count: usize, bank_size: usize) -> io::Result<SubpaletteAssignment>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    if !(1..=256).contains(&count) || !(2..=256).contains(&bank_size) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Subpalettes must number between 1 and 256, each of 2 to 256 colors."));
+    }
+
+    let (width, height) = image.dimensions();
+    let (tw, th) = tsize;
+
+    if tw == 0 || th == 0 || !width.is_multiple_of(tw) || !height.is_multiple_of(th) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image size is not a multiple of the tile size."));
+    }
+
+    let capacity = bank_size - 1;
+    let pixels = image_colors(image);
+    let tile_order_pixels = {
+        let mut out = vec![Rgba([0u8; 4]); pixels.len()];
+
+        for (i, pixel) in pixels.into_iter().enumerate() {
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            let tile = (y / th) * (width / tw) + x / tw;
+
+            out[(tile * tw * th + (y % th) * tw + x % tw) as usize] = pixel;
+        }
+
+        out
+    };
+    let tiles : Vec<&[Rgba<u8>]> = tile_order_pixels.chunks((tw * th) as usize).collect();
+    let opaque : Vec<Vec<[u8; 3]>> = tiles.iter().map(|t| t.iter().filter(|p| p[3] != 0).map(|p| [p[0], p[1], p[2]]).collect()).collect();
+
+    //The colors each tile wants, reduced to fit a single subpalette.
+    let wants : Vec<Vec<[u8; 3]>> = opaque.iter().map(|colors| {
+        let mut distinct = colors.clone();
+
+        distinct.sort_unstable();
+        distinct.dedup();
+
+        if distinct.len() > capacity {
+            distinct = median_cut(colors.clone(), capacity).iter().map(|c| [c[0], c[1], c[2]]).collect();
+        }
+
+        distinct
+    }).collect();
+
+    //Pack tiles, those wanting the most colors first, into whichever
+    //subpalette they add the fewest new colors to.
+    let mut order : Vec<usize> = (0..tiles.len()).collect();
+    let mut palettes : Vec<Vec<[u8; 3]>> = Vec::new();
+    let mut users : Vec<usize> = Vec::new();
+
+    order.sort_by_key(|&t| Reverse(wants[t].len()));
+
+    for t in order {
+        let best = palettes.iter().enumerate().filter_map(|(i, p)| {
+            let added = wants[t].iter().filter(|c| !p.contains(c)).count();
+
+            match p.len() + added <= capacity {
+                true => Some((i, added)),
+                false => None
+            }
+        }).min_by_key(|&(_, added)| added);
+
+        match best {
+            Some((i, _)) => {
+                for c in &wants[t] {
+                    if !palettes[i].contains(c) {
+                        palettes[i].push(*c);
+                    }
+                }
+
+                users[i] += 1;
+            },
+            None => {
+                palettes.push(wants[t].clone());
+                users.push(1);
+            }
+        }
+    }
+
+    if palettes.is_empty() {
+        palettes.push(Vec::new());
+    }
+
+    //Too many subpalettes to fit exactly; start from the most used one and
+    //those least like the ones already chosen, then refine them k-means
+    //style, assigning tiles to their closest subpalette and requantizing each
+    //subpalette from the tiles assigned to it.
+    if palettes.len() > count {
+        let mut chosen = vec![(0..palettes.len()).max_by_key(|&i| (users[i], Reverse(i))).unwrap()];
+
+        while chosen.len() < count {
+            let next = (0..palettes.len()).filter(|i| !chosen.contains(i)).max_by_key(|&i| {
+                let distance = chosen.iter().map(|&c| palette_error(&palettes[i], &palettes[c])).min().unwrap();
+
+                (distance, users[i], Reverse(i))
+            }).unwrap();
+
+            chosen.push(next);
+        }
+
+        palettes = chosen.into_iter().map(|i| palettes[i].clone()).collect();
+
+        let mut assignment = vec![0; tiles.len()];
+
+        for _ in 0..8 {
+            let next : Vec<usize> = opaque.iter().map(|colors| {
+                (0..palettes.len()).min_by_key(|&i| palette_error(colors, &palettes[i])).unwrap()
+            }).collect();
+
+            if next == assignment {
+                break;
+            }
+
+            assignment = next;
+
+            for (i, palette) in palettes.iter_mut().enumerate() {
+                let members : Vec<[u8; 3]> = opaque.iter().zip(assignment.iter()).filter(|(_, a)| **a == i).flat_map(|(c, _)| c.iter().cloned()).collect();
+
+                if !members.is_empty() {
+                    *palette = median_cut(members, capacity).iter().map(|c| [c[0], c[1], c[2]]).collect();
+                }
+            }
+        }
+    }
+
+    let palettes : Vec<Vec<Rgba<u8>>> = palettes.iter().map(|p| {
+        let mut out = vec![Rgba([0, 0, 0, 0])];
+
+        out.extend(p.iter().map(|c| Rgba([c[0], c[1], c[2], 255])));
+        out
+    }).collect();
+
+    let mut tile_banks = Vec::with_capacity(tiles.len());
+    let mut indexes = Vec::with_capacity(tile_order_pixels.len());
+
+    for (tile, colors) in tiles.iter().zip(opaque.iter()) {
+        let bank = (0..palettes.len()).min_by_key(|&i| {
+            let rgb : Vec<[u8; 3]> = palettes[i][1..].iter().map(|c| [c[0], c[1], c[2]]).collect();
+
+            palette_error(colors, &rgb)
+        }).unwrap();
+
+        tile_banks.push(bank as u8);
+        indexes.extend(tile.iter().map(|p| nearest_color(&palettes[bank], *p).unwrap_or(0) as u8));
+    }
+
+    Ok(SubpaletteAssignment { bank_size, palettes, tile_banks, indexes })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use image::{ImageBuffer, Rgba};
+    use awsmimg::quantize::{QuantizeMethod, palette_from_image, nearest_color, quantize, quantize_dithered, map_to_palette, assign_subpalettes, palette_from_images, quantize_shared, TransparentKey, apply_color_key, move_transparent_to_index_zero, ColorMetric, ciede2000, nearest_color_with_metric, map_to_palette_with_metric, quantize_with_reserved, quantize_with_color_table};
+    use awsmimg::tilemap::{MapEntry, render_tilemap};
+
+    fn two_clusters() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(8, 8, |x, y| match x < 4 {
+            true => Rgba([250 + (y % 4) as u8, 0, 0, 255]),
+            false => Rgba([0, 0, 200 + y as u8, 255])
+        })
+    }
+
+    #[test]
+    fn median_cut_clusters() {
+        let palette = palette_from_image(&two_clusters(), 2, QuantizeMethod::MedianCut);
+
+        assert_eq!(palette.len(), 2);
+        assert!(palette.iter().any(|c| c[0] >= 250 && c[2] == 0));
+        assert!(palette.iter().any(|c| c[0] == 0 && c[2] >= 200));
+    }
+
+    #[test]
+    fn octree_clusters() {
+        let palette = palette_from_image(&two_clusters(), 2, QuantizeMethod::Octree);
+
+        assert_eq!(palette.len(), 2);
+        assert!(palette.iter().any(|c| c[0] >= 250 && c[2] == 0));
+        assert!(palette.iter().any(|c| c[0] == 0 && c[2] >= 200));
+
+        let exact = palette_from_image(&two_clusters(), 64, QuantizeMethod::Octree);
+
+        assert_eq!(exact.len(), 12);
+    }
+
+    #[test]
+    fn octree_fewer_than_eight() {
+        //One color in each octant, with o + 1 pixels of octant o.
+        let img : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(36, 1, |x, _| {
+            let octant = (0..8).find(|&o| x < (o + 1) * (o + 2) / 2).unwrap();
+
+            Rgba([(octant >> 2) as u8 * 255, (octant >> 1 & 1) as u8 * 255, (octant & 1) as u8 * 255, 255])
+        });
+
+        for n in 1..8 {
+            assert_eq!(palette_from_image(&img, n, QuantizeMethod::Octree).len(), n);
+        }
+
+        assert!(palette_from_image(&img, 1, QuantizeMethod::Octree)[0][0] > 128);
+        assert!(palette_from_image(&img, 7, QuantizeMethod::Octree).contains(&Rgba([255, 255, 255, 255])));
+    }
+
+    #[test]
+    fn octree_gradient() {
+        let gradient : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(256, 1, |x, _| Rgba([x as u8, x as u8, x as u8, 255]));
+        let palette = palette_from_image(&gradient, 16, QuantizeMethod::Octree);
+
+        assert!(palette.len() <= 16);
+        assert!(palette.len() >= 8);
+
+        let worst = (0..256u32).map(|x| {
+            let nearest = palette[nearest_color(&palette, Rgba([x as u8, x as u8, x as u8, 255])).unwrap()];
+            (nearest[0] as i32 - x as i32).abs()
+        }).max().unwrap();
+
+        assert!(worst <= 16);
+    }
+
+    #[test]
+    fn quantize_transparency_and_order() {
+        let img : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(4, 2, |x, _| match x {
+            0 => Rgba([0, 0, 0, 0]),
+            1 => Rgba([255, 255, 255, 255]),
+            _ => Rgba([0, 0, 0, 255])
+        });
+        let (palette, indexes) = quantize(&img, 4, QuantizeMethod::MedianCut, (2, 2)).unwrap();
+
+        assert_eq!(palette[0], Rgba([0, 0, 0, 0]));
+        assert_eq!(palette.len(), 3);
+        assert_eq!(indexes[0], 0);
+        assert_eq!(palette[indexes[1] as usize], Rgba([255, 255, 255, 255]));
+        assert_eq!(indexes[2], 0);
+        assert_eq!(palette[indexes[4] as usize], Rgba([0, 0, 0, 255]));
+
+        let odd : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_pixel(10, 9, Rgba([0, 0, 0, 255]));
+
+        assert!(quantize(&odd, 4, QuantizeMethod::MedianCut, (8, 8)).is_err());
+        assert!(quantize(&img, 257, QuantizeMethod::MedianCut, (2, 2)).is_err());
+        assert!(quantize_shared(&[&img, &odd], 4, QuantizeMethod::Octree, (2, 2)).is_err());
+    }
+
+    #[test]
+    fn dithered_gradient() {
+        //A flat gray between the two palette colors should come out as a mix of
+        //both when dithered, averaging to roughly the original level.
+        let gray : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(8, 8, |x, _| match x {
+            0 => Rgba([0, 0, 0, 255]),
+            1 => Rgba([255, 255, 255, 255]),
+            _ => Rgba([96, 96, 96, 255])
+        });
+        let (palette, flat) = quantize(&gray, 2, QuantizeMethod::MedianCut, (8, 8)).unwrap();
+        let (_, dithered) = quantize_dithered(&gray, 2, QuantizeMethod::MedianCut, 1.0, (8, 8)).unwrap();
+        let (_, none) = quantize_dithered(&gray, 2, QuantizeMethod::MedianCut, 0.0, (8, 8)).unwrap();
+        let level = |indexes: &[u8]| {
+            let body : Vec<u32> = indexes.chunks(8).flat_map(|row| row[2..].iter().map(|i| palette[*i as usize][0] as u32)).collect();
+            body.iter().sum::<u32>() / body.len() as u32
+        };
+
+        assert_eq!(flat, none);
+        assert!(flat[2..8].iter().all(|i| *i == flat[2]));
+        assert!((level(&dithered) as i32 - 96).abs() <= 16);
+        assert!((level(&flat) as i32 - 96).abs() > 16);
+    }
+
+    #[test]
+    fn fixed_palette_mapping() {
+        let palette = vec![Rgba([0u8, 0, 0, 0]), Rgba([255u8, 0, 0, 255]), Rgba([0u8, 0, 255, 255])];
+        let img : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(3, 1, |x, _| match x {
+            0 => Rgba([200, 30, 10, 255]),
+            1 => Rgba([20, 20, 220, 255]),
+            _ => Rgba([255, 255, 255, 0])
+        });
+
+        assert_eq!(map_to_palette(&img, &palette).unwrap().into_raw(), vec![1, 2, 0]);
+        assert!(map_to_palette(&img, &palette[..1]).is_err());
+        assert!(map_to_palette(&img, &vec![Rgba([0u8, 0, 0, 255]); 257]).is_err());
+    }
+
+    #[test]
+    fn subpalettes_exact() {
+        //Four 2x2 tiles needing five colors between them, which fit exactly
+        //in two subpalettes of three colors plus transparency.
+        let (r, g, b, w) = (Rgba([255u8, 0, 0, 255]), Rgba([0u8, 255, 0, 255]), Rgba([0u8, 0, 255, 255]), Rgba([255u8, 255, 255, 255]));
+        let clear = Rgba([0u8, 0, 0, 0]);
+        let tiles = [[r, g, g, clear], [b, w, w, w], [r, r, r, r], [w, clear, b, w]];
+        let img : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(4, 4, |x, y| {
+            tiles[((y / 2) * 2 + x / 2) as usize][((y % 2) * 2 + x % 2) as usize]
+        });
+        let result = assign_subpalettes(&img, (2, 2), 2, 4).unwrap();
+
+        assert_eq!(result.palettes.len(), 2);
+        assert!(result.palettes.iter().all(|p| p.len() <= 4 && p[0] == clear));
+        assert_eq!(result.tile_banks[0], result.tile_banks[2]);
+        assert_eq!(result.tile_banks[1], result.tile_banks[3]);
+        assert!(result.tile_banks[0] != result.tile_banks[1]);
+
+        let entries : Vec<MapEntry> = result.tile_banks.iter().enumerate().map(|(i, bank)| {
+            MapEntry { tile: i as u16, hflip: false, vflip: false, palette: *bank, priority: false }
+        }).collect();
+        let rendered = render_tilemap(&entries, 2, &result.indexes, (2, 2), &result.palette(), 4).unwrap();
+
+        assert_eq!(rendered.into_raw(), img.into_raw());
+    }
+
+    #[test]
+    fn subpalettes_lossy() {
+        //Eight tiles of unrelated colors can't share two subpalettes exactly,
+        //but every tile should still get the subpalette nearest its colors.
+        let img : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(16, 2, |x, y| {
+            let tile = x / 2;
+            let shade = ((x % 2) + y * 2) as u8 * 10;
+
+            match tile < 4 {
+                true => Rgba([200 + shade, tile as u8 * 8, 0, 255]),
+                false => Rgba([0, tile as u8 * 8, 200 + shade, 255])
+            }
+        });
+        let result = assign_subpalettes(&img, (2, 2), 2, 4).unwrap();
+
+        assert_eq!(result.palettes.len(), 2);
+        assert_eq!(result.palette().len(), 8);
+        assert!(result.indexes.iter().all(|i| *i != 0 && *i < 4));
+        assert!(result.tile_banks[..4].iter().all(|b| *b == result.tile_banks[0]));
+        assert!(result.tile_banks[4..].iter().all(|b| *b == result.tile_banks[4]));
+        assert!(result.tile_banks[0] != result.tile_banks[4]);
+        assert!(assign_subpalettes(&img, (3, 2), 2, 4).is_err());
+        assert!(assign_subpalettes(&img, (2, 2), 0, 4).is_err());
+    }
+
+    #[test]
+    fn shared_palette_frames() {
+        //Each frame alone would get a palette of its own two colors; together
+        //they must share three.
+        let first : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(2, 2, |x, _| match x {
+            0 => Rgba([255, 0, 0, 255]),
+            _ => Rgba([0, 255, 0, 255])
+        });
+        let second : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(2, 2, |x, _| match x {
+            0 => Rgba([0, 255, 0, 255]),
+            _ => Rgba([0, 0, 255, 255])
+        });
+        let palette = palette_from_images(&[&first, &second], 4, QuantizeMethod::MedianCut);
+
+        assert_eq!(palette.len(), 3);
+
+        let (shared, indexes) = quantize_shared(&[&first, &second], 4, QuantizeMethod::Octree, (1, 1)).unwrap();
+
+        assert_eq!(shared.len(), 3);
+        assert_eq!(indexes.len(), 2);
+        assert_eq!(indexes[0][1], indexes[1][0]);
+        assert_eq!(shared[indexes[0][1] as usize], Rgba([0u8, 255, 0, 255]));
+        assert!(indexes[0][0] != indexes[1][1]);
+    }
+
+    #[test]
+    fn transparent_index_zero() {
+        let (r, g, clear) = (Rgba([255u8, 0, 0, 255]), Rgba([0u8, 255, 0, 255]), Rgba([9u8, 9, 9, 0]));
+        let mut palette = vec![r, g, clear];
+        let mut indexes = vec![0u8, 1, 2, 2, 1];
+
+        move_transparent_to_index_zero(&mut palette, &mut indexes, TransparentKey::Alpha).unwrap();
+
+        assert_eq!(palette, vec![clear, r, g]);
+        assert_eq!(indexes, vec![1, 2, 0, 0, 2]);
+
+        move_transparent_to_index_zero(&mut palette, &mut indexes, TransparentKey::Color(g)).unwrap();
+
+        assert_eq!(palette, vec![g, clear, r]);
+        assert_eq!(indexes, vec![2, 0, 1, 1, 0]);
+
+        let magenta = Rgba([255u8, 0, 255, 255]);
+        let mut opaque = vec![r, g];
+        let mut opaque_indexes = vec![0u8, 1];
+
+        move_transparent_to_index_zero(&mut opaque, &mut opaque_indexes, TransparentKey::Color(magenta)).unwrap();
+
+        assert_eq!(opaque, vec![magenta, r, g]);
+        assert_eq!(opaque_indexes, vec![1, 2]);
+        assert!(move_transparent_to_index_zero(&mut vec![r; 256], &mut [], TransparentKey::Alpha).is_err());
+    }
+
+    #[test]
+    fn color_key_quantize() {
+        let magenta = Rgba([255u8, 0, 255, 255]);
+        let img : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(2, 1, |x, _| match x {
+            0 => Rgba([0, 0, 255, 255]),
+            _ => magenta
+        });
+        let (palette, indexes) = quantize(&apply_color_key(&img, magenta), 4, QuantizeMethod::MedianCut, (1, 1)).unwrap();
+
+        assert_eq!(palette, vec![Rgba([0u8, 0, 0, 0]), Rgba([0u8, 0, 255, 255])]);
+        assert_eq!(indexes, vec![1, 0]);
+    }
+
+    #[test]
+    fn ciede2000_reference() {
+        //Pairs from Sharma, Wu and Dalal's CIEDE2000 test data.
+        let pairs = [([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+                     ([50.0, 2.5, 0.0], [50.0, 0.0, -2.5], 4.3065),
+                     ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+                     ([60.2574, -34.0099, 36.2677], [60.4626, -34.1751, 39.4387], 1.2644)];
+
+        for (a, b, expected) in pairs.iter() {
+            assert!((ciede2000(*a, *b) - expected).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn perceptual_matching() {
+        //A light yellow is nearer a dark purple than a light cyan in RGB
+        //terms, but its lightness makes it look nearer the cyan to the eye.
+        let palette = vec![Rgba([64u8, 32, 128, 255]), Rgba([32u8, 224, 224, 255])];
+        let yellow = Rgba([224u8, 192, 96, 255]);
+
+        for metric in [ColorMetric::RGB, ColorMetric::OKLab, ColorMetric::CIEDE2000].iter() {
+            assert_eq!(metric.distance(yellow, yellow), 0.0);
+            assert_eq!(nearest_color_with_metric(&palette, palette[1], *metric), Some(1));
+        }
+
+        let img : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_pixel(1, 1, yellow);
+
+        assert_eq!(map_to_palette(&img, &palette).unwrap().into_raw(), vec![0]);
+        assert_eq!(map_to_palette_with_metric(&img, &palette, ColorMetric::OKLab).unwrap().into_raw(), vec![1]);
+        assert_eq!(map_to_palette_with_metric(&img, &palette, ColorMetric::CIEDE2000).unwrap().into_raw(), vec![1]);
+    }
+
+    #[test]
+    fn reserved_entries() {
+        let clear = Rgba([0u8, 0, 0, 0]);
+        let white = Rgba([255u8, 255, 255, 255]);
+        let img : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(4, 1, |x, _| match x {
+            0 => Rgba([0, 0, 0, 0]),
+            1 => Rgba([255, 255, 255, 255]),
+            2 => Rgba([200, 0, 0, 255]),
+            _ => Rgba([0, 0, 200, 255])
+        });
+        let (palette, indexes) = quantize_with_reserved(&img, 16, QuantizeMethod::MedianCut, &[(0, clear), (3, white)], (1, 1)).unwrap();
+
+        assert_eq!(palette.len(), 4);
+        assert_eq!(palette[0], clear);
+        assert_eq!(palette[3], white);
+        assert_eq!(indexes[0], 0);
+        assert_eq!(indexes[1], 3);
+        assert_eq!(palette[indexes[2] as usize], Rgba([200u8, 0, 0, 255]));
+        assert_eq!(palette[indexes[3] as usize], Rgba([0u8, 0, 200, 255]));
+
+        let (sparse, sparse_indexes) = quantize_with_reserved(&img, 16, QuantizeMethod::MedianCut, &[(0, clear), (9, white)], (1, 1)).unwrap();
+
+        assert_eq!(sparse.len(), 10);
+        assert_eq!(sparse_indexes[1], 9);
+        assert!(sparse_indexes.iter().all(|i| *i < 3 || *i == 9));
+        assert!(quantize_with_reserved(&img, 4, QuantizeMethod::MedianCut, &[(4, clear)], (1, 1)).is_err());
+        assert!(quantize_with_reserved(&img, 4, QuantizeMethod::MedianCut, &[(1, clear), (1, white)], (1, 1)).is_err());
+    }
+
+    #[test]
+    fn color_table_keys() {
+        let magenta = Rgba([255u8, 0, 255, 255]);
+        let white = Rgba([255u8, 255, 255, 255]);
+        let img : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(4, 1, |x, _| match x {
+            0 => magenta,
+            1 => white,
+            2 => Rgba([40, 80, 120, 255]),
+            _ => magenta
+        });
+        let (palette, indexes) = quantize_with_color_table(&img, 16, QuantizeMethod::MedianCut, &[(0, magenta), (15, white)], (1, 1)).unwrap();
+
+        assert_eq!(palette.len(), 16);
+        assert_eq!(indexes, vec![0, 15, 1, 0]);
+        assert_eq!(palette[1], Rgba([40u8, 80, 120, 255]));
+        assert!(quantize_with_color_table(&img, 16, QuantizeMethod::MedianCut, &[(0, magenta), (1, Rgba([0, 255, 0, 255]))], (1, 1)).is_err());
+        assert!(quantize_with_color_table(&img, 16, QuantizeMethod::MedianCut, &[(0, magenta), (1, magenta)], (1, 1)).is_err());
+        assert!(quantize_with_color_table(&img, 16, QuantizeMethod::MedianCut, &[(0, magenta), (0, magenta)], (1, 1)).is_err());
+    }
+}
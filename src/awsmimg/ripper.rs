@@ -0,0 +1,278 @@
+use std::io;
+use image::{ImageBuffer, LumaA};
+
+use awsmimg::formats::{IndexedGraphicsProperties, IndexedFormat};
+use awsmimg::decoder::IndexedGraphicsDecoder;
+use awsmimg::conversion::luma_from_indexes;
+
+/// A sheet of ripped tiles, as a grayscale view of their index data.
+pub type TileSheet = Box<ImageBuffer<LumaA<u8>, Vec<u8>>>;
+
+/// The number of bytes a single tile occupies in a given format, or None if
+/// the format cannot be ripped.
+///
+/// Untiled formats count each pixel as a tile.
+pub fn tile_bytes_for_format(format: IndexedFormat) -> Option<usize> {
+    let mut empty = io::empty();
+
+    with_indexed_codec!(format, &mut empty, |dec| dec.tile_bytes())
+}
+
+/// Given a decoder, decode up to size bytes of index data and lay the decoded
+/// tiles out as a sheet the given number of tiles wide.
+///
+/// The sheet is as tall as needed to hold every decoded tile. Space left over
+/// in the last row of tiles is filled with transparent pixels.
+pub fn rip_tiles<'a, E>(enc: &mut E, size: usize, columns: u32) -> io::Result<TileSheet> where E: IndexedGraphicsDecoder + 'a {
+    if columns == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Tile sheets must be at least one tile wide."));
+    }
+
+    let indexes : Vec<u8> = enc.decode_indexes(size)?;
+    let (tw, th) = enc.tile_size();
+    let tcount = (indexes.len() / (tw * th) as usize) as u32;
+    let rows = tcount.div_ceil(columns).max(1);
+
+    match luma_from_indexes(indexes, enc.palette_maxcol(), (tw, th), Some((columns * tw, rows * th))) {
+        Some(i) => Ok(i),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, "Decoded data does not hold a whole number of tiles."))
+    }
+}
+
+/// Given an arbitrary binary and a format description, rip count tiles
+/// starting at a byte offset into a sheet the given number of tiles wide.
+///
+/// Ripping stops early at the end of the binary. Any partial tile at the end
+/// of the binary is ignored, so that every offset within it can be ripped.
+pub fn rip_tiles_with_format(format: IndexedFormat, data: &[u8], offset: usize, count: usize, columns: u32) -> io::Result<TileSheet> {
+    let tbytes = match tile_bytes_for_format(format) {
+        Some(t) => t,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "This format cannot be ripped one tile at a time."))
+    };
+
+    if offset > data.len() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Proposed offset exceeds length of data."));
+    }
+
+    let avail = data.len() - offset;
+    let size = count.saturating_mul(tbytes).min(avail - avail % tbytes);
+    let mut r = &data[offset..];
+
+    with_indexed_codec!(format, &mut r, |dec| rip_tiles(&mut dec, size, columns))
+}
+
+/// Scan through an arbitrary binary, ripping a sheet of tiles at each of a
+/// series of offsets.
+///
+/// Sheets are ripped starting at offset and every step bytes after, until
+/// either pages sheets have been ripped or the end of the binary is reached.
+/// Each sheet is returned along with the offset it was ripped from.
+pub fn scan_tiles_with_format(format: IndexedFormat, data: &[u8], offset: usize, step: usize, pages: usize, count: usize, columns: u32) -> io::Result<Vec<(usize, TileSheet)>> {
+    if step == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Scan step must be at least one byte."));
+    }
+
+    let mut out = Vec::new();
+
+    for page_offset in (offset..data.len()).step_by(step).take(pages) {
+        out.push((page_offset, rip_tiles_with_format(format, data, page_offset, count, columns)?));
+    }
+
+    Ok(out)
+}
+
+/// What a byte range has been guessed to hold.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GuessKind {
+    Tiles(IndexedFormat), //Tile data in a rippable indexed format
+    BGR555Palette         //Palette data as written by agb::encode_palette
+}
+
+/// A scored guess at the format of a byte range. Scores range from 0, for
+/// data that shows no sign of being in the format, to 1.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FormatGuess {
+    pub kind: GuessKind,
+    pub score: f32
+}
+
+/// Score tiled index data by how coherent and simple each tile is.
+///
+/// Real graphics tend to repeat the same index across neighboring pixels and
+/// use only a few indexes per tile, while data in the wrong format, or not
+/// graphics at all, looks close to random noise. The score is the fraction
+/// of horizontally adjacent pixels with equal indexes, weighted by how far
+/// each tile's index entropy falls short of the maximum possible for its
+/// pixel count.
+fn tile_score(indexes: &[u8], tsize: (u32, u32)) -> f32 {
+    let (tw, th) = (tsize.0 as usize, tsize.1 as usize);
+    let tiles: Vec<&[u8]> = indexes.chunks(tw * th).filter(|t| t.len() == tw * th).collect();
+
+    if tiles.is_empty() || tw < 2 {
+        return 0.0;
+    }
+
+    let max_entropy = ((tw * th) as f32).log2();
+    let total: f32 = tiles.iter().map(|tile| {
+        let mut counts = [0u32; 256];
+
+        for index in tile.iter() {
+            counts[*index as usize] += 1;
+        }
+
+        let entropy: f32 = counts.iter().filter(|c| **c > 0).map(|c| {
+            let p = *c as f32 / tile.len() as f32;
+            -p * p.log2()
+        }).sum();
+        let equal = tile.chunks(tw).map(|row| row.windows(2).filter(|pair| pair[0] == pair[1]).count()).sum::<usize>();
+        let coherence = equal as f32 / (th * (tw - 1)) as f32;
+
+        coherence * (1.0 - entropy / max_entropy).max(0.0)
+    }).sum();
+
+    total / tiles.len() as f32
+}
+
+/// Score a byte range as BGR555 palette data.
+///
+/// Palettes hold at most 256 colors, never set the top bit of each word, and
+/// rarely repeat a color. The score is zero for ranges too long or oddly
+/// sized to be a palette, and otherwise scales with how far the fraction of
+/// words with the top bit clear exceeds that of random data, weighted by the
+/// fraction of words that are distinct.
+fn palette_score(data: &[u8]) -> f32 {
+    if data.is_empty() || data.len() > 512 || !data.len().is_multiple_of(2) {
+        return 0.0;
+    }
+
+    let words: Vec<u16> = data.chunks(2).map(|w| w[0] as u16 | (w[1] as u16) << 8).collect();
+    let clear = words.iter().filter(|w| **w & 0x8000 == 0).count() as f32 / words.len() as f32;
+    let mut distinct = words.clone();
+
+    distinct.sort_unstable();
+    distinct.dedup();
+
+    (2.0 * clear - 1.0).max(0.0) * distinct.len() as f32 / words.len() as f32
+}
+
+/// Score a byte range against each known rippable format and palette format,
+/// returning the guesses ranked from most to least likely.
+///
+/// This is a heuristic meant to suggest where to start ripping, not a reliable
+/// identification. Chunky 8bpp data cannot be told apart from tiled 8bpp data
+/// without knowing the image width, so only tiled formats are guessed. Very
+/// simple graphics, such as solid fills, are equally valid in several formats
+/// and may be ranked in any order.
+pub fn guess_formats(data: &[u8]) -> Vec<FormatGuess> {
+    let mut guesses = Vec::new();
+
+    for format in &[IndexedFormat::AGB4, IndexedFormat::AGB8Tiled] {
+        let tbytes = tile_bytes_for_format(*format).unwrap();
+        let size = data.len() - data.len() % tbytes;
+        let mut r = &data[..size];
+        let decoded: io::Result<(Vec<u8>, (u32, u32))> = with_indexed_codec!(*format, &mut r, |dec| dec.decode_indexes(size).map(|i| (i, dec.tile_size())));
+
+        if let Ok((indexes, tsize)) = decoded {
+            guesses.push(FormatGuess {
+                kind: GuessKind::Tiles(*format),
+                score: tile_score(&indexes, tsize)
+            });
+        }
+    }
+
+    guesses.push(FormatGuess {
+        kind: GuessKind::BGR555Palette,
+        score: palette_score(data)
+    });
+
+    guesses.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+    guesses
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use awsmimg::formats::IndexedFormat;
+    use std::io::Cursor;
+    use awsmimg::encoder::IndexedGraphicsEncoder;
+    use awsmimg::formats::agb::{encode_palette, AGB4Encoder, AGB8Encoder};
+    use awsmimg::ripper::{rip_tiles_with_format, scan_tiles_with_format, guess_formats, GuessKind};
+
+    #[test]
+    fn rip_sheet_layout() {
+        let mut data : Vec<u8> = vec![0; 3 * 32 + 5];
+        data[2 * 32] = 0xFF;
+
+        let sheet = rip_tiles_with_format(IndexedFormat::AGB4, &data, 0, 16, 2).unwrap();
+
+        assert_eq!(sheet.dimensions(), (16, 16));
+        assert_eq!(sheet.get_pixel(0, 8), &image::LumaA([255u8, 255]));
+        assert_eq!(sheet.get_pixel(8, 8), &image::LumaA([0u8, 0]));
+        assert!(rip_tiles_with_format(IndexedFormat::AGB4, &data, data.len() + 1, 1, 2).is_err());
+        assert!(rip_tiles_with_format(IndexedFormat::CGA, &data, 0, 1, 2).is_err());
+    }
+
+    #[test]
+    fn scan_offsets() {
+        let data : Vec<u8> = (0..=255).collect();
+
+        let sheets = scan_tiles_with_format(IndexedFormat::AGB8Tiled, &data, 1, 100, 8, 1, 1).unwrap();
+        let offsets : Vec<usize> = sheets.iter().map(|(o, _)| *o).collect();
+
+        assert_eq!(offsets, vec![1, 101, 201]);
+        assert_eq!(sheets[0].1.get_pixel(0, 0), &image::LumaA([1u8, 255]));
+        assert_eq!(sheets[2].1.get_pixel(0, 0), &image::LumaA([0u8, 0]));
+    }
+
+    /// A 16x16 tile sheet of a filled circle and a few bands, in tile order.
+    fn sprite_indexes(base: u8) -> Vec<u8> {
+        let mut out = Vec::with_capacity(256);
+
+        for tile in 0..4 {
+            for y in 0..8 {
+                for x in 0..8 {
+                    let (px, py) = ((tile % 2) * 8 + x, (tile / 2) * 8 + y);
+                    let inside = (px - 8) * (px - 8) + (py - 8) * (py - 8) < 36;
+
+                    out.push(base + if inside { 1 + (py / 5) as u8 } else { 0 });
+                }
+            }
+        }
+
+        out
+    }
+
+    #[test]
+    fn guess_agb4_sprite() {
+        let mut data = Cursor::new(Vec::new());
+        AGB4Encoder::new(&mut data).encode_indexes(sprite_indexes(0), 16, 16).unwrap();
+
+        let guesses = guess_formats(data.get_ref());
+
+        assert_eq!(guesses[0].kind, GuessKind::Tiles(IndexedFormat::AGB4));
+        assert!(guesses[0].score > guesses[1].score);
+    }
+
+    #[test]
+    fn guess_agb8_sprite() {
+        let mut data = Cursor::new(Vec::new());
+        AGB8Encoder::new_tiled(&mut data).encode_indexes(sprite_indexes(0x43), 16, 16).unwrap();
+
+        let guesses = guess_formats(data.get_ref());
+
+        assert_eq!(guesses[0].kind, GuessKind::Tiles(IndexedFormat::AGB8Tiled));
+    }
+
+    #[test]
+    fn guess_palette_and_noise() {
+        let palette: Vec<image::Rgba<u8>> = (0..16u32).map(|i| image::Rgba([(i * 16) as u8, (255 - i * 16) as u8, (i * 37) as u8, 255])).collect();
+        let mut data = Cursor::new(Vec::new());
+        encode_palette(&mut data, palette.into_iter(), false).unwrap();
+
+        let noise: Vec<u8> = (0..1024u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+
+        assert_eq!(guess_formats(data.get_ref())[0].kind, GuessKind::BGR555Palette);
+        assert!(guess_formats(&noise).iter().all(|g| g.score < 0.25));
+    }
+}
@@ -0,0 +1,789 @@
+use std::io;
+use std::io::{Read, Write};
+use std::collections::HashMap;
+use image::{GenericImage, ImageBuffer, Luma, Pixel, Primitive, Rgba};
+
+use awsmimg::formats::IndexedFormat;
+use awsmimg::formats::agb::AGB8Encoder;
+use awsmimg::encoder::IndexedGraphicsEncoder;
+use awsmimg::decoder::IndexedGraphicsDecoder;
+use awsmimg::conversion::indexes_from_index_image;
+use awsmimg::quantize::SubpaletteAssignment;
+
+/// How the entries of a tilemap are laid out.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MapFormat {
+    AGBText, //Little-endian words: PPPPVHTTTTTTTTTT, as used by AGB and NTR text backgrounds
+    SFC,     //Little-endian words: VHOPPPTTTTTTTTTT, where O is priority
+    MD,      //Big-endian words: OPPVHTTTTTTTTTTT, where O is priority
+    Byte     //One tile number per byte with no attributes, as in DMG and NES maps
+}
+
+/// A single decoded tilemap entry.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct MapEntry {
+    pub tile: u16,
+    pub hflip: bool,
+    pub vflip: bool,
+    pub palette: u8,
+    pub priority: bool
+}
+
+impl MapFormat {
+    /// The number of bytes a single map entry occupies.
+    pub fn entry_bytes(&self) -> usize {
+        match *self {
+            MapFormat::Byte => 1,
+            _ => 2
+        }
+    }
+
+    /// Parse a single map entry from exactly entry_bytes bytes.
+    pub fn parse_entry(&self, bytes: &[u8]) -> MapEntry {
+        let le = || bytes[0] as u16 | (bytes[1] as u16) << 8;
+
+        match *self {
+            MapFormat::AGBText => {
+                let word = le();
+                MapEntry { tile: word & 0x3FF, hflip: word & 0x0400 != 0, vflip: word & 0x0800 != 0, palette: (word >> 12) as u8, priority: false }
+            },
+            MapFormat::SFC => {
+                let word = le();
+                MapEntry { tile: word & 0x3FF, hflip: word & 0x4000 != 0, vflip: word & 0x8000 != 0, palette: ((word >> 10) & 0x07) as u8, priority: word & 0x2000 != 0 }
+            },
+            MapFormat::MD => {
+                let word = (bytes[0] as u16) << 8 | bytes[1] as u16;
+                MapEntry { tile: word & 0x7FF, hflip: word & 0x0800 != 0, vflip: word & 0x1000 != 0, palette: ((word >> 13) & 0x03) as u8, priority: word & 0x8000 != 0 }
+            },
+            MapFormat::Byte => MapEntry { tile: bytes[0] as u16, hflip: false, vflip: false, palette: 0, priority: false }
+        }
+    }
+
+    /// Whether entries can flip their tiles horizontally and vertically.
+    pub fn has_flips(&self) -> bool {
+        *self != MapFormat::Byte
+    }
+
+    /// Whether entries can give their tiles priority over sprites and other
+    /// backgrounds. AGB text backgrounds set priority for the whole layer.
+    pub fn has_priority(&self) -> bool {
+        *self == MapFormat::SFC || *self == MapFormat::MD
+    }
+
+    /// The largest tile number an entry can hold.
+    pub fn max_tile(&self) -> u16 {
+        match *self {
+            MapFormat::AGBText | MapFormat::SFC => 0x3FF,
+            MapFormat::MD => 0x7FF,
+            MapFormat::Byte => 0xFF
+        }
+    }
+
+    /// The largest palette bank an entry can select.
+    pub fn max_palette(&self) -> u8 {
+        match *self {
+            MapFormat::AGBText => 15,
+            MapFormat::SFC => 7,
+            MapFormat::MD => 3,
+            MapFormat::Byte => 0
+        }
+    }
+
+    /// Lay out a single map entry as entry_bytes bytes, reversing parse_entry.
+    ///
+    /// Entries whose tile or palette bank don't fit the layout are rejected,
+    /// as are flipped or prioritized entries in layouts without those bits.
+    pub fn encode_entry(&self, entry: MapEntry) -> io::Result<Vec<u8>> {
+        if entry.tile > self.max_tile() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Tile number does not fit in the map entry."));
+        }
+
+        if entry.palette > self.max_palette() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Palette bank does not fit in the map entry."));
+        }
+
+        if entry.priority && !self.has_priority() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Map entries of this layout cannot set tile priority."));
+        }
+
+        let flip = |bit: u16, set: bool| if set { bit } else { 0 };
+
+        Ok(match *self {
+            MapFormat::AGBText => {
+                let word = entry.tile | flip(0x0400, entry.hflip) | flip(0x0800, entry.vflip) | (entry.palette as u16) << 12;
+                word.to_le_bytes().to_vec()
+            },
+            MapFormat::SFC => {
+                let word = entry.tile | flip(0x4000, entry.hflip) | flip(0x8000, entry.vflip) | flip(0x2000, entry.priority) | (entry.palette as u16) << 10;
+                word.to_le_bytes().to_vec()
+            },
+            MapFormat::MD => {
+                let word = entry.tile | flip(0x0800, entry.hflip) | flip(0x1000, entry.vflip) | flip(0x8000, entry.priority) | (entry.palette as u16) << 13;
+                word.to_be_bytes().to_vec()
+            },
+            MapFormat::Byte => {
+                if entry.hflip || entry.vflip {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "Byte maps cannot flip tiles."));
+                }
+
+                vec![entry.tile as u8]
+            }
+        })
+    }
+}
+
+pub fn interpret_map_format_name(fmt_given: &str) -> Option<MapFormat> {
+    let fmt = fmt_given.to_ascii_lowercase();
+
+    match fmt.as_ref() {
+        "agbtext" => Some(MapFormat::AGBText),
+        "sfc" => Some(MapFormat::SFC),
+        "md" => Some(MapFormat::MD),
+        "byte" => Some(MapFormat::Byte),
+        _ => None
+    }
+}
+
+/// Read up to size bytes of tilemap data and parse every entry in it.
+///
+/// If the data ends partway through an entry, an error is returned.
+pub fn decode_map<R: Read>(r: &mut R, format: MapFormat, size: usize) -> io::Result<Vec<MapEntry>> {
+    let mut buf = Vec::new();
+
+    r.take(size as u64).read_to_end(&mut buf)?;
+
+    if !buf.len().is_multiple_of(format.entry_bytes()) {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Tilemap data ends partway through an entry."));
+    }
+
+    Ok(buf.chunks(format.entry_bytes()).map(|e| format.parse_entry(e)).collect())
+}
+
+/// Write map entries in the given layout, one after another.
+pub fn encode_map<W: Write>(w: &mut W, format: MapFormat, entries: &[MapEntry]) -> io::Result<()> {
+    for entry in entries {
+        w.write_all(&format.encode_entry(*entry)?)?;
+    }
+
+    Ok(())
+}
+
+/// Which channel of a mask image marks tiles as high priority.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PriorityMask {
+    Alpha, //The alpha channel, as of a source image or a layer exported on its own
+    Luma   //Grayscale values, as of a separate black and white mask image
+}
+
+/// Given a mask image, decide which of its tiles are high priority, in tile
+/// order.
+///
+/// A tile is high priority if any of its pixels has an 8-bit value of at
+/// least 128 in the given channel, so that priority can be painted roughly
+/// over the parts of the art that should appear in front. The mask must be a
+/// whole number of tiles in each dimension, and is normally the same size as
+/// the image being tilemapped.
+pub fn tile_priorities<I, P, S>(mask: &I, tsize: (u32, u32), channel: PriorityMask) -> io::Result<Vec<bool>>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    let (width, height) = mask.dimensions();
+    let (tw, th) = tsize;
+
+    if !width.is_multiple_of(tw) || !height.is_multiple_of(th) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Priority masks must be a whole number of tiles in size."));
+    }
+
+    let imgmax = S::max_value().to_f32().unwrap();
+    let mut out = vec![false; ((width / tw) * (height / th)) as usize];
+
+    for (x, y, pixel) in mask.pixels() {
+        let la = pixel.to_luma_alpha();
+        let value = match channel {
+            PriorityMask::Alpha => la[1],
+            PriorityMask::Luma => la[0]
+        };
+
+        if value.to_f32().unwrap() / imgmax * 255f32 >= 128f32 {
+            out[((y / th) * (width / tw) + x / tw) as usize] = true;
+        }
+    }
+
+    Ok(out)
+}
+
+/// Set the priority of every map entry from a list of per-tile flags, such
+/// as tile_priorities returns. There must be one flag for every entry.
+pub fn apply_priorities(entries: &mut [MapEntry], priorities: &[bool]) -> io::Result<()> {
+    if entries.len() != priorities.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Priority mask does not cover the same tiles as the map."));
+    }
+
+    for (entry, priority) in entries.iter_mut().zip(priorities) {
+        entry.priority = *priority;
+    }
+
+    Ok(())
+}
+
+/// Rearrange the entries of an AGB text background map, laid out row by row
+/// map_width entries wide, into the order its screenblocks are loaded into
+/// VRAM.
+///
+/// AGB text backgrounds larger than 32x32 tiles are made of several 32x32
+/// entry screenblocks stored one after another, rather than of longer rows:
+/// 64x32 maps store the left block then the right, 32x64 maps the top then
+/// the bottom, and 64x64 maps the top left, top right, bottom left, then
+/// bottom right. Only those sizes and 32x32 are accepted.
+pub fn agb_screenblocks(entries: &[MapEntry], map_width: u32) -> io::Result<Vec<MapEntry>> {
+    let map_height = match map_width {
+        0 => 0,
+        w => entries.len() as u32 / w
+    };
+    let valid = map_width * map_height == entries.len() as u32
+        && (map_width == 32 || map_width == 64)
+        && (map_height == 32 || map_height == 64);
+
+    if !valid {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "AGB text background maps must be 32 or 64 entries in each dimension."));
+    }
+
+    let mut out = Vec::with_capacity(entries.len());
+
+    for block_y in 0..map_height / 32 {
+        for block_x in 0..map_width / 32 {
+            for y in 0..32 {
+                let start = ((block_y * 32 + y) * map_width + block_x * 32) as usize;
+
+                out.extend_from_slice(&entries[start..start + 32]);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// A set of distinct tiles and a map placing them to form an image.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Tilemap {
+    /// Index data for each distinct tile, one tile after another.
+    pub tileset: Vec<u8>,
+
+    /// Which tile goes where, left to right then top to bottom.
+    pub entries: Vec<MapEntry>
+}
+
+/// Given tile-ordered index data, as produced by indexes_from_luma or
+/// indexes_from_index_image, find the distinct tiles in it and map where each
+/// tile is used.
+///
+/// Tiles are numbered in the order they first appear, so the first tile of
+/// the image is always tile 0. Trailing index data too short to fill a tile
+/// is ignored.
+pub fn dedupe_tiles(indexes: &[u8], tsize: (u32, u32)) -> Tilemap {
+    dedupe_tiles_with_flips(indexes, tsize, false)
+}
+
+/// Flip a tile of index data horizontally and/or vertically.
+fn flip_tile(tile: &[u8], tsize: (u32, u32), hflip: bool, vflip: bool) -> Vec<u8> {
+    let (tw, th) = (tsize.0 as usize, tsize.1 as usize);
+
+    (0..tw * th).map(|i| {
+        let (x, y) = (i % tw, i / tw);
+        let sx = if hflip { tw - 1 - x } else { x };
+        let sy = if vflip { th - 1 - y } else { y };
+
+        tile[sy * tw + sx]
+    }).collect()
+}
+
+/// Given tile-ordered index data, find the distinct tiles in it as
+/// dedupe_tiles does, optionally also matching tiles that are flipped copies
+/// of earlier ones.
+///
+/// When flips is true, a tile matching an earlier tile flipped horizontally,
+/// vertically, or both is mapped to that tile with the corresponding flip
+/// bits set, rather than added to the tileset. Unflipped matches are
+/// preferred, then horizontal, vertical, and both. Map layouts without flip
+/// bits should not be given flipped entries.
+pub fn dedupe_tiles_with_flips(indexes: &[u8], tsize: (u32, u32), flips: bool) -> Tilemap {
+    let tlen = (tsize.0 * tsize.1) as usize;
+    let mut seen : HashMap<Vec<u8>, u16> = HashMap::new();
+    let mut tileset = Vec::new();
+    let mut entries = Vec::with_capacity(indexes.len() / tlen.max(1));
+    let orientations : &[(bool, bool)] = match flips {
+        true => &[(false, false), (true, false), (false, true), (true, true)],
+        false => &[(false, false)]
+    };
+
+    for tile in indexes.chunks_exact(tlen.max(1)) {
+        let found = orientations.iter().filter_map(|&(hflip, vflip)| {
+            seen.get(&flip_tile(tile, tsize, hflip, vflip)).map(|number| MapEntry { tile: *number, hflip, vflip, palette: 0, priority: false })
+        }).next();
+
+        let entry = match found {
+            Some(entry) => entry,
+            None => {
+                let number = seen.len() as u16;
+
+                seen.insert(tile.to_vec(), number);
+                tileset.extend_from_slice(tile);
+                MapEntry { tile: number, hflip: false, vflip: false, palette: 0, priority: false }
+            }
+        };
+
+        entries.push(entry);
+    }
+
+    Tilemap { tileset, entries }
+}
+
+/// Given an image of color indexes, an encoder for its tileset, and a writer
+/// for its map, encode the image as a tileset of distinct tiles and a map of
+/// where they go.
+///
+/// If flips is true and the map layout has flip bits, tiles that are flipped
+/// copies of others are stored once, as dedupe_tiles_with_flips does. The
+/// image must be a whole number of tiles in each dimension, and its map
+/// is as many entries wide as the image is tiles wide. Images with more
+/// distinct tiles than the map layout can number, or holding indexes the
+/// format cannot represent, are rejected. The tileset and map are returned
+/// as well as written.
+pub fn encode_tilemap<'a, E, I, M>(enc: &mut E, map: &mut M, map_format: MapFormat, flips: bool, image: &I) -> io::Result<Tilemap> where E: IndexedGraphicsEncoder + 'a, I: GenericImage<Pixel=Luma<u8>>, M: Write + 'a {
+    let (width, height) = image.dimensions();
+    let (tw, th) = enc.tile_size();
+
+    check_tiled((tw, th))?;
+
+    if !width.is_multiple_of(tw) || !height.is_multiple_of(th) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Tilemapped images must be a whole number of tiles in size."));
+    }
+
+    let indexes = indexes_from_index_image(image, (tw, th));
+
+    if indexes.iter().any(|i| *i as u16 > enc.palette_maxcol()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Image holds an index beyond the format's palette."));
+    }
+
+    let tilemap = dedupe_tiles_with_flips(&indexes, (tw, th), flips && map_format.has_flips());
+
+    write_tilemap(enc, map, map_format, tilemap)
+}
+
+/// Check that a format's tiles are larger than a single pixel, as tilemaps of
+/// untiled formats would hold one entry per pixel.
+fn check_tiled(tsize: (u32, u32)) -> io::Result<()> {
+    match tsize {
+        (0, _) | (_, 0) | (1, 1) => Err(io::Error::new(io::ErrorKind::InvalidInput, "Tilemaps are not supported for untiled formats.")),
+        _ => Ok(())
+    }
+}
+
+/// Encode a tileset through an encoder and write its map, checking that the
+/// map layout can number every tile.
+fn write_tilemap<'a, E, M>(enc: &mut E, map: &mut M, map_format: MapFormat, tilemap: Tilemap) -> io::Result<Tilemap> where E: IndexedGraphicsEncoder + 'a, M: Write + 'a {
+    let (tw, th) = enc.tile_size();
+    let count = tilemap.tileset.len() as u32 / (tw * th);
+
+    if count > map_format.max_tile() as u32 + 1 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Image has {} distinct tiles, more than the map can number.", count)));
+    }
+
+    enc.encode_indexes(tilemap.tileset.clone(), tw, th * count)?;
+    encode_map(map, map_format, &tilemap.entries)?;
+
+    Ok(tilemap)
+}
+
+/// Given an image divided between subpalettes by assign_subpalettes, find
+/// its distinct tiles and map where each is used, selecting each tile's
+/// subpalette in its map entry.
+///
+/// Tiles are compared by their indexes alone, so tiles drawn alike in
+/// different subpalettes share one tile in the tileset and differ only in
+/// the palette bank of their entries.
+pub fn tilemap_from_subpalettes(assignment: &SubpaletteAssignment, tsize: (u32, u32), flips: bool) -> Tilemap {
+    let mut tilemap = dedupe_tiles_with_flips(&assignment.indexes, tsize, flips);
+
+    for (entry, bank) in tilemap.entries.iter_mut().zip(assignment.tile_banks.iter()) {
+        entry.palette = *bank;
+    }
+
+    tilemap
+}
+
+/// Given an image divided between subpalettes, an encoder for its tileset,
+/// and a writer for its map, encode the image as a tileset and a map whose
+/// entries select each tile's subpalette.
+///
+/// The assignment must have been made with the encoder's tile size and with
+/// banks no larger than the encoder's palette, and must not use more
+/// subpalettes than the map layout can select. Flips are handled as
+/// encode_tilemap does. The tileset and map are returned as well as written.
+pub fn encode_subpalette_tilemap<'a, E, M>(enc: &mut E, map: &mut M, map_format: MapFormat, flips: bool, assignment: &SubpaletteAssignment) -> io::Result<Tilemap> where E: IndexedGraphicsEncoder + 'a, M: Write + 'a {
+    check_tiled(enc.tile_size())?;
+
+    if enc.palette_maxcol() >= 255 && assignment.palettes.len() > 1 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Formats indexing 256 colors have no palette banks to select."));
+    }
+
+    if assignment.bank_size > enc.palette_maxcol() as usize + 1 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Subpalettes are larger than the format's palette."));
+    }
+
+    if assignment.palettes.len() > map_format.max_palette() as usize + 1 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image uses more subpalettes than the map can select."));
+    }
+
+    let tilemap = tilemap_from_subpalettes(assignment, enc.tile_size(), flips && map_format.has_flips());
+
+    write_tilemap(enc, map, map_format, tilemap)
+}
+
+/// Given an image of color indexes, format descriptions for its tileset and
+/// map, and writers for both, encode the image as a tileset and map.
+///
+/// This function allows access to built-in, private type implementations of
+/// these traits, in the same manner as encode_index_image_with_format.
+pub fn encode_tilemap_with_format<'a, W, M, I>(format: IndexedFormat, map_format: MapFormat, flips: bool, tile_data: &mut W, map_data: &mut M, image: &I) -> io::Result<Tilemap> where W: Write + 'a, M: Write + 'a, I: GenericImage<Pixel=Luma<u8>> {
+    with_indexed_codec!(format, tile_data, |enc| encode_tilemap(&mut enc, map_data, map_format, flips, image))
+}
+
+/// Given an image of color indexes and writers for its tileset and map,
+/// encode the image as an AGB affine background.
+///
+/// Affine backgrounds use 8bpp tiles and one byte per map entry, with no
+/// flip or palette bits, so flipped copies of tiles are stored separately.
+/// The image must be square and 16, 32, 64, or 128 tiles on each side, and
+/// must have no more than 256 distinct tiles.
+pub fn encode_agb_affine_tilemap<'a, W, M, I>(tile_data: &mut W, map_data: &mut M, image: &I) -> io::Result<Tilemap> where W: Write + 'a, M: Write + 'a, I: GenericImage<Pixel=Luma<u8>> {
+    let (width, height) = image.dimensions();
+
+    if width != height || ![128, 256, 512, 1024].contains(&width) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "AGB affine backgrounds must be square, and 16, 32, 64, or 128 tiles on each side."));
+    }
+
+    let indexes = indexes_from_index_image(image, (8, 8));
+    let tilemap = dedupe_tiles(&indexes, (8, 8));
+    let count = tilemap.tileset.len() / 64;
+
+    if count > 256 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Image has {} distinct tiles, but affine backgrounds can only use 256.", count)));
+    }
+
+    write_tilemap(&mut AGB8Encoder::new_tiled(tile_data), map_data, MapFormat::Byte, tilemap)
+}
+
+/// Given an image divided between subpalettes, format descriptions for its
+/// tileset and map, and writers for both, encode the image as a tileset and
+/// a map selecting each tile's subpalette.
+///
+/// This function allows access to built-in, private type implementations of
+/// these traits, in the same manner as encode_tilemap_with_format. Formats
+/// indexing 256 colors have no palette banks, so only a single subpalette may
+/// be used with them.
+pub fn encode_subpalette_tilemap_with_format<'a, W, M>(format: IndexedFormat, map_format: MapFormat, flips: bool, tile_data: &mut W, map_data: &mut M, assignment: &SubpaletteAssignment) -> io::Result<Tilemap> where W: Write + 'a, M: Write + 'a {
+    with_indexed_codec!(format, tile_data, |enc| encode_subpalette_tilemap(&mut enc, map_data, map_format, flips, assignment))
+}
+
+/// Given parsed map entries, decoded index data for a tileset, and a palette,
+/// render the background image the map describes.
+///
+/// The map is map_width tiles wide and as many rows tall as its entries fill.
+/// Each entry's tile is looked up by number in the tileset and flipped as the
+/// entry specifies. Palette banks are bank_size colors long, and an entry's
+/// palette bank selects which of them its tile's indexes are looked up in; a
+/// bank_size of zero ignores palette banks altogether. Tiles missing from the
+/// tileset, and colors missing from the palette, render as transparent.
+pub fn render_tilemap(entries: &[MapEntry], map_width: u32, tileset: &[u8], tsize: (u32, u32), palette: &[Rgba<u8>], bank_size: usize) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+    if map_width == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Tilemaps must be at least one tile wide."));
+    }
+
+    let (tw, th) = tsize;
+    let tlen = (tw * th) as usize;
+    let map_height = (entries.len() as u32).div_ceil(map_width);
+
+    Ok(ImageBuffer::from_fn(map_width * tw, map_height * th, |x, y| {
+        let entry = match entries.get(((y / th) * map_width + x / tw) as usize) {
+            Some(e) => e,
+            None => return Rgba([0, 0, 0, 0])
+        };
+
+        let px = if entry.hflip { tw - 1 - x % tw } else { x % tw };
+        let py = if entry.vflip { th - 1 - y % th } else { y % th };
+
+        match tileset.get(entry.tile as usize * tlen + (py * tw + px) as usize) {
+            Some(&index) => palette.get(entry.palette as usize * bank_size + index as usize).cloned().unwrap_or(Rgba([0, 0, 0, 0])),
+            None => Rgba([0, 0, 0, 0])
+        }
+    }))
+}
+
+/// Given decoders for a tileset and its palette, render a tilemap with them.
+///
+/// Palette banks are as many colors long as the tileset's format can index,
+/// except for formats indexing 256 colors, which ignore palette banks as the
+/// hardware does. The whole of both decoders' data sources is decoded.
+pub fn decode_tilemap<'a, E, D>(entries: &[MapEntry], map_width: u32, enc: &mut E, pal: &mut D) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> where E: IndexedGraphicsDecoder + 'a, D: IndexedGraphicsDecoder + 'a {
+    let palette = pal.decode_palette(usize::MAX)?;
+
+    decode_tilemap_with_palette(entries, map_width, enc, &palette)
+}
+
+/// Render a tilemap with a tileset decoder and an already-decoded palette.
+fn decode_tilemap_with_palette<'a, E>(entries: &[MapEntry], map_width: u32, enc: &mut E, palette: &[Rgba<u8>]) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> where E: IndexedGraphicsDecoder + 'a {
+    check_tiled(enc.tile_size())?;
+
+    let tileset : Vec<u8> = enc.decode_indexes(usize::MAX)?;
+    let bank_size = match enc.palette_maxcol() {
+        maxcol if maxcol >= 255 => 0,
+        maxcol => maxcol as usize + 1
+    };
+
+    render_tilemap(entries, map_width, &tileset, enc.tile_size(), palette, bank_size)
+}
+
+/// Given tilemap, tileset, and palette data, and format descriptions for the
+/// map and the tileset, render the composed background image.
+///
+/// Up to map_size bytes of tilemap data are decoded, and the map is laid out
+/// map_width tiles wide.
+///
+/// This function allows access to built-in, private type implementations of
+/// these traits, in the same manner as decode_indexes_as_image_with_format.
+pub fn decode_tilemap_with_format<'a, M, R, Q>(format: IndexedFormat, map_format: MapFormat, map_data: &mut M, map_size: usize, map_width: u32, tile_data: &mut R, palette_data: &mut Q) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> where M: Read + 'a, R: Read + 'a, Q: Read + 'a {
+    let entries = decode_map(map_data, map_format, map_size)?;
+
+    let palette = with_indexed_codec!(format, palette_data, |pal| pal.decode_palette(usize::MAX))?;
+
+    with_indexed_codec!(format, tile_data, |dec| decode_tilemap_with_palette(&entries, map_width, &mut dec, &palette))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use awsmimg::formats::IndexedFormat;
+    use image::{ImageBuffer, Luma, LumaA, Rgba};
+    use awsmimg::tilemap::{MapFormat, MapEntry, decode_map, render_tilemap, decode_tilemap_with_format, encode_map, interpret_map_format_name, dedupe_tiles, dedupe_tiles_with_flips, encode_tilemap_with_format, encode_subpalette_tilemap_with_format, agb_screenblocks, encode_agb_affine_tilemap, PriorityMask, tile_priorities, apply_priorities};
+    use awsmimg::quantize::assign_subpalettes;
+
+    #[test]
+    fn map_entry_layouts() {
+        assert_eq!(MapFormat::AGBText.parse_entry(&[0x05, 0x3C]), MapEntry { tile: 5, hflip: true, vflip: true, palette: 3, priority: false });
+        assert_eq!(MapFormat::SFC.parse_entry(&[0x05, 0x4C]), MapEntry { tile: 5, hflip: true, vflip: false, palette: 3, priority: false });
+        assert_eq!(MapFormat::MD.parse_entry(&[0x58, 0x05]), MapEntry { tile: 5, hflip: true, vflip: true, palette: 2, priority: false });
+        assert_eq!(MapFormat::Byte.parse_entry(&[0x05]), MapEntry { tile: 5, hflip: false, vflip: false, palette: 0, priority: false });
+        assert!(decode_map(&mut &[0u8, 0, 0][..], MapFormat::AGBText, 3).is_err());
+        assert_eq!(decode_map(&mut &[0u8, 0, 0][..], MapFormat::AGBText, 2).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn render_flips_and_banks() {
+        let tileset : Vec<u8> = vec![0, 1,
+                                     2, 3];
+        let palette : Vec<image::Rgba<u8>> = (0..8).map(|i| image::Rgba([i, 0, 0, 255])).collect();
+        let entries = vec![MapEntry { tile: 0, hflip: false, vflip: false, palette: 0, priority: false },
+                           MapEntry { tile: 0, hflip: true, vflip: true, palette: 1, priority: false },
+                           MapEntry { tile: 9, hflip: false, vflip: false, palette: 0, priority: false }];
+        let img = render_tilemap(&entries, 2, &tileset, (2, 2), &palette, 4).unwrap();
+
+        assert_eq!(img.dimensions(), (4, 4));
+        assert_eq!(img.get_pixel(1, 0), &image::Rgba([1u8, 0, 0, 255]));
+        assert_eq!(img.get_pixel(2, 0), &image::Rgba([7u8, 0, 0, 255]));
+        assert_eq!(img.get_pixel(3, 1), &image::Rgba([4u8, 0, 0, 255]));
+        assert_eq!(img.get_pixel(0, 2), &image::Rgba([0u8, 0, 0, 0]));
+        assert_eq!(img.get_pixel(2, 2), &image::Rgba([0u8, 0, 0, 0]));
+    }
+
+    #[test]
+    fn decode_agb_tilemap() {
+        let tiles : Vec<u8> = vec![0x10; 32];
+        let palette : Vec<u8> = vec![0x00, 0x00, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x00,
+                                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                                     0x00, 0x00, 0xE0, 0x03];
+        let map : Vec<u8> = vec![0x00, 0x04, 0x00, 0x10];
+        let img = decode_tilemap_with_format(IndexedFormat::AGB4, MapFormat::AGBText, &mut &map[..], map.len(), 2,
+                                             &mut &tiles[..], &mut &palette[..]).unwrap();
+
+        assert_eq!(img.dimensions(), (16, 8));
+        assert_eq!(img.get_pixel(0, 0), &image::Rgba([255u8, 0, 0, 255]));
+        assert_eq!(img.get_pixel(1, 0), &image::Rgba([0u8, 0, 0, 255]));
+        assert_eq!(img.get_pixel(8, 0), &image::Rgba([0u8, 0, 0, 255]));
+        assert_eq!(img.get_pixel(9, 0), &image::Rgba([0u8, 255, 0, 255]));
+        assert!(decode_tilemap_with_format(IndexedFormat::CGA, MapFormat::Byte, &mut &map[..], 4, 2, &mut &tiles[..], &mut &palette[..]).is_err());
+    }
+
+    #[test]
+    fn map_entry_roundtrip() {
+        let entry = MapEntry { tile: 5, hflip: true, vflip: false, palette: 3, priority: false };
+
+        for format in &[MapFormat::AGBText, MapFormat::SFC, MapFormat::MD] {
+            assert_eq!(format.parse_entry(&format.encode_entry(entry).unwrap()), entry);
+        }
+
+        assert_eq!(MapFormat::AGBText.encode_entry(MapEntry { tile: 5, hflip: true, vflip: true, palette: 3, priority: false }).unwrap(), vec![0x05, 0x3C]);
+        assert!(MapFormat::SFC.encode_entry(MapEntry { palette: 8, ..entry }).is_err());
+        assert!(MapFormat::Byte.encode_entry(entry).is_err());
+        assert!(MapFormat::AGBText.encode_entry(MapEntry { tile: 1024, ..entry }).is_err());
+        assert_eq!(interpret_map_format_name("AGBText"), Some(MapFormat::AGBText));
+    }
+
+    #[test]
+    fn tile_dedup() {
+        let indexes : Vec<u8> = vec![1, 1, 2, 2, 1, 1, 3, 3, 2, 2];
+        let tilemap = dedupe_tiles(&indexes, (2, 1));
+
+        assert_eq!(tilemap.tileset, vec![1, 1, 2, 2, 3, 3]);
+        assert_eq!(tilemap.entries.iter().map(|e| e.tile).collect::<Vec<_>>(), vec![0, 1, 0, 2, 1]);
+    }
+
+    #[test]
+    fn encode_agb_tilemap() {
+        //Two identical tiles beside a third, different one.
+        let image : ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(24, 8, |x, _| Luma([if x >= 16 { 2 } else { 1 }]));
+        let mut tiles = Vec::new();
+        let mut map = Vec::new();
+        let tilemap = encode_tilemap_with_format(IndexedFormat::AGB4, MapFormat::AGBText, true, &mut tiles, &mut map, &image).unwrap();
+
+        assert_eq!(tilemap.entries.len(), 3);
+        assert_eq!(tiles.len(), 64);
+        assert_eq!(tiles[0], 0x11);
+        assert_eq!(tiles[32], 0x22);
+        assert_eq!(map, vec![0x00, 0x00, 0x00, 0x00, 0x01, 0x00]);
+
+        let mut again = Vec::new();
+        encode_map(&mut again, MapFormat::AGBText, &tilemap.entries).unwrap();
+        assert_eq!(again, map);
+
+        let ragged : ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::new(12, 8);
+        assert!(encode_tilemap_with_format(IndexedFormat::AGB4, MapFormat::AGBText, true, &mut Vec::new(), &mut Vec::new(), &ragged).is_err());
+        assert!(encode_tilemap_with_format(IndexedFormat::CGA, MapFormat::Byte, true, &mut Vec::new(), &mut Vec::new(), &image).is_err());
+    }
+
+    #[test]
+    fn flipped_tile_dedup() {
+        let tile : Vec<u8> = vec![1, 2,
+                                  3, 4];
+        let hflip : Vec<u8> = vec![2, 1, 4, 3];
+        let vflip : Vec<u8> = vec![3, 4, 1, 2];
+        let hvflip : Vec<u8> = vec![4, 3, 2, 1];
+        let indexes : Vec<u8> = [&tile, &hflip, &vflip, &hvflip].iter().flat_map(|t| t.iter().cloned()).collect();
+        let tilemap = dedupe_tiles_with_flips(&indexes, (2, 2), true);
+
+        assert_eq!(tilemap.tileset, tile);
+        assert_eq!(tilemap.entries.iter().map(|e| (e.tile, e.hflip, e.vflip)).collect::<Vec<_>>(),
+                   vec![(0, false, false), (0, true, false), (0, false, true), (0, true, true)]);
+        assert_eq!(dedupe_tiles(&indexes, (2, 2)).entries.iter().map(|e| e.tile).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+
+        //Symmetric tiles match unflipped first.
+        let symmetric : Vec<u8> = vec![5, 5, 5, 5, 5, 5, 5, 5];
+        assert!(dedupe_tiles_with_flips(&symmetric, (2, 2), true).entries.iter().all(|e| !e.hflip && !e.vflip));
+
+        let rendered = render_tilemap(&tilemap.entries, 4, &tilemap.tileset, (2, 2), &(0..5).map(|i| image::Rgba([i, 0, 0, 255])).collect::<Vec<_>>(), 0).unwrap();
+        let expected = render_tilemap(&dedupe_tiles(&indexes, (2, 2)).entries, 4, &indexes, (2, 2), &(0..5).map(|i| image::Rgba([i, 0, 0, 255])).collect::<Vec<_>>(), 0).unwrap();
+        assert_eq!(rendered.into_raw(), expected.into_raw());
+    }
+
+    #[test]
+    fn subpalette_map_banks() {
+        //A red tile and a blue tile, which can't share a two-color bank.
+        let image : ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(16, 8, |x, _| match x < 8 {
+            true => Rgba([255, 0, 0, 255]),
+            false => Rgba([0, 0, 255, 255])
+        });
+        let assignment = assign_subpalettes(&image, (8, 8), 2, 2).unwrap();
+        let mut tiles = Vec::new();
+        let mut map = Vec::new();
+        let tilemap = encode_subpalette_tilemap_with_format(IndexedFormat::AGB4, MapFormat::AGBText, true, &mut tiles, &mut map, &assignment).unwrap();
+
+        assert_eq!(tiles.len(), 32);
+        assert_eq!(tilemap.entries.iter().map(|e| (e.tile, e.palette)).collect::<Vec<_>>(), vec![(0, assignment.tile_banks[0]), (0, assignment.tile_banks[1])]);
+        assert_ne!(assignment.tile_banks[0], assignment.tile_banks[1]);
+        assert_eq!(map[1] >> 4, assignment.tile_banks[0]);
+        assert_eq!(map[3] >> 4, assignment.tile_banks[1]);
+
+        let rendered = render_tilemap(&tilemap.entries, 2, &tilemap.tileset, (8, 8), &assignment.palette(), assignment.bank_size).unwrap();
+        assert_eq!(rendered.get_pixel(0, 0), &Rgba([255u8, 0, 0, 255]));
+        assert_eq!(rendered.get_pixel(8, 0), &Rgba([0u8, 0, 255, 255]));
+        assert!(encode_subpalette_tilemap_with_format(IndexedFormat::AGB4, MapFormat::Byte, true, &mut Vec::new(), &mut Vec::new(), &assignment).is_err());
+    }
+
+    #[test]
+    fn screenblock_order() {
+        let map = |w: u32, h: u32| -> Vec<MapEntry> {
+            (0..w * h).map(|i| MapEntry { tile: (i % w / 32 + i / w / 32 * 2) as u16, hflip: false, vflip: false, palette: 0, priority: false }).collect()
+        };
+        let blocks = |entries: Vec<MapEntry>| -> Vec<u16> { entries.chunks(1024).map(|b| b[0].tile).collect() };
+
+        //Each entry's tile number is the screenblock it belongs in.
+        let wide = agb_screenblocks(&map(64, 32), 64).unwrap();
+        assert_eq!(blocks(wide.clone()), vec![0, 1]);
+        assert!(wide.chunks(1024).all(|b| b.iter().all(|e| e.tile == b[0].tile)));
+        assert_eq!(blocks(agb_screenblocks(&map(32, 64), 32).unwrap()), vec![0, 2]);
+        assert_eq!(blocks(agb_screenblocks(&map(64, 64), 64).unwrap()), vec![0, 1, 2, 3]);
+        assert!(agb_screenblocks(&map(64, 64), 64).unwrap().chunks(1024).all(|b| b.iter().all(|e| e.tile == b[0].tile)));
+        assert_eq!(agb_screenblocks(&map(32, 32), 32).unwrap(), map(32, 32));
+        assert!(agb_screenblocks(&map(48, 32), 48).is_err());
+        assert!(agb_screenblocks(&map(64, 16), 64).is_err());
+    }
+
+    #[test]
+    fn agb_affine_map() {
+        //A checkerboard of two tiles.
+        let image : ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(128, 128, |x, y| Luma([((x / 8 + y / 8) % 2) as u8 + 1]));
+        let mut tiles = Vec::new();
+        let mut map = Vec::new();
+        let tilemap = encode_agb_affine_tilemap(&mut tiles, &mut map, &image).unwrap();
+
+        assert_eq!(tiles.len(), 128);
+        assert_eq!(tiles[0], 1);
+        assert_eq!(tiles[64], 2);
+        assert_eq!(map.len(), 256);
+        assert_eq!(&map[..3], &[0, 1, 0]);
+        assert_eq!(map[16], 1);
+        assert!(tilemap.entries.iter().all(|e| !e.hflip && !e.vflip && e.palette == 0));
+
+        //Every one of the 1024 tiles distinct.
+        let crowded : ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(256, 256, |x, y| {
+            let n = x / 8 + y / 8 * 32;
+            Luma([if x % 8 == 0 { n as u8 } else { (n >> 8) as u8 }])
+        });
+        assert!(encode_agb_affine_tilemap(&mut Vec::new(), &mut Vec::new(), &crowded).is_err());
+        assert!(encode_agb_affine_tilemap(&mut Vec::new(), &mut Vec::new(), &ImageBuffer::<Luma<u8>, Vec<u8>>::new(256, 128)).is_err());
+        assert!(encode_agb_affine_tilemap(&mut Vec::new(), &mut Vec::new(), &ImageBuffer::<Luma<u8>, Vec<u8>>::new(96, 96)).is_err());
+    }
+
+    #[test]
+    fn sfc_priority_entries() {
+        let entry = MapEntry { tile: 0x105, hflip: false, vflip: true, palette: 2, priority: true };
+
+        assert_eq!(MapFormat::SFC.encode_entry(entry).unwrap(), vec![0x05, 0xA9]);
+        assert_eq!(MapFormat::SFC.parse_entry(&[0x05, 0xA9]), entry);
+        assert_eq!(MapFormat::MD.parse_entry(&MapFormat::MD.encode_entry(entry).unwrap()), entry);
+        assert!(MapFormat::AGBText.encode_entry(entry).is_err());
+
+        //The top left tile is painted in the mask, the others are not.
+        let mask : ImageBuffer<LumaA<u8>, Vec<u8>> = ImageBuffer::from_fn(16, 16, |x, y| match (x, y) {
+            (3, 4) => LumaA([255, 200]),
+            (12, 12) => LumaA([0, 200]),
+            _ => LumaA([0, 0])
+        });
+
+        assert_eq!(tile_priorities(&mask, (8, 8), PriorityMask::Luma).unwrap(), vec![true, false, false, false]);
+        assert_eq!(tile_priorities(&mask, (8, 8), PriorityMask::Alpha).unwrap(), vec![true, false, false, true]);
+        assert!(tile_priorities(&mask, (6, 8), PriorityMask::Luma).is_err());
+
+        let image : ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(16, 16, |x, _| Luma([(x / 8) as u8]));
+        let mut tilemap = encode_tilemap_with_format(IndexedFormat::SFC4, MapFormat::SFC, true, &mut Vec::new(), &mut Vec::new(), &image).unwrap();
+        let mut map = Vec::new();
+
+        apply_priorities(&mut tilemap.entries, &tile_priorities(&mask, (8, 8), PriorityMask::Luma).unwrap()).unwrap();
+        encode_map(&mut map, MapFormat::SFC, &tilemap.entries).unwrap();
+
+        assert_eq!(map, vec![0x00, 0x20, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00]);
+        assert!(apply_priorities(&mut tilemap.entries, &[true]).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use image::Primitive;
+
+/// Iterates over linearized index data one tile at a time.
+///
+/// Index data produced by indexes_from_luma is already arranged such that
+/// every tile's pixels are contiguous and row-major. This iterator splits such
+/// data into tiles of a given size, yielding each as its own Vec of exactly
+/// tile width * tile height indexes.
+///
+/// If the data ends partway through a tile, the last tile is padded with index
+/// zero so that encoders can always work with complete tiles.
+pub struct TileChunkIterator<'a, P: 'a> {
+    data: &'a [P],
+    tile_len: usize,
+    pos: usize
+}
+
+impl<'a, P: Primitive + 'a> TileChunkIterator<'a, P> {
+    pub fn new(data: &'a [P], tsize: (u32, u32)) -> TileChunkIterator<'a, P> {
+        let (tw, th) = tsize;
+
+        TileChunkIterator {
+            data,
+            tile_len: (tw * th) as usize,
+            pos: 0
+        }
+    }
+}
+
+impl<'a, P: Primitive + 'a> Iterator for TileChunkIterator<'a, P> {
+    type Item = Vec<P>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.tile_len == 0 || self.pos >= self.data.len() {
+            return None;
+        }
+
+        let end = self.data.len().min(self.pos + self.tile_len);
+        let mut tile = self.data[self.pos..end].to_vec();
+
+        tile.resize(self.tile_len, P::zero());
+        self.pos = end;
+
+        Some(tile)
+    }
+}
+
+/// Interleave the bits of a coordinate pair into a Morton (Z-curve) index.
+///
+/// Bits of x occupy the even bits of the result and bits of y the odd bits,
+/// so that (1, 0) is index 1 and (0, 1) is index 2.
+pub fn morton_index(x: u32, y: u32) -> u32 {
+    let mut out = 0;
+
+    for bit in 0..16 {
+        out |= ((x >> bit) & 1) << (bit * 2) | ((y >> bit) & 1) << (bit * 2 + 1);
+    }
+
+    out
+}
+
+/// List the pixel coordinates of an image in Morton-swizzled tile order.
+///
+/// The image is split into square tiles of the given size, which must be a
+/// power of two. Tiles are visited left to right, then top to bottom, and
+/// the pixels within each tile are visited in Morton order. Image dimensions
+/// must be multiples of the tile size.
+pub fn morton_tile_order(width: u32, height: u32, tsize: u32) -> Vec<(u32, u32)> {
+    let tlen = tsize * tsize;
+    let mut out = vec![(0, 0); (width * height) as usize];
+
+    for ty in 0..height / tsize {
+        for tx in 0..width / tsize {
+            let tbase = (ty * (width / tsize) + tx) * tlen;
+
+            for py in 0..tsize {
+                for px in 0..tsize {
+                    out[(tbase + morton_index(px, py)) as usize] = (tx * tsize + px, ty * tsize + py);
+                }
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use awsmimg::tiles::{TileChunkIterator, morton_index, morton_tile_order};
+
+    #[test]
+    fn tile_chunks() {
+        let src : Vec<u8> = (0..20).collect();
+        let tiles : Vec<Vec<u8>> = TileChunkIterator::new(&src, (4, 2)).collect();
+
+        assert_eq!(tiles.len(), 3);
+        assert_eq!(tiles[0], vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(tiles[1], vec![8, 9, 10, 11, 12, 13, 14, 15]);
+        assert_eq!(tiles[2], vec![16, 17, 18, 19, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn morton_order() {
+        assert_eq!(morton_index(1, 0), 1);
+        assert_eq!(morton_index(0, 1), 2);
+        assert_eq!(morton_index(3, 3), 15);
+        assert_eq!(morton_index(7, 0), 21);
+
+        let order = morton_tile_order(4, 2, 2);
+
+        assert_eq!(order, vec![(0, 0), (1, 0), (0, 1), (1, 1), (2, 0), (3, 0), (2, 1), (3, 1)]);
+    }
+}
@@ -0,0 +1,155 @@
+use std::io;
+use image::{GenericImage, ImageBuffer, Pixel, Primitive, Rgba};
+
+use awsmimg::formats::{IndexedFormat, DirectFormat};
+use awsmimg::encoder::{encode_image_as_indexes_with_format, encode_image_as_direct_color_with_format};
+use awsmimg::decoder::{decode_indexes_as_image_with_format, decode_image_as_direct_color_with_format};
+
+/// A pixel which did not survive a round trip unchanged.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PixelDifference {
+    pub x: u32,
+    pub y: u32,
+    pub expected: Rgba<u8>,
+    pub actual: Rgba<u8>
+}
+
+/// The result of encoding an image and decoding it again.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RoundtripReport {
+    pub encoded_size: usize,
+    pub dimensions: (u32, u32),
+    pub decoded_dimensions: (u32, u32),
+    pub differences: Vec<PixelDifference>
+}
+
+impl RoundtripReport {
+    /// Whether the decoded image matches the original exactly.
+    pub fn is_lossless(&self) -> bool {
+        self.dimensions == self.decoded_dimensions && self.differences.is_empty()
+    }
+
+    /// The largest difference in any one channel of any one pixel.
+    pub fn max_channel_error(&self) -> u8 {
+        self.differences.iter().flat_map(|d| {
+            (0..4).map(move |i| (d.expected[i] as i16 - d.actual[i] as i16).unsigned_abs() as u8)
+        }).max().unwrap_or(0)
+    }
+}
+
+/// Given an image, a function which encodes it, and a function which decodes
+/// the encoded data, report how the decoded image differs from the original.
+///
+/// Both images are compared as 8-bit RGBA. Pixels are compared wherever both
+/// images have them; a difference in dimensions is reported separately.
+///
+/// The encode and decode functions are expected to construct an encoder or
+/// decoder over the data given to them, e.g.:
+///
+/// ```ignore
+/// verify_roundtrip(&img, |w, i| AGB16Encoder::new_agb(w).encode_colors(i),
+///                  |d| AGB16Decoder::new_agb(&mut &d[..]).decode_colors(width, d.len()))
+/// ```
+pub fn verify_roundtrip<I, P, S, FE, FD>(image: &I, encode: FE, decode: FD) -> io::Result<RoundtripReport>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static,
+          FE: FnOnce(&mut Vec<u8>, &I) -> io::Result<()>, FD: FnOnce(&[u8]) -> io::Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+
+    let mut data = Vec::new();
+
+    encode(&mut data, image)?;
+
+    let decoded = decode(&data)?;
+    let imgmax = S::max_value().to_f32().unwrap();
+    let (width, height) = image.dimensions();
+    let mut differences = Vec::new();
+
+    for y in 0..height.min(decoded.height()) {
+        for x in 0..width.min(decoded.width()) {
+            let rgba = image.get_pixel(x, y).to_rgba();
+            let mut expected = Rgba([0u8; 4]);
+
+            for i in 0..4 {
+                expected[i] = (rgba[i].to_f32().unwrap() / imgmax * 255f32) as u8;
+            }
+
+            let actual = *decoded.get_pixel(x, y);
+
+            if expected != actual {
+                differences.push(PixelDifference { x, y, expected, actual });
+            }
+        }
+    }
+
+    Ok(RoundtripReport {
+        encoded_size: data.len(),
+        dimensions: (width, height),
+        decoded_dimensions: decoded.dimensions(),
+        differences
+    })
+}
+
+/// Round trip an image through an indexed format's encoder and decoder.
+///
+/// Index data is encoded from and decoded to grayscale values, as with
+/// encode_image_as_indexes_with_format.
+pub fn verify_indexes_roundtrip<I, P, S>(format: IndexedFormat, image: &I) -> io::Result<RoundtripReport>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    let (width, height) = image.dimensions();
+
+    verify_roundtrip(image, |w, i| encode_image_as_indexes_with_format(format, w, i), |d| {
+        let luma = decode_indexes_as_image_with_format(format, &mut &d[..], d.len(), Some((width, height)))?;
+
+        Ok(ImageBuffer::from_fn(width, height, |x, y| luma.get_pixel(x, y).to_rgba()))
+    })
+}
+
+/// Round trip an image through a direct color format's encoder and decoder.
+pub fn verify_direct_roundtrip<I, P, S>(format: DirectFormat, image: &I) -> io::Result<RoundtripReport>
+    where I: GenericImage<Pixel=P>, P: Pixel<Subpixel=S> + 'static, S: Primitive + 'static {
+
+    let width = image.width();
+
+    verify_roundtrip(image, |w, i| encode_image_as_direct_color_with_format(format, w, i), |d| {
+        decode_image_as_direct_color_with_format(format, &mut &d[..], width, d.len())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate image;
+
+    use awsmimg::formats::{IndexedFormat, DirectFormat};
+    use awsmimg::verify::{verify_indexes_roundtrip, verify_direct_roundtrip, PixelDifference};
+
+    #[test]
+    fn direct_lossless() {
+        let img = image::ImageBuffer::from_fn(4, 2, |x, y| image::Rgba([(x * 0x21) as u8, (y * 0x84) as u8, 0xFFu8, 255u8]));
+        let report = verify_direct_roundtrip(DirectFormat::AGB16, &img).unwrap();
+
+        assert_eq!(report.encoded_size, 16);
+        assert!(report.is_lossless());
+    }
+
+    #[test]
+    fn direct_lossy() {
+        let img = image::ImageBuffer::from_fn(2, 1, |x, _| image::Rgba([x as u8 * 0x0C, 0u8, 0u8, 128u8]));
+        let report = verify_direct_roundtrip(DirectFormat::AGB16, &img).unwrap();
+
+        assert_eq!(report.differences, vec![
+            PixelDifference { x: 0, y: 0, expected: image::Rgba([0, 0, 0, 128]), actual: image::Rgba([0, 0, 0, 255]) },
+            PixelDifference { x: 1, y: 0, expected: image::Rgba([0x0C, 0, 0, 128]), actual: image::Rgba([0x08, 0, 0, 255]) }
+        ]);
+        assert_eq!(report.max_channel_error(), 127);
+    }
+
+    #[test]
+    fn indexes_roundtrip() {
+        let exact = image::ImageBuffer::from_fn(8, 8, |x, _| image::LumaA([(x * 17) as u8, 255u8]));
+        let inexact = image::ImageBuffer::from_fn(8, 8, |_, _| image::LumaA([100u8, 255u8]));
+
+        assert!(verify_indexes_roundtrip(IndexedFormat::AGB4, &exact).unwrap().is_lossless());
+        assert_eq!(verify_indexes_roundtrip(IndexedFormat::AGB4, &inexact).unwrap().differences.len(), 64);
+        assert!(verify_indexes_roundtrip(IndexedFormat::AGB4, &image::ImageBuffer::from_fn(4, 4, |_, _| image::LumaA([0u8, 255u8]))).is_err());
+    }
+}
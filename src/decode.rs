@@ -1,49 +1,121 @@
 extern crate argparse;
 extern crate image;
 extern crate num;
+extern crate awsmimg;
 
-mod awsmimg;
-
-use argparse::{ArgumentParser, Store, StoreFalse, StoreTrue};
+use argparse::{ArgumentParser, Store, StoreTrue};
 use std::fs::{OpenOptions};
 use std::io;
-use std::io::Seek;
-use awsmimg::decoder::{decode_indexes_as_image_with_format};
-use awsmimg::formats::{interpret_indexed_format_name, interpret_direct_format_name};
+use std::io::{Read, Seek};
+use awsmimg::awsmimg::decoder::{decode, decode_to_indexed_png, decode_indexes_as_dump_with_format};
+use awsmimg::awsmimg::dump::write_index_dump;
+use awsmimg::awsmimg::ripper::scan_tiles_with_format;
+use awsmimg::awsmimg::formats::interpret_indexed_format_name;
+use awsmimg::awsmimg::compression::agb::decompress_if_compressed;
 
 fn main() -> io::Result<()> {
     let mut input_filename = "".to_string();
     let mut output_filename = "".to_string();
     let mut format = "".to_string();
     let mut offset = 0u64;
-    let mut size = u64::max_value();
+    let mut size = u64::MAX;
+    let mut tiles = 0usize;
+    let mut columns = 16u32;
+    let mut step = 0usize;
+    let mut pages = 1usize;
+    let mut decompress = false;
+    let mut palette_filename = "".to_string();
+    let mut width = 0u32;
+    let mut dump = false;
 
     {
         let mut ap = ArgumentParser::new();
 
         ap.set_description("Convert retro image data into a modern format.");
 
-        ap.refer(&mut input_filename).add_argument("input", Store, "The retro image data to decode.");
+        ap.refer(&mut input_filename).add_argument("input", Store, "The retro image data to decode, or - to read it from standard input.");
         ap.refer(&mut output_filename).add_argument("output", Store, "Where to store the modern image file.");
         ap.refer(&mut format).add_option(&["--format"], Store, "The format to convert the image from.");
         ap.refer(&mut offset).add_option(&["--offset"], Store, "Where to read data from within the source file.");
         ap.refer(&mut size).add_option(&["--size"], Store, "Maximum amount of data to read from the file.");
+        ap.refer(&mut width).add_option(&["--width"], Store, "Width of the decoded image, in pixels. Required for direct color formats.");
+        ap.refer(&mut tiles).add_option(&["--tiles"], Store, "Rip this many tiles into a sheet instead of decoding by size.");
+        ap.refer(&mut columns).add_option(&["--columns"], Store, "Width of ripped tile sheets, in tiles.");
+        ap.refer(&mut step).add_option(&["--step"], Store, "Rip further sheets at this many bytes past the offset. Requires --tiles.");
+        ap.refer(&mut pages).add_option(&["--pages"], Store, "Maximum number of sheets to rip when stepping.");
+        ap.refer(&mut palette_filename).add_option(&["--palette"], Store, "Palette data to decode alongside the image, writing an indexed-color PNG.");
+        ap.refer(&mut dump).add_option(&["--dump"], StoreTrue, "Write decoded indexes exactly, as a PGM index dump.");
+        ap.refer(&mut decompress).add_option(&["--decompress"], StoreTrue, "Decompress data at the offset if it has an AGB BIOS compression header.");
 
         ap.parse_args_or_exit();
     }
 
     println!("Decoding {} to {}", input_filename, output_filename);
 
-    let mut bin = OpenOptions::new().read(true).open(input_filename)?;
-    let orig_length = bin.seek(io::SeekFrom::End(0))?;
-    if offset > orig_length {
-        //Seeking beyond the end of a file is implementation defined. Hence, we error out
-        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Proposed offset length exceeds length of file."))
+    let mut bin : Box<dyn Read> = match input_filename.as_ref() {
+        "-" => {
+            //Standard input can't be seeked, so skip to the offset by reading past it
+            let mut stdin = io::stdin();
+            let skipped = io::copy(&mut stdin.by_ref().take(offset), &mut io::sink())?;
+            if skipped < offset {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Proposed offset length exceeds length of input."))
+            }
+            Box::new(stdin)
+        },
+        _ => {
+            let mut file = OpenOptions::new().read(true).open(&input_filename)?;
+            let orig_length = file.seek(io::SeekFrom::End(0))?;
+            if offset > orig_length {
+                //Seeking beyond the end of a file is implementation defined. Hence, we error out
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Proposed offset length exceeds length of file."))
+            }
+            file.seek(io::SeekFrom::Start(offset))?;
+            Box::new(file)
+        }
+    };
+
+    if decompress {
+        bin = Box::new(io::Cursor::new(decompress_if_compressed(&mut bin)?));
+    }
+
+    if tiles > 0 || dump || !palette_filename.is_empty() {
+        let idxfmt = match interpret_indexed_format_name(&format) {
+            Some(f) => f,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Ripping tiles, dumping indexes and decoding palettes require an indexed format."))
+        };
+
+        if dump {
+            let indexes = decode_indexes_as_dump_with_format(idxfmt, &mut bin, size as usize, None)?;
+
+            return write_index_dump(&mut OpenOptions::new().write(true).create(true).truncate(true).open(&output_filename)?, &indexes);
+        }
+
+        if !palette_filename.is_empty() {
+            let mut palette = OpenOptions::new().read(true).open(&palette_filename)?;
+            let out = OpenOptions::new().write(true).create(true).truncate(true).open(&output_filename)?;
+
+            return decode_to_indexed_png(idxfmt, &mut bin, &mut palette, size as usize, None, out);
+        }
+
+        let mut data = Vec::new();
+        bin.read_to_end(&mut data)?;
+
+        let step = if step > 0 { step } else { data.len().max(1) };
+        let sheets = scan_tiles_with_format(idxfmt, &data, 0, step, pages, tiles, columns)?;
+
+        for (sheet_offset, sheet) in sheets {
+            let sheet_filename = match step < data.len() {
+                true => output_filename.replacen(".", &format!("_{:x}.", offset as usize + sheet_offset), 1),
+                false => output_filename.clone()
+            };
+
+            sheet.save(sheet_filename)?;
+        }
+
+        return Ok(());
     }
-    bin.seek(io::SeekFrom::Start(offset))?;
 
-    let idxfmt = interpret_indexed_format_name(&format).unwrap();
-    let img = decode_indexes_as_image_with_format(idxfmt, &mut bin, size as usize, None)?;
+    let img = decode(&format, &mut bin, size as usize, Some(width).filter(|w| *w > 0))?;
 
     img.save(output_filename)
 }
@@ -1,15 +1,20 @@
 extern crate argparse;
 extern crate image;
 extern crate num;
-
-mod awsmimg;
+extern crate awsmimg;
 
 use argparse::{ArgumentParser, Store, StoreFalse, StoreTrue};
 use std::fs::{OpenOptions};
 use std::io;
 use std::io::Seek;
-use awsmimg::encoder::{encode_image_as_indexes_with_format, encode_image_as_direct_color_with_format};
-use awsmimg::formats::{interpret_indexed_format_name, interpret_direct_format_name};
+use awsmimg::awsmimg::encoder::{encode_with_alpha_threshold, encode_image_as_indexes_with_format_and_ramp, encode_dump_with_format, encode_index_image_with_format, encode_palette_with_format, PaletteFit, fit_palette};
+use awsmimg::awsmimg::formats::{interpret_indexed_format_name, DEFAULT_ALPHA_THRESHOLD};
+use awsmimg::awsmimg::formats::palette::interpret_palette_format_name;
+use awsmimg::awsmimg::conversion::interpret_gray_ramp;
+use awsmimg::awsmimg::dump::read_index_dump;
+use awsmimg::awsmimg::indexed_png::read_indexed_png;
+use awsmimg::awsmimg::quantize::{TransparentKey, move_transparent_to_index_zero};
+use awsmimg::awsmimg::tilemap::{encode_tilemap_with_format, interpret_map_format_name, agb_screenblocks, encode_map, encode_agb_affine_tilemap, PriorityMask, tile_priorities, apply_priorities};
 
 fn main() -> io::Result<()> {
     let mut input_filename = "".to_string();
@@ -17,6 +22,20 @@ fn main() -> io::Result<()> {
     let mut format = "".to_string();
     let mut truncatemode = true;
     let mut offset = 0u64;
+    let mut dump = false;
+    let mut palette_filename = "".to_string();
+    let mut zero_transparent = false;
+    let mut palette_slot = 0usize;
+    let mut palette_format = "".to_string();
+    let mut tilemap_filename = "".to_string();
+    let mut map_format = "agbtext".to_string();
+    let mut flips = true;
+    let mut screenblocks = false;
+    let mut affine = false;
+    let mut priority_filename = "".to_string();
+    let mut priority_alpha = false;
+    let mut alpha_threshold = DEFAULT_ALPHA_THRESHOLD;
+    let mut ramp = "".to_string();
 
     {
         let mut ap = ArgumentParser::new();
@@ -29,10 +48,45 @@ fn main() -> io::Result<()> {
         ap.refer(&mut truncatemode).add_option(&["--overlay"], StoreFalse, "Overlay encoding result onto existing file. Negates --truncate.")
                                    .add_option(&["--truncate"], StoreTrue, "Erases existing file (if any) before encoding. Negates --overlay.");
         ap.refer(&mut offset).add_option(&["--offset"], Store, "Where to write data to within the target file.");
+        ap.refer(&mut dump).add_option(&["--dump"], StoreTrue, "Read the input as a PGM index dump, encoding its indexes exactly.");
+        ap.refer(&mut palette_filename).add_option(&["--palette"], Store, "Where to store the palette of an indexed-color PNG input, in index order.");
+        ap.refer(&mut zero_transparent).add_option(&["--zero-transparent"], StoreTrue, "Move the transparent color of an indexed-color PNG input to index zero.");
+        ap.refer(&mut palette_slot).add_option(&["--palette-slot"], Store, "Pad the stored palette with black to this many colors, failing if it holds more.");
+
+        ap.refer(&mut palette_format).add_option(&["--palette-format"], Store, "Store the palette in this color format, rather than the one native to --format.");
+        ap.refer(&mut tilemap_filename).add_option(&["--tilemap"], Store, "Store only distinct tiles of an indexed-color PNG input, writing a map of where they go here.");
+        ap.refer(&mut map_format).add_option(&["--map-format"], Store, "The layout of tilemap entries: agbtext, sfc, md or byte.");
+        ap.refer(&mut screenblocks).add_option(&["--screenblocks"], StoreTrue, "Split 64 tile wide or tall tilemaps into AGB screenblocks in VRAM order.");
+        ap.refer(&mut affine).add_option(&["--affine"], StoreTrue, "Make the tilemap an AGB affine background map, with 8bpp tiles and byte entries.");
+        ap.refer(&mut priority_filename).add_option(&["--priority-mask"], Store, "Give tiles painted light in this grayscale image priority in the tilemap.");
+        ap.refer(&mut priority_alpha).add_option(&["--priority-alpha"], StoreTrue, "Give tiles priority where the priority mask, or the input if there is none, is opaque.");
+        ap.refer(&mut flips).add_option(&["--no-flip"], StoreFalse, "Store flipped copies of tiles separately, rather than flipping them in the tilemap.");
+        ap.refer(&mut alpha_threshold).add_option(&["--alpha-threshold"], Store, "Treat pixels and palette colors with less alpha than this (0-255) as transparent.");
+        ap.refer(&mut ramp).add_option(&["--ramp"], Store, "Read grays as indexes through this ramp: linear, levels:<gray>,... or breakpoints:<gray>,...");
 
         ap.parse_args_or_exit();
     }
 
+    let palfmt = match palette_format.as_ref() {
+        "" => None,
+        name => match interpret_palette_format_name(name) {
+            Some(f) => Some(f),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown palette format."))
+        }
+    };
+
+    let ramp = match ramp.as_ref() {
+        "" => None,
+        desc => match interpret_gray_ramp(desc) {
+            Some(r) => Some(r),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown grayscale ramp."))
+        }
+    };
+
+    if affine && (!priority_filename.is_empty() || priority_alpha) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Affine tilemaps have no priority bits."));
+    }
+
     println!("Converting {} to {}", input_filename, output_filename);
 
     let mut bin = OpenOptions::new().write(true).create(true).truncate(truncatemode).open(output_filename)?;
@@ -43,15 +97,96 @@ fn main() -> io::Result<()> {
     }
     bin.seek(io::SeekFrom::Start(offset))?;
 
-    let img = image::open(input_filename).unwrap();
-    let idxfmt = interpret_indexed_format_name(&format);
+    if dump {
+        let idxfmt = match interpret_indexed_format_name(&format) {
+            Some(f) => f,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Index dumps can only be encoded to indexed formats."))
+        };
+        let indexes = read_index_dump(&mut OpenOptions::new().read(true).open(input_filename)?)?;
+
+        return encode_dump_with_format(idxfmt, &mut bin, &indexes);
+    }
+
+    //Indexed-color PNGs are encoded from their indexes, rather than from
+    //their colors' luminance, so that every pixel keeps the index it was drawn with.
+    if let Some(idxfmt) = interpret_indexed_format_name(&format) {
+        if let Ok((mut indexes, mut palette)) = read_indexed_png(OpenOptions::new().read(true).open(&input_filename)?) {
+            if zero_transparent {
+                move_transparent_to_index_zero(&mut palette, &mut indexes, TransparentKey::Alpha)?;
+            }
+
+            let fit = match palette_slot {
+                0 => PaletteFit::AsIs,
+                size => PaletteFit::Pad(size, image::Rgba([0, 0, 0, 255]))
+            };
+            let palette = fit_palette(palette, fit)?;
+
+            if tilemap_filename.is_empty() {
+                encode_index_image_with_format(idxfmt, &mut bin, &indexes)?;
+            } else {
+                let mapfmt = match interpret_map_format_name(&map_format) {
+                    Some(f) => f,
+                    None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown tilemap format."))
+                };
+                let mut map = OpenOptions::new().write(true).create(true).truncate(true).open(&tilemap_filename)?;
 
-    match idxfmt {
-        Some(fmt) => encode_image_as_indexes_with_format(fmt, &mut bin, &img),
-        None => {
-            let dirfmt = interpret_direct_format_name(&format).unwrap();
+                if affine {
+                    encode_agb_affine_tilemap(&mut bin, &mut map, &indexes)?;
+                } else {
+                    //Every tilemapped format uses 8x8 tiles.
+                    let mut tilemap = encode_tilemap_with_format(idxfmt, mapfmt, flips, &mut bin, &mut io::sink(), &indexes)?;
 
-            encode_image_as_direct_color_with_format(dirfmt, &mut bin, &img)
+                    if !priority_filename.is_empty() || priority_alpha {
+                        let mask_filename = match priority_filename.as_ref() {
+                            "" => &input_filename,
+                            name => name
+                        };
+                        let mask = image::open(mask_filename).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Priority mask is not a readable image."))?;
+                        let channel = match priority_alpha {
+                            true => PriorityMask::Alpha,
+                            false => PriorityMask::Luma
+                        };
+
+                        apply_priorities(&mut tilemap.entries, &tile_priorities(&mask, (8, 8), channel)?)?;
+                    }
+
+                    let entries = match screenblocks {
+                        true => agb_screenblocks(&tilemap.entries, indexes.width() / 8)?,
+                        false => tilemap.entries
+                    };
+
+                    encode_map(&mut map, mapfmt, &entries)?;
+                }
+            }
+
+            if !palette_filename.is_empty() {
+                let mut pal = OpenOptions::new().write(true).create(true).truncate(true).open(&palette_filename)?;
+
+                match palfmt {
+                    Some(f) => f.encode_colors_with_alpha_threshold(&mut pal, palette.into_iter(), alpha_threshold)?,
+                    None => encode_palette_with_format(idxfmt, &mut pal, palette)?
+                }
+            }
+
+            return Ok(());
         }
     }
+
+    if !palette_filename.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Palettes can only be taken from indexed-color PNG input."));
+    }
+
+    if !tilemap_filename.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Tilemaps can only be made from indexed-color PNG input."));
+    }
+
+    let img = image::open(input_filename).unwrap();
+
+    match ramp {
+        Some(ramp) => match interpret_indexed_format_name(&format) {
+            Some(idxfmt) => encode_image_as_indexes_with_format_and_ramp(idxfmt, &mut bin, &img, &ramp, alpha_threshold),
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Grayscale ramps only apply to indexed formats."))
+        },
+        None => encode_with_alpha_threshold(&format, &mut bin, &img, alpha_threshold)
+    }
 }
@@ -1,4 +1,5 @@
 extern crate image;
 extern crate num;
+extern crate png;
 
 pub mod awsmimg;
\ No newline at end of file